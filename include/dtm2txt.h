@@ -0,0 +1,90 @@
+/*
+ * C interface to dtm2txt, built with `cargo build --release --features ffi`
+ * (libdtm2txt.so, libdtm2txt.dylib or dtm2txt.dll).
+ *
+ * Functions that can fail return 0 on success, or -1 with the reason
+ * available from dtm2txt_last_error(). Only GameCube movies are supported.
+ */
+#ifndef DTM2TXT_H
+#define DTM2TXT_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Button bits, the same as the first two bytes of a dtm input. */
+#define DTM2TXT_START                0x0001
+#define DTM2TXT_A                    0x0002
+#define DTM2TXT_B                    0x0004
+#define DTM2TXT_X                    0x0008
+#define DTM2TXT_Y                    0x0010
+#define DTM2TXT_Z                    0x0020
+#define DTM2TXT_UP                   0x0040
+#define DTM2TXT_DOWN                 0x0080
+#define DTM2TXT_LEFT                 0x0100
+#define DTM2TXT_RIGHT                0x0200
+#define DTM2TXT_L                    0x0400
+#define DTM2TXT_R                    0x0800
+#define DTM2TXT_CHANGE_DISC          0x1000
+#define DTM2TXT_RESET                0x2000
+#define DTM2TXT_CONTROLLER_CONNECTED 0x4000
+#define DTM2TXT_RESERVED             0x8000
+
+typedef struct {
+    uint16_t buttons;
+    uint8_t l_pressure;
+    uint8_t r_pressure;
+    uint8_t analog_x;
+    uint8_t analog_y;
+    uint8_t c_x;
+    uint8_t c_y;
+} Dtm2txtInput;
+
+typedef struct {
+    /* NUL-terminated JSON object with the same fields as a txt header. */
+    char *header_json;
+    Dtm2txtInput *inputs;
+    size_t input_count;
+    /* Bytes after the inputs. */
+    uint8_t *trailing;
+    size_t trailing_len;
+} Dtm2txtMovie;
+
+/*
+ * Decodes the len bytes of a dtm at data into *movie, which must be released
+ * with dtm2txt_movie_free. data may be NULL only if len is 0.
+ */
+int dtm2txt_decode(const uint8_t *data, size_t len, Dtm2txtMovie *movie);
+
+/*
+ * Encodes *movie as dtm bytes, storing them in *data and their length in
+ * *len; release them with dtm2txt_bytes_free. The movie can come from
+ * dtm2txt_decode or be filled in by the caller, in which case inputs and
+ * trailing may be NULL only if their lengths are 0. The header's counts have
+ * to agree with the inputs.
+ */
+int dtm2txt_encode(const Dtm2txtMovie *movie, uint8_t **data, size_t *len);
+
+/*
+ * Releases a movie filled in by dtm2txt_decode and zeroes it. Don't pass a
+ * movie the caller put together itself.
+ */
+void dtm2txt_movie_free(Dtm2txtMovie *movie);
+
+/* Releases bytes from dtm2txt_encode. */
+void dtm2txt_bytes_free(uint8_t *data, size_t len);
+
+/*
+ * Why the last call on this thread failed, or NULL if none has. The string
+ * stays valid until the next call that fails.
+ */
+const char *dtm2txt_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,265 @@
+use std::fmt;
+
+use dtm::{Dtm, ControllerInput, Button, Axis};
+
+// Trigger pressure at which the digital L or R click happens on a real
+// controller.
+pub const TRIGGER_CLICK_PRESSURE: u8 = 255;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AuditOptions {
+    // Pressure from which an unclicked trigger counts as a problem. Some
+    // controllers click a bit before the end of the pull.
+    pub trigger_threshold: u8,
+}
+
+impl Default for AuditOptions {
+    fn default() -> AuditOptions {
+        AuditOptions {
+            trigger_threshold: TRIGGER_CLICK_PRESSURE,
+        }
+    }
+}
+
+impl AuditOptions {
+    pub fn trigger_threshold(mut self, trigger_threshold: u8) -> AuditOptions {
+        self.trigger_threshold = trigger_threshold;
+        self
+    }
+}
+
+const TRIGGERS: [(Button, Axis); 2] = [(Button::L, Axis::LPressure), (Button::R, Axis::RPressure)];
+
+// Inputs no real controller can make, or that games and Dolphin handle
+// badly. Partly pulled triggers are normal, so pressure only counts as a
+// problem from the click threshold on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputProblem {
+    UpAndDown,
+    LeftAndRight,
+    ClickWithoutPressure(Button),
+    PressureWithoutClick(Button),
+    InputWhileDisconnected,
+}
+
+impl fmt::Display for InputProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InputProblem::UpAndDown => f.write_str("up and down pressed together"),
+            InputProblem::LeftAndRight => f.write_str("left and right pressed together"),
+            InputProblem::ClickWithoutPressure(button) =>
+                write!(f, "{} clicked without any pressure", button.long_name()),
+            InputProblem::PressureWithoutClick(button) =>
+                write!(f, "{} pulled past the click without clicking", button.long_name()),
+            InputProblem::InputWhileDisconnected => f.write_str("input while the controller is disconnected"),
+        }
+    }
+}
+
+// `port` is numbered from 0, like everywhere in the library, and shown
+// numbered from 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Finding {
+    pub frame: u64,
+    pub port: u8,
+    pub problem: InputProblem,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "frame {}, port {}: {}", self.frame, self.port + 1, self.problem)
+    }
+}
+
+fn is_idle(input: &ControllerInput) -> bool {
+    let neutral = ControllerInput {
+        controller_connected: input.controller_connected,
+        ..ControllerInput::NEUTRAL
+    };
+    *input == neutral
+}
+
+// `check_connected` should only be set for movies that record whether the
+// controller is connected; older Dolphin versions leave it unset on every
+// frame.
+pub fn check_input(input: &ControllerInput, options: &AuditOptions, check_connected: bool) -> Vec<InputProblem> {
+    let mut problems = Vec::new();
+    if input.up && input.down {
+        problems.push(InputProblem::UpAndDown);
+    }
+    if input.left && input.right {
+        problems.push(InputProblem::LeftAndRight);
+    }
+    for &(button, axis) in TRIGGERS.iter() {
+        let pressure = input.axis(axis);
+        if input.pressed(button) && pressure == 0 {
+            problems.push(InputProblem::ClickWithoutPressure(button));
+        }
+        else if !input.pressed(button) && pressure >= options.trigger_threshold {
+            problems.push(InputProblem::PressureWithoutClick(button));
+        }
+    }
+    if check_connected && !input.controller_connected && !is_idle(input) {
+        problems.push(InputProblem::InputWhileDisconnected);
+    }
+    problems
+}
+
+// What fixing a problem changed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    Released(Button),
+    Pressed(Button),
+    SetAxis(Axis, u8),
+    Cleared,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Change::Released(button) => write!(f, "released {}", button.long_name()),
+            Change::Pressed(button) => write!(f, "pressed {}", button.long_name()),
+            Change::SetAxis(axis, value) => write!(f, "set {} to {}", axis.name(), value),
+            Change::Cleared => f.write_str("cleared the input"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fix {
+    pub frame: u64,
+    pub port: u8,
+    pub problem: InputProblem,
+    pub change: Change,
+}
+
+impl fmt::Display for Fix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "frame {}, port {}: {}; {}", self.frame, self.port + 1, self.problem, self.change)
+    }
+}
+
+// Resolves opposite directions held together. The one that was just pressed
+// wins, like on a controller where the newer press takes over. When both
+// were pressed on the same frame there's no telling which was meant, so both
+// are released.
+fn fix_opposites(input: &mut ControllerInput, previous: Option<&ControllerInput>, first: Button, second: Button, changes: &mut Vec<Change>) {
+    let held_before = |button| previous.map(|previous| previous.pressed(button)).unwrap_or(false);
+    let released = match (held_before(first), held_before(second)) {
+        (true, false) => vec![first],
+        (false, true) => vec![second],
+        _ => vec![first, second],
+    };
+    for button in released {
+        input.set_pressed(button, false);
+        changes.push(Change::Released(button));
+    }
+}
+
+// Makes one frame possible, returning what was changed for each problem.
+// Triggers are made to agree with whichever half is engaged: a click gets
+// full pressure, and pressure past the threshold gets the click. Input on a
+// disconnected controller is cleared, since Dolphin ignores it anyway.
+pub fn fix_input(input: &mut ControllerInput, previous: Option<&ControllerInput>, options: &AuditOptions, check_connected: bool) -> Vec<(InputProblem, Change)> {
+    let mut fixes = Vec::new();
+    for problem in check_input(input, options, check_connected) {
+        let mut changes = Vec::new();
+        match problem {
+            InputProblem::UpAndDown => fix_opposites(input, previous, Button::Up, Button::Down, &mut changes),
+            InputProblem::LeftAndRight => fix_opposites(input, previous, Button::Left, Button::Right, &mut changes),
+            InputProblem::ClickWithoutPressure(button) => {
+                let axis = TRIGGERS.iter().find(|&&(trigger, _)| trigger == button).map(|&(_, axis)| axis);
+                if let Some(axis) = axis {
+                    input.set_axis(axis, TRIGGER_CLICK_PRESSURE);
+                    changes.push(Change::SetAxis(axis, TRIGGER_CLICK_PRESSURE));
+                }
+            }
+            InputProblem::PressureWithoutClick(button) => {
+                input.set_pressed(button, true);
+                changes.push(Change::Pressed(button));
+            }
+            InputProblem::InputWhileDisconnected => {
+                *input = ControllerInput::NEUTRAL;
+                changes.push(Change::Cleared);
+            }
+        }
+        fixes.extend(changes.into_iter().map(|change| (problem, change)));
+    }
+    fixes
+}
+
+// With several controllers, the inputs take turns between the connected
+// ports. A movie without any says port 1.
+fn frame_and_port(idx: usize, ports: &[u8]) -> (u64, u8) {
+    let count = ports.len().max(1);
+    (idx as u64 / count as u64, ports.get(idx % count).cloned().unwrap_or(0))
+}
+
+impl Dtm {
+    // Every problem with every controller input, in frame order.
+    pub fn audit(&self) -> Vec<Finding> {
+        self.audit_with(&AuditOptions::default())
+    }
+
+    pub fn audit_with(&self, options: &AuditOptions) -> Vec<Finding> {
+        let check_connected = self.records_connection();
+        let ports = self.header.gamecube_ports();
+        self.controller_data.iter()
+            .enumerate()
+            .flat_map(|(idx, input)| {
+                let (frame, port) = frame_and_port(idx, &ports);
+                check_input(input, options, check_connected).into_iter().map(move |problem| Finding {
+                    frame: frame,
+                    port: port,
+                    problem: problem,
+                })
+            })
+            .collect()
+    }
+
+    // Fixes everything `audit_with` would find, returning every change made.
+    pub fn sanitize(&mut self, options: &AuditOptions) -> Vec<Fix> {
+        let check_connected = self.records_connection();
+        let ports = self.header.gamecube_ports();
+        let mut fixes = Vec::new();
+        for idx in 0..self.controller_data.len() {
+            let previous = idx.checked_sub(ports.len().max(1)).map(|previous| self.controller_data[previous]);
+            let (frame, port) = frame_and_port(idx, &ports);
+            let input = &mut self.controller_data[idx];
+            for (problem, change) in fix_input(input, previous.as_ref(), options, check_connected) {
+                fixes.push(Fix {
+                    frame: frame,
+                    port: port,
+                    problem: problem,
+                    change: change,
+                });
+            }
+        }
+        fixes
+    }
+
+    fn records_connection(&self) -> bool {
+        self.controller_data.iter().any(|input| input.controller_connected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dtm::DtmHeader;
+
+    #[test]
+    fn reports_frame_and_port() {
+        let header = DtmHeader::builder().gamecube_ports(&[1, 3]).build();
+        let mut dtm = Dtm::blank(header, 4);
+        dtm.controller_data[5].up = true;
+        dtm.controller_data[5].down = true;
+
+        let findings = dtm.audit();
+        assert_eq!(findings, vec![Finding { frame: 2, port: 3, problem: InputProblem::UpAndDown }]);
+        assert_eq!(findings[0].to_string(), "frame 2, port 4: up and down pressed together");
+
+        let fixes = dtm.sanitize(&AuditOptions::default());
+        assert_eq!((fixes[0].frame, fixes[0].port), (2, 3));
+    }
+}
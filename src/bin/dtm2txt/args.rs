@@ -0,0 +1,181 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Bound;
+use std::str::FromStr;
+
+// Options that are followed by a value. Everything else starting with "--"
+// is a flag.
+const VALUED_OPTIONS: &[&str] = &[
+    "at",
+    "author",
+    "button",
+    "buttons",
+    "columns",
+    "connect",
+    "format",
+    "frames",
+    "from",
+    "game-id",
+    "header",
+    "inputs",
+    "interval",
+    "layout",
+    "output",
+    "per-row",
+    "port",
+    "press-chance",
+    "protocol",
+    "range",
+    "rate",
+    "reserved",
+    "seed",
+    "set",
+    "skip",
+    "syntax",
+    "take",
+    "times",
+    "transform",
+    "trigger-threshold",
+    "where",
+    "window",
+];
+
+// Old names of options, and what they're called now.
+const ALIASES: &[(&str, &str)] = &[
+    ("game", "game-id"),
+];
+
+// A range of frames as given on the command line.
+pub type FrameRange = (Bound<u64>, Bound<u64>);
+
+pub struct Args {
+    positional: Vec<String>,
+    // Every value an option was given, in order. Most options only use the
+    // last one.
+    options: HashMap<String, Vec<String>>,
+    flags: HashSet<String>,
+}
+
+impl Args {
+    pub fn parse<I>(args: I) -> Result<Args, String>
+        where I: IntoIterator<Item = String>,
+    {
+        let mut positional = Vec::new();
+        let mut options = HashMap::new();
+        let mut flags = HashSet::new();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let name = if arg == "-o" {
+                "output".to_string()
+            }
+            else if arg == "-h" {
+                "help".to_string()
+            }
+            else if arg == "-V" {
+                "version".to_string()
+            }
+            else if let Some(name) = arg.strip_prefix("--") {
+                let (option, value) = match name.find('=') {
+                    Some(eq_idx) => name.split_at(eq_idx),
+                    None => (name, ""),
+                };
+                match ALIASES.iter().find(|&&(alias, _)| alias == option) {
+                    Some(&(_, current)) => format!("{}{}", current, value),
+                    None => name.to_string(),
+                }
+            }
+            else {
+                positional.push(arg);
+                continue;
+            };
+
+            if let Some(eq_idx) = name.find('=') {
+                options.entry(name[..eq_idx].to_string())
+                    .or_insert_with(Vec::new)
+                    .push(name[eq_idx + 1..].to_string());
+            }
+            else if VALUED_OPTIONS.contains(&name.as_str()) {
+                let value = args.next().ok_or_else(|| format!("--{} needs a value", name))?;
+                options.entry(name).or_insert_with(Vec::new).push(value);
+            }
+            else {
+                flags.insert(name);
+            }
+        }
+
+        Ok(Args {
+            positional: positional,
+            options: options,
+            flags: flags,
+        })
+    }
+
+    pub fn positional(&self) -> &[String] {
+        &self.positional
+    }
+
+    // Every option and flag that was given, without the leading "--".
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.options.keys()
+            .chain(self.flags.iter())
+            .map(String::as_str)
+    }
+
+    pub fn set_flag(&mut self, name: &str) {
+        self.flags.insert(name.to_string());
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.flags.contains(name)
+    }
+
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.options.get(name)
+            .and_then(|values| values.last())
+            .map(String::as_str)
+    }
+
+    // For options that can be given more than once.
+    pub fn values(&self, name: &str) -> &[String] {
+        self.options.get(name).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn parse_value<T>(&self, name: &str) -> Result<Option<T>, String>
+        where T: FromStr,
+              T::Err: ToString,
+    {
+        match self.value(name) {
+            Some(value) => value.parse::<T>()
+                .map(Some)
+                .map_err(|err| format!("invalid value for --{}: {}", name, err.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    pub fn parse_range(&self, name: &str) -> Result<Option<FrameRange>, String> {
+        match self.value(name) {
+            Some(value) => parse_frame_range(value)
+                .map(Some)
+                .map_err(|reason| format!("invalid value for --{}: {}", name, reason)),
+            None => Ok(None),
+        }
+    }
+}
+
+// START..END, START.. or ..END, with END excluded like a Rust range.
+pub fn parse_frame_range(value: &str) -> Result<FrameRange, String> {
+    let dots_idx = value.find("..").ok_or_else(|| "expected START..END".to_string())?;
+    let bound = |number: &str, bound: fn(u64) -> Bound<u64>| {
+        if number.is_empty() {
+            Ok(Bound::Unbounded)
+        }
+        else {
+            number.parse::<u64>()
+                .map(bound)
+                .map_err(|err| err.to_string())
+        }
+    };
+    let start = bound(&value[..dots_idx], Bound::Included)?;
+    let end = bound(&value[dots_idx + 2..], Bound::Excluded)?;
+    Ok((start, end))
+}
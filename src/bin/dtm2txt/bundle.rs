@@ -0,0 +1,72 @@
+use std::io::{Read, Seek, Write};
+
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+use zip::result::{ZipError, ZipResult};
+use zip::write::FileOptions;
+
+// A savestate-anchored movie zipped up together with its savestate, which is
+// how those usually get passed around.
+pub struct Bundle {
+    // Name of the dtm inside the archive.
+    pub name: String,
+    pub movie: Vec<u8>,
+    pub savestate: Option<Vec<u8>>,
+}
+
+fn read_entry<R>(archive: &mut ZipArchive<R>, name: &str) -> ZipResult<Vec<u8>>
+    where R: Read + Seek,
+{
+    let mut bytes = Vec::new();
+    archive.by_name(name)?.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+impl Bundle {
+    // Takes the first dtm in the archive, by name.
+    pub fn read<R>(reader: R) -> ZipResult<Bundle>
+        where R: Read + Seek,
+    {
+        let mut archive = ZipArchive::new(reader)?;
+        let mut names: Vec<String> = archive.file_names().map(String::from).collect();
+        names.sort();
+
+        let name = names.iter()
+            .find(|name| name.to_ascii_lowercase().ends_with(".dtm"))
+            .cloned()
+            .ok_or(ZipError::FileNotFound)?;
+        let movie = read_entry(&mut archive, &name)?;
+
+        // Dolphin looks for movie.dtm.sav, but bundles often call it just
+        // movie.sav.
+        let stem = &name[..name.len() - ".dtm".len()];
+        let savestate_names = [format!("{}.sav", name), format!("{}.sav", stem)];
+        let savestate = match savestate_names.iter().find(|savestate_name| names.contains(savestate_name)) {
+            Some(savestate_name) => Some(read_entry(&mut archive, savestate_name)?),
+            None => None,
+        };
+
+        Ok(Bundle {
+            name: name,
+            movie: movie,
+            savestate: savestate,
+        })
+    }
+
+    // The savestate is stored under Dolphin's name for it, so the archive can
+    // be unpacked straight into a movie folder.
+    pub fn write<W>(&self, writer: W) -> ZipResult<()>
+        where W: Write + Seek,
+    {
+        let mut zip = ZipWriter::new(writer);
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+        zip.start_file(self.name.as_str(), options)?;
+        zip.write_all(&self.movie)?;
+        if let Some(ref savestate) = self.savestate {
+            zip.start_file(format!("{}.sav", self.name), options)?;
+            zip.write_all(savestate)?;
+        }
+        zip.finish()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,324 @@
+// Every subcommand along with the options it understands, used both to reject
+// misspelled options and to print --help.
+pub struct Command {
+    pub name: &'static str,
+    pub usage: &'static str,
+    pub about: &'static str,
+    // Takes the options for reading and/or writing movies.
+    pub reads: bool,
+    pub writes: bool,
+    pub options: &'static [&'static str],
+}
+
+pub const COMMANDS: &[Command] = &[
+    Command {
+        name: "convert",
+        usage: "<input> [output]",
+        about: "Convert between dtm, txt, csv, zip, yaml, cbor and json, or export to bk2, overlay or srt",
+        reads: true,
+        writes: true,
+        options: &["cache", "stream", "keep-comments", "transform", "where", "fix-counts", "set", "port"],
+    },
+    Command {
+        name: "compile",
+        usage: "<script.dts> [-o <output>]",
+        about: "Expand a script like \"hold A 30; press Z\" into a movie",
+        reads: false,
+        writes: true,
+        options: &["game-id", "header"],
+    },
+    Command {
+        name: "info",
+        usage: "<movie>",
+        about: "Print a summary of a movie's header",
+        reads: true,
+        writes: false,
+        options: &[],
+    },
+    Command {
+        name: "header",
+        usage: "<movie>",
+        about: "Print a movie's header as JSON or YAML",
+        reads: true,
+        writes: false,
+        options: &["format"],
+    },
+    Command {
+        name: "patch-header",
+        usage: "<movie.dtm> <patch.json>",
+        about: "Rewrite fields of a dtm's header in place",
+        reads: false,
+        writes: false,
+        options: &["allow-inconsistent"],
+    },
+    Command {
+        name: "new",
+        usage: "--game-id <id> [--frames <n>] [-o <output>]",
+        about: "Start a movie from a blank header and neutral frames",
+        reads: false,
+        writes: true,
+        options: &["game-id", "author", "frames"],
+    },
+    Command {
+        name: "fuzz",
+        usage: "--game-id <id> --frames <n>",
+        about: "Generate a movie with random inputs",
+        reads: false,
+        writes: true,
+        options: &["game-id", "frames", "seed", "buttons", "press-chance", "where"],
+    },
+    Command {
+        name: "join",
+        usage: "<first> <second> [<more>...]",
+        about: "Append movies to each other",
+        reads: true,
+        writes: true,
+        options: &[],
+    },
+    Command {
+        name: "split",
+        usage: "<movie> --at <frame>",
+        about: "Cut a movie in two",
+        reads: true,
+        writes: true,
+        options: &["at"],
+    },
+    Command {
+        name: "extract",
+        usage: "<movie> (--range <start..end> | --where <filter>) -o <output>",
+        about: "Copy a range of frames into a new movie",
+        reads: true,
+        writes: true,
+        options: &["range", "where"],
+    },
+    Command {
+        name: "repeat",
+        usage: "<movie> --range <start..end> --times <n> [-o <output>]",
+        about: "Play a range of frames several times in a row",
+        reads: true,
+        writes: true,
+        options: &["range", "times"],
+    },
+    Command {
+        name: "anonymize",
+        usage: "<movie> -o <output>",
+        about: "Strip the author, start time, revision and reserved bytes",
+        reads: true,
+        writes: true,
+        options: &[],
+    },
+    Command {
+        name: "canonicalize",
+        usage: "<movie> -o <output>",
+        about: "Rewrite the parts of a movie Dolphin ignores into one fixed form",
+        reads: true,
+        writes: true,
+        options: &[],
+    },
+    Command {
+        name: "diff",
+        usage: "<first> <second>",
+        about: "Compare two movies",
+        reads: true,
+        writes: false,
+        options: &[],
+    },
+    Command {
+        name: "make-patch",
+        usage: "<base> <edited> [-o <patch>]",
+        about: "Save the changes between two movies as a small patch file",
+        reads: true,
+        writes: false,
+        options: &["output", "force"],
+    },
+    Command {
+        name: "patch",
+        usage: "<base> <changes.dtmpatch> -o <output>",
+        about: "Apply a patch from make-patch to the movie it was made for",
+        reads: true,
+        writes: true,
+        options: &[],
+    },
+    Command {
+        name: "compose",
+        usage: "--header <movie> --inputs <movie> -o <output>",
+        about: "Put the inputs of one movie under the header of another",
+        reads: true,
+        writes: true,
+        options: &["inputs", "transform", "fix-counts", "set"],
+    },
+    Command {
+        name: "merge-port",
+        usage: "<movie> <track> --port <n> -o <output>",
+        about: "Put one port's inputs, taken out with convert --port, back in",
+        reads: true,
+        writes: true,
+        options: &["port"],
+    },
+    Command {
+        name: "find",
+        usage: "<movie> --where <filter> [--port <n>]",
+        about: "Print the frames that match a filter expression",
+        reads: true,
+        writes: false,
+        options: &["where", "ranges", "port"],
+    },
+    Command {
+        name: "audit",
+        usage: "<movie> [--fix -o <output>]",
+        about: "List inputs no real controller could make",
+        reads: true,
+        writes: true,
+        options: &["fix", "trigger-threshold"],
+    },
+    Command {
+        name: "stats",
+        usage: "<movie> [--where <filter> | --window <n> [-o <output.csv>]]",
+        about: "Print button, stick and idle statistics",
+        reads: true,
+        writes: false,
+        options: &["where", "window", "output", "force", "port"],
+    },
+    Command {
+        name: "timeline",
+        usage: "<movie> --button <button> [--per-row <n>]",
+        about: "Draw when a button is held as a row of characters per stretch of frames",
+        reads: true,
+        writes: false,
+        options: &["button", "per-row", "port"],
+    },
+    Command {
+        name: "verify",
+        usage: "<movie.dtm>",
+        about: "Check that a dtm converts back byte for byte",
+        reads: false,
+        writes: false,
+        options: &[],
+    },
+    Command {
+        name: "verify-md5",
+        usage: "<movie> <game.iso>",
+        about: "Check that a game image is the one a movie was recorded on",
+        reads: true,
+        writes: false,
+        options: &[],
+    },
+    Command {
+        name: "watch",
+        usage: "[dir]",
+        about: "Convert dtm files to txt whenever they change",
+        reads: false,
+        writes: false,
+        options: &[
+            "reverse", "interval", "strict", "lenient", "lenient-header", "reserved", "syntax", "collapse-repeats",
+            "allow-inconsistent", "truncate-strings",
+        ],
+    },
+    Command {
+        name: "feed",
+        usage: "<movie> -o <pipe> | --connect <host:port>",
+        about: "Stream inputs frame by frame in real time, like to Dolphin's pipe input",
+        reads: true,
+        writes: false,
+        options: &["output", "connect", "port", "rate", "protocol"],
+    },
+];
+
+const READ_OPTIONS: &[&str] = &[
+    "from", "strict", "lenient", "lenient-header", "reserved", "header", "game-id", "columns", "skip", "take",
+];
+const WRITE_OPTIONS: &[&str] = &[
+    "output", "format", "syntax", "layout", "collapse-repeats", "allow-inconsistent", "truncate-strings", "force", "gzip",
+];
+const GLOBAL_OPTIONS: &[&str] = &["help", "version"];
+
+// Name, value placeholder (empty for flags) and description.
+const OPTION_HELP: &[(&str, &str, &str)] = &[
+    ("allow-inconsistent", "", "Write movies whose header disagrees with their inputs"),
+    ("at", "<frame>", "Frame the second half starts at"),
+    ("author", "<name>", "Author for a new header"),
+    ("button", "<button>", "Button to draw, like A, start or lt"),
+    ("buttons", "<list>", "Buttons that may be pressed, like A,B,Z"),
+    ("cache", "", "Only re-encode the parts of a txt file that changed"),
+    ("collapse-repeats", "", "Write runs of identical frames as one line ending in * N"),
+    ("columns", "<list>", "Extra csv or input log columns to read, like Jump=a,Tilt=analog_x"),
+    ("connect", "<host:port>", "Send to a TCP socket instead of a file or pipe"),
+    ("fix", "", "Make every flagged input possible and write the result"),
+    ("fix-counts", "", "Recount inputs and estimate vi_count and tick_count from them"),
+    ("force", "", "Overwrite output files that already exist"),
+    ("format", "<format>", "Output format: dtm, txt, csv, zip, yaml, cbor, json, jsonl, bk2, overlay or srt"),
+    ("frames", "<n>", "Number of frames to generate"),
+    ("from", "<format>", "Input format: dtm, txt, csv, zip, yaml, cbor, json, bk2, fm2 or log"),
+    ("game-id", "<id>", "Game ID for a new header, like GALE01 (also --game)"),
+    ("gzip", "", "Gzip output files that aren't given a name"),
+    ("header", "<file>", "JSON header or movie whose header to use"),
+    ("inputs", "<movie>", "Movie to take the inputs of"),
+    ("interval", "<secs>", "How often to check for changed files"),
+    ("keep-comments", "", "Save txt comments to a .comments file"),
+    ("layout", "<layout>", "Port layout of txt output: interleaved, labeled or joined"),
+    ("lenient", "", "Read as much of a damaged file as possible"),
+    ("lenient-header", "", "Fill in header fields missing from a txt file"),
+    ("output", "<file>", "Output file (also -o), or - for stdout"),
+    ("per-row", "<n>", "Frames to a row (default 60)"),
+    ("port", "<n>", "GameCube port to work on, from 1 to 4"),
+    ("press-chance", "<p>", "Chance of each button being pressed on a frame"),
+    ("protocol", "<protocol>", "What feed writes: dolphin pipe commands or txt lines"),
+    ("rate", "<hz>", "Frames per second to feed; defaults to the game's, 0 for no waiting"),
+    ("range", "<start..end>", "Frames to work on; either end can be left out"),
+    ("ranges", "", "Print runs of matching frames as start..end"),
+    ("reserved", "<keep|clear>", "Keep unknown reserved header bytes quietly, or zero them"),
+    ("reverse", "", "Watch txt files and convert them to dtm instead"),
+    ("seed", "<n>", "Random seed"),
+    ("set", "<field=value>", "Set a header field, like author=me; repeatable"),
+    ("skip", "<n>", "Leave out the first n frames of the input"),
+    ("stream", "", "Convert a dtm to txt as it comes in, like from a pipe, one frame at a time"),
+    ("strict", "", "Refuse files whose header disagrees with their inputs"),
+    ("syntax", "<syntax>", "Input line syntax of txt output: classic, mnemonic, table or compact"),
+    ("take", "<n>", "Read at most n frames of the input, after --skip"),
+    ("times", "<n>", "How many times the range is played in total"),
+    ("transform", "<edit>", "Edit frames, optionally in a range like mirror@100..200; repeatable"),
+    ("trigger-threshold", "<n>", "Pressure from which a trigger should click (default 255)"),
+    ("truncate-strings", "", "Cut header strings that are too long instead of refusing them"),
+    ("where", "<filter>", "Filter expression like \"Z && analog_x == 255\""),
+    ("window", "<n>", "Write csv statistics for every n frames instead"),
+];
+
+impl Command {
+    pub fn allows(&self, option: &str) -> bool {
+        self.options.contains(&option)
+            || (self.reads && READ_OPTIONS.contains(&option))
+            || (self.writes && WRITE_OPTIONS.contains(&option))
+            || GLOBAL_OPTIONS.contains(&option)
+    }
+
+    pub fn print_help(&self) {
+        println!("Usage: dtm2txt {} {} [options]", self.name, self.usage);
+        println!();
+        println!("{}.", self.about);
+        println!();
+        println!("Options:");
+        for &(name, value, description) in OPTION_HELP.iter() {
+            if self.allows(name) {
+                println!("  {:<30} {}", format!("--{} {}", name, value), description);
+            }
+        }
+    }
+}
+
+pub fn find(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+pub fn print_help() {
+    println!("dtm2txt {} by OnVar", env!("CARGO_PKG_VERSION"));
+    println!();
+    println!("Usage: dtm2txt <command> [arguments] [options]");
+    println!("       dtm2txt <input> [output]");
+    println!();
+    println!("Commands:");
+    for command in COMMANDS.iter() {
+        println!("  {:<14} {}", command.name, command.about);
+    }
+    println!();
+    println!("Run dtm2txt <command> --help for a command's options.");
+}
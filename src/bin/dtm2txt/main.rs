@@ -0,0 +1,2173 @@
+#![allow(clippy::redundant_field_names)]
+
+extern crate dtm2txt;
+extern crate serde_json;
+extern crate flate2;
+extern crate zip;
+#[cfg(feature = "yaml")]
+extern crate serde_yaml;
+#[cfg(unix)]
+extern crate libc;
+
+mod args;
+mod bundle;
+mod commands;
+mod watch;
+
+use std::cmp;
+use std::env;
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Cursor, IsTerminal, Read, Write, Seek, SeekFrom};
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::net::TcpStream;
+use std::process;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use zip::ZipArchive;
+
+use dtm2txt::dtm::{Dtm, DtmHeader, Button, ControllerInput};
+use dtm2txt::frames::{FrameStore, RawFrames};
+use dtm2txt::feed::{FrameFeed, FeedProtocol};
+use dtm2txt::encoder::text_encoder::{TextEncoder, FrameSyntax, PortLayout};
+use dtm2txt::encoder::dtm_encoder::DtmEncoder;
+use dtm2txt::encoder::csv_encoder::{CsvEncoder, CsvColumn};
+use dtm2txt::encoder::jsonl_encoder::JsonlEncoder;
+use dtm2txt::encoder::json_encoder::JsonEncoder;
+use dtm2txt::decoder::text_decoder::{TextDecoder, DecodedText};
+use dtm2txt::decoder::dtm_decoder::DtmDecoder;
+use dtm2txt::decoder::csv_decoder::CsvDecoder;
+use dtm2txt::decoder::json_decoder::JsonDecoder;
+use dtm2txt::decoder::input_log_decoder::InputLogDecoder;
+#[cfg(feature = "yaml")]
+use dtm2txt::decoder::yaml_decoder::YamlDecoder;
+#[cfg(feature = "yaml")]
+use dtm2txt::encoder::yaml_encoder::YamlEncoder;
+#[cfg(feature = "cbor")]
+use dtm2txt::decoder::cbor_decoder::CborDecoder;
+#[cfg(feature = "cbor")]
+use dtm2txt::encoder::cbor_encoder::CborEncoder;
+use dtm2txt::decoder::options::{DecodeOptions, DecodeWarning, ReservedBytes};
+use dtm2txt::cache::{self, BlockCache};
+use dtm2txt::comments;
+use dtm2txt::audit::AuditOptions;
+use dtm2txt::consistency;
+use dtm2txt::edit;
+use dtm2txt::export;
+use dtm2txt::error::{Dtm2txtError, Dtm2txtResult};
+use dtm2txt::hash;
+use dtm2txt::transcode::{self, Direction};
+use dtm2txt::validate;
+use dtm2txt::patch::MoviePatch;
+use dtm2txt::script::Script;
+use dtm2txt::header_patch;
+use dtm2txt::filter::Filter;
+use dtm2txt::fuzz::{self, FuzzOptions};
+use dtm2txt::humanize;
+use dtm2txt::stats::StickDirection;
+use dtm2txt::transform::{InRange, Matching, Transform};
+use dtm2txt::verify;
+
+use args::{Args, FrameRange};
+use bundle::Bundle;
+use commands::Command;
+use watch::Watcher;
+
+trait UnwrapOrBarfExt<T> {
+    fn unwrap_or_barf(self, err_str: &str) -> T;
+}
+
+impl<T, E> UnwrapOrBarfExt<T> for Result<T, E>
+    where E: Error
+{
+    fn unwrap_or_barf(self, err_desc: &str) -> T {
+        self.unwrap_or_else(|err| {
+            let err_string = format!("{}: {}", err_desc, err);
+            barf(&err_string);
+        })
+    }
+}
+
+impl<T> UnwrapOrBarfExt<T> for Option<T> {
+    fn unwrap_or_barf(self, err_desc: &str) -> T {
+        self.unwrap_or_else(|| barf(err_desc))
+    }
+}
+
+// Errors go to stderr, where they can't be mistaken for piped output.
+fn barf(message: &str) -> ! {
+    eprintln!("Error: {}", message);
+    if let Ok(staged) = STAGED.lock() {
+        for staging in staged.iter() {
+            let _ = fs::remove_file(staging);
+        }
+    }
+    process::exit(1);
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+
+// "-" stands for stdin or stdout.
+fn is_stdio(filename: &Path) -> bool {
+    filename == Path::new("-")
+}
+
+// Files ending in .gz are compressed on the way out.
+fn is_gzip(filename: &Path) -> bool {
+    filename.extension()
+        .map(|extension| extension.eq_ignore_ascii_case("gz"))
+        .unwrap_or(false)
+}
+
+// The filename without a .gz on the end.
+fn uncompressed_name(filename: &Path) -> PathBuf {
+    if is_gzip(filename) {
+        filename.with_extension("")
+    }
+    else {
+        filename.to_path_buf()
+    }
+}
+
+// Gzipped input is recognized by its magic number rather than its name, so
+// it works from stdin too.
+fn open_input(filename: &Path) -> BufReader<Box<dyn Read>> {
+    let input: Box<dyn Read> = if is_stdio(filename) {
+        Box::new(io::stdin())
+    }
+    else {
+        Box::new(File::open(filename).unwrap_or_barf("Could not open file"))
+    };
+    let mut input = BufReader::new(input);
+    let gzipped = input.fill_buf()
+        .map(|start| start.starts_with(GZIP_MAGIC))
+        .unwrap_or_barf("Could not read file");
+    if gzipped {
+        BufReader::new(Box::new(GzDecoder::new(input)))
+    }
+    else {
+        input
+    }
+}
+
+// Pipes and devices are written to rather than replaced, so they don't need
+// --force.
+fn check_overwrite(filename: &Path, force: bool) {
+    if !force && filename.is_file() {
+        barf(&format!("{} already exists (pass --force to overwrite it)", filename.display()));
+    }
+}
+
+// Staging files of outputs that aren't finished yet, which barf cleans up.
+static STAGED: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+// An output file that's written next to where it goes and only renamed over
+// it by `finish`, so a refused or failed write never costs the file that was
+// already there or leaves half a movie behind. Stdout is written as is.
+struct OutputFile {
+    writer: BufWriter<Box<dyn Write>>,
+    staging: Option<(PathBuf, PathBuf)>,
+}
+
+impl OutputFile {
+    fn finish(self) {
+        // Taking the writer apart flushes it, and dropping what's left
+        // finishes any gzip stream.
+        let inner = self.writer.into_inner()
+            .map_err(|err| err.into_error())
+            .unwrap_or_barf("Could not write file");
+        drop(inner);
+        if let Some((staging, filename)) = self.staging {
+            fs::rename(&staging, &filename)
+                .unwrap_or_else(|err| barf(&format!("Could not replace {}: {}", filename.display(), err)));
+            STAGED.lock().unwrap_or_barf("Could not write file").retain(|staged| *staged != staging);
+        }
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+fn create_output(filename: &Path, force: bool) -> OutputFile {
+    if is_stdio(filename) {
+        return OutputFile {
+            writer: BufWriter::new(Box::new(io::stdout())),
+            staging: None,
+        };
+    }
+    check_overwrite(filename, force);
+    let staging = staging_path(filename);
+    STAGED.lock().unwrap_or_barf("Could not create file").push(staging.clone());
+    OutputFile {
+        writer: create_output_file(&staging, filename),
+        staging: Some((staging, filename.to_path_buf())),
+    }
+}
+
+// Creates `path` to be written like `filename` would be, gzipped if that's
+// what its name says.
+fn create_output_file(path: &Path, filename: &Path) -> BufWriter<Box<dyn Write>> {
+    let file = File::create(path).unwrap_or_barf("Could not create file");
+    let output: Box<dyn Write> = if is_gzip(filename) {
+        Box::new(GzEncoder::new(file, Compression::default()))
+    }
+    else {
+        Box::new(file)
+    };
+    BufWriter::new(output)
+}
+
+// A hidden file next to `filename` for output that might not be finished, so
+// it can be renamed over `filename` without copying.
+fn staging_path(filename: &Path) -> PathBuf {
+    let name = filename.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    filename.with_file_name(format!(".{}.{}.partial", name, process::id()))
+}
+
+// --from wins over the extension, and is the only way to tell what's coming
+// in on stdin.
+fn input_format(filename: &Path, args: &Args) -> String {
+    match args.value("from") {
+        Some(format) => format.to_string(),
+        None if is_stdio(filename) => barf("Reading from stdin needs --from dtm, txt or csv"),
+        None => extension(filename).to_string(),
+    }
+}
+
+// Where a converted file goes when no output is given: next to the input, or
+// stdout when reading from stdin. It's gzipped if the input was or --gzip is
+// given.
+fn default_output(filename: &Path, extension: &str, args: &Args) -> PathBuf {
+    if is_stdio(filename) {
+        return filename.to_path_buf();
+    }
+
+    let mut output = uncompressed_name(filename).with_extension(extension).into_os_string();
+    output.push(gzip_suffix(filename, args));
+    output.into()
+}
+
+fn gzip_suffix(filename: &Path, args: &Args) -> &'static str {
+    if is_gzip(filename) || args.flag("gzip") { ".gz" } else { "" }
+}
+
+// The format a filename says it holds, looking past a .gz.
+fn format_of(filename: &Path) -> Option<&str> {
+    let name = if is_gzip(filename) { Path::new(filename.file_stem()?) } else { filename };
+    name.extension()?.to_str()
+}
+
+fn extension(filename: &Path) -> &str {
+    format_of(filename).unwrap_or_barf("Filename has no extension")
+}
+
+// Dolphin needs a savestate-anchored movie's .sav file next to it, which
+// only the CLI is in a position to check.
+fn check_savestate(filename: &Path, header: &DtmHeader, args: &Args) {
+    // A gzipped movie has to be unpacked before Dolphin can play it, and then
+    // it's the unpacked name that the savestate goes with.
+    if let Some(problem) = consistency::check_savestate(header, &uncompressed_name(filename)) {
+        if !args.flag("allow-inconsistent") {
+            barf(&format!("Refusing to write inconsistent movie: {} (pass --allow-inconsistent to write it anyway)", problem));
+        }
+    }
+}
+
+fn frame_syntax(args: &Args) -> FrameSyntax {
+    match args.value("syntax") {
+        None | Some("classic") => FrameSyntax::Classic,
+        Some("mnemonic") => FrameSyntax::Mnemonic,
+        Some("table") => FrameSyntax::Table,
+        Some("compact") => FrameSyntax::Compact,
+        Some(other) => barf(&format!("Unknown frame syntax '{}' (expected classic, mnemonic, table or compact)", other)),
+    }
+}
+
+fn feed_protocol(args: &Args) -> FeedProtocol {
+    match args.value("protocol") {
+        None | Some("dolphin") => FeedProtocol::Dolphin,
+        Some("text") => FeedProtocol::Text,
+        Some(other) => barf(&format!("Unknown feed protocol '{}' (expected dolphin or text)", other)),
+    }
+}
+
+fn port_layout(args: &Args) -> PortLayout {
+    match args.value("layout") {
+        None | Some("interleaved") => PortLayout::Interleaved,
+        Some("labeled") => PortLayout::Labeled,
+        Some("joined") => PortLayout::Joined,
+        Some(other) => barf(&format!("Unknown port layout '{}' (expected interleaved, labeled or joined)", other)),
+    }
+}
+
+fn text_encoder<W>(output_file: W, args: &Args) -> TextEncoder<W>
+    where W: Write,
+{
+    let encoder = TextEncoder::new(output_file)
+        .syntax(frame_syntax(args))
+        .layout(port_layout(args))
+        .collapse_repeats(args.flag("collapse-repeats"));
+    if show_progress() { encoder.progress(progress_bar("Writing")) } else { encoder }
+}
+
+fn dtm_encoder<W>(output_file: W, args: &Args) -> DtmEncoder<W>
+    where W: Write,
+{
+    let encoder = DtmEncoder::new(output_file)
+        .allow_inconsistent(args.flag("allow-inconsistent"))
+        .truncate_strings(args.flag("truncate-strings"));
+    if show_progress() { encoder.progress(progress_bar("Writing")) } else { encoder }
+}
+
+// Only worth drawing for movies that take a moment, and only when someone is
+// watching the terminal.
+const PROGRESS_MIN_INPUTS: u64 = 100_000;
+const PROGRESS_WIDTH: u64 = 40;
+
+fn show_progress() -> bool {
+    io::stderr().is_terminal()
+}
+
+fn progress_bar(label: &'static str) -> impl FnMut(u64, u64) {
+    let mut last_percent = None;
+    move |done, total| {
+        if total < PROGRESS_MIN_INPUTS && last_percent.is_none() {
+            return;
+        }
+        // An empty movie is as done as it gets.
+        let (done, total) = if total == 0 { (1, 1) } else { (done.min(total), total) };
+        let percent = done * 100 / total;
+        if last_percent == Some(percent) && done != total {
+            return;
+        }
+        last_percent = Some(percent);
+
+        let filled = (done * PROGRESS_WIDTH / total) as usize;
+        eprint!("\r{} [{:<width$}] {:>3}%", label, "#".repeat(filled), percent, width = PROGRESS_WIDTH as usize);
+        if done == total {
+            eprintln!();
+        }
+    }
+}
+
+fn decode_options(args: &Args) -> DecodeOptions {
+    let options = match (args.flag("strict"), args.flag("lenient")) {
+        (true, true) => barf("--strict and --lenient can't be used together"),
+        (true, false) => DecodeOptions::strict(),
+        (false, true) => DecodeOptions::lenient(),
+        (false, false) => DecodeOptions::default(),
+    };
+    let reserved = match args.value("reserved") {
+        None => ReservedBytes::Warn,
+        Some("keep") => ReservedBytes::Keep,
+        Some("clear") => ReservedBytes::Clear,
+        Some(other) => barf(&format!("Unknown --reserved mode '{}' (expected keep or clear)", other)),
+    };
+    let mut options = options.reserved(reserved);
+    if let Some(skip) = args.parse_value("skip").unwrap_or_else(|err| barf(&err)) {
+        options = options.skip(skip);
+    }
+    if let Some(take) = args.parse_value("take").unwrap_or_else(|err| barf(&err)) {
+        options = options.take(take);
+    }
+    options
+}
+
+// csv files and input logs are decoded without DecodeOptions, so --skip and
+// --take are applied afterwards.
+fn windowed(mut dtm: Dtm, args: &Args) -> Dtm {
+    decode_options(args).apply_window(&mut dtm);
+    dtm
+}
+
+// Warnings go to stderr so they don't end up in a converted file piped to
+// stdout.
+fn print_warnings(warnings: &[DecodeWarning]) {
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
+    }
+}
+
+fn dtm_decoder<R>(file: R, args: &Args) -> DtmDecoder<R>
+    where R: Read,
+{
+    let decoder = DtmDecoder::new(file).options(decode_options(args));
+    if show_progress() { decoder.progress(progress_bar("Reading")) } else { decoder }
+}
+
+fn text_decoder<R>(file: R, args: &Args) -> TextDecoder<R>
+    where R: Read,
+{
+    let decoder = TextDecoder::new(file)
+        .lenient_header(args.flag("lenient-header"))
+        .options(decode_options(args));
+    if show_progress() { decoder.progress(progress_bar("Reading")) } else { decoder }
+}
+
+fn write_movie<S>(filename: &Path, dtm: &Dtm<S>, args: &Args)
+    where S: MovieFrames,
+{
+    if !is_stdio(filename) {
+        match extension(filename) {
+            "dtm" => check_savestate(filename, &dtm.header, args),
+            "zip" => check_savestate(&bundled_dtm_name(filename), &dtm.header, args),
+            _ => (),
+        }
+    }
+    S::encode_movie(filename, dtm, args);
+}
+
+// What the dtm encoder would refuse to write, for commands that have to know
+// before they start writing.
+fn check_consistency<S>(filename: &Path, dtm: &Dtm<S>, args: &Args)
+    where S: FrameStore,
+{
+    if args.flag("allow-inconsistent") || output_format(filename, args) != "dtm" {
+        return;
+    }
+    let problems = consistency::check(&dtm.header, dtm.controller_data.len(), dtm.wiimote_data.len() as u64);
+    if !problems.is_empty() {
+        barf(&format!("Refusing to write {}: {} (pass --allow-inconsistent to write it anyway)",
+            filename.display(), Dtm2txtError::InconsistentMovieError(problems)));
+    }
+}
+
+// How split, extract and join read and write the movies they cut up and put
+// together. When every file involved is a dtm, the inputs are kept as
+// RawFrames and copied over without being decoded and encoded again.
+trait MovieFrames: FrameStore + Clone {
+    fn read_movie(filename: &Path, args: &Args) -> Dtm<Self>;
+    fn read_segment(filename: &Path, base: &DtmHeader, args: &Args) -> Dtm<Self>;
+    fn encode_movie(filename: &Path, dtm: &Dtm<Self>, args: &Args);
+}
+
+impl MovieFrames for Vec<ControllerInput> {
+    fn read_movie(filename: &Path, args: &Args) -> Dtm {
+        read_movie(filename, args)
+    }
+
+    fn read_segment(filename: &Path, base: &DtmHeader, args: &Args) -> Dtm {
+        read_segment(filename, base, args)
+    }
+
+    fn encode_movie(filename: &Path, dtm: &Dtm, args: &Args) {
+        encode_movie(filename, dtm, args)
+    }
+}
+
+impl MovieFrames for RawFrames {
+    fn read_movie(filename: &Path, args: &Args) -> Dtm<RawFrames> {
+        let (dtm, warnings) = dtm_decoder(open_input(filename), args)
+            .decode_into_with_warnings()
+            .unwrap_or_barf("Could not decode dtm");
+        print_warnings(&warnings);
+        dtm
+    }
+
+    fn read_segment(filename: &Path, _base: &DtmHeader, args: &Args) -> Dtm<RawFrames> {
+        RawFrames::read_movie(filename, args)
+    }
+
+    fn encode_movie(filename: &Path, dtm: &Dtm<RawFrames>, args: &Args) {
+        let mut output_file = create_output(filename, args.flag("force"));
+        dtm_encoder(&mut output_file, args).encode(dtm).unwrap_or_barf("Could not encode dtm");
+        output_file.finish();
+        print_validation(dtm);
+    }
+}
+
+fn all_dtm(inputs: &[&Path], output: &Path, args: &Args) -> bool {
+    inputs.iter().all(|input| input_format(input, args) == "dtm") && output_format(output, args) == "dtm"
+}
+
+// Anything questionable about a movie that was just written. Savestates are
+// left to check_savestate, which knows where the movie ends up.
+fn print_validation<S>(dtm: &Dtm<S>)
+    where S: FrameStore,
+{
+    for warning in dtm.validate() {
+        eprintln!("Warning: {}", warning);
+    }
+}
+
+// Output to stdout is written as --format, or dtm by default.
+fn output_format<'a>(filename: &'a Path, args: &'a Args) -> &'a str {
+    if is_stdio(filename) {
+        args.value("format").unwrap_or("dtm")
+    }
+    else {
+        extension(filename)
+    }
+}
+
+// Like write_movie, but leaves checking for a savestate to the caller.
+fn encode_movie(filename: &Path, dtm: &Dtm, args: &Args) {
+    let format = output_format(filename, args);
+    let mut output_file = create_output(filename, args.flag("force"));
+    match format {
+        "dtm" => dtm_encoder(&mut output_file, args).encode(dtm).unwrap_or_barf("Could not encode dtm"),
+        "txt" => text_encoder(&mut output_file, args).encode(dtm).unwrap_or_barf("Could not encode txt"),
+        "csv" => {
+            if !dtm.trailing.is_empty() {
+                eprintln!("Warning: csv can't hold the {} bytes after the inputs; they were left out", dtm.trailing.len());
+            }
+            CsvEncoder::new(&mut output_file).encode(dtm).unwrap_or_barf("Could not encode csv")
+        }
+        "zip" => write_bundle(&mut output_file, filename, dtm, args),
+        "yaml" => write_yaml(&mut output_file, dtm, args),
+        "cbor" => write_cbor(&mut output_file, dtm),
+        "jsonl" => JsonlEncoder::new(&mut output_file).encode(dtm).unwrap_or_barf("Could not encode jsonl"),
+        "json" => JsonEncoder::new(&mut output_file).encode(dtm).unwrap_or_barf("Could not encode json"),
+        other => match export::find(other) {
+            Some(export_format) => export_format.export(dtm, &mut output_file)
+                .unwrap_or_barf(&format!("Could not export {}", export_format.name())),
+            None => barf("Output file must be a txt, csv, dtm, zip, yaml, cbor, json, jsonl, bk2, overlay or srt."),
+        },
+    }
+    output_file.finish();
+    print_validation(dtm);
+}
+
+// The yaml feature can be turned off to do without serde_yaml, in which case
+// yaml files are refused like any unknown format, just with a better reason.
+#[cfg(feature = "yaml")]
+fn write_yaml<W>(output_file: W, dtm: &Dtm, args: &Args)
+    where W: Write,
+{
+    YamlEncoder::new(output_file)
+        .syntax(frame_syntax(args))
+        .encode(dtm)
+        .unwrap_or_barf("Could not encode yaml")
+}
+
+#[cfg(not(feature = "yaml"))]
+fn write_yaml<W>(_output_file: W, _dtm: &Dtm, _args: &Args) {
+    barf("This dtm2txt was built without yaml support.")
+}
+
+#[cfg(feature = "yaml")]
+fn read_yaml<R>(file: R, args: &Args) -> Dtm
+    where R: Read,
+{
+    let (dtm, warnings) = YamlDecoder::new(file)
+        .options(decode_options(args))
+        .decode_with_warnings()
+        .unwrap_or_barf("Could not decode yaml");
+    print_warnings(&warnings);
+    dtm
+}
+
+#[cfg(not(feature = "yaml"))]
+fn read_yaml<R>(_file: R, _args: &Args) -> Dtm {
+    barf("This dtm2txt was built without yaml support.")
+}
+
+#[cfg(feature = "yaml")]
+fn read_yaml_header<R>(file: R, args: &Args) -> DtmHeader
+    where R: Read,
+{
+    YamlDecoder::new(file)
+        .options(decode_options(args))
+        .decode_header()
+        .unwrap_or_barf("Could not decode yaml header")
+}
+
+#[cfg(not(feature = "yaml"))]
+fn read_yaml_header<R>(_file: R, _args: &Args) -> DtmHeader {
+    barf("This dtm2txt was built without yaml support.")
+}
+
+#[cfg(feature = "cbor")]
+fn write_cbor<W>(output_file: W, dtm: &Dtm)
+    where W: Write,
+{
+    CborEncoder::new(output_file).encode(dtm).unwrap_or_barf("Could not encode cbor")
+}
+
+#[cfg(not(feature = "cbor"))]
+fn write_cbor<W>(_output_file: W, _dtm: &Dtm) {
+    barf("This dtm2txt was built without cbor support.")
+}
+
+#[cfg(feature = "cbor")]
+fn read_cbor<R>(file: R, args: &Args) -> Dtm
+    where R: Read,
+{
+    let (dtm, warnings) = CborDecoder::new(file)
+        .options(decode_options(args))
+        .decode_with_warnings()
+        .unwrap_or_barf("Could not decode cbor");
+    print_warnings(&warnings);
+    dtm
+}
+
+#[cfg(not(feature = "cbor"))]
+fn read_cbor<R>(_file: R, _args: &Args) -> Dtm {
+    barf("This dtm2txt was built without cbor support.")
+}
+
+#[cfg(feature = "cbor")]
+fn read_cbor_header<R>(file: R, args: &Args) -> DtmHeader
+    where R: Read,
+{
+    CborDecoder::new(file)
+        .options(decode_options(args))
+        .decode_header()
+        .unwrap_or_barf("Could not decode cbor header")
+}
+
+#[cfg(not(feature = "cbor"))]
+fn read_cbor_header<R>(_file: R, _args: &Args) -> DtmHeader {
+    barf("This dtm2txt was built without cbor support.")
+}
+
+fn check_options(args: &Args, command: &Command) {
+    if let Some(name) = args.names().find(|name| !command.allows(name)) {
+        barf(&format!("{} doesn't take --{} (see dtm2txt {} --help)", command.name, name, command.name));
+    }
+}
+
+fn main() {
+    let mut args = Args::parse(env::args().skip(1)).unwrap_or_else(|err| barf(&err));
+    if args.flag("version") {
+        println!("dtm2txt {}", env!("CARGO_PKG_VERSION"));
+        return;
+    }
+
+    let name = match args.positional().first() {
+        Some(name) => name.clone(),
+        None => return commands::print_help(),
+    };
+    let command = match commands::find(&name) {
+        Some(command) => command,
+        None => {
+            // A bare filename, as when a file is dragged onto the executable.
+            // There's no way to pass --force then, so this always overwrote.
+            if args.flag("help") {
+                return commands::print_help();
+            }
+            let filename = Path::new(&name);
+            if filename.extension().is_none() && !filename.exists() && !is_stdio(filename) {
+                barf(&format!("Unknown command '{}' (see dtm2txt --help)", name));
+            }
+            check_options(&args, commands::find("convert").unwrap_or_barf("convert command is missing"));
+            args.set_flag("force");
+            return convert(&args, args.positional());
+        }
+    };
+    if args.flag("help") {
+        return command.print_help();
+    }
+    check_options(&args, command);
+
+    match command.name {
+        "convert" => convert(&args, &args.positional()[1..]),
+        "new" => new(&args),
+        "fuzz" => fuzz(&args),
+        "compile" => compile(&args),
+        "info" => info(&args),
+        "header" => header(&args),
+        "patch-header" => patch_header(&args),
+        "join" => join(&args),
+        "split" => split(&args),
+        "extract" => extract(&args),
+        "repeat" => repeat(&args),
+        "anonymize" => anonymize(&args),
+        "canonicalize" => canonicalize(&args),
+        "diff" => diff(&args),
+        "make-patch" => make_patch(&args),
+        "patch" => patch(&args),
+        "compose" => compose(&args),
+        "merge-port" => merge_port(&args),
+        "find" => find(&args),
+        "audit" => audit(&args),
+        "stats" => stats(&args),
+        "timeline" => timeline(&args),
+        "verify" => verify(&args),
+        "verify-md5" => verify_md5(&args),
+        "watch" => watch(&args),
+        "feed" => feed(&args),
+        _ => unreachable!(),
+    }
+}
+
+// The time on this computer's clock, counted in seconds since 1970 as if it
+// were UTC, which is how Dolphin stores when a recording started.
+#[cfg(unix)]
+fn local_clock_secs() -> u64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0);
+    let time = now as libc::time_t;
+    let mut local: libc::tm = unsafe { std::mem::zeroed() };
+    if unsafe { libc::localtime_r(&time, &mut local) }.is_null() {
+        return now;
+    }
+    (now as i64).saturating_add(local.tm_gmtoff as i64).max(0) as u64
+}
+
+// Without a way to ask for the time zone, UTC is the best guess.
+#[cfg(not(unix))]
+fn local_clock_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0)
+}
+
+fn new(args: &Args) {
+    let game_id = args.value("game-id").unwrap_or_barf("new needs --game-id");
+    let frames = args.parse_value("frames")
+        .unwrap_or_else(|err| barf(&err))
+        .unwrap_or(0);
+
+    // Dolphin stamps a new recording with the time it started.
+    let start_time = local_clock_secs();
+    let header = DtmHeader::builder()
+        .game_id(game_id)
+        .author(args.value("author").unwrap_or_default())
+        .start_time(start_time)
+        .build();
+    let dtm = Dtm::blank(header, frames);
+
+    let output_filename = Path::new(args.value("output").unwrap_or("new.txt"));
+    write_movie(output_filename, &dtm, args);
+    println!("Wrote a blank movie with {} frames to {}.", frames, output_filename.display());
+}
+
+fn fuzz(args: &Args) {
+    let game_id = args.value("game-id").unwrap_or_barf("fuzz needs --game-id");
+    let frames = args.parse_value("frames")
+        .unwrap_or_else(|err| barf(&err))
+        .unwrap_or_barf("fuzz needs --frames");
+    let seed = args.parse_value("seed")
+        .unwrap_or_else(|err| barf(&err))
+        .unwrap_or(0);
+
+    let mut options = FuzzOptions::new(frames, seed);
+    if let Some(buttons) = args.value("buttons") {
+        options.buttons = buttons.split(',')
+            .map(|name| Button::from_name(name.trim()).unwrap_or_barf(&format!("Unknown button '{}'", name)))
+            .collect();
+    }
+    if let Some(press_chance) = args.parse_value("press-chance").unwrap_or_else(|err| barf(&err)) {
+        options.press_chance = press_chance;
+    }
+    if let Some(expr) = args.value("where") {
+        options.constraint = Some(Filter::parse(expr).unwrap_or_barf("Invalid --where expression"));
+    }
+
+    let header = DtmHeader::builder().game_id(game_id).build();
+    let dtm = fuzz::generate(header, &options).unwrap_or_barf("Could not generate movie");
+
+    let output_filename = Path::new(args.value("output").unwrap_or("fuzz.dtm"));
+    write_movie(output_filename, &dtm, args);
+    println!("Generated {} random frames.", frames);
+}
+
+fn read_movie(filename: &Path, args: &Args) -> Dtm {
+    let file = open_input(filename);
+    match input_format(filename, args).as_str() {
+        "dtm" => {
+            let (dtm, warnings) = dtm_decoder(file, args).decode_with_warnings().unwrap_or_barf("Could not decode dtm");
+            print_warnings(&warnings);
+            dtm
+        }
+        "txt" => {
+            let (dtm, warnings) = text_decoder(file, args).decode_with_warnings().unwrap_or_barf("Could not decode txt");
+            print_warnings(&warnings);
+            dtm
+        }
+        "csv" => windowed(csv_decoder(file, args).decode().unwrap_or_barf("Could not decode csv"), args),
+        "zip" => {
+            let bundle = read_bundle(file);
+            let (dtm, warnings) = dtm_decoder(&bundle.movie[..], args).decode_with_warnings().unwrap_or_barf("Could not decode dtm");
+            print_warnings(&warnings);
+            dtm
+        }
+        "yaml" => read_yaml(file, args),
+        "cbor" => read_cbor(file, args),
+        "json" => read_json(file, args),
+        format @ "bk2" | format @ "fm2" | format @ "log" => windowed(read_input_log(file, format, args), args),
+        _ => barf("File must be a txt, csv, dtm, zip, yaml, cbor, json, bk2, fm2 or log."),
+    }
+}
+
+fn read_json<R>(file: R, args: &Args) -> Dtm
+    where R: Read,
+{
+    let (dtm, warnings) = JsonDecoder::new(file)
+        .options(decode_options(args))
+        .decode_with_warnings()
+        .unwrap_or_barf("Could not decode json");
+    print_warnings(&warnings);
+    dtm
+}
+
+// zip archives need seeking, which stdin and gzip streams can't do, so the
+// whole archive is read into memory first.
+fn read_bundle<R>(mut file: R) -> Bundle
+    where R: Read,
+{
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).unwrap_or_barf("Could not read zip");
+    Bundle::read(Cursor::new(bytes)).unwrap_or_barf("Could not find a dtm in the zip")
+}
+
+// Bundles a dtm with the savestate next to where it would be written.
+fn write_bundle<W>(output_file: W, filename: &Path, dtm: &Dtm, args: &Args)
+    where W: Write,
+{
+    let mut movie = Vec::new();
+    dtm_encoder(&mut movie, args).encode(dtm).unwrap_or_barf("Could not encode dtm");
+
+    let dtm_filename = bundled_dtm_name(filename);
+    let savestate = match File::open(edit::savestate_path(&dtm_filename)) {
+        Ok(mut savestate_file) => {
+            let mut savestate = Vec::new();
+            savestate_file.read_to_end(&mut savestate).unwrap_or_barf("Could not read savestate");
+            Some(savestate)
+        }
+        Err(_) => None,
+    };
+    let bundle = Bundle {
+        name: dtm_filename.file_name().unwrap_or_barf("Error processing filename").to_string_lossy().into_owned(),
+        movie: movie,
+        savestate: savestate,
+    };
+
+    let mut zipped = Cursor::new(Vec::new());
+    bundle.write(&mut zipped).unwrap_or_barf("Could not write zip");
+    let mut output_file = output_file;
+    output_file.write_all(&zipped.into_inner()).unwrap_or_barf("Could not write zip");
+}
+
+// movie.zip holds movie.dtm, whose savestate is movie.dtm.sav.
+fn bundled_dtm_name(filename: &Path) -> PathBuf {
+    uncompressed_name(filename).with_extension("dtm")
+}
+
+// Only reads the header, so it's instant even for huge movies.
+fn read_header(filename: &Path, args: &Args) -> DtmHeader {
+    read_header_as(filename, &input_format(filename, args), args)
+}
+
+fn read_header_as(filename: &Path, format: &str, args: &Args) -> DtmHeader {
+    let file = open_input(filename);
+    match format {
+        "dtm" => dtm_decoder(file, args).decode_header().unwrap_or_barf("Could not decode dtm header"),
+        "txt" => text_decoder(file, args).decode_header().unwrap_or_barf("Could not decode txt header"),
+        "zip" => {
+            let bundle = read_bundle(file);
+            dtm_decoder(&bundle.movie[..], args).decode_header().unwrap_or_barf("Could not decode dtm header")
+        }
+        "yaml" => read_yaml_header(file, args),
+        "cbor" => read_cbor_header(file, args),
+        "json" => JsonDecoder::new(file).options(decode_options(args)).decode_header().unwrap_or_barf("Could not decode json header"),
+        format @ "bk2" | format @ "fm2" | format @ "log" => read_input_log(file, format, args).header,
+        _ => barf("File must be a txt, dtm, zip, yaml, cbor, json, bk2, fm2 or log."),
+    }
+}
+
+// Port and slot numbers start at 1 here, like in Dolphin's settings.
+fn describe_controllers(header: &DtmHeader) -> String {
+    let mut controllers: Vec<String> = header.gamecube_ports().iter()
+        .map(|port| format!("GameCube port {}", port + 1))
+        .collect();
+    controllers.extend(header.wiimote_slots().iter().map(|slot| format!("Wii Remote {}", slot + 1)));
+    if controllers.is_empty() {
+        "none".to_string()
+    }
+    else {
+        controllers.join(", ")
+    }
+}
+
+fn compile(args: &Args) {
+    let filename = Path::new(args.positional().get(1).unwrap_or_barf("compile needs a script file"));
+    let mut text = String::new();
+    open_input(filename).read_to_string(&mut text).unwrap_or_barf("Could not read script");
+    let script = Script::parse(&text).unwrap_or_barf("Could not parse script");
+    let dtm = script.compile(template_header(args)).unwrap_or_barf("Could not compile script");
+
+    let output_filename = match args.value("output") {
+        Some(output) => PathBuf::from(output),
+        None => default_output(filename, "dtm", args),
+    };
+    write_movie(&output_filename, &dtm, args);
+    println!("Compiled {} frames into {}.", dtm.frame_count(), output_filename.display());
+}
+
+fn info(args: &Args) {
+    let filename = Path::new(args.positional().get(1).unwrap_or_barf("info needs a movie file"));
+    let header = read_header(filename, args);
+
+    let platform = if header.wii_game { "Wii" } else { "GameCube" };
+    match header.parsed_game_id() {
+        Ok(game_id) => println!("Game:       {} ({}, {})", game_id, platform, game_id.region()),
+        Err(_) => println!("Game:       {} ({})", header.game_id, platform),
+    }
+    if !header.author.is_empty() {
+        println!("Author:     {}", header.author);
+    }
+    println!("Inputs:     {} ({} lag frames)", header.input_count, header.lag_counter);
+    println!("Length:     {} ({} VIs at {:.2}Hz)",
+        humanize::format_duration(header.duration_secs()), header.vi_count, header.vi_rate());
+    println!("CPU time:   {} ({} ticks)",
+        humanize::format_duration(header.tick_duration_secs()), header.tick_count);
+    println!("Rerecords:  {}", header.rerecord_count);
+    println!("Starts:     {}", if header.savestate { "from a savestate" } else { "from power-on" });
+    println!("Controls:   {}", describe_controllers(&header));
+    println!("Started:    {}", humanize::format_timestamp(header.start_time));
+
+    let version = header.guess_dolphin_version();
+    println!("Dolphin:    {}", version);
+    for clue in version.clues.iter() {
+        println!("            - {}", clue);
+    }
+}
+
+fn join(args: &Args) {
+    let filenames: Vec<&Path> = args.positional()[1..].iter().map(Path::new).collect();
+    if filenames.len() < 2 {
+        barf("join needs at least two movie files");
+    }
+    let output_filename = Path::new(args.value("output").unwrap_or("joined.dtm"));
+    if all_dtm(&filenames, output_filename, args) {
+        join_movies::<RawFrames>(&filenames, output_filename, args);
+    }
+    else {
+        join_movies::<Vec<ControllerInput>>(&filenames, output_filename, args);
+    }
+}
+
+fn join_movies<S>(filenames: &[&Path], output_filename: &Path, args: &Args)
+    where S: MovieFrames,
+{
+    let mut joined = S::read_movie(filenames[0], args);
+    for filename in filenames[1..].iter() {
+        let next = S::read_segment(filename, &joined.header, args);
+        for problem in joined.concat_problems(&next).iter().filter(|problem| !problem.is_fatal()) {
+            eprintln!("Warning: {}: {}", filename.display(), problem);
+        }
+        joined = joined.concat(&next).unwrap_or_barf(&format!("Could not join {}", filename.display()));
+    }
+
+    write_movie(output_filename, &joined, args);
+    if !is_stdio(output_filename) {
+        println!("Joined {} movies into {} frames.", filenames.len(), joined.frame_count());
+    }
+}
+
+// A movie to append to one with `base` as its header. A txt file of only
+// input lines takes the settings from `base`, with counters of its own.
+fn read_segment(filename: &Path, base: &DtmHeader, args: &Args) -> Dtm {
+    if input_format(filename, args) != "txt" {
+        return read_movie(filename, args);
+    }
+    let mut file = open_input(filename);
+    let first_byte = file.fill_buf()
+        .unwrap_or_barf("Could not read file")
+        .iter()
+        .find(|byte| !byte.is_ascii_whitespace())
+        .cloned();
+    if first_byte == Some(b'{') {
+        return read_movie(filename, args);
+    }
+
+    let mut header = base.clone();
+    header.savestate = false;
+    header.rerecord_count = 0;
+    header.lag_counter = 0;
+    let (mut dtm, warnings) = text_decoder(file, args)
+        .headerless(header)
+        .decode_with_warnings()
+        .unwrap_or_barf("Could not decode txt");
+    print_warnings(&warnings);
+    dtm.estimate_timing();
+    dtm
+}
+
+fn split(args: &Args) {
+    let filename = Path::new(args.positional().get(1).unwrap_or_barf("split needs a movie file"));
+    let frame = args.parse_value("at")
+        .unwrap_or_else(|err| barf(&err))
+        .unwrap_or_barf("split needs --at");
+    if all_dtm(&[filename], filename, args) {
+        split_movie::<RawFrames>(filename, frame, args);
+    }
+    else {
+        split_movie::<Vec<ControllerInput>>(filename, frame, args);
+    }
+}
+
+fn split_movie<S>(filename: &Path, frame: u64, args: &Args)
+    where S: MovieFrames,
+{
+    let dtm = S::read_movie(filename, args);
+    let (first, second) = dtm.split_at(frame).unwrap_or_barf("Could not split movie");
+
+    let stem = uncompressed_name(filename);
+    let stem = stem.file_stem().unwrap_or_barf("Error processing filename").to_string_lossy();
+    let suffix = gzip_suffix(filename, args);
+    let first_filename = filename.with_file_name(format!("{}-1.{}{}", stem, extension(filename), suffix));
+    let second_filename = filename.with_file_name(format!("{}-2.{}{}", stem, extension(filename), suffix));
+    // Anything that would stop either half from being written is caught
+    // before the first one is, so a split never stops halfway.
+    for &(half_filename, half) in [(&first_filename, &first), (&second_filename, &second)].iter() {
+        check_overwrite(half_filename, args.flag("force"));
+        check_consistency(half_filename, half, args);
+    }
+    write_movie(&first_filename, &first, args);
+    // Nobody has a savestate of the split point yet, so the second half is
+    // written regardless.
+    S::encode_movie(&second_filename, &second, args);
+
+    println!("Wrote {} frames to {} and {} frames to {}.",
+        first.frame_count(), first_filename.display(), second.frame_count(), second_filename.display());
+    warn_needs_savestate(&second_filename, frame);
+}
+
+// Which frames extract copies.
+enum Extent {
+    Range(FrameRange),
+    // From the first frame that matches to the last, trimming the rest.
+    Matching(Filter),
+}
+
+fn extract(args: &Args) {
+    let filename = Path::new(args.positional().get(1).unwrap_or_barf("extract needs a movie file"));
+    let range = args.parse_range("range").unwrap_or_else(|err| barf(&err));
+    let filter = args.value("where").map(|expr| Filter::parse(expr).unwrap_or_barf("Invalid --where expression"));
+    let extent = match (range, filter) {
+        (Some(range), None) => Extent::Range(range),
+        (None, Some(filter)) => Extent::Matching(filter),
+        (Some(_), Some(_)) => barf("Pass either --range or --where to extract, not both"),
+        (None, None) => barf("extract needs --range or --where"),
+    };
+    let output_filename = Path::new(args.value("output").unwrap_or_barf("extract needs -o"));
+    if all_dtm(&[filename], output_filename, args) {
+        extract_movie::<RawFrames>(filename, extent, output_filename, args);
+    }
+    else {
+        extract_movie::<Vec<ControllerInput>>(filename, extent, output_filename, args);
+    }
+}
+
+fn extract_movie<S>(filename: &Path, extent: Extent, output_filename: &Path, args: &Args)
+    where S: MovieFrames,
+{
+    let dtm = S::read_movie(filename, args);
+    let range = match extent {
+        Extent::Range(range) => range,
+        Extent::Matching(filter) => {
+            let frames = dtm.find_frames(&filter);
+            match (frames.first(), frames.last()) {
+                (Some(&first), Some(&last)) => (Bound::Included(first), Bound::Excluded(last + 1)),
+                _ => barf("No frames match the --where expression"),
+            }
+        }
+    };
+    let start = match range.0 {
+        Bound::Included(start) => start,
+        _ => 0,
+    };
+    let part = dtm.extract(range).unwrap_or_barf("Could not extract frames");
+
+    if start == 0 {
+        write_movie(output_filename, &part, args);
+    }
+    else {
+        S::encode_movie(output_filename, &part, args);
+    }
+
+    if !is_stdio(output_filename) {
+        println!("Wrote {} frames to {}.", part.frame_count(), output_filename.display());
+    }
+    if start > 0 {
+        warn_needs_savestate(output_filename, start);
+    }
+}
+
+// Writes next to the input as movie-repeated.txt unless given -o.
+fn repeat(args: &Args) {
+    let filename = Path::new(args.positional().get(1).unwrap_or_barf("repeat needs a movie file"));
+    let range = args.parse_range("range")
+        .unwrap_or_else(|err| barf(&err))
+        .unwrap_or_barf("repeat needs --range");
+    let times = args.parse_value("times")
+        .unwrap_or_else(|err| barf(&err))
+        .unwrap_or_barf("repeat needs --times");
+    let mut dtm = read_movie(filename, args);
+    let frames = dtm.frame_count();
+    dtm.repeat_range(range, times).unwrap_or_barf("Could not repeat frames");
+
+    let output_filename = match args.value("output") {
+        Some(output) => PathBuf::from(output),
+        None => {
+            let stem = uncompressed_name(filename);
+            let stem = stem.file_stem().unwrap_or_barf("Error processing filename").to_string_lossy();
+            filename.with_file_name(format!("{}-repeated.{}{}", stem, extension(filename), gzip_suffix(filename, args)))
+        }
+    };
+    write_movie(&output_filename, &dtm, args);
+    println!("Wrote {} frames ({} before) to {}.", dtm.frame_count(), frames, output_filename.display());
+}
+
+fn anonymize(args: &Args) {
+    let filename = Path::new(args.positional().get(1).unwrap_or_barf("anonymize needs a movie file"));
+    let output_filename = Path::new(args.value("output").unwrap_or_barf("anonymize needs -o"));
+    let mut dtm = read_movie(filename, args);
+    dtm.header.anonymize();
+    write_movie(output_filename, &dtm, args);
+    println!("Wrote {}.", output_filename.display());
+}
+
+fn canonicalize(args: &Args) {
+    let filename = Path::new(args.positional().get(1).unwrap_or_barf("canonicalize needs a movie file"));
+    let output_filename = Path::new(args.value("output").unwrap_or_barf("canonicalize needs -o"));
+    let mut dtm = read_movie(filename, args);
+    dtm.canonicalize();
+    write_movie(output_filename, &dtm, args);
+    println!("Wrote {}.", output_filename.display());
+}
+
+fn diff(args: &Args) {
+    let first_filename = Path::new(args.positional().get(1).unwrap_or_barf("diff needs two movie files"));
+    let second_filename = Path::new(args.positional().get(2).unwrap_or_barf("diff needs two movie files"));
+    let first = read_movie(first_filename, args);
+    let second = read_movie(second_filename, args);
+    let diff = first.diff(&second).unwrap_or_barf("Could not compare movies");
+
+    for difference in diff.header.iter() {
+        println!("Header {}: {} vs {}", difference.field, difference.first, difference.second);
+    }
+    if let Some((frame, ref difference)) = diff.first_difference {
+        println!("First differing frame: {} ({})", frame, difference);
+    }
+    let frames = cmp::max(first.header.input_count, second.header.input_count);
+    println!("{} of {} frames differ.", diff.differing_frames, frames);
+    if diff.trailing {
+        println!("The bytes after the inputs differ ({} vs {} bytes).", first.trailing.len(), second.trailing.len());
+    }
+
+    // Like diff(1), so scripts can check whether the movies match.
+    if !diff.is_empty() {
+        process::exit(1);
+    }
+}
+
+fn make_patch(args: &Args) {
+    let base_filename = Path::new(args.positional().get(1).unwrap_or_barf("make-patch needs two movie files"));
+    let edited_filename = Path::new(args.positional().get(2).unwrap_or_barf("make-patch needs two movie files"));
+    let base = read_movie(base_filename, args);
+    let edited = read_movie(edited_filename, args);
+    let patch = base.make_patch(&edited).unwrap_or_barf("Could not compare movies");
+
+    let output_filename = match args.value("output") {
+        Some(output) => PathBuf::from(output),
+        None => uncompressed_name(edited_filename).with_extension("dtmpatch"),
+    };
+    let mut output_file = create_output(&output_filename, args.flag("force"));
+    patch.write(&mut output_file).unwrap_or_barf("Could not write patch");
+    output_file.finish();
+    println!("Wrote {} changed frames and {} header fields to {}.",
+        patch.changed_frames(), patch.header.len(), output_filename.display());
+}
+
+fn patch(args: &Args) {
+    let filename = Path::new(args.positional().get(1).unwrap_or_barf("patch needs a movie and a patch file"));
+    let patch_filename = Path::new(args.positional().get(2).unwrap_or_barf("patch needs a movie and a patch file"));
+    let output_filename = Path::new(args.value("output").unwrap_or_barf("patch needs -o"));
+    let mut dtm = read_movie(filename, args);
+    let patch = MoviePatch::read(open_input(patch_filename)).unwrap_or_barf("Could not read patch");
+    dtm.apply_patch(&patch).unwrap_or_barf("Could not apply patch");
+
+    write_movie(output_filename, &dtm, args);
+    println!("Changed {} frames and {} header fields.", patch.changed_frames(), patch.header.len());
+}
+
+// Puts the inputs of one movie under the header of another, e.g. to move
+// edited inputs onto a fresh recording made with a newer Dolphin.
+fn compose(args: &Args) {
+    args.value("header").unwrap_or_barf("compose needs --header");
+    let inputs_filename = Path::new(args.value("inputs").unwrap_or_barf("compose needs --inputs"));
+    let output_filename = Path::new(args.value("output").unwrap_or_barf("compose needs -o"));
+    let header = template_header(args);
+    let mut dtm = read_movie(inputs_filename, args);
+    dtm.header = header;
+    dtm.recount();
+    edit_movie(&mut dtm, args);
+
+    write_movie(output_filename, &dtm, args);
+    println!("Wrote {} inputs under the new header to {}.", dtm.header.input_count, output_filename.display());
+}
+
+// The way back from convert --port: puts an edited track back into the
+// movie it came from.
+fn merge_port(args: &Args) {
+    let filename = Path::new(args.positional().get(1).unwrap_or_barf("merge-port needs a movie and a track"));
+    let track_filename = Path::new(args.positional().get(2).unwrap_or_barf("merge-port needs a movie and a track"));
+    let port = port_arg(args).unwrap_or_barf("merge-port needs --port");
+    let output_filename = Path::new(args.value("output").unwrap_or_barf("merge-port needs -o"));
+    let mut dtm = read_movie(filename, args);
+    let track = read_movie(track_filename, args);
+    dtm.replace_port(port, &track).unwrap_or_barf("Could not merge the track");
+
+    write_movie(output_filename, &dtm, args);
+    println!("Replaced the inputs of port {} with {} frames.", port + 1, track.frame_count());
+}
+
+// Prints one frame number per line, like grep, and exits with status 1 when
+// nothing matches.
+fn find(args: &Args) {
+    let filename = Path::new(args.positional().get(1).unwrap_or_barf("find needs a movie file"));
+    let filter = Filter::parse(args.value("where").unwrap_or_barf("find needs --where"))
+        .unwrap_or_barf("Invalid --where expression");
+    let mut dtm = read_movie(filename, args);
+    if dtm.controller_data.is_empty() && !dtm.wiimote_data.is_empty() {
+        barf("find only works on GameCube controller inputs");
+    }
+    // Which port's input matched is lost in a list of frames.
+    match port_arg(args) {
+        Some(port) => dtm = dtm.single_port(port).unwrap_or_barf("Could not take the port's inputs"),
+        None if dtm.header.gamecube_ports().len() > 1 => barf("This movie has more than one controller; pick one with --port"),
+        None => (),
+    }
+
+    let frames = dtm.find_frames(&filter);
+    let lines: Vec<String> = if args.flag("ranges") {
+        // Ends are exclusive, so each run can be passed to extract --range.
+        let mut runs = Vec::new();
+        let mut idx = 0;
+        while idx < frames.len() {
+            let start = frames[idx];
+            let mut end = start + 1;
+            idx += 1;
+            while idx < frames.len() && frames[idx] == end {
+                end += 1;
+                idx += 1;
+            }
+            runs.push(format!("{}..{}", start, end));
+        }
+        runs
+    }
+    else {
+        frames.iter().map(|frame| frame.to_string()).collect()
+    };
+
+    // Output usually goes to head or the like, which may stop reading early.
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for line in lines.iter() {
+        if writeln!(out, "{}", line).is_err() {
+            break;
+        }
+    }
+
+    if frames.is_empty() {
+        process::exit(1);
+    }
+}
+
+// Exits with status 1 when anything was found, so scripts can check a movie
+// before submitting it. With --fix, prints what was changed instead.
+fn audit(args: &Args) {
+    let filename = Path::new(args.positional().get(1).unwrap_or_barf("audit needs a movie file"));
+    let mut dtm = read_movie(filename, args);
+    let mut options = AuditOptions::default();
+    if let Some(threshold) = args.parse_value("trigger-threshold").unwrap_or_else(|err| barf(&err)) {
+        options = options.trigger_threshold(threshold);
+    }
+
+    if args.flag("fix") {
+        let output_filename = Path::new(args.value("output").unwrap_or_barf("--fix needs -o"));
+        let fixes = dtm.sanitize(&options);
+        for fix in fixes.iter() {
+            println!("{}", fix);
+        }
+        write_movie(output_filename, &dtm, args);
+        println!("Made {} changes.", fixes.len());
+        return;
+    }
+
+    let findings = dtm.audit_with(&options);
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for finding in findings.iter() {
+        if writeln!(out, "{}", finding).is_err() {
+            break;
+        }
+    }
+
+    if findings.is_empty() {
+        println!("No impossible inputs found.");
+    }
+    else {
+        eprintln!("{} problems found.", findings.len());
+        process::exit(1);
+    }
+}
+
+fn stats(args: &Args) {
+    let filename = Path::new(args.positional().get(1).unwrap_or_barf("stats needs a movie file"));
+    let filter = args.value("where").map(|expr| Filter::parse(expr).unwrap_or_barf("Invalid --where expression"));
+    let mut dtm = read_movie(filename, args);
+    match port_arg(args) {
+        Some(port) => dtm = dtm.single_port(port).unwrap_or_barf("Could not take the port's inputs"),
+        // Otherwise the counts would be of inputs, not frames.
+        None if filter.is_some() && dtm.header.gamecube_ports().len() > 1 => barf("This movie has more than one controller; pick one with --port"),
+        None => (),
+    }
+    if args.value("window").is_some() {
+        if filter.is_some() {
+            barf("--where can't be used with --window");
+        }
+        return window_stats(&dtm, args);
+    }
+    let stats = match filter {
+        Some(ref filter) => dtm.statistics_where(filter),
+        None => dtm.statistics(),
+    };
+
+    if filter.is_some() {
+        println!("Frames:       {} of {} match", stats.frames, dtm.frame_count());
+    }
+    else {
+        println!("Frames:       {}", stats.frames + stats.wiimote_frames);
+    }
+    println!("Length:       {}", humanize::format_duration(stats.duration_secs));
+    println!("CPU time:     {}", humanize::format_duration(stats.tick_duration_secs));
+    println!("Input hash:   {:016x}", dtm.input_hash());
+    if stats.frames == 0 {
+        return;
+    }
+
+    match stats.longest_idle {
+        Some(idle) => println!("Longest idle: {} frames, from frame {}", idle.frames, idle.start),
+        None => println!("Longest idle: none"),
+    }
+
+    println!();
+    println!("{:<22} {:>8} {:>12} {:>13}", "Button", "Presses", "Held frames", "Longest hold");
+    for button in stats.buttons.iter().filter(|button| button.held_frames != 0) {
+        println!("{:<22} {:>8} {:>12} {:>13}",
+            button.button.long_name(), button.presses, button.held_frames, button.longest_hold);
+    }
+
+    for &(name, usage) in [("Main stick", &stats.main_stick), ("C stick", &stats.c_stick)].iter() {
+        println!();
+        println!("{}:", name);
+        for &direction in StickDirection::ALL.iter() {
+            let frames = usage.frames(direction);
+            if frames != 0 {
+                println!("  {:<11} {:>6.2}%", direction.name(), frames as f64 * 100.0 / stats.frames as f64);
+            }
+        }
+    }
+}
+
+// A csv row per window, for plotting how busy each part of a run is.
+// Controller Connected and the reserved bit aren't input, so they're left out.
+fn window_stats(dtm: &Dtm, args: &Args) {
+    let window: u64 = args.parse_value("window")
+        .unwrap_or_else(|err| barf(&err))
+        .unwrap_or(0);
+    if window == 0 {
+        barf("--window must be at least 1");
+    }
+    if dtm.controller_data.is_empty() && !dtm.wiimote_data.is_empty() {
+        barf("stats --window only works on GameCube controller inputs");
+    }
+    if dtm.header.gamecube_ports().len() > 1 {
+        barf("This movie has more than one controller; pick one with --port");
+    }
+
+    let buttons: Vec<Button> = Button::ALL.iter()
+        .cloned()
+        .filter(|&button| button != Button::ControllerConnected && button != Button::Reserved)
+        .collect();
+    let output_filename = PathBuf::from(args.value("output").unwrap_or("-"));
+    let mut out = create_output(&output_filename, args.flag("force"));
+    let mut columns = vec!["start_frame", "frames", "presses"];
+    columns.extend(buttons.iter().map(|button| button.long_name()));
+    columns.extend(&["main_stick_travel", "c_stick_travel"]);
+    writeln!(out, "{}", columns.join(",")).unwrap_or_barf("Could not write statistics");
+    for stats in dtm.window_statistics(window).iter() {
+        let presses: Vec<u64> = buttons.iter().map(|&button| stats.presses(button)).collect();
+        let mut row = format!("{},{},{}", stats.start, stats.frames, presses.iter().sum::<u64>());
+        for count in presses.iter() {
+            row += &format!(",{}", count);
+        }
+        row += &format!(",{:.1},{:.1}", stats.main_stick_travel, stats.c_stick_travel);
+        if let Err(err) = writeln!(out, "{}", row) {
+            if !is_stdio(&output_filename) {
+                barf(&format!("Could not write statistics: {}", err));
+            }
+            break;
+        }
+    }
+    // Stdout may be piped into head or the like, which stops reading early.
+    if is_stdio(&output_filename) {
+        let _ = out.flush();
+    }
+    else {
+        out.finish();
+    }
+}
+
+fn timeline(args: &Args) {
+    let filename = Path::new(args.positional().get(1).unwrap_or_barf("timeline needs a movie file"));
+    let name = args.value("button").unwrap_or_barf("timeline needs --button");
+    let button = Button::from_name(name).unwrap_or_else(|| barf(&format!("Unknown button '{}'", name)));
+    let per_row: usize = args.parse_value("per-row")
+        .unwrap_or_else(|err| barf(&err))
+        .unwrap_or(60);
+    if per_row == 0 {
+        barf("--per-row must be at least 1");
+    }
+    let mut dtm = read_movie(filename, args);
+    if dtm.controller_data.is_empty() && !dtm.wiimote_data.is_empty() {
+        barf("timeline only works on GameCube controller inputs");
+    }
+    match port_arg(args) {
+        Some(port) => dtm = dtm.single_port(port).unwrap_or_barf("Could not take the port's inputs"),
+        None if dtm.header.gamecube_ports().len() > 1 => barf("This movie has more than one controller; pick one with --port"),
+        None => (),
+    }
+
+    // Rows start with their first frame, lined up to the widest one.
+    let rows = dtm.timeline(button, per_row);
+    let width = rows.last().map(|row| row.start.to_string().len()).unwrap_or(1);
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for row in rows.iter() {
+        if writeln!(out, "{:>width$} {}", row.start, row.marks, width = width).is_err() {
+            break;
+        }
+    }
+}
+
+fn verify(args: &Args) {
+    let filename = Path::new(args.positional().get(1).unwrap_or_barf("verify needs a dtm file"));
+    if extension(filename) != "dtm" {
+        barf("verify needs a dtm file");
+    }
+    let mut original = Vec::new();
+    open_input(filename).read_to_end(&mut original).unwrap_or_barf("Could not read file");
+
+    let mut all_ok = true;
+    for &(name, through_text) in [("dtm -> dtm", false), ("dtm -> txt -> dtm", true)].iter() {
+        match verify::verify_round_trip(&original, through_text).unwrap_or_barf("Could not round trip movie") {
+            None => println!("{}: identical", name),
+            Some(mismatch) => {
+                println!("{}: differs at {}", name, mismatch);
+                all_ok = false;
+            }
+        }
+    }
+
+    if !all_ok {
+        process::exit(1);
+    }
+}
+
+// Exits with status 1 when the image doesn't match, like verify.
+fn verify_md5(args: &Args) {
+    let filename = Path::new(args.positional().get(1).unwrap_or_barf("verify-md5 needs a movie file"));
+    let game_filename = args.positional().get(2).unwrap_or_barf("verify-md5 needs a game image");
+    let header = read_header(filename, args);
+    if !header.has_game_md5() {
+        barf("The movie doesn't record its game's md5 (Dolphin only saves it when asked to)");
+    }
+
+    let game_file = File::open(game_filename).unwrap_or_barf("Could not open game image");
+    let md5 = hash::game_md5(game_file).unwrap_or_barf("Could not read game image");
+    if md5 == header.md5 {
+        println!("The game image matches the movie.");
+    }
+    else {
+        println!("The game image doesn't match the movie:");
+        println!("  movie expects {}", header.md5);
+        println!("  image is      {}", md5);
+        println!("Compressed images (rvz, wbfs, ...) have to be converted to a plain iso first.");
+        process::exit(1);
+    }
+}
+
+fn watch(args: &Args) {
+    let dir = Path::new(args.positional().get(1).map(String::as_str).unwrap_or("."));
+    let (from, to) = if args.flag("reverse") { ("txt", "dtm") } else { ("dtm", "txt") };
+    let interval: f64 = args.parse_value("interval")
+        .unwrap_or_else(|err| barf(&err))
+        .unwrap_or(1.0);
+    if !interval.is_finite() || interval <= 0.0 {
+        barf("--interval must be more than 0 seconds");
+    }
+
+    // Files that were converted since they last changed are left alone.
+    let mut watcher = Watcher::new(dir, from);
+    for path in watcher.files().unwrap_or_barf("Could not read directory") {
+        if is_newer(&path.with_extension(to), &path) {
+            watcher.skip(&path).unwrap_or_barf("Could not read file");
+        }
+    }
+
+    println!("Watching {} for {} files. Press Ctrl+C to stop.", dir.display(), from);
+    loop {
+        for path in watcher.poll().unwrap_or_barf("Could not read directory") {
+            let output_filename = path.with_extension(to);
+            match convert_file(&path, &output_filename, args) {
+                Ok(()) => println!("Converted {} to {}", path.display(), output_filename.display()),
+                // A broken file shouldn't stop the watch.
+                Err(err) => eprintln!("Error: Could not convert {}: {}", path.display(), err),
+            }
+        }
+        thread::sleep(Duration::from_secs_f64(interval));
+    }
+}
+
+fn is_newer(path: &Path, than: &Path) -> bool {
+    let modified = |path: &Path| path.metadata().and_then(|metadata| metadata.modified()).ok();
+    match (modified(path), modified(than)) {
+        (Some(path_modified), Some(than_modified)) => path_modified >= than_modified,
+        _ => false,
+    }
+}
+
+// A plain dtm to txt or txt to dtm conversion that reports errors instead of
+// exiting.
+fn convert_file(filename: &Path, output_filename: &Path, args: &Args) -> Dtm2txtResult<()> {
+    let file = BufReader::new(File::open(filename)?);
+    if extension(filename) == "dtm" {
+        let (dtm, warnings) = dtm_decoder(file, args).decode_with_warnings()?;
+        print_warnings(&warnings);
+        let comments = match File::open(comments::comments_path(filename)) {
+            Ok(comments_file) => comments::load(BufReader::new(comments_file))?,
+            Err(_) => Vec::new(),
+        };
+        write_staged(output_filename, |output_file| text_encoder(output_file, args).comments(comments).encode(&dtm))
+    }
+    else {
+        let (dtm, warnings) = text_decoder(file, args).decode_with_warnings()?;
+        print_warnings(&warnings);
+        if let Some(problem) = consistency::check_savestate(&dtm.header, output_filename) {
+            if !args.flag("allow-inconsistent") {
+                return Err(Dtm2txtError::InconsistentMovieError(vec![problem]));
+            }
+        }
+        write_staged(output_filename, |output_file| dtm_encoder(output_file, args).encode(&dtm))
+    }
+}
+
+// What OutputFile does, for convert_file, which reports errors instead of
+// exiting.
+fn write_staged<F>(output_filename: &Path, write: F) -> Dtm2txtResult<()>
+    where F: FnOnce(&mut BufWriter<File>) -> Dtm2txtResult<()>,
+{
+    let staging = staging_path(output_filename);
+    let written = File::create(&staging)
+        .map_err(Dtm2txtError::from)
+        .and_then(|file| {
+            let mut output_file = BufWriter::new(file);
+            write(&mut output_file)?;
+            output_file.flush()?;
+            Ok(fs::rename(&staging, output_filename)?)
+        });
+    if written.is_err() {
+        let _ = fs::remove_file(&staging);
+    }
+    written
+}
+
+fn warn_needs_savestate(filename: &Path, frame: u64) {
+    if !is_stdio(filename) && extension(filename) == "dtm" {
+        eprintln!("Warning: {} starts from a savestate and will only sync with a savestate of frame {} saved as {}",
+            filename.display(), frame, edit::savestate_path(uncompressed_name(filename)).display());
+    }
+    else {
+        eprintln!("Warning: {} starts from a savestate and will only sync with a savestate of frame {}",
+            filename.display(), frame);
+    }
+}
+
+fn header(args: &Args) {
+    let filename = Path::new(args.positional().get(1).unwrap_or_barf("header needs a movie file"));
+    let header = read_header(filename, args);
+
+    match args.value("format").unwrap_or("json") {
+        "json" => {
+            let json = serde_json::to_string_pretty(&header).unwrap_or_barf("Could not encode header");
+            println!("{}", json);
+        }
+        "yaml" => print_yaml_header(&header),
+        other => barf(&format!("Unknown header format '{}' (expected json or yaml)", other)),
+    }
+}
+
+#[cfg(feature = "yaml")]
+fn print_yaml_header(header: &DtmHeader) {
+    let yaml = serde_yaml::to_string(header).unwrap_or_barf("Could not encode header");
+    print!("{}", yaml);
+}
+
+#[cfg(not(feature = "yaml"))]
+fn print_yaml_header(_header: &DtmHeader) {
+    barf("This dtm2txt was built without yaml support.")
+}
+
+// Rewrites just the header of a dtm, e.g. to change the author or rerecord
+// count of a huge movie.
+fn patch_header(args: &Args) {
+    let filename = Path::new(args.positional().get(1).unwrap_or_barf("patch-header needs a dtm file"));
+    let patch_filename = args.positional().get(2).unwrap_or_barf("patch-header needs a JSON file");
+    let patch_file = BufReader::new(File::open(patch_filename).unwrap_or_barf("Could not open JSON file"));
+    let patch: serde_json::Value = serde_json::from_reader(patch_file).unwrap_or_barf("Could not parse JSON file");
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(filename)
+        .unwrap_or_barf("Could not open dtm");
+    let old_header = DtmDecoder::new(&mut file).decode_header().unwrap_or_barf("Could not decode dtm header");
+    let new_header = header_patch::merge_header(&old_header, &patch).unwrap_or_barf("Could not patch header");
+    check_savestate(filename, &new_header, args);
+
+    file.seek(SeekFrom::Start(0)).unwrap_or_barf("Could not seek in dtm");
+    header_patch::patch_header(&mut file, &patch, args.flag("allow-inconsistent")).unwrap_or_barf("Could not patch header");
+    println!("Successfully patched the header.");
+}
+
+// Applies each --transform in the order given. A transform followed by
+// @START..END only touches those frames.
+fn transform_movie(dtm: &mut Dtm, args: &Args) {
+    let transforms: Vec<InRange<Transform>> = args.values("transform").iter()
+        .map(|spec| {
+            let (spec, range) = match spec.rfind('@') {
+                Some(at_idx) => {
+                    let range = args::parse_frame_range(&spec[at_idx + 1..])
+                        .unwrap_or_else(|err| barf(&format!("Bad --transform range in '{}': {}", spec, err)));
+                    (&spec[..at_idx], range)
+                }
+                None => (&spec[..], (Bound::Unbounded, Bound::Unbounded)),
+            };
+            let transform = Transform::parse(spec).unwrap_or_else(|err| barf(&format!("Bad --transform: {}", err)));
+            InRange::new(range, transform)
+        })
+        .collect();
+    if transforms.is_empty() {
+        return;
+    }
+    if !dtm.wiimote_data.is_empty() {
+        eprintln!("Warning: --transform only edits GameCube controller inputs; the Wii remote inputs were left alone");
+    }
+    match args.value("where") {
+        Some(expr) => {
+            let filter = Filter::parse(expr).unwrap_or_barf("Invalid --where expression");
+            dtm.map_frames(&Matching::new(filter, transforms));
+        }
+        None => dtm.map_frames(&transforms),
+    }
+}
+
+// Applies each --set field=value. Values are read as JSON like the fields of
+// a txt header, so numbers and true/false work as is; anything that isn't
+// JSON, or that the field won't take as JSON, is tried as a string.
+fn set_header_fields(dtm: &mut Dtm, args: &Args) {
+    for spec in args.values("set").iter() {
+        let eq_idx = spec.find('=').unwrap_or_barf(&format!("--set needs FIELD=VALUE, not '{}'", spec));
+        let (name, text) = (spec[..eq_idx].trim(), &spec[eq_idx + 1..]);
+        let set = |value: serde_json::Value| {
+            let mut patch = serde_json::Map::new();
+            patch.insert(name.to_string(), value);
+            header_patch::merge_header(&dtm.header, &serde_json::Value::Object(patch))
+        };
+        let header = match serde_json::from_str(text) {
+            Ok(serde_json::Value::String(text)) => set(serde_json::Value::String(text)),
+            Ok(value) => set(value).or_else(|_| set(serde_json::Value::String(text.to_string()))),
+            Err(_) => set(serde_json::Value::String(text.to_string())),
+        };
+        dtm.header = header.unwrap_or_barf(&format!("Could not set {}", name));
+    }
+}
+
+// --port counts from 1 like Dolphin's settings; the library counts from 0.
+fn port_arg(args: &Args) -> Option<u8> {
+    let port: u8 = args.parse_value("port").unwrap_or_else(|err| barf(&err))?;
+    if !(1..=4).contains(&port) {
+        barf("--port must be 1 to 4");
+    }
+    Some(port - 1)
+}
+
+// Plays a movie's inputs into a pipe or socket at the speed the game would
+// poll them, for anything that wants them live rather than from a file.
+fn feed(args: &Args) {
+    let filename = Path::new(args.positional().get(1).unwrap_or_barf("feed needs a movie file"));
+    let mut dtm = read_movie(filename, args);
+    if !dtm.wiimote_data.is_empty() {
+        barf("feed only works on GameCube controller inputs");
+    }
+    match port_arg(args) {
+        Some(port) => dtm = dtm.single_port(port).unwrap_or_barf("Could not take the port's inputs"),
+        None if dtm.header.gamecube_ports().len() > 1 => barf("This movie has more than one controller; pick one with --port"),
+        None => (),
+    }
+    let rate: f64 = args.parse_value("rate")
+        .unwrap_or_else(|err| barf(&err))
+        .unwrap_or_else(|| dtm.header.vi_rate());
+    if !rate.is_finite() || rate < 0.0 {
+        barf("--rate must be 0 or more frames per second");
+    }
+
+    // Opening a FIFO waits until something opens the other end.
+    let output: Box<dyn Write> = match (args.value("connect"), args.value("output")) {
+        (Some(address), None) => Box::new(TcpStream::connect(address).unwrap_or_barf("Could not connect")),
+        (None, Some("-")) => Box::new(io::stdout()),
+        (None, Some(output)) => Box::new(OpenOptions::new().write(true).create(true).truncate(true).open(output)
+            .unwrap_or_barf("Could not open output")),
+        (Some(_), Some(_)) => barf("feed takes -o or --connect, not both"),
+        (None, None) => barf("feed needs -o or --connect"),
+    };
+    let mut feed = FrameFeed::new(output).protocol(feed_protocol(args));
+
+    // Frames are scheduled from the start rather than each from the last, so
+    // slow writes don't add up to drift.
+    let start = Instant::now();
+    for (frame, input) in dtm.controller_data.iter().enumerate() {
+        if rate > 0.0 {
+            let due = start + Duration::from_secs_f64(frame as f64 / rate);
+            let now = Instant::now();
+            if due > now {
+                thread::sleep(due - now);
+            }
+        }
+        feed.write_frame(input).unwrap_or_barf("Could not write frame");
+    }
+    eprintln!("Fed {} frames in {:.1}s.", dtm.controller_data.len(), start.elapsed().as_secs_f64());
+}
+
+// Converts a dtm to txt without waiting for the end of it, so a recording can
+// be watched or piped on as it's written. Everything that needs the whole
+// movie is refused.
+fn convert_stream<R>(file: R, filename: &Path, output_opt: Option<String>, args: &Args)
+    where R: Read,
+{
+    if input_format(filename, args) != "dtm" {
+        barf("--stream only reads dtm files");
+    }
+    // Pipes often have no extension, or one that isn't a format at all, so
+    // only another movie format's is refused.
+    let format = args.value("format").or_else(|| output_opt.as_ref().and_then(|val| format_of(Path::new(val))));
+    if let Some(format) = format.filter(|&format| format != "txt") {
+        let movie_format = ["dtm", "csv", "zip", "yaml", "cbor", "json", "jsonl"].contains(&format) || export::find(format).is_some();
+        if movie_format || args.value("format").is_some() {
+            barf("--stream only writes txt files");
+        }
+    }
+    if args.flag("cache") || args.flag("keep-comments") || args.flag("collapse-repeats") {
+        barf("--cache, --keep-comments and --collapse-repeats can't be used with --stream");
+    }
+    if args.value("skip").is_some() || args.value("take").is_some() || args.value("port").is_some() {
+        barf("--skip, --take and --port can't be used with --stream");
+    }
+    if !args.values("transform").is_empty() || args.flag("fix-counts") || !args.values("set").is_empty() {
+        barf("--transform, --fix-counts and --set can't be used with --stream");
+    }
+    let output_filename: PathBuf = output_opt
+        .map(|val| val.into())
+        .unwrap_or_else(|| default_output(filename, "txt", args));
+
+    let mut decoder = DtmDecoder::new(file).options(decode_options(args));
+    let header = decoder.decode_header().unwrap_or_barf("Could not read dtm header");
+    let wiimotes = header.has_wiimote_inputs();
+    if wiimotes && header.gamecube_port_count() != 0 {
+        barf("Movies with both GameCube controllers and Wii remotes can't be converted");
+    }
+    let mut stream = text_encoder(create_output(&output_filename, args.flag("force")), args)
+        .stream(&header)
+        .unwrap_or_barf("Could not write txt header");
+
+    let mut inputs = 0;
+    if wiimotes {
+        while let Some(input) = decoder.next_wiimote_input().unwrap_or_barf("Could not read input") {
+            stream.write_wiimote_input(&input).unwrap_or_barf("Could not write txt");
+            inputs += 1;
+        }
+    }
+    else {
+        while let Some(input) = decoder.next_controller_input().unwrap_or_barf("Could not read input") {
+            stream.write_input(&input).unwrap_or_barf("Could not write txt");
+            inputs += 1;
+        }
+    }
+    stream.finish().unwrap_or_barf("Could not write txt").finish();
+    if inputs != header.input_count {
+        eprintln!("Warning: the header says {} inputs but {} came in", header.input_count, inputs);
+    }
+    if !is_stdio(&output_filename) {
+        println!("Streamed {} inputs from dtm to txt.", inputs);
+    }
+}
+
+// Plain conversions between dtm and txt go a frame at a time, so they take
+// the same memory however long the movie is. Anything that needs the whole
+// movie at hand, like editing it or keeping comments, reads it all first.
+// The dtm side has to be a file: one being read is gone through twice, and
+// one being written gets its header last.
+fn transcode_direction(filename: &Path, output_opt: Option<&String>, args: &Args) -> Option<Direction> {
+    let editing = port_arg(args).is_some() || !args.values("transform").is_empty()
+        || args.flag("fix-counts") || !args.values("set").is_empty();
+    if editing || args.flag("cache") || args.flag("keep-comments") || args.flag("collapse-repeats") {
+        return None;
+    }
+    let output_format = args.value("format").or_else(|| output_opt.and_then(|val| format_of(Path::new(val))));
+    match (input_format(filename, args).as_str(), output_format) {
+        ("dtm", None) | ("dtm", Some("txt")) => {
+            let seekable = !is_stdio(filename) && !is_gzip(filename);
+            // Comments kept by --keep-comments are put back by the usual way.
+            if seekable && !comments::comments_path(filename).exists() { Some(Direction::DtmToTxt) } else { None }
+        }
+        ("txt", None) | ("txt", Some("dtm")) => {
+            let output_filename = output_opt
+                .map(PathBuf::from)
+                .unwrap_or_else(|| default_output(filename, "dtm", args));
+            let seekable = !is_stdio(&output_filename) && !is_gzip(&output_filename);
+            if seekable && format_of(&output_filename) == Some("dtm") { Some(Direction::TxtToDtm) } else { None }
+        }
+        _ => None,
+    }
+}
+
+fn convert_transcoded(filename: &Path, output_opt: Option<String>, direction: Direction, args: &Args) {
+    let (extension, from, to) = match direction {
+        Direction::DtmToTxt => ("txt", "dtm", "txt"),
+        Direction::TxtToDtm => ("dtm", "txt", "dtm"),
+    };
+    let output_filename: PathBuf = output_opt
+        .map(|val| val.into())
+        .unwrap_or_else(|| default_output(filename, extension, args));
+
+    // Unlike a movie read in full, this one can turn out to be unconvertible
+    // after the output is started, which is what OutputFile is for.
+    let transcoded = match direction {
+        Direction::DtmToTxt => {
+            let file = BufReader::new(File::open(filename).unwrap_or_barf("Could not open file"));
+            let mut output_file = create_output(&output_filename, args.flag("force"));
+            let transcoded = transcode::dtm_to_txt(dtm_decoder(file, args), text_encoder(&mut output_file, args))
+                .unwrap_or_else(|err| barf(&format!("Could not convert dtm to txt: {}", err)));
+            output_file.finish();
+            transcoded
+        }
+        Direction::TxtToDtm => {
+            // The dtm encoder seeks back to the header, so this one is staged
+            // by hand.
+            check_overwrite(&output_filename, args.flag("force"));
+            let staging = staging_path(&output_filename);
+            let give_up = |message: String| -> ! {
+                let _ = fs::remove_file(&staging);
+                barf(&message)
+            };
+            let output_file = BufWriter::new(File::create(&staging).unwrap_or_barf("Could not create file"));
+            let transcoded = transcode::txt_to_dtm(text_decoder(open_input(filename), args), dtm_encoder(output_file, args))
+                .unwrap_or_else(|err| give_up(format!("Could not convert txt to dtm: {}", err)));
+            let problem = consistency::check_savestate(&transcoded.header, &uncompressed_name(&output_filename));
+            if let (Some(problem), false) = (problem, args.flag("allow-inconsistent")) {
+                give_up(format!("Refusing to write inconsistent movie: {} (pass --allow-inconsistent to write it anyway)", problem));
+            }
+            fs::rename(&staging, &output_filename)
+                .unwrap_or_else(|err| give_up(format!("Could not replace {}: {}", output_filename.display(), err)));
+            transcoded
+        }
+    };
+
+    print_warnings(&transcoded.warnings);
+    for warning in validate::check(&transcoded.header, transcoded.frames, transcoded.wiimote_frames) {
+        eprintln!("Warning: {}", warning);
+    }
+    if !is_stdio(&output_filename) {
+        println!("Successfully converted from {} to {}.", from, to);
+    }
+}
+
+// Everything convert does to a movie between reading and writing it.
+fn edit_movie(dtm: &mut Dtm, args: &Args) {
+    if let Some(port) = port_arg(args) {
+        *dtm = dtm.single_port(port).unwrap_or_barf("Could not take the port's inputs");
+    }
+    transform_movie(dtm, args);
+    if args.flag("fix-counts") {
+        dtm.recount();
+        dtm.estimate_timing();
+    }
+    set_header_fields(dtm, args);
+}
+
+fn convert(args: &Args, files: &[String]) {
+    let filename: PathBuf = files.first().unwrap_or_barf("convert needs an input file").into();
+    let output_opt = files.get(1).cloned().or_else(|| args.value("output").map(String::from));
+    if args.value("where").is_some() && args.values("transform").is_empty() {
+        barf("--where only limits --transform, and there isn't one");
+    }
+    if !args.flag("stream") {
+        if let Some(direction) = transcode_direction(&filename, output_opt.as_ref(), args) {
+            return convert_transcoded(&filename, output_opt, direction, args);
+        }
+    }
+    let file = open_input(&filename);
+    let to_stdout = output_opt.as_ref().map(|val| is_stdio(Path::new(val))).unwrap_or(is_stdio(&filename));
+    if args.flag("stream") {
+        return convert_stream(file, &filename, output_opt, args);
+    }
+
+    match input_format(&filename, args).as_str() {
+        input_format @ "dtm" | input_format @ "zip" => {
+            let (mut dtm_bin, warnings, savestate) = if input_format == "zip" {
+                let bundle = read_bundle(file);
+                let (dtm_bin, warnings) = dtm_decoder(&bundle.movie[..], args).decode_with_warnings().unwrap_or_barf("Could not make dtm decoder");
+                (dtm_bin, warnings, bundle.savestate)
+            }
+            else {
+                let (dtm_bin, warnings) = dtm_decoder(file, args).decode_with_warnings().unwrap_or_barf("Could not make dtm decoder");
+                (dtm_bin, warnings, None)
+            };
+            print_warnings(&warnings);
+            edit_movie(&mut dtm_bin, args);
+
+            // --format wins over the output extension, which wins over txt.
+            let format = args.value("format")
+                .or_else(|| output_opt.as_ref().and_then(|val| format_of(Path::new(val))))
+                .unwrap_or("txt")
+                .to_string();
+            let output_filename: PathBuf = output_opt
+                .map(|val| val.into())
+                .unwrap_or_else(|| default_output(&filename, &format, args));
+
+            // Comments kept by an earlier --keep-comments conversion.
+            let comments = File::open(comments::comments_path(&filename))
+                .ok()
+                .map(|comments_file| comments::load(BufReader::new(comments_file)).unwrap_or_barf("Could not read comments"))
+                .unwrap_or_default();
+
+            match format.as_str() {
+                "txt" => {
+                    let mut output_file = create_output(&output_filename, args.flag("force"));
+                    text_encoder(&mut output_file, args)
+                        .comments(comments)
+                        .encode(&dtm_bin)
+                        .unwrap_or_barf("Could not encode txt");
+                    output_file.finish();
+                    print_validation(&dtm_bin);
+                }
+                // dtm to dtm is only useful with --transform or --fix-counts.
+                "dtm" | "csv" | "zip" | "yaml" | "cbor" | "json" | "jsonl" => write_movie(&output_filename, &dtm_bin, args),
+                other if export::find(other).is_some() => write_movie(&output_filename, &dtm_bin, args),
+                _ => barf("Format must be txt, dtm, csv, zip, yaml, cbor, json, jsonl, bk2, overlay or srt."),
+            }
+
+            // A bundled savestate goes where the movie will be converted
+            // back to, so the two stay paired.
+            if let Some(savestate) = savestate {
+                if to_stdout {
+                    eprintln!("Warning: the savestate in the zip was not extracted, since it needs an output file to go next to");
+                }
+                else if format != "zip" {
+                    let savestate_filename = edit::savestate_path(bundled_dtm_name(&output_filename));
+                    check_overwrite(&savestate_filename, args.flag("force"));
+                    File::create(&savestate_filename)
+                        .and_then(|mut savestate_file| savestate_file.write_all(&savestate))
+                        .unwrap_or_barf("Could not write savestate");
+                    println!("Extracted the savestate to {}.", savestate_filename.display());
+                }
+            }
+
+            if !to_stdout {
+                println!("Successfully converted from {} to {}.", input_format, format)
+            }
+        }
+        "txt" => {
+            let output_filename: PathBuf = output_opt
+                .map(|val| val.into())
+                .unwrap_or_else(|| default_output(&filename, "dtm", args));
+
+            if args.flag("cache") {
+                if to_stdout {
+                    barf("--cache needs an output file");
+                }
+                if args.flag("keep-comments") {
+                    barf("--keep-comments can't be used with --cache");
+                }
+                if args.flag("strict") || args.flag("lenient") {
+                    barf("--strict and --lenient can't be used with --cache");
+                }
+                if extension(&output_filename) != "dtm" {
+                    barf("--cache can only write dtm files");
+                }
+                if args.value("skip").is_some() || args.value("take").is_some() || args.value("port").is_some() {
+                    barf("--skip, --take and --port can't be used with --cache");
+                }
+                if !args.values("transform").is_empty() || args.flag("fix-counts") || !args.values("set").is_empty() {
+                    barf("--transform, --fix-counts and --set can't be used with --cache");
+                }
+
+                // A missing or unreadable cache just means every block gets encoded.
+                let cache_filename = cache::cache_path(&output_filename);
+                let old_cache = File::open(&cache_filename)
+                    .ok()
+                    .and_then(|cache_file| BlockCache::load(BufReader::new(cache_file)).ok())
+                    .unwrap_or_default();
+
+                let (dtm_txt, new_cache) = cache::decode_text_cached(text_decoder(file, args), &old_cache).unwrap_or_barf("Could not make text decoder");
+                let mut encoded = Vec::new();
+                dtm_encoder(&mut encoded, args).encode(&dtm_txt).unwrap_or_barf("Could not encode dtm");
+                check_savestate(&output_filename, &dtm_txt.header, args);
+                check_overwrite(&output_filename, args.flag("force"));
+                // Nothing touches the old dtm until the new one is all there.
+                let staging = staging_path(&output_filename);
+                let written = fs::write(&staging, &encoded).and_then(|_| fs::rename(&staging, &output_filename));
+                if written.is_err() {
+                    let _ = fs::remove_file(&staging);
+                }
+                written.unwrap_or_barf("Could not write file");
+
+                let cache_file = BufWriter::new(File::create(cache_filename).unwrap_or_barf("Could not create cache file"));
+                new_cache.save(cache_file).unwrap_or_barf("Could not write cache");
+            }
+            else {
+                let decoder = text_decoder(file, args);
+                let decoded: DecodedText = decoder.decode_all().unwrap_or_barf("Could not make text decoder");
+                print_warnings(&decoded.warnings);
+                let (mut dtm_txt, comments) = (decoded.dtm, decoded.comments);
+                edit_movie(&mut dtm_txt, args);
+
+                if args.flag("keep-comments") {
+                    if to_stdout {
+                        barf("--keep-comments needs an output file");
+                    }
+                    let comments_file = BufWriter::new(File::create(comments::comments_path(&output_filename)).unwrap_or_barf("Could not create comments file"));
+                    comments::save(&comments, comments_file).unwrap_or_barf("Could not write comments");
+                }
+
+                write_movie(&output_filename, &dtm_txt, args);
+            }
+            if !to_stdout {
+                println!("Successfully converted from txt to {}.", format_of(&output_filename).unwrap_or("dtm"))
+            }
+        }
+        "csv" => {
+            let output_filename: PathBuf = output_opt
+                .map(|val| val.into())
+                .unwrap_or_else(|| default_output(&filename, "dtm", args));
+            let mut dtm_csv = windowed(csv_decoder(file, args).decode().unwrap_or_barf("Could not decode csv"), args);
+            edit_movie(&mut dtm_csv, args);
+            write_movie(&output_filename, &dtm_csv, args);
+            if !to_stdout {
+                println!("Successfully converted from csv to {}.", extension(&output_filename))
+            }
+        }
+        input_format @ "yaml" | input_format @ "cbor" | input_format @ "json"
+            | input_format @ "bk2" | input_format @ "fm2" | input_format @ "log" => {
+            let output_filename: PathBuf = output_opt
+                .map(|val| val.into())
+                .unwrap_or_else(|| default_output(&filename, "dtm", args));
+            let mut dtm = match input_format {
+                "yaml" => read_yaml(file, args),
+                "cbor" => read_cbor(file, args),
+                "json" => read_json(file, args),
+                _ => windowed(read_input_log(file, input_format, args), args),
+            };
+            edit_movie(&mut dtm, args);
+            write_movie(&output_filename, &dtm, args);
+            if !to_stdout {
+                println!("Successfully converted from {} to {}.", input_format, extension(&output_filename))
+            }
+        }
+        _ => barf("File must be a txt, csv, dtm, zip, yaml, cbor, json, bk2, fm2 or log."),
+    }
+}
+
+// csv files and input logs only hold inputs, so the header comes from
+// --header (a JSON file), or is Dolphin's defaults for the --game-id.
+// --header is a JSON object like a txt header, or a movie to take the header
+// of. A json movie is a JSON object too, with the header under "header".
+fn template_header(args: &Args) -> DtmHeader {
+    match args.value("header") {
+        Some(header_filename) => match extension(Path::new(header_filename)) {
+            format @ "dtm" | format @ "txt" | format @ "zip" | format @ "yaml" | format @ "cbor" => {
+                read_header_as(Path::new(header_filename), format, args)
+            }
+            _ => {
+                let header_file = BufReader::new(File::open(header_filename).unwrap_or_barf("Could not open header file"));
+                let mut value: serde_json::Value = serde_json::from_reader(header_file).unwrap_or_barf("Could not parse header file");
+                if let Some(header) = value.get_mut("header") {
+                    value = header.take();
+                }
+                serde_json::from_value(value).unwrap_or_barf("Could not parse header file")
+            }
+        },
+        None => DtmHeader::builder().game_id(args.value("game-id").unwrap_or_default()).build(),
+    }
+}
+
+// --columns Jump=a,Tilt=analog_x
+fn column_mappings(args: &Args) -> Vec<(&str, CsvColumn)> {
+    let columns = match args.value("columns") {
+        Some(columns) => columns,
+        None => return Vec::new(),
+    };
+    columns.split(',')
+        .map(|mapping| {
+            let mut parts = mapping.splitn(2, '=');
+            let name = parts.next().unwrap_or_default().trim();
+            let column = parts.next()
+                .and_then(|column| CsvColumn::from_name(column.trim()))
+                .unwrap_or_barf(&format!("Invalid column mapping '{}'", mapping));
+            (name, column)
+        })
+        .collect()
+}
+
+fn csv_decoder<R>(file: R, args: &Args) -> CsvDecoder<R>
+    where R: Read,
+{
+    let mut decoder = CsvDecoder::new(file).header(template_header(args));
+    for (name, column) in column_mappings(args) {
+        decoder = decoder.map_column(name, column);
+    }
+    decoder
+}
+
+// bk2 files are zips with the log in Input Log.txt; fm2 files and bare logs
+// are the log itself.
+fn read_input_log<R>(mut file: R, format: &str, args: &Args) -> Dtm
+    where R: Read,
+{
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).unwrap_or_barf("Could not read input log");
+    if format == "bk2" {
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap_or_barf("Could not read bk2");
+        let mut log = Vec::new();
+        archive.by_name("Input Log.txt")
+            .unwrap_or_barf("Could not find the input log in the bk2")
+            .read_to_end(&mut log)
+            .unwrap_or_barf("Could not read input log");
+        bytes = log;
+    }
+
+    let mut decoder = InputLogDecoder::new(&bytes[..]).header(template_header(args));
+    for (name, column) in column_mappings(args) {
+        decoder = decoder.map_column(name, column);
+    }
+    decoder.decode().unwrap_or_barf("Could not decode input log")
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// Modification time and length. A file whose stamp hasn't changed between
+// two polls is done being written.
+type Stamp = (SystemTime, u64);
+
+fn stamp(path: &Path) -> io::Result<Stamp> {
+    let metadata = fs::metadata(path)?;
+    Ok((metadata.modified()?, metadata.len()))
+}
+
+// Polls a directory for new or modified files with one extension. There's no
+// portable way to get notified of changes without extra dependencies, and a
+// movie only changes when Dolphin stops recording, so polling is plenty.
+pub struct Watcher {
+    dir: PathBuf,
+    extension: String,
+    handled: HashMap<PathBuf, Stamp>,
+    settling: HashMap<PathBuf, Stamp>,
+}
+
+impl Watcher {
+    pub fn new<P>(dir: P, extension: &str) -> Watcher
+        where P: Into<PathBuf>,
+    {
+        Watcher {
+            dir: dir.into(),
+            extension: extension.to_string(),
+            handled: HashMap::new(),
+            settling: HashMap::new(),
+        }
+    }
+
+    // Treats a file as already handled, so it's only picked up once it
+    // changes.
+    pub fn skip(&mut self, path: &Path) -> io::Result<()> {
+        let stamp = stamp(path)?;
+        self.handled.insert(path.to_path_buf(), stamp);
+        Ok(())
+    }
+
+    pub fn files(&self) -> io::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let matches = path.extension()
+                .map(|extension| extension.eq_ignore_ascii_case(self.extension.as_str()))
+                .unwrap_or(false);
+            if matches && path.is_file() {
+                files.push(path);
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+
+    // Returns the files that changed since they were last returned and have
+    // stopped changing since the previous poll.
+    pub fn poll(&mut self) -> io::Result<Vec<PathBuf>> {
+        let mut ready = Vec::new();
+        for path in self.files()? {
+            // The file may be gone again already.
+            let stamp = match stamp(&path) {
+                Ok(stamp) => stamp,
+                Err(_) => continue,
+            };
+            if self.handled.get(&path) == Some(&stamp) {
+                continue;
+            }
+
+            if self.settling.get(&path) == Some(&stamp) {
+                self.settling.remove(&path);
+                self.handled.insert(path.clone(), stamp);
+                ready.push(path);
+            }
+            else {
+                self.settling.insert(path, stamp);
+            }
+        }
+        Ok(ready)
+    }
+}
@@ -0,0 +1,101 @@
+use dtm::{DtmHeader, CpuCore, Md5};
+
+macro_rules! setters {
+    ($($field:ident: $type:ty),* $(,)*) => {
+        $(
+            pub fn $field(mut self, $field: $type) -> DtmHeaderBuilder {
+                self.header.$field = $field;
+                self
+            }
+        )*
+    };
+}
+
+// Builds a header starting from Dolphin's defaults for a fresh recording.
+// Counts like `input_count` are left alone since the encoder and decoders
+// take them from the inputs.
+//
+//     let header = DtmHeader::builder().game_id("GALE01").author("me").build();
+pub struct DtmHeaderBuilder {
+    header: DtmHeader,
+}
+
+impl DtmHeader {
+    pub fn builder() -> DtmHeaderBuilder {
+        DtmHeaderBuilder {
+            header: DtmHeader::default(),
+        }
+    }
+}
+
+impl DtmHeaderBuilder {
+    pub fn game_id(mut self, game_id: &str) -> DtmHeaderBuilder {
+        self.header.game_id = game_id.to_string();
+        self.header.game_id_raw = None;
+        self
+    }
+
+    pub fn author(mut self, author: &str) -> DtmHeaderBuilder {
+        self.header.author = author.to_string();
+        self.header.author_raw = None;
+        self
+    }
+
+    pub fn video_backend(mut self, video_backend: &str) -> DtmHeaderBuilder {
+        self.header.video_backend = video_backend.to_string();
+        self.header.video_backend_raw = None;
+        self
+    }
+
+    pub fn second_disc(mut self, second_disc: &str) -> DtmHeaderBuilder {
+        self.header.second_disc = second_disc.to_string();
+        self.header.second_disc_raw = None;
+        self
+    }
+
+    // Connects GameCube controllers to the given ports (0 to 3) and
+    // disconnects the rest.
+    pub fn gamecube_ports(mut self, ports: &[u8]) -> DtmHeaderBuilder {
+        self.header.controllers &= 0xF0;
+        for &port in ports.iter().filter(|&&port| port < 4) {
+            self.header.controllers |= 1 << port;
+        }
+        self
+    }
+
+    // Connects Wii remotes to the given slots (0 to 3) and disconnects the
+    // rest. Also marks the movie as a Wii game.
+    pub fn wiimotes(mut self, slots: &[u8]) -> DtmHeaderBuilder {
+        self.header.controllers &= 0x0F;
+        for &slot in slots.iter().filter(|&&slot| slot < 4) {
+            self.header.controllers |= 0x10 << slot;
+        }
+        self.header.wii_game = true;
+        self
+    }
+
+    setters! {
+        wii_game: bool,
+        controllers: u8,
+        savestate: bool,
+        rerecord_count: u32,
+        md5: Md5,
+        start_time: u64,
+        idle_skipping: bool,
+        dual_core: bool,
+        progressive_scan: bool,
+        dsp_hle: bool,
+        fast_disc: bool,
+        cpu_core: CpuCore,
+        memory_cards: u8,
+        memory_card_blank: bool,
+        bongos_plugged: u8,
+        sync_gpu: bool,
+        netplay: bool,
+        sysconf_pal60: bool,
+    }
+
+    pub fn build(self) -> DtmHeader {
+        self.header
+    }
+}
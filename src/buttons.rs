@@ -0,0 +1,155 @@
+use std::fmt;
+use std::ops::{BitAnd, BitOr, BitXor, Not};
+
+use dtm::{ControllerInput, Button};
+
+// The sixteen buttons of a GameCube input as a set of bit flags, laid out
+// exactly like the first two bytes of a dtm input.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Buttons(u16);
+
+impl Buttons {
+    pub const START: Buttons = Buttons(0x0001);
+    pub const A: Buttons = Buttons(0x0002);
+    pub const B: Buttons = Buttons(0x0004);
+    pub const X: Buttons = Buttons(0x0008);
+    pub const Y: Buttons = Buttons(0x0010);
+    pub const Z: Buttons = Buttons(0x0020);
+    pub const UP: Buttons = Buttons(0x0040);
+    pub const DOWN: Buttons = Buttons(0x0080);
+    pub const LEFT: Buttons = Buttons(0x0100);
+    pub const RIGHT: Buttons = Buttons(0x0200);
+    pub const L: Buttons = Buttons(0x0400);
+    pub const R: Buttons = Buttons(0x0800);
+    pub const CHANGE_DISC: Buttons = Buttons(0x1000);
+    pub const RESET: Buttons = Buttons(0x2000);
+    pub const CONTROLLER_CONNECTED: Buttons = Buttons(0x4000);
+    pub const RESERVED: Buttons = Buttons(0x8000);
+
+    pub fn empty() -> Buttons {
+        Buttons(0)
+    }
+
+    pub fn all() -> Buttons {
+        Buttons(0xFFFF)
+    }
+
+    pub fn from_bits(bits: u16) -> Buttons {
+        Buttons(bits)
+    }
+
+    pub fn bits(self) -> u16 {
+        self.0
+    }
+
+    pub fn from_bytes(bytes: [u8; 2]) -> Buttons {
+        Buttons(u16::from_le_bytes(bytes))
+    }
+
+    pub fn to_bytes(self) -> [u8; 2] {
+        self.0.to_le_bytes()
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(self, other: Buttons) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn intersects(self, other: Buttons) -> bool {
+        self.0 & other.0 != 0
+    }
+
+    pub fn insert(&mut self, other: Buttons) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Buttons) {
+        self.0 &= !other.0;
+    }
+
+    pub fn toggle(&mut self, other: Buttons) {
+        self.0 ^= other.0;
+    }
+
+    pub fn set(&mut self, other: Buttons, pressed: bool) {
+        if pressed {
+            self.insert(other);
+        }
+        else {
+            self.remove(other);
+        }
+    }
+
+    pub fn iter(self) -> impl Iterator<Item = Button> {
+        Button::ALL.iter()
+            .cloned()
+            .filter(move |&button| self.contains(button.into()))
+    }
+}
+
+impl From<Button> for Buttons {
+    fn from(button: Button) -> Buttons {
+        // Button::ALL is in bit order.
+        let bit = Button::ALL.iter().position(|&other| other == button).unwrap_or(0);
+        Buttons(1 << bit)
+    }
+}
+
+impl BitOr for Buttons {
+    type Output = Buttons;
+
+    fn bitor(self, other: Buttons) -> Buttons {
+        Buttons(self.0 | other.0)
+    }
+}
+
+impl BitAnd for Buttons {
+    type Output = Buttons;
+
+    fn bitand(self, other: Buttons) -> Buttons {
+        Buttons(self.0 & other.0)
+    }
+}
+
+impl BitXor for Buttons {
+    type Output = Buttons;
+
+    fn bitxor(self, other: Buttons) -> Buttons {
+        Buttons(self.0 ^ other.0)
+    }
+}
+
+impl Not for Buttons {
+    type Output = Buttons;
+
+    fn not(self) -> Buttons {
+        Buttons(!self.0)
+    }
+}
+
+// Buttons(A | RT)
+impl fmt::Debug for Buttons {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<_> = self.iter().map(|button| button.name()).collect();
+        write!(f, "Buttons({})", names.join(" | "))
+    }
+}
+
+impl ControllerInput {
+    pub fn buttons(&self) -> Buttons {
+        let mut buttons = Buttons::empty();
+        for &button in Button::ALL.iter() {
+            buttons.set(button.into(), self.pressed(button));
+        }
+        buttons
+    }
+
+    pub fn set_buttons(&mut self, buttons: Buttons) {
+        for &button in Button::ALL.iter() {
+            self.set_pressed(button, buttons.contains(button.into()));
+        }
+    }
+}
@@ -0,0 +1,427 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hasher;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+
+use decoder::Inputs;
+use decoder::text_decoder::{TextDecoder, InputReader, InputLine, InputRecord, Directive, LineSource};
+use dtm::{Dtm, ControllerInput, WiimoteInput, CONTROLLER_RECORD_SIZE};
+use error::{Dtm2txtError, Dtm2txtResult};
+use hash::Fnv1a;
+
+const CACHE_MAGIC: &[u8; 4] = b"DTMC";
+const CACHE_VERSION: u8 = 4;
+
+// A block ends after a line whose hash has this many top bits clear, so
+// blocks average about a thousand lines. Going by the lines themselves rather
+// than counting them means inserting or deleting lines only changes the
+// blocks they're in, and every later block is still found in the cache.
+const BOUNDARY_BITS: u32 = 10;
+const MIN_BLOCK_LINES: usize = 64;
+// Long runs of the same line never hit a boundary on their own.
+const MAX_BLOCK_LINES: usize = 8192;
+
+// Maps the hash of a block of txt frame lines to those lines already parsed.
+// Reconverting a txt file with a cache from the previous run only parses the
+// blocks that were edited since.
+pub struct BlockCache {
+    blocks: HashMap<u64, CachedBlock>,
+}
+
+#[derive(Clone)]
+struct CachedBlock {
+    lines: u64,
+    bytes: Vec<u8>,
+}
+
+impl BlockCache {
+    pub fn new() -> BlockCache {
+        BlockCache {
+            blocks: HashMap::new(),
+        }
+    }
+
+    pub fn load<R>(mut reader: R) -> Dtm2txtResult<BlockCache>
+        where R: Read,
+    {
+        let mut magic_buffer = [0; 4];
+        reader.read_exact(&mut magic_buffer)?;
+        if magic_buffer != *CACHE_MAGIC || reader.read_u8()? != CACHE_VERSION {
+            return Err(Dtm2txtError::BadMagicError);
+        }
+
+        let block_count = reader.read_u64::<LE>()?;
+        let mut blocks = HashMap::new();
+        for _ in 0..block_count {
+            let hash = reader.read_u64::<LE>()?;
+            let lines = reader.read_u64::<LE>()?;
+            let len = reader.read_u32::<LE>()?;
+            let mut bytes = Vec::new();
+            reader.by_ref().take(len as u64).read_to_end(&mut bytes)?;
+            blocks.insert(hash, CachedBlock {
+                lines: lines,
+                bytes: bytes,
+            });
+        }
+
+        Ok(BlockCache {
+            blocks: blocks,
+        })
+    }
+
+    pub fn save<W>(&self, mut writer: W) -> Dtm2txtResult<()>
+        where W: Write,
+    {
+        writer.write_all(CACHE_MAGIC)?;
+        writer.write_u8(CACHE_VERSION)?;
+        writer.write_u64::<LE>(self.blocks.len() as u64)?;
+        for (hash, block) in self.blocks.iter() {
+            writer.write_u64::<LE>(*hash)?;
+            writer.write_u64::<LE>(block.lines)?;
+            writer.write_u32::<LE>(block.bytes.len() as u32)?;
+            writer.write_all(&block.bytes)?;
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
+
+impl Default for BlockCache {
+    fn default() -> BlockCache {
+        BlockCache::new()
+    }
+}
+
+// The cache for an output file lives next to it, e.g. "movie.dtm.cache".
+pub fn cache_path<P>(output_path: P) -> PathBuf
+    where P: AsRef<Path>,
+{
+    let mut path = output_path.as_ref().as_os_str().to_owned();
+    path.push(".cache");
+    path.into()
+}
+
+// Decodes a txt movie, reusing the parsed lines of blocks from `cache` where
+// the txt lines are unchanged. Everything else, from `!def` and `!loop` to
+// the port label checks, works the same as without a cache. Returns the
+// movie along with a cache holding exactly the blocks of this file, so stale
+// blocks don't pile up across runs.
+pub fn decode_text_cached<R>(decoder: TextDecoder<R>, cache: &BlockCache) -> Dtm2txtResult<(Dtm, BlockCache)>
+    where R: Read,
+{
+    let mut new_cache = BlockCache::new();
+    let mut inputs = Inputs::<Vec<ControllerInput>>::default();
+    let decoded = decoder.decode_inputs_with(&mut inputs, |lines| CachedLines {
+        lines: lines,
+        cache: cache,
+        new_cache: &mut new_cache,
+        pending: VecDeque::new(),
+    })?;
+
+    let dtm = Dtm {
+        header: decoded.header,
+        controller_data: inputs.controller_data,
+        wiimote_data: inputs.wiimote_data,
+        trailing: decoded.trailing,
+    };
+    Ok((dtm, new_cache))
+}
+
+// Parses a block of lines at a time, or takes them from the cache.
+struct CachedLines<'a, I> {
+    lines: I,
+    cache: &'a BlockCache,
+    new_cache: &'a mut BlockCache,
+    // The rest of the current block, with the line number each came from.
+    pending: VecDeque<(u64, Dtm2txtResult<InputLine>)>,
+}
+
+impl<'a, I> CachedLines<'a, I>
+    where I: Iterator<Item = io::Result<String>>,
+{
+    fn next_block(&mut self) -> Vec<io::Result<String>> {
+        let mut block = Vec::new();
+        while block.len() < MAX_BLOCK_LINES {
+            let line = match self.lines.next() {
+                Some(line) => line,
+                None => break,
+            };
+            let boundary = match line {
+                Ok(ref text) => Fnv1a::hash_bytes(text.as_bytes()) >> (64 - BOUNDARY_BITS) == 0,
+                Err(_) => true,
+            };
+            block.push(line);
+            if boundary && block.len() >= MIN_BLOCK_LINES {
+                break;
+            }
+        }
+        block
+    }
+
+    fn fill(&mut self, reader: &mut InputReader) {
+        let block = self.next_block();
+        // Blocks that couldn't be read in full aren't cached.
+        let hash = block.iter()
+            .map(|line| line.as_ref().ok())
+            .collect::<Option<Vec<_>>>()
+            .map(|text_lines| {
+                let mut hasher = Fnv1a::new();
+                for line in text_lines {
+                    hasher.write(line.as_bytes());
+                    hasher.write(b"\n");
+                }
+                hasher.finish()
+            });
+
+        let start = reader.line;
+        if let Some((hash, cached)) = hash.and_then(|hash| self.cache.blocks.get(&hash).map(|cached| (hash, cached))) {
+            // A cache that doesn't read back is as good as a miss.
+            if let Ok(lines) = read_cached_lines(&cached.bytes, cached.lines) {
+                if lines.len() == block.len() {
+                    for (idx, line) in lines.into_iter().enumerate() {
+                        self.pending.push_back((start + idx as u64, Ok(line)));
+                    }
+                    reader.skip_lines(block.len() as u64);
+                    self.new_cache.blocks.insert(hash, cached.clone());
+                    return;
+                }
+            }
+        }
+
+        let lines = block.len() as u64;
+        let mut bytes = Vec::new();
+        let mut parsed = true;
+        for line in block {
+            let line_number = reader.line;
+            match reader.read_line(line) {
+                Ok(input_line) => {
+                    write_cached_line(&mut bytes, &input_line);
+                    self.pending.push_back((line_number, Ok(input_line)));
+                }
+                Err(err) => {
+                    parsed = false;
+                    self.pending.push_back((line_number, Err(err)));
+                    reader.skip_lines(1);
+                }
+            }
+        }
+        if let (Some(hash), true) = (hash, parsed) {
+            self.new_cache.blocks.insert(hash, CachedBlock {
+                lines: lines,
+                bytes: bytes,
+            });
+        }
+    }
+}
+
+impl<'a, I> LineSource for CachedLines<'a, I>
+    where I: Iterator<Item = io::Result<String>>,
+{
+    fn next_line(&mut self, reader: &mut InputReader) -> Option<Dtm2txtResult<InputLine>> {
+        if self.pending.is_empty() {
+            self.fill(reader);
+        }
+        let (line, input_line) = self.pending.pop_front()?;
+        // Like reading one line at a time: past a line that was read, and on
+        // a line that wasn't.
+        reader.line = if input_line.is_ok() { line + 1 } else { line };
+        Some(input_line)
+    }
+}
+
+const LINE_EMPTY: u8 = 0;
+const LINE_CONTROLLER: u8 = 1;
+const LINE_PORTS: u8 = 2;
+const LINE_WIIMOTE: u8 = 3;
+const LINE_DEFINE: u8 = 4;
+const LINE_LOOP: u8 = 5;
+const LINE_END: u8 = 6;
+const LINE_CALL: u8 = 7;
+const NO_PORT: u8 = 0xFF;
+
+fn write_cached_str(bytes: &mut Vec<u8>, text: &str) {
+    bytes.extend_from_slice(&(text.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(text.as_bytes());
+}
+
+// A parsed line as a kind byte and what goes with it, then the port label,
+// the repeat count and the comment.
+fn write_cached_line(bytes: &mut Vec<u8>, line: &InputLine) {
+    match (&line.record, &line.directive) {
+        (&Some(InputRecord::Controller(ref input)), _) => {
+            bytes.push(LINE_CONTROLLER);
+            bytes.extend_from_slice(&input.to_bytes());
+        }
+        (&Some(InputRecord::Ports(ref inputs)), _) => {
+            bytes.push(LINE_PORTS);
+            bytes.push(inputs.len() as u8);
+            for input in inputs.iter() {
+                bytes.extend_from_slice(&input.to_bytes());
+            }
+        }
+        (&Some(InputRecord::Wiimote(ref input)), _) => {
+            bytes.push(LINE_WIIMOTE);
+            bytes.extend_from_slice(&(input.report.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&input.report);
+        }
+        (&None, &Some(Directive::Define(ref name))) => {
+            bytes.push(LINE_DEFINE);
+            write_cached_str(bytes, name);
+        }
+        (&None, &Some(Directive::Loop(times))) => {
+            bytes.push(LINE_LOOP);
+            bytes.extend_from_slice(&times.to_le_bytes());
+        }
+        (&None, &Some(Directive::End)) => bytes.push(LINE_END),
+        (&None, &Some(Directive::Call(ref name, times))) => {
+            bytes.push(LINE_CALL);
+            write_cached_str(bytes, name);
+            bytes.extend_from_slice(&times.to_le_bytes());
+        }
+        (&None, &None) => bytes.push(LINE_EMPTY),
+    }
+    bytes.push(line.port.unwrap_or(NO_PORT));
+    bytes.extend_from_slice(&line.repeat.to_le_bytes());
+    match line.comment {
+        Some(ref comment) => {
+            bytes.push(1);
+            write_cached_str(bytes, comment);
+        }
+        None => bytes.push(0),
+    }
+}
+
+fn read_cached_bytes<R>(reader: &mut R, len: usize) -> Dtm2txtResult<Vec<u8>>
+    where R: Read,
+{
+    let mut bytes = Vec::new();
+    reader.by_ref().take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(Dtm2txtError::IoError(io::ErrorKind::UnexpectedEof.into()));
+    }
+    Ok(bytes)
+}
+
+fn read_cached_str<R>(reader: &mut R) -> Dtm2txtResult<String>
+    where R: Read,
+{
+    let len = reader.read_u32::<LE>()?;
+    Ok(String::from_utf8(read_cached_bytes(reader, len as usize)?)?)
+}
+
+fn read_cached_input<R>(reader: &mut R) -> Dtm2txtResult<ControllerInput>
+    where R: Read,
+{
+    let mut record = [0; CONTROLLER_RECORD_SIZE];
+    reader.read_exact(&mut record)?;
+    Ok(ControllerInput::from_bytes(&record))
+}
+
+fn read_cached_lines(mut bytes: &[u8], count: u64) -> Dtm2txtResult<Vec<InputLine>> {
+    let reader = &mut bytes;
+    let mut lines = Vec::new();
+    for _ in 0..count {
+        let (record, directive) = match reader.read_u8()? {
+            LINE_EMPTY => (None, None),
+            LINE_CONTROLLER => (Some(InputRecord::Controller(read_cached_input(reader)?)), None),
+            LINE_PORTS => {
+                let ports = reader.read_u8()?;
+                let inputs = (0..ports)
+                    .map(|_| read_cached_input(reader))
+                    .collect::<Dtm2txtResult<Vec<_>>>()?;
+                (Some(InputRecord::Ports(inputs)), None)
+            }
+            LINE_WIIMOTE => {
+                let len = reader.read_u32::<LE>()?;
+                let report = read_cached_bytes(reader, len as usize)?;
+                (Some(InputRecord::Wiimote(WiimoteInput { report: report })), None)
+            }
+            LINE_DEFINE => (None, Some(Directive::Define(read_cached_str(reader)?))),
+            LINE_LOOP => (None, Some(Directive::Loop(reader.read_u64::<LE>()?))),
+            LINE_END => (None, Some(Directive::End)),
+            LINE_CALL => {
+                let name = read_cached_str(reader)?;
+                (None, Some(Directive::Call(name, reader.read_u64::<LE>()?)))
+            }
+            _ => return Err(Dtm2txtError::BadMagicError),
+        };
+        let port = match reader.read_u8()? {
+            NO_PORT => None,
+            port => Some(port),
+        };
+        let repeat = reader.read_u64::<LE>()?;
+        let comment = match reader.read_u8()? {
+            0 => None,
+            _ => Some(read_cached_str(reader)?),
+        };
+        lines.push(InputLine {
+            record: record,
+            directive: directive,
+            port: port,
+            repeat: repeat,
+            comment: comment,
+        });
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dtm::DtmHeader;
+
+    fn frame_lines(count: u64) -> String {
+        (0..count)
+            .map(|frame| format!("S A B X Y Z U D L R LT RT 0 0 {} {} 128 128\n", frame % 256, frame / 256 % 256))
+            .collect()
+    }
+
+    fn decode(text: &str, cache: &BlockCache) -> Dtm2txtResult<(Dtm, BlockCache)> {
+        decode_text_cached(TextDecoder::new(text.as_bytes()).headerless(DtmHeader::default()), cache)
+    }
+
+    #[test]
+    fn matches_uncached_decoding() {
+        let text = format!("!def idle {{\ns a b x y z u d l r lt rt 0 0 128 128 128 128 * 3\n}}\n{}!idle * 2\n", frame_lines(5000));
+        let expected = TextDecoder::new(text.as_bytes()).headerless(DtmHeader::default()).decode().unwrap();
+
+        let (first, cache) = decode(&text, &BlockCache::new()).unwrap();
+        assert_eq!(first, expected);
+        let (second, _) = decode(&text, &cache).unwrap();
+        assert_eq!(second, expected);
+    }
+
+    #[test]
+    fn later_blocks_survive_an_insertion() {
+        let text = frame_lines(20000);
+        let (_, cache) = decode(&text, &BlockCache::new()).unwrap();
+        assert!(cache.len() > 4);
+
+        let edited = format!("s a b x y z u d l r lt rt 0 0 128 128 128 128\n{}", text);
+        let (dtm, new_cache) = decode(&edited, &cache).unwrap();
+        assert_eq!(dtm.controller_data.len(), 20001);
+        let reused = new_cache.blocks.keys().filter(|hash| cache.blocks.contains_key(hash)).count();
+        assert!(reused + 1 >= new_cache.len(), "only {} of {} blocks reused", reused, new_cache.len());
+    }
+
+    #[test]
+    fn cached_lines_report_errors_on_their_line() {
+        let text = frame_lines(300);
+        let (_, cache) = decode(&text, &BlockCache::new()).unwrap();
+
+        let broken = format!("{}P2: s a b x y z u d l r lt rt 0 0 128 128 128 128\n", text);
+        match decode(&broken, &cache) {
+            Err(Dtm2txtError::ControllerInputParseError{line, ..}) => assert_eq!(line, 301),
+            other => panic!("decoded {:?}", other.map(|_| ())),
+        }
+    }
+}
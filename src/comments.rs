@@ -0,0 +1,39 @@
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json;
+
+use error::Dtm2txtResult;
+
+// A `#` comment from the frame section of a txt file. dtm files have nowhere
+// to keep these, so they can be saved to a sidecar file next to the dtm and
+// put back when it's converted to txt again.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Comment {
+    // A comment on its own line comes before this input; a trailing one is
+    // on the same line as it.
+    pub frame: u64,
+    pub trailing: bool,
+    pub text: String,
+}
+
+// The sidecar for "movie.dtm" is "movie.dtm.comments".
+pub fn comments_path<P>(movie_path: P) -> PathBuf
+    where P: AsRef<Path>,
+{
+    let mut path = movie_path.as_ref().as_os_str().to_owned();
+    path.push(".comments");
+    path.into()
+}
+
+pub fn load<R>(reader: R) -> Dtm2txtResult<Vec<Comment>>
+    where R: Read,
+{
+    Ok(serde_json::from_reader(reader)?)
+}
+
+pub fn save<W>(comments: &[Comment], writer: W) -> Dtm2txtResult<()>
+    where W: Write,
+{
+    Ok(serde_json::to_writer_pretty(writer, comments)?)
+}
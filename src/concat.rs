@@ -0,0 +1,135 @@
+use std::fmt;
+
+use dtm::{Dtm, DtmHeader};
+use error::{Dtm2txtError, Dtm2txtResult};
+use frames::FrameStore;
+
+// Ways two movies can disagree when one is appended to the other. The first
+// four make the result unplayable; settings differences only risk a desync.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConcatProblem {
+    DifferentGame {
+        first: String,
+        second: String,
+    },
+    SecondStartsFromSavestate,
+    DifferentControllers {
+        first: u8,
+        second: u8,
+    },
+    DifferentConsole,
+    DifferentSetting(&'static str),
+}
+
+impl ConcatProblem {
+    pub fn is_fatal(&self) -> bool {
+        !matches!(*self, ConcatProblem::DifferentSetting(_))
+    }
+}
+
+impl fmt::Display for ConcatProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConcatProblem::DifferentGame{ref first, ref second} =>
+                write!(f, "movies are for different games ({} and {})", first, second),
+            ConcatProblem::SecondStartsFromSavestate =>
+                f.write_str("second movie starts from a savestate"),
+            ConcatProblem::DifferentControllers{first, second} =>
+                write!(f, "movies use different controllers (0x{:02X} and 0x{:02X})", first, second),
+            ConcatProblem::DifferentConsole =>
+                f.write_str("one movie is a Wii game and the other isn't"),
+            ConcatProblem::DifferentSetting(setting) =>
+                write!(f, "movies were recorded with different {} settings", setting),
+        }
+    }
+}
+
+// Emulation settings that change how a game runs, so inputs recorded with one
+// value may desync with another.
+fn different_settings(first: &DtmHeader, second: &DtmHeader) -> Vec<&'static str> {
+    let settings = [
+        ("idle_skipping", first.idle_skipping == second.idle_skipping),
+        ("dual_core", first.dual_core == second.dual_core),
+        ("progressive_scan", first.progressive_scan == second.progressive_scan),
+        ("dsp_hle", first.dsp_hle == second.dsp_hle),
+        ("fast_disc", first.fast_disc == second.fast_disc),
+        ("cpu_core", first.cpu_core == second.cpu_core),
+        ("efb_access", first.efb_access == second.efb_access),
+        ("efb_copy", first.efb_copy == second.efb_copy),
+        ("efb_to_texture", first.efb_to_texture == second.efb_to_texture),
+        ("sync_gpu", first.sync_gpu == second.sync_gpu),
+        ("memory_cards", first.memory_cards == second.memory_cards),
+        ("sysconf_pal60", first.sysconf_pal60 == second.sysconf_pal60),
+    ];
+    settings.iter()
+        .filter(|&&(_, same)| !same)
+        .map(|&(setting, _)| setting)
+        .collect()
+}
+
+impl<S> Dtm<S>
+    where S: FrameStore + Clone,
+{
+    // Everything that doesn't line up between this movie and `other`.
+    pub fn concat_problems(&self, other: &Dtm<S>) -> Vec<ConcatProblem> {
+        let first = &self.header;
+        let second = &other.header;
+
+        let mut problems = Vec::new();
+        if first.game_id != second.game_id {
+            problems.push(ConcatProblem::DifferentGame {
+                first: first.game_id.clone(),
+                second: second.game_id.clone(),
+            });
+        }
+        if second.savestate {
+            problems.push(ConcatProblem::SecondStartsFromSavestate);
+        }
+        if first.controllers != second.controllers {
+            problems.push(ConcatProblem::DifferentControllers {
+                first: first.controllers,
+                second: second.controllers,
+            });
+        }
+        if first.wii_game != second.wii_game {
+            problems.push(ConcatProblem::DifferentConsole);
+        }
+        problems.extend(different_settings(first, second).into_iter().map(ConcatProblem::DifferentSetting));
+
+        problems
+    }
+
+    // Appends `other`'s inputs to this movie's. The counters in the header are
+    // summed; everything else comes from this movie. Refuses movies that
+    // can't be played back as one (see `concat_problems`), but not ones that
+    // merely differ in settings.
+    pub fn concat(&self, other: &Dtm<S>) -> Dtm2txtResult<Dtm<S>> {
+        let fatal: Vec<_> = self.concat_problems(other)
+            .into_iter()
+            .filter(ConcatProblem::is_fatal)
+            .collect();
+        if !fatal.is_empty() {
+            return Err(Dtm2txtError::IncompatibleMoviesError(fatal));
+        }
+
+        let mut controller_data = self.controller_data.clone();
+        controller_data.append_from(&other.controller_data);
+        let mut wiimote_data = self.wiimote_data.clone();
+        wiimote_data.extend_from_slice(&other.wiimote_data);
+
+        let mut header = self.header.clone();
+        header.input_count = controller_data.len() + wiimote_data.len() as u64;
+        header.vi_count = self.header.vi_count.saturating_add(other.header.vi_count);
+        header.tick_count = self.header.tick_count.saturating_add(other.header.tick_count);
+        header.lag_counter = self.header.lag_counter.saturating_add(other.header.lag_counter);
+        header.rerecord_count = self.header.rerecord_count.saturating_add(other.header.rerecord_count);
+
+        Ok(Dtm {
+            header: header,
+            controller_data: controller_data,
+            wiimote_data: wiimote_data,
+            // Whatever followed the inputs still ends the file.
+            trailing: other.trailing.clone(),
+        })
+    }
+}
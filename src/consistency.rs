@@ -0,0 +1,103 @@
+use std::fmt;
+use std::path::Path;
+
+use dtm::DtmHeader;
+use edit::savestate_path;
+
+// Ways a header can disagree with the data it is written with. Dolphin trusts
+// the header, so any of these make for a movie that desyncs or won't load.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Inconsistency {
+    InputCountMismatch {
+        header: u64,
+        frames: u64,
+    },
+    NoControllers,
+    UnexpectedControllerData,
+    UnexpectedWiimoteData,
+    WiimotesOnGameCube,
+    UnevenPortData {
+        ports: u32,
+        frames: u64,
+    },
+    MissingSavestate,
+}
+
+impl fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Inconsistency::InputCountMismatch{header, frames} =>
+                write!(f, "header says {} inputs but there are {}", header, frames),
+            Inconsistency::NoControllers => f.write_str("inputs present but no controllers are connected"),
+            Inconsistency::UnexpectedControllerData => f.write_str("GameCube inputs present but no GameCube controllers are connected"),
+            Inconsistency::UnexpectedWiimoteData => f.write_str("Wii remote inputs present but no Wii remotes are connected"),
+            Inconsistency::WiimotesOnGameCube => f.write_str("Wii remotes connected in a GameCube movie"),
+            Inconsistency::UnevenPortData{ports, frames} =>
+                write!(f, "{} inputs can't be split evenly between {} controller ports", frames, ports),
+            Inconsistency::MissingSavestate => f.write_str("movie starts from a savestate but no .sav file is next to it"),
+        }
+    }
+}
+
+impl DtmHeader {
+    pub fn gamecube_port_count(&self) -> u32 {
+        (self.controllers & 0x0F).count_ones()
+    }
+
+    pub fn wiimote_count(&self) -> u32 {
+        (self.controllers & 0xF0).count_ones()
+    }
+
+    // Whether the inputs after this header are Wii remote reports rather
+    // than GameCube controller inputs. Wii remotes only count in a Wii game.
+    pub fn has_wiimote_inputs(&self) -> bool {
+        self.wii_game && self.wiimote_count() != 0
+    }
+}
+
+// `frames` counts GameCube controller inputs and `wiimote_frames` Wii remote
+// reports; Dolphin counts both towards input_count.
+pub fn check(header: &DtmHeader, frames: u64, wiimote_frames: u64) -> Vec<Inconsistency> {
+    let mut problems = Vec::new();
+
+    if header.input_count != frames + wiimote_frames {
+        problems.push(Inconsistency::InputCountMismatch {
+            header: header.input_count,
+            frames: frames + wiimote_frames,
+        });
+    }
+    if header.controllers == 0 && frames + wiimote_frames != 0 {
+        problems.push(Inconsistency::NoControllers);
+    }
+    if frames != 0 && header.controllers != 0 && header.gamecube_port_count() == 0 {
+        problems.push(Inconsistency::UnexpectedControllerData);
+    }
+    if wiimote_frames != 0 && header.controllers != 0 && header.wiimote_count() == 0 {
+        problems.push(Inconsistency::UnexpectedWiimoteData);
+    }
+    if !header.wii_game && header.wiimote_count() != 0 {
+        problems.push(Inconsistency::WiimotesOnGameCube);
+    }
+
+    // GameCube inputs are interleaved one per connected port each poll.
+    let ports = header.gamecube_port_count();
+    if ports > 1 && header.wiimote_count() == 0 && frames % ports as u64 != 0 {
+        problems.push(Inconsistency::UnevenPortData {
+            ports: ports,
+            frames: frames,
+        });
+    }
+
+    problems
+}
+
+// Dolphin loads the savestate for a savestate-anchored movie from next to the
+// movie file, so this can only be checked once the output path is known.
+pub fn check_savestate(header: &DtmHeader, movie_path: &Path) -> Option<Inconsistency> {
+    if header.savestate && !savestate_path(movie_path).exists() {
+        Some(Inconsistency::MissingSavestate)
+    }
+    else {
+        None
+    }
+}
@@ -0,0 +1,88 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::{AsyncRead, ReadBuf};
+
+use decoder::dtm_decoder::DtmDecoder;
+use decoder::options::{DecodeOptions, DecodeWarning};
+use dtm::Dtm;
+use error::Dtm2txtResult;
+
+// How much is asked of the stream at a time.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// Reads a dtm from an `AsyncRead` without blocking the runtime, for services
+// that get movies over the network. The bytes are collected as they arrive
+// and parsed once the stream ends; parsing takes a fraction of the time the
+// transfer does, and this way it works exactly like `DtmDecoder`.
+pub struct AsyncDtmDecoder<R> {
+    inner: R,
+    options: DecodeOptions,
+}
+
+impl<R> AsyncDtmDecoder<R>
+    where R: AsyncRead + Unpin,
+{
+    pub fn new(inner: R) -> AsyncDtmDecoder<R> {
+        AsyncDtmDecoder {
+            inner: inner,
+            options: DecodeOptions::default(),
+        }
+    }
+
+    pub fn options(mut self, options: DecodeOptions) -> AsyncDtmDecoder<R> {
+        self.options = options;
+        self
+    }
+
+    pub fn decode(self) -> Decode<R, Dtm> {
+        self.decode_with(|decoder| decoder.decode())
+    }
+
+    // Also returns what a lenient decode had to recover from.
+    pub fn decode_with_warnings(self) -> Decode<R, (Dtm, Vec<DecodeWarning>)> {
+        self.decode_with(|decoder| decoder.decode_with_warnings())
+    }
+
+    fn decode_with<T>(self, parse: fn(DtmDecoder<&[u8]>) -> Dtm2txtResult<T>) -> Decode<R, T> {
+        Decode {
+            inner: self.inner,
+            options: self.options,
+            bytes: Vec::new(),
+            chunk: vec![0; CHUNK_SIZE],
+            parse: parse,
+        }
+    }
+}
+
+// The future returned by `AsyncDtmDecoder::decode`.
+pub struct Decode<R, T> {
+    inner: R,
+    options: DecodeOptions,
+    bytes: Vec<u8>,
+    chunk: Vec<u8>,
+    parse: fn(DtmDecoder<&[u8]>) -> Dtm2txtResult<T>,
+}
+
+impl<R, T> Future for Decode<R, T>
+    where R: AsyncRead + Unpin,
+{
+    type Output = Dtm2txtResult<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Dtm2txtResult<T>> {
+        let this = self.get_mut();
+        loop {
+            let mut buf = ReadBuf::new(&mut this.chunk);
+            match Pin::new(&mut this.inner).poll_read(cx, &mut buf) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err.into())),
+                Poll::Ready(Ok(())) if buf.filled().is_empty() => break,
+                Poll::Ready(Ok(())) => this.bytes.extend_from_slice(buf.filled()),
+            }
+        }
+
+        let decoder = DtmDecoder::new(&this.bytes[..]).options(this.options);
+        Poll::Ready((this.parse)(decoder))
+    }
+}
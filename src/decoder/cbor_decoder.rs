@@ -0,0 +1,79 @@
+use std::io::Read;
+
+use ciborium;
+
+use dtm::{Dtm, DtmHeader, ControllerInput, WiimoteInput, CONTROLLER_RECORD_SIZE};
+use decoder::options::{DecodeOptions, DecodeWarning};
+use encoder::cbor_encoder::CborMovie;
+use error::{Dtm2txtError, Dtm2txtResult};
+
+// Only the header of a CBOR movie. The other fields still have to be read
+// past, but aren't kept.
+#[derive(Deserialize)]
+struct CborHeader {
+    header: DtmHeader,
+}
+
+// Reads what `CborEncoder` writes.
+pub struct CborDecoder<R> {
+    inner: R,
+    options: DecodeOptions,
+}
+
+impl<R> CborDecoder<R>
+    where R: Read,
+{
+    pub fn new(inner: R) -> CborDecoder<R> {
+        CborDecoder {
+            inner: inner,
+            options: DecodeOptions::default(),
+        }
+    }
+
+    pub fn options(mut self, options: DecodeOptions) -> CborDecoder<R> {
+        self.options = options;
+        self
+    }
+
+    pub fn decode(self) -> Dtm2txtResult<Dtm> {
+        self.decode_with_warnings().map(|(dtm, _)| dtm)
+    }
+
+    pub fn decode_header(self) -> Dtm2txtResult<DtmHeader> {
+        let mut warnings = Vec::new();
+        let mut cbor_header: CborHeader = ciborium::de::from_reader(self.inner)?;
+        self.options.check_reserved(&mut cbor_header.header, &mut warnings);
+        Ok(cbor_header.header)
+    }
+
+    pub fn decode_with_warnings(self) -> Dtm2txtResult<(Dtm, Vec<DecodeWarning>)> {
+        let mut warnings = Vec::new();
+        let movie: CborMovie = ciborium::de::from_reader(self.inner)?;
+        let records = movie.controller_data.0.chunks_exact(CONTROLLER_RECORD_SIZE);
+        if !records.remainder().is_empty() {
+            return Err(Dtm2txtError::TruncatedInputsError {
+                expected: movie.header.input_count,
+                found: records.len() as u64,
+            });
+        }
+
+        let mut dtm = Dtm {
+            controller_data: records
+                .map(|record| {
+                    let mut bytes = [0; CONTROLLER_RECORD_SIZE];
+                    bytes.copy_from_slice(record);
+                    ControllerInput::from_bytes(&bytes)
+                })
+                .collect(),
+            wiimote_data: movie.wiimote_data.into_iter()
+                .map(|report| WiimoteInput {
+                    report: report.0,
+                })
+                .collect(),
+            header: movie.header,
+            trailing: movie.trailing.0,
+        };
+        self.options.check_deserialized(&mut dtm, &mut warnings)?;
+        Ok((dtm, warnings))
+    }
+}
@@ -0,0 +1,144 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::mem;
+
+use dtm::{Dtm, DtmHeader, ControllerInput};
+use encoder::csv_encoder::CsvColumn;
+use error::{Dtm2txtError, ControllerInputParseError, Dtm2txtResult};
+use frames::FrameStore;
+
+// Reads the spreadsheet format written by `CsvEncoder`. Columns are matched
+// by their header name, so they can come in any order; missing columns are
+// left unpressed (or centered, for sticks) and unknown ones are ignored
+// unless they've been mapped with `map_column`.
+pub struct CsvDecoder<R> {
+    inner: R,
+    header: DtmHeader,
+    mapping: HashMap<String, CsvColumn>,
+}
+
+impl<R> CsvDecoder<R>
+    where R: Read,
+{
+    pub fn new(inner: R) -> CsvDecoder<R> {
+        CsvDecoder {
+            inner: inner,
+            header: DtmHeader::default(),
+            mapping: HashMap::new(),
+        }
+    }
+
+    // A csv file has no metadata, so the movie gets this header.
+    pub fn header(mut self, header: DtmHeader) -> CsvDecoder<R> {
+        self.header = header;
+        self
+    }
+
+    // Reads the column called `name` (in any case) as `column`, e.g. a
+    // "Jump" column as the A button.
+    pub fn map_column(mut self, name: &str, column: CsvColumn) -> CsvDecoder<R> {
+        self.mapping.insert(name.to_ascii_lowercase(), column);
+        self
+    }
+
+    pub fn decode(self) -> Dtm2txtResult<Dtm> {
+        self.decode_into()
+    }
+
+    pub fn decode_into<S>(self) -> Dtm2txtResult<Dtm<S>>
+        where S: FrameStore + Default,
+    {
+        let CsvDecoder { inner, header, mapping } = self;
+        let mut lines = BufReader::new(inner).lines();
+        let mut line_number = 1;
+
+        let header_line = match lines.next() {
+            Some(line) => line.map_err(|err| parse_error(ControllerInputParseError::IoError(err), line_number))?,
+            None => return Err(parse_error(ControllerInputParseError::MissingTokenError, line_number)),
+        };
+        let columns: Vec<Option<CsvColumn>> = split_fields(&header_line)
+            .iter()
+            .map(|name| {
+                let name = name.trim();
+                mapping.get(&name.to_ascii_lowercase())
+                    .cloned()
+                    .or_else(|| CsvColumn::from_name(name))
+            })
+            .collect();
+
+        let mut controller_data = S::default();
+        for line in lines {
+            line_number += 1;
+            let line = line.map_err(|err| parse_error(ControllerInputParseError::IoError(err), line_number))?;
+            // Spreadsheets like to leave blank rows at the end.
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let mut input = ControllerInput::NEUTRAL;
+            for (column, field) in columns.iter().zip(split_fields(&line).iter()) {
+                let field = field.trim();
+                match *column {
+                    Some(CsvColumn::Button(button)) => {
+                        let pressed = match field.to_ascii_lowercase().as_str() {
+                            "1" | "true" => true,
+                            "0" | "false" | "" => false,
+                            _ => return Err(parse_error(ControllerInputParseError::InvalidButtonError, line_number)),
+                        };
+                        input.set_pressed(button, pressed);
+                    }
+                    Some(CsvColumn::Axis(axis)) => {
+                        let value = field.parse::<u8>()
+                            .map_err(|err| parse_error(ControllerInputParseError::ParseIntError(err), line_number))?;
+                        input.set_axis(axis, value);
+                    }
+                    // Rows are taken in file order; the frame column is only
+                    // there for people reading the spreadsheet.
+                    Some(CsvColumn::Frame) | None => {}
+                }
+            }
+            controller_data.push(input);
+        }
+
+        let mut header = header;
+        header.input_count = controller_data.len();
+
+        Ok(Dtm {
+            header: header,
+            controller_data: controller_data,
+            wiimote_data: Vec::new(),
+            trailing: Vec::new(),
+        })
+    }
+}
+
+fn parse_error(reason: ControllerInputParseError, line: u64) -> Dtm2txtError {
+    Dtm2txtError::ControllerInputParseError {
+        reason: reason,
+        line: line,
+    }
+}
+
+// Splits a row on commas, honoring double-quoted fields (with "" for a
+// literal quote) the way spreadsheets write them.
+fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+
+    fields
+}
@@ -1,30 +1,20 @@
-use std::io::Read;
+use std::io::{Read, Seek, SeekFrom, ErrorKind};
 
 use byteorder::{ReadBytesExt, LE};
-use dtm::{Dtm, DtmHeader, ControllerInput, AudioEmulator, Md5, Reserved2, GitRevision, Reserved3};
+use decoder::{Discard, Inputs, InputSink};
+use decoder::options::{DecodeOptions, DecodeWarning, Strictness};
+use dtm::{Dtm, DtmHeader, DTM_MAGIC, HEADER_SIZE, CONTROLLER_RECORD_SIZE, RawString, ControllerInput, WiimoteInput, CpuCore, HexBytes};
 use error::{Dtm2txtError, Dtm2txtResult};
+use frames::FrameStore;
+use progress::{Progress, ProgressCallback};
 
-const DTM_MAGIC: &[u8; 4] = b"DTM\x1A";
-
-const START_MASK: u8 = 0x01;
-const A_MASK: u8 = 0x02;
-const B_MASK: u8 = 0x04;
-const X_MASK: u8 = 0x08;
-const Y_MASK: u8 = 0x10;
-const Z_MASK: u8 = 0x20;
-const UP_MASK: u8 = 0x40;
-const DOWN_MASK: u8 = 0x80;
-const LEFT_MASK: u8 = 0x01;
-const RIGHT_MASK: u8 = 0x02;
-const L_MASK: u8 = 0x04;
-const R_MASK: u8 = 0x08;
-const CHANGE_DISC_MASK: u8 = 0x10;
-const RESET_MASK: u8 = 0x20;
-const CONTROLLER_CONNECTED_MASK: u8 = 0x40;
-const RESERVED_MASK: u8 = 0x80;
+// The most inputs space is set aside for up front. input_count comes straight
+// from the file, so a damaged header could otherwise ask for any amount of
+// memory before a single input is read; longer movies just grow as they go.
+const MAX_RESERVED_INPUTS: u64 = 1 << 20;
 
 trait ReadDtmExt: Read {
-    fn read_string(&mut self, len: usize) -> Dtm2txtResult<String> {
+    fn read_string(&mut self, len: usize) -> Dtm2txtResult<(String, Option<RawString>)> {
         let mut buffer = vec![0; len];
         self.read_exact(&mut buffer)?;
 
@@ -32,7 +22,7 @@ trait ReadDtmExt: Read {
             buffer.pop();
         }
 
-        Ok(String::from_utf8(buffer)?)
+        Ok(RawString::decode(buffer))
     }
 
     fn read_bool(&mut self) -> Dtm2txtResult<bool> {
@@ -44,6 +34,9 @@ impl<R> ReadDtmExt for R where R: Read {}
 
 pub struct DtmDecoder<R> {
     inner: R,
+    options: DecodeOptions,
+    warnings: Vec<DecodeWarning>,
+    progress: Option<ProgressCallback>,
 }
 
 impl<R> DtmDecoder<R>
@@ -52,84 +45,212 @@ impl<R> DtmDecoder<R>
     pub fn new(inner: R) -> DtmDecoder<R> {
         DtmDecoder {
             inner: inner,
+            options: DecodeOptions::default(),
+            warnings: Vec::new(),
+            progress: None,
         }
     }
 
-    pub fn decode(mut self) -> Dtm2txtResult<Dtm> {
-        let header = self.decode_header()?;
+    pub fn options(mut self, options: DecodeOptions) -> DtmDecoder<R> {
+        self.options = options;
+        self
+    }
 
-        let mut controller_data = Vec::new();
-        for _ in 0..header.input_count {
-            controller_data.push(self.decode_controller_input()?);
-        }
+    // Reports how far along the inputs it is; see `ProgressCallback`.
+    pub fn progress<F>(mut self, callback: F) -> DtmDecoder<R>
+        where F: FnMut(u64, u64) + 'static,
+    {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    pub fn decode(self) -> Dtm2txtResult<Dtm> {
+        self.decode_into()
+    }
+
+    // Also returns what a lenient decode had to recover from.
+    pub fn decode_with_warnings(self) -> Dtm2txtResult<(Dtm, Vec<DecodeWarning>)> {
+        self.decode_all()
+    }
 
-        Ok(Dtm {
+    // Decodes straight into the given frame storage, so a run-length encoded
+    // movie never has to exist as a full `Vec` first.
+    pub fn decode_into<S>(self) -> Dtm2txtResult<Dtm<S>>
+        where S: FrameStore + Default,
+    {
+        self.decode_all().map(|(dtm, _)| dtm)
+    }
+
+    // `decode_into` and `decode_with_warnings` in one.
+    pub fn decode_into_with_warnings<S>(self) -> Dtm2txtResult<(Dtm<S>, Vec<DecodeWarning>)>
+        where S: FrameStore + Default,
+    {
+        self.decode_all()
+    }
+
+    fn decode_all<S>(mut self) -> Dtm2txtResult<(Dtm<S>, Vec<DecodeWarning>)>
+        where S: FrameStore + Default,
+    {
+        let mut inputs = Inputs::<S>::default();
+        let (header, trailing) = self.decode_inputs(&mut inputs)?;
+
+        let dtm = Dtm {
             header: header,
-            controller_data: controller_data,
-        })
+            controller_data: inputs.controller_data,
+            wiimote_data: inputs.wiimote_data,
+            trailing: trailing,
+        };
+
+        Ok((dtm, self.warnings))
+    }
+
+    // Hands the inputs to `sink` as they're read, returning the header as it
+    // stands once they're all in and whatever came after them.
+    fn decode_inputs<K>(&mut self, sink: &mut K) -> Dtm2txtResult<(DtmHeader, Vec<u8>)>
+        where K: InputSink,
+    {
+        let mut header = self.decode_header()?;
+
+        // Records aren't tagged with their controller, so movies using both
+        // GameCube controllers and Wii remotes can't be split apart.
+        let gamecube = header.gamecube_port_count() != 0;
+        let wiimotes = header.has_wiimote_inputs();
+        if gamecube && wiimotes {
+            return Err(Dtm2txtError::UnsupportedControllerLayoutError {
+                controllers: header.controllers,
+            });
+        }
+
+        let reserved = self.options.kept(0, header.input_count).min(MAX_RESERVED_INPUTS);
+        if wiimotes {
+            sink.reserve(0, reserved);
+        }
+        else {
+            sink.reserve(reserved, 0);
+        }
+        let mut progress = Progress::new(self.progress.take(), header.input_count);
+        let mut truncated = false;
+        for read in 0..header.input_count {
+            progress.update(read);
+            let keep = self.options.kept(read, 1) == 1;
+            // Only errors reading the movie can mean it's cut short, so the
+            // sink's own are kept apart until then.
+            let result = if wiimotes {
+                self.decode_wiimote_input().map(|input| if keep { sink.push_wiimote(input) } else { Ok(()) })
+            }
+            else {
+                self.decode_controller_record().map(|record| if keep { sink.push_record(&record) } else { Ok(()) })
+            };
+
+            match result {
+                Err(Dtm2txtError::IoError(ref err)) if err.kind() == ErrorKind::UnexpectedEof => {
+                    if self.options.strictness != Strictness::Lenient {
+                        return Err(Dtm2txtError::TruncatedInputsError {
+                            expected: header.input_count,
+                            found: read,
+                        });
+                    }
+                    self.warnings.push(DecodeWarning::Truncated {
+                        expected: header.input_count,
+                        found: read,
+                    });
+                    header.input_count = read;
+                    truncated = true;
+                    break;
+                }
+                Err(err) => return Err(err),
+                Ok(pushed) => pushed?,
+            }
+        }
+        progress.finish(header.input_count);
+
+        // A truncated file can't have anything after its inputs.
+        let mut trailing = Vec::new();
+        if !truncated {
+            self.inner.read_to_end(&mut trailing)?;
+        }
+
+        let total = header.input_count;
+        let (frames, wiimote_frames) = if wiimotes { (0, total) } else { (total, 0) };
+        self.options.check_consistency(&header, frames, wiimote_frames, &mut self.warnings)?;
+        self.options.fit_window(&mut header, &mut trailing, total);
+
+        Ok((header, trailing))
     }
 
-    fn decode_header(&mut self) -> Dtm2txtResult<DtmHeader> {
+    // Reads only the 256-byte header, leaving the decoder at the first input.
+    pub fn decode_header(&mut self) -> Dtm2txtResult<DtmHeader> {
+        // The whole header is read up front so that however it gets parsed,
+        // the inputs start right after it.
+        let mut header_bytes = [0; HEADER_SIZE];
+        self.inner.read_exact(&mut header_bytes)?;
+        let mut reader = &header_bytes[..];
+
         let mut magic_buffer = [0; 4];
-        self.inner.read_exact(&mut magic_buffer)?;
+        reader.read_exact(&mut magic_buffer)?;
         if magic_buffer != *DTM_MAGIC {
-            return Err(Dtm2txtError::BadMagicError);
+            if self.options.strictness != Strictness::Lenient {
+                return Err(Dtm2txtError::BadMagicError);
+            }
+            self.warnings.push(DecodeWarning::BadMagic);
         }
 
-        let game_id = self.inner.read_string(6)?;
-        let wii_game = self.inner.read_bool()?;
-        let controllers = self.inner.read_u8()?;
-        let savestate = self.inner.read_bool()?;
-        let vi_count = self.inner.read_u64::<LE>()?;
-        let input_count = self.inner.read_u64::<LE>()?;
-        let lag_counter = self.inner.read_u64::<LE>()?;
-        let reserved1 = self.inner.read_u64::<LE>()?;
-        let rerecord_count = self.inner.read_u32::<LE>()?;
-        let author = self.inner.read_string(32)?;
-        let video_backend = self.inner.read_string(16)?;
+        let (game_id, game_id_raw) = reader.read_string(6)?;
+        let wii_game = reader.read_bool()?;
+        let controllers = reader.read_u8()?;
+        let savestate = reader.read_bool()?;
+        let vi_count = reader.read_u64::<LE>()?;
+        let input_count = reader.read_u64::<LE>()?;
+        let lag_counter = reader.read_u64::<LE>()?;
+        let reserved1 = reader.read_u64::<LE>()?;
+        let rerecord_count = reader.read_u32::<LE>()?;
+        let (author, author_raw) = reader.read_string(32)?;
+        let (video_backend, video_backend_raw) = reader.read_string(16)?;
         let mut audio_emulator_buffer = [0; 16];
-        self.inner.read_exact(&mut audio_emulator_buffer)?;
-        let audio_emulator = AudioEmulator(audio_emulator_buffer);
+        reader.read_exact(&mut audio_emulator_buffer)?;
+        let audio_emulator = HexBytes(audio_emulator_buffer);
         let mut md5_buffer = [0; 16];
-        self.inner.read_exact(&mut md5_buffer)?;
-        let md5 = Md5(md5_buffer);
-        let start_time = self.inner.read_u64::<LE>()?;
-        let valid_config = self.inner.read_bool()?;
-        let idle_skipping = self.inner.read_bool()?;
-        let dual_core = self.inner.read_bool()?;
-        let progressive_scan = self.inner.read_bool()?;
-        let dsp_hle = self.inner.read_bool()?;
-        let fast_disc = self.inner.read_bool()?;
-        let cpu_core = self.inner.read_u8()?;
-        let efb_access = self.inner.read_bool()?;
-        let efb_copy = self.inner.read_bool()?;
-        let efb_to_texture = self.inner.read_bool()?;
-        let efb_copy_cache = self.inner.read_bool()?;
-        let emulate_format_changes = self.inner.read_bool()?;
-        let use_xfb = self.inner.read_bool()?;
-        let use_real_xfb = self.inner.read_bool()?;
-        let memory_cards = self.inner.read_u8()?;
-        let memory_card_blank = self.inner.read_bool()?;
-        let bongos_plugged = self.inner.read_u8()?;
-        let sync_gpu = self.inner.read_bool()?;
-        let netplay = self.inner.read_bool()?;
-        let sysconf_pal60 = self.inner.read_bool()?;
+        reader.read_exact(&mut md5_buffer)?;
+        let md5 = HexBytes(md5_buffer);
+        let start_time = reader.read_u64::<LE>()?;
+        let valid_config = reader.read_bool()?;
+        let idle_skipping = reader.read_bool()?;
+        let dual_core = reader.read_bool()?;
+        let progressive_scan = reader.read_bool()?;
+        let dsp_hle = reader.read_bool()?;
+        let fast_disc = reader.read_bool()?;
+        let cpu_core = CpuCore::from(reader.read_u8()?);
+        let efb_access = reader.read_bool()?;
+        let efb_copy = reader.read_bool()?;
+        let efb_to_texture = reader.read_bool()?;
+        let efb_copy_cache = reader.read_bool()?;
+        let emulate_format_changes = reader.read_bool()?;
+        let use_xfb = reader.read_bool()?;
+        let use_real_xfb = reader.read_bool()?;
+        let memory_cards = reader.read_u8()?;
+        let memory_card_blank = reader.read_bool()?;
+        let bongos_plugged = reader.read_u8()?;
+        let sync_gpu = reader.read_bool()?;
+        let netplay = reader.read_bool()?;
+        let sysconf_pal60 = reader.read_bool()?;
         let mut reserved2_buffer = [0; 12];
-        self.inner.read_exact(&mut reserved2_buffer)?;
-        let reserved2 = Reserved2(reserved2_buffer);
-        let second_disc = self.inner.read_string(40)?;
+        reader.read_exact(&mut reserved2_buffer)?;
+        let reserved2 = HexBytes(reserved2_buffer);
+        let (second_disc, second_disc_raw) = reader.read_string(40)?;
         let mut git_revision_buffer = [0; 20];
-        self.inner.read_exact(&mut git_revision_buffer)?;
-        let git_revision = GitRevision(git_revision_buffer);
-        let dsp_irom_hash = self.inner.read_u32::<LE>()?;
-        let dsp_coef_hash = self.inner.read_u32::<LE>()?;
-        let tick_count = self.inner.read_u64::<LE>()?;
+        reader.read_exact(&mut git_revision_buffer)?;
+        let git_revision = HexBytes(git_revision_buffer);
+        let dsp_irom_hash = reader.read_u32::<LE>()?;
+        let dsp_coef_hash = reader.read_u32::<LE>()?;
+        let tick_count = reader.read_u64::<LE>()?;
         let mut reserved3_buffer = [0; 11];
-        self.inner.read_exact(&mut reserved3_buffer)?;
-        let reserved3 = Reserved3(reserved3_buffer);
+        reader.read_exact(&mut reserved3_buffer)?;
+        let reserved3 = HexBytes(reserved3_buffer);
+        debug_assert!(reader.is_empty(), "header fields don't add up to HEADER_SIZE");
 
-        Ok(DtmHeader {
+        let mut header = DtmHeader {
             game_id: game_id,
+            game_id_raw: game_id_raw,
             wii_game: wii_game,
             controllers: controllers,
             savestate: savestate,
@@ -139,7 +260,9 @@ impl<R> DtmDecoder<R>
             reserved1: reserved1,
             rerecord_count: rerecord_count,
             author: author,
+            author_raw: author_raw,
             video_backend: video_backend,
+            video_backend_raw: video_backend_raw,
             audio_emulator: audio_emulator,
             md5: md5,
             start_time: start_time,
@@ -165,47 +288,102 @@ impl<R> DtmDecoder<R>
             sysconf_pal60: sysconf_pal60,
             reserved2: reserved2,
             second_disc: second_disc,
+            second_disc_raw: second_disc_raw,
             git_revision: git_revision,
             dsp_irom_hash: dsp_irom_hash,
             dsp_coef_hash: dsp_coef_hash,
             tick_count: tick_count,
             reserved3: reserved3,
-        })
-    }
-
-    fn decode_controller_input(&mut self) -> Dtm2txtResult<ControllerInput> {
-        let mut bytes = [0; 2];
-        self.inner.read_exact(&mut bytes)?;
-        let l_pressure = self.inner.read_u8()?;
-        let r_pressure = self.inner.read_u8()?;
-        let analog_x = self.inner.read_u8()?;
-        let analog_y = self.inner.read_u8()?;
-        let c_x = self.inner.read_u8()?;
-        let c_y = self.inner.read_u8()?;
-
-        Ok(ControllerInput {
-            start: bytes[0] & START_MASK != 0,
-            a: bytes[0] & A_MASK != 0,
-            b: bytes[0] & B_MASK != 0,
-            x: bytes[0] & X_MASK != 0,
-            y: bytes[0] & Y_MASK != 0,
-            z: bytes[0] & Z_MASK != 0,
-            up: bytes[0] & UP_MASK != 0,
-            down: bytes[0] & DOWN_MASK != 0,
-            left: bytes[1] & LEFT_MASK != 0,
-            right: bytes[1] & RIGHT_MASK != 0,
-            l: bytes[1] & L_MASK != 0,
-            r: bytes[1] & R_MASK != 0,
-            change_disc: bytes[1] & CHANGE_DISC_MASK != 0,
-            reset: bytes[1] & RESET_MASK != 0,
-            controller_connected: bytes[1] & CONTROLLER_CONNECTED_MASK != 0,
-            reserved: bytes[1] & RESERVED_MASK != 0,
-            l_pressure: l_pressure,
-            r_pressure: r_pressure,
-            analog_x: analog_x,
-            analog_y: analog_y,
-            c_x: c_x,
-            c_y: c_y,
-        })
-    }
-}
\ No newline at end of file
+        };
+        self.options.check_reserved(&mut header, &mut self.warnings);
+
+        Ok(header)
+    }
+
+    pub(crate) fn decode_wiimote_input(&mut self) -> Dtm2txtResult<WiimoteInput> {
+        read_wiimote_input(&mut self.inner)
+    }
+
+    pub(crate) fn decode_controller_input(&mut self) -> Dtm2txtResult<ControllerInput> {
+        read_controller_input(&mut self.inner)
+    }
+
+    fn decode_controller_record(&mut self) -> Dtm2txtResult<[u8; CONTROLLER_RECORD_SIZE]> {
+        let mut record = [0; CONTROLLER_RECORD_SIZE];
+        self.inner.read_exact(&mut record)?;
+        Ok(record)
+    }
+
+    // Reads inputs one at a time after `decode_header`, for a movie that's
+    // still being written: its input_count isn't final, so this goes until
+    // the stream ends instead. None means it ended cleanly between inputs.
+    pub fn next_controller_input(&mut self) -> Dtm2txtResult<Option<ControllerInput>> {
+        match self.next_byte()? {
+            Some(first) => read_controller_input(&mut (&[first][..]).chain(&mut self.inner)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // Like `next_controller_input`, for Wii movies.
+    pub fn next_wiimote_input(&mut self) -> Dtm2txtResult<Option<WiimoteInput>> {
+        match self.next_byte()? {
+            Some(first) => read_wiimote_input(&mut (&[first][..]).chain(&mut self.inner)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_byte(&mut self) -> Dtm2txtResult<Option<u8>> {
+        let mut byte = [0];
+        loop {
+            match self.inner.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(ref err) if err.kind() == ErrorKind::Interrupted => (),
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+}
+
+impl<R> DtmDecoder<R>
+    where R: Read + Seek,
+{
+    // Goes through the movie twice without keeping its inputs: first to learn
+    // the header and trailing bytes it ends up with, which `start` gets to set
+    // up the sink with, and then again to hand that sink each input.
+    pub(crate) fn decode_streaming<K, F>(mut self, start: F) -> Dtm2txtResult<(DtmHeader, K, Vec<DecodeWarning>)>
+        where K: InputSink,
+              F: FnOnce(&DtmHeader, &[u8]) -> Dtm2txtResult<K>,
+    {
+        let begin = self.inner.stream_position()?;
+        let progress = self.progress.take();
+        let (header, trailing) = self.decode_inputs(&mut Discard)?;
+        let mut sink = start(&header, &trailing)?;
+
+        self.inner.seek(SeekFrom::Start(begin))?;
+        self.warnings.clear();
+        self.progress = progress;
+        self.decode_inputs(&mut sink)?;
+        Ok((header, sink, self.warnings))
+    }
+}
+
+// A size byte followed by the raw report.
+fn read_wiimote_input<R>(reader: &mut R) -> Dtm2txtResult<WiimoteInput>
+    where R: Read,
+{
+    let size = reader.read_u8()?;
+    let mut report = vec![0; size as usize];
+    reader.read_exact(&mut report)?;
+    Ok(WiimoteInput {
+        report: report,
+    })
+}
+
+fn read_controller_input<R>(reader: &mut R) -> Dtm2txtResult<ControllerInput>
+    where R: Read,
+{
+    let mut record = [0; CONTROLLER_RECORD_SIZE];
+    reader.read_exact(&mut record)?;
+    Ok(ControllerInput::from_bytes(&record))
+}
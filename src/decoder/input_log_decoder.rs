@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+
+use dtm::{Dtm, DtmHeader, ControllerInput};
+use encoder::csv_encoder::CsvColumn;
+use error::{Dtm2txtError, ControllerInputParseError, Dtm2txtResult};
+use frames::FrameStore;
+
+// One control in a log line: an analog value ending in a comma, or a single
+// mnemonic character that's '.' when the button isn't pressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Token {
+    Axis(u8),
+    Button(bool),
+}
+
+// Reads the input log of another TAS tool, like the Input Log.txt of a bk2 or
+// an fm2 file: one line per frame, with fields between '|'. Lines that don't
+// start with '|' are skipped, so the rest of an fm2 file can be fed in as is.
+//
+// Controls are numbered from 1 across the whole line, and named by the
+// LogKey line if there is one. Only controls mapped with `map_column` (or
+// named like a csv column) are read; the rest are ignored.
+pub struct InputLogDecoder<R> {
+    inner: R,
+    header: DtmHeader,
+    mapping: HashMap<String, CsvColumn>,
+}
+
+impl<R> InputLogDecoder<R>
+    where R: Read,
+{
+    pub fn new(inner: R) -> InputLogDecoder<R> {
+        InputLogDecoder {
+            inner: inner,
+            header: DtmHeader::default(),
+            mapping: HashMap::new(),
+        }
+    }
+
+    // An input log has no metadata Dolphin would understand, so the movie
+    // gets this header.
+    pub fn header(mut self, header: DtmHeader) -> InputLogDecoder<R> {
+        self.header = header;
+        self
+    }
+
+    // Reads the control called `name` in the LogKey (in any case), or the
+    // control at that position when `name` is a number, as `column`.
+    pub fn map_column(mut self, name: &str, column: CsvColumn) -> InputLogDecoder<R> {
+        self.mapping.insert(name.to_ascii_lowercase(), column);
+        self
+    }
+
+    pub fn decode(self) -> Dtm2txtResult<Dtm> {
+        self.decode_into()
+    }
+
+    pub fn decode_into<S>(self) -> Dtm2txtResult<Dtm<S>>
+        where S: FrameStore + Default,
+    {
+        let InputLogDecoder { inner, header, mapping } = self;
+        let mut names: Vec<String> = Vec::new();
+        let mut controller_data = S::default();
+
+        for (idx, line) in BufReader::new(inner).lines().enumerate() {
+            let line_number = idx as u64 + 1;
+            let line = line.map_err(|err| parse_error(ControllerInputParseError::IoError(err), line_number))?;
+            let line = line.trim_end();
+
+            if let Some(key) = line.strip_prefix("LogKey:") {
+                names = parse_log_key(key);
+                continue;
+            }
+            if !line.starts_with('|') {
+                continue;
+            }
+
+            let mut input = ControllerInput::NEUTRAL;
+            let tokens = parse_line(line).map_err(|reason| parse_error(reason, line_number))?;
+            for (position, token) in tokens.iter().enumerate() {
+                let column = names.get(position)
+                    .and_then(|name| mapping.get(&name.to_ascii_lowercase()).cloned().or_else(|| CsvColumn::from_name(name)))
+                    .or_else(|| mapping.get(&(position + 1).to_string()).cloned());
+                match (column, *token) {
+                    (Some(CsvColumn::Button(button)), Token::Button(pressed)) => input.set_pressed(button, pressed),
+                    (Some(CsvColumn::Axis(axis)), Token::Axis(value)) => input.set_axis(axis, value),
+                    (Some(CsvColumn::Button(_)), Token::Axis(_)) | (Some(CsvColumn::Axis(_)), Token::Button(_)) =>
+                        return Err(parse_error(ControllerInputParseError::InvalidButtonError, line_number)),
+                    (Some(CsvColumn::Frame), _) | (None, _) => {}
+                }
+            }
+            controller_data.push(input);
+        }
+
+        let mut header = header;
+        header.input_count = controller_data.len();
+
+        Ok(Dtm {
+            header: header,
+            controller_data: controller_data,
+            wiimote_data: Vec::new(),
+            trailing: Vec::new(),
+        })
+    }
+}
+
+fn parse_error(reason: ControllerInputParseError, line: u64) -> Dtm2txtError {
+    Dtm2txtError::ControllerInputParseError {
+        reason: reason,
+        line: line,
+    }
+}
+
+// "#Reset|Power|#P1 Up|P1 Down|" has a '#' before each group of controls.
+fn parse_log_key(key: &str) -> Vec<String> {
+    key.split(['|', '#'])
+        .filter(|name| !name.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+// "|..|  128,  255,U.......|" has two fields. Analog values are padded with
+// spaces and end in a comma; everything else is one character per button.
+fn parse_line(line: &str) -> Result<Vec<Token>, ControllerInputParseError> {
+    let mut tokens = Vec::new();
+    for field in line.trim_matches('|').split('|') {
+        let mut rest = field;
+        while let Some(c) = rest.chars().next() {
+            let value = rest.find(',')
+                .map(|comma| (comma, rest[..comma].trim()))
+                .filter(|&(_, value)| !value.is_empty() && value.chars().all(|c| c.is_ascii_digit() || c == '-'));
+            match value {
+                Some((comma, value)) => {
+                    let value = value.parse().map_err(ControllerInputParseError::ParseIntError)?;
+                    tokens.push(Token::Axis(value));
+                    rest = &rest[comma + 1..];
+                }
+                None => {
+                    tokens.push(Token::Button(c != '.' && c != ' '));
+                    rest = &rest[c.len_utf8()..];
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
@@ -0,0 +1,54 @@
+use std::io::Read;
+
+use serde_json;
+
+use dtm::{Dtm, DtmHeader};
+use decoder::options::{DecodeOptions, DecodeWarning};
+use error::Dtm2txtResult;
+
+#[derive(Deserialize)]
+struct JsonHeader {
+    header: DtmHeader,
+}
+
+// Reads what `JsonEncoder` writes.
+pub struct JsonDecoder<R> {
+    inner: R,
+    options: DecodeOptions,
+}
+
+impl<R> JsonDecoder<R>
+    where R: Read,
+{
+    pub fn new(inner: R) -> JsonDecoder<R> {
+        JsonDecoder {
+            inner: inner,
+            options: DecodeOptions::default(),
+        }
+    }
+
+    pub fn options(mut self, options: DecodeOptions) -> JsonDecoder<R> {
+        self.options = options;
+        self
+    }
+
+    pub fn decode(self) -> Dtm2txtResult<Dtm> {
+        self.decode_with_warnings().map(|(dtm, _)| dtm)
+    }
+
+    // The inputs are still parsed past, since JSON can't be skipped over
+    // without reading it.
+    pub fn decode_header(self) -> Dtm2txtResult<DtmHeader> {
+        let mut warnings = Vec::new();
+        let mut json_header: JsonHeader = serde_json::from_reader(self.inner)?;
+        self.options.check_reserved(&mut json_header.header, &mut warnings);
+        Ok(json_header.header)
+    }
+
+    pub fn decode_with_warnings(self) -> Dtm2txtResult<(Dtm, Vec<DecodeWarning>)> {
+        let mut warnings = Vec::new();
+        let mut dtm: Dtm = serde_json::from_reader(self.inner)?;
+        self.options.check_deserialized(&mut dtm, &mut warnings)?;
+        Ok((dtm, warnings))
+    }
+}
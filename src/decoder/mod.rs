@@ -1,2 +1,81 @@
 pub mod text_decoder;
-pub mod dtm_decoder;
\ No newline at end of file
+pub mod dtm_decoder;
+pub mod csv_decoder;
+pub mod json_decoder;
+pub mod input_log_decoder;
+#[cfg(feature = "yaml")]
+pub mod yaml_decoder;
+#[cfg(feature = "cbor")]
+pub mod cbor_decoder;
+#[cfg(feature = "tokio")]
+pub mod async_dtm_decoder;
+pub mod options;
+
+use dtm::{ControllerInput, WiimoteInput, CONTROLLER_RECORD_SIZE};
+use error::Dtm2txtResult;
+use frames::FrameStore;
+
+// Where a decoder puts the inputs it reads: into a movie, or on to an encoder
+// as they come when transcoding.
+pub(crate) trait InputSink {
+    fn push_controller(&mut self, input: ControllerInput) -> Dtm2txtResult<()>;
+    fn push_wiimote(&mut self, input: WiimoteInput) -> Dtm2txtResult<()>;
+
+    // Like `push_controller`, for an input still in the bytes a dtm stores
+    // it as.
+    fn push_record(&mut self, record: &[u8; CONTROLLER_RECORD_SIZE]) -> Dtm2txtResult<()> {
+        self.push_controller(ControllerInput::from_bytes(record))
+    }
+
+    // A hint that about this many more inputs are coming.
+    fn reserve(&mut self, _controller: u64, _wiimote: u64) {}
+}
+
+// The inputs of a movie being decoded in full.
+#[derive(Default)]
+pub(crate) struct Inputs<S> {
+    pub controller_data: S,
+    pub wiimote_data: Vec<WiimoteInput>,
+}
+
+impl<S> InputSink for Inputs<S>
+    where S: FrameStore,
+{
+    fn push_controller(&mut self, input: ControllerInput) -> Dtm2txtResult<()> {
+        self.controller_data.push(input);
+        Ok(())
+    }
+
+    fn push_wiimote(&mut self, input: WiimoteInput) -> Dtm2txtResult<()> {
+        self.wiimote_data.push(input);
+        Ok(())
+    }
+
+    fn push_record(&mut self, record: &[u8; CONTROLLER_RECORD_SIZE]) -> Dtm2txtResult<()> {
+        self.controller_data.push_record(record);
+        Ok(())
+    }
+
+    fn reserve(&mut self, controller: u64, wiimote: u64) {
+        self.controller_data.reserve(controller);
+        self.wiimote_data.reserve(wiimote as usize);
+    }
+}
+
+// Throws the inputs away, for a first pass that only wants to know how a
+// movie ends.
+pub(crate) struct Discard;
+
+impl InputSink for Discard {
+    fn push_controller(&mut self, _input: ControllerInput) -> Dtm2txtResult<()> {
+        Ok(())
+    }
+
+    fn push_wiimote(&mut self, _input: WiimoteInput) -> Dtm2txtResult<()> {
+        Ok(())
+    }
+
+    fn push_record(&mut self, _record: &[u8; CONTROLLER_RECORD_SIZE]) -> Dtm2txtResult<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+use std::fmt;
+
+use consistency::{self, Inconsistency};
+use dtm::{Dtm, DtmHeader};
+use edit;
+use error::{Dtm2txtError, ControllerInputParseError, Dtm2txtResult};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Strictness {
+    // Any disagreement between the header and the inputs is an error, even
+    // ones that are usually harmless, like a stale input count in a txt.
+    Strict,
+    // Broken files are errors, but the input count is taken from the inputs.
+    #[default]
+    Normal,
+    // Keeps whatever can be read and reports the problems as warnings.
+    Lenient,
+}
+
+// What to do with header bytes that are reserved but not zero. Every mode
+// but `Clear` keeps them, and they show up in the txt header either way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ReservedBytes {
+    // Keep them and warn, since the movie likely comes from a newer Dolphin
+    // whose settings dtm2txt can't show by name.
+    #[default]
+    Warn,
+    Keep,
+    Clear,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct DecodeOptions {
+    pub strictness: Strictness,
+    pub reserved: ReservedBytes,
+    // Only the records from `skip` on are kept, and at most `take` of them.
+    // The rest are still read and checked, just not stored.
+    pub skip: u64,
+    pub take: Option<u64>,
+}
+
+impl DecodeOptions {
+    pub fn strict() -> DecodeOptions {
+        DecodeOptions {
+            strictness: Strictness::Strict,
+            ..DecodeOptions::default()
+        }
+    }
+
+    pub fn lenient() -> DecodeOptions {
+        DecodeOptions {
+            strictness: Strictness::Lenient,
+            ..DecodeOptions::default()
+        }
+    }
+
+    pub fn reserved(mut self, reserved: ReservedBytes) -> DecodeOptions {
+        self.reserved = reserved;
+        self
+    }
+
+    pub fn skip(mut self, frames: u64) -> DecodeOptions {
+        self.skip = frames;
+        self
+    }
+
+    pub fn take(mut self, frames: u64) -> DecodeOptions {
+        self.take = Some(frames);
+        self
+    }
+
+    fn window_end(&self) -> u64 {
+        self.take.map(|take| self.skip.saturating_add(take)).unwrap_or(u64::MAX)
+    }
+
+    // How many of the `count` records starting at record `start` are kept.
+    pub(crate) fn kept(&self, start: u64, count: u64) -> u64 {
+        let from = start.max(self.skip);
+        let to = start.saturating_add(count).min(self.window_end());
+        to.saturating_sub(from)
+    }
+
+    // Where a position in the whole movie ends up in the kept part, if it's
+    // in there. The end of the kept part counts, for comments after its last
+    // frame.
+    pub(crate) fn kept_position(&self, position: u64) -> Option<u64> {
+        if position >= self.skip && position <= self.window_end() {
+            Some(position - self.skip)
+        }
+        else {
+            None
+        }
+    }
+
+    // Makes the header of a movie with `total` records describe just the
+    // kept ones, the way `Dtm::extract` does: the counters are shared out by
+    // frame, and a part that doesn't start at the beginning starts from a
+    // savestate. Trailing bytes only stay if the part runs to the end.
+    pub(crate) fn fit_window(&self, header: &mut DtmHeader, trailing: &mut Vec<u8>, total: u64) {
+        if self.skip == 0 && self.take.is_none() {
+            return;
+        }
+        let kept = self.kept(0, total);
+        header.savestate = header.savestate || (self.skip > 0 && kept > 0);
+        header.input_count = kept;
+        header.vi_count = edit::scale(header.vi_count, kept, total);
+        header.tick_count = edit::scale(header.tick_count, kept, total);
+        header.lag_counter = edit::scale(header.lag_counter, kept, total);
+        if self.window_end() < total {
+            trailing.clear();
+        }
+    }
+
+    // Cuts a movie that was decoded whole down to the kept records, for
+    // formats that can't be read a record at a time.
+    pub fn apply_window(&self, dtm: &mut Dtm) {
+        let total = dtm.controller_data.len() as u64 + dtm.wiimote_data.len() as u64;
+        let start = self.skip.min(total) as usize;
+        let end = self.window_end().min(total) as usize;
+        if !dtm.controller_data.is_empty() {
+            dtm.controller_data.truncate(end);
+            dtm.controller_data.drain(..start);
+        }
+        if !dtm.wiimote_data.is_empty() {
+            dtm.wiimote_data.truncate(end);
+            dtm.wiimote_data.drain(..start);
+        }
+        self.fit_window(&mut dtm.header, &mut dtm.trailing, total);
+    }
+
+    pub(crate) fn check_reserved(&self, header: &mut DtmHeader, warnings: &mut Vec<DecodeWarning>) {
+        match self.reserved {
+            ReservedBytes::Warn => {
+                for field in header.unknown_reserved() {
+                    warnings.push(DecodeWarning::UnknownReserved(field));
+                }
+            }
+            ReservedBytes::Keep => (),
+            ReservedBytes::Clear => header.clear_unknown_reserved(),
+        }
+    }
+}
+
+// Problems a lenient decode recovered from.
+#[derive(Debug)]
+pub enum DecodeWarning {
+    BadMagic,
+    // The file ended partway through the inputs; the ones before are kept.
+    Truncated {
+        expected: u64,
+        found: u64,
+    },
+    // A txt input line that couldn't be read and was skipped.
+    SkippedLine {
+        line: u64,
+        reason: ControllerInputParseError,
+    },
+    Inconsistent(Inconsistency),
+    // A reserved header field isn't zero.
+    UnknownReserved(&'static str),
+}
+
+impl fmt::Display for DecodeWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeWarning::BadMagic => f.write_str("bad magic"),
+            DecodeWarning::Truncated{expected, found} =>
+                write!(f, "file ends after {} of {} inputs", found, expected),
+            DecodeWarning::SkippedLine{line, ref reason} =>
+                write!(f, "skipped line {}: {}", line, reason),
+            DecodeWarning::Inconsistent(ref problem) => problem.fmt(f),
+            DecodeWarning::UnknownReserved(field) =>
+                write!(f, "{} holds data this version doesn't know about", field),
+        }
+    }
+}
+
+impl DecodeOptions {
+    // Checks the header against the inputs that were read: an error when
+    // strict, warnings when lenient, and nothing otherwise.
+    pub(crate) fn check_consistency(&self, header: &DtmHeader, frames: u64, wiimote_frames: u64, warnings: &mut Vec<DecodeWarning>) -> Dtm2txtResult<()> {
+        let problems = match self.strictness {
+            Strictness::Normal => return Ok(()),
+            _ => consistency::check(header, frames, wiimote_frames),
+        };
+
+        if self.strictness == Strictness::Strict && !problems.is_empty() {
+            return Err(Dtm2txtError::InconsistentMovieError(problems));
+        }
+        warnings.extend(problems.into_iter().map(DecodeWarning::Inconsistent));
+        Ok(())
+    }
+
+    // For formats that hold a whole `Dtm` as serde data. Like the txt
+    // decoder, the input count comes from the inputs rather than the header.
+    pub(crate) fn check_deserialized(&self, dtm: &mut Dtm, warnings: &mut Vec<DecodeWarning>) -> Dtm2txtResult<()> {
+        self.check_reserved(&mut dtm.header, warnings);
+        let frames = dtm.controller_data.len() as u64;
+        let wiimote_frames = dtm.wiimote_data.len() as u64;
+        self.check_consistency(&dtm.header, frames, wiimote_frames, warnings)?;
+        dtm.header.input_count = frames + wiimote_frames;
+        self.apply_window(dtm);
+        Ok(())
+    }
+}
@@ -1,11 +1,22 @@
-use std::io::{self, Read, BufRead, BufReader, Error as IoError};
+use std::collections::HashMap;
+use std::io::{self, Read, BufRead, BufReader, Lines, Error as IoError};
+use std::iter::Skip;
+use std::mem;
 
 use serde::Deserialize;
 use serde_json;
+use serde_json::Value;
 use serde_json::de::IoRead as JsonIoRead;
 
-use dtm::{Dtm, DtmHeader, ControllerInput};
+use dtm::{self, Dtm, DtmHeader, ControllerInput, WiimoteInput, Button, Axis, MAX_EXPANDED_INPUTS};
+use encoder::text_encoder::{MNEMONIC_BUTTONS, MNEMONICS, TABLE_AXES, TABLE_EXTRAS};
+use comments::Comment;
+use decoder::{Inputs, InputSink};
+use decoder::options::{DecodeOptions, DecodeWarning, Strictness};
 use error::{Dtm2txtError, ControllerInputParseError, Dtm2txtResult};
+use frames::FrameStore;
+use progress::{Progress, ProgressCallback};
+use header_patch;
 
 struct LineCountRead<R> {
     inner: R,
@@ -34,7 +45,7 @@ impl<R> Read for LineCountRead<R>
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         let bytes_read = self.inner.read(buf)?;
         for byte in buf[0..bytes_read].iter() {
-            if *byte == '\n' as u8 {
+            if *byte == b'\n' {
                 self.lines += 1;
             }
         }
@@ -42,17 +53,54 @@ impl<R> Read for LineCountRead<R>
     }
 }
 
-struct InputReader {
-    line: u64,
+#[derive(Clone)]
+pub(crate) enum InputRecord {
+    Controller(ControllerInput),
+    // One frame of every port, from a line like `... ; ...`.
+    Ports(Vec<ControllerInput>),
+    Wiimote(WiimoteInput),
+}
+
+// A line starting with '!', or a lone '}' closing a block.
+pub(crate) enum Directive {
+    // !def name {
+    Define(String),
+    // !loop N {
+    Loop(u64),
+    // }
+    End,
+    // !name or !name * N
+    Call(String, u64),
+}
+
+pub(crate) struct InputLine {
+    // None for lines that are blank, only a comment or a directive.
+    pub(crate) record: Option<InputRecord>,
+    pub(crate) directive: Option<Directive>,
+    // The port from a `P1:` label, numbered from 0.
+    pub(crate) port: Option<u8>,
+    pub(crate) repeat: u64,
+    pub(crate) comment: Option<String>,
+}
+
+pub(crate) struct InputReader {
+    pub(crate) line: u64,
+    // Directives are only understood where something expands them.
+    pub(crate) directives: bool,
 }
 
 impl InputReader {
-    fn new() -> InputReader {
+    pub(crate) fn new() -> InputReader {
         InputReader {
             line: 0,
+            directives: false,
         }
     }
 
+    pub(crate) fn skip_lines(&mut self, count: u64) {
+        self.line += count;
+    }
+
     fn get_token<'a>(&self, token_opt: Option<&'a str>) -> Dtm2txtResult<&'a str> {
         match token_opt {
             Some(token) => Ok(token),
@@ -89,12 +137,281 @@ impl InputReader {
             })
     }
 
-    fn read_controller_input(&mut self, line_result: Result<String, IoError>) -> Dtm2txtResult<ControllerInput> {
+    fn error(&self, reason: ControllerInputParseError) -> Dtm2txtError {
+        Dtm2txtError::ControllerInputParseError {
+            reason: reason,
+            line: self.line,
+        }
+    }
+
+    pub(crate) fn read_line(&mut self, line_result: Result<String, IoError>) -> Dtm2txtResult<InputLine> {
         let line = line_result
-            .map_err(|err| Dtm2txtError::ControllerInputParseError {
-                reason: ControllerInputParseError::IoError(err),
-                line: self.line,
-            })?;
+            .map_err(|err| self.error(ControllerInputParseError::IoError(err)))?;
+
+        // Everything after a '#' is a comment.
+        let (line, comment) = match line.find('#') {
+            Some(idx) => (&line[..idx], Some(line[idx + 1..].trim().to_string())),
+            None => (&line[..], None),
+        };
+        // The column names over table lines are only there to be read.
+        if line.trim().is_empty() || is_table_columns(line) {
+            self.line += 1;
+            return Ok(InputLine {
+                record: None,
+                directive: None,
+                port: None,
+                repeat: 0,
+                comment: comment,
+            });
+        }
+        if line.trim_start().starts_with('!') || line.trim() == "}" {
+            if !self.directives {
+                return Err(self.error(ControllerInputParseError::UnsupportedDirectiveError));
+            }
+            let directive = self.read_directive(line.trim())?;
+            self.line += 1;
+            return Ok(InputLine {
+                record: None,
+                directive: Some(directive),
+                port: None,
+                repeat: 0,
+                comment: comment,
+            });
+        }
+
+        let (line, repeat) = self.split_repeat(line)?;
+        let (line, port) = self.split_port_label(line)?;
+
+        let record = if line.contains(';') {
+            let inputs = line.split(';')
+                .map(|part| self.read_gamecube_input(part.trim()))
+                .collect::<Dtm2txtResult<Vec<_>>>()?;
+            InputRecord::Ports(inputs)
+        }
+        else if line.starts_with('W') {
+            InputRecord::Wiimote(self.read_wiimote_input(line)?)
+        }
+        else {
+            InputRecord::Controller(self.read_gamecube_input(line)?)
+        };
+
+        self.line += 1;
+
+        Ok(InputLine {
+            record: Some(record),
+            directive: None,
+            port: port,
+            repeat: repeat,
+            comment: comment,
+        })
+    }
+
+    fn read_directive(&self, line: &str) -> Dtm2txtResult<Directive> {
+        if line == "}" {
+            return Ok(Directive::End);
+        }
+        let (body, repeat) = self.split_repeat(&line[1..])?;
+        let tokens: Vec<&str> = body.split_whitespace().collect();
+        let is_name = |name: &str| !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+
+        match tokens[..] {
+            ["def", name, "{"] if repeat == 1 && is_name(name) && name != "def" && name != "loop" => {
+                Ok(Directive::Define(name.to_string()))
+            }
+            ["loop", times, "{"] if repeat == 1 => {
+                let times = times.parse::<u64>()
+                    .map_err(|err| self.error(ControllerInputParseError::ParseIntError(err)))?;
+                if times == 0 {
+                    return Err(self.error(ControllerInputParseError::InvalidRepeatError));
+                }
+                Ok(Directive::Loop(times))
+            }
+            [name] if is_name(name) && name != "def" && name != "loop" => Ok(Directive::Call(name.to_string(), repeat)),
+            _ => Err(self.error(ControllerInputParseError::InvalidDirectiveError)),
+        }
+    }
+
+    // `P2: ...` says which port a line is for, counting from 1 like Dolphin.
+    fn split_port_label<'a>(&self, line: &'a str) -> Dtm2txtResult<(&'a str, Option<u8>)> {
+        let line = line.trim_start();
+        if !line.starts_with('P') {
+            return Ok((line, None));
+        }
+        let colon_idx = line.find(':').ok_or_else(|| self.error(ControllerInputParseError::InvalidPortLabelError))?;
+        match line[1..colon_idx].parse::<u8>() {
+            Ok(port @ 1..=4) => Ok((line[colon_idx + 1..].trim_start(), Some(port - 1))),
+            _ => Err(self.error(ControllerInputParseError::InvalidPortLabelError)),
+        }
+    }
+
+    fn read_gamecube_input(&self, line: &str) -> Dtm2txtResult<ControllerInput> {
+        if line.starts_with('|') {
+            self.read_mnemonic_input(line)
+        }
+        // Only a compact line starts with all twelve buttons in one token.
+        else if line.split_whitespace().next().map(|buttons| buttons.len()) == Some(MNEMONICS.len()) {
+            self.read_compact_input(line)
+        }
+        // A classic line never has dots. One with every button pressed is
+        // the same in both syntaxes.
+        else if line.contains('.') {
+            self.read_table_input(line)
+        }
+        else {
+            self.read_controller_input(line)
+        }
+    }
+
+    // `... * N` stands for N copies of the line.
+    fn split_repeat<'a>(&self, line: &'a str) -> Dtm2txtResult<(&'a str, u64)> {
+        match line.rfind('*') {
+            Some(idx) => {
+                let repeat = line[idx + 1..].trim()
+                    .parse::<u64>()
+                    .map_err(|err| self.error(ControllerInputParseError::ParseIntError(err)))?;
+                if repeat == 0 {
+                    return Err(self.error(ControllerInputParseError::InvalidRepeatError));
+                }
+                Ok((&line[..idx], repeat))
+            }
+            None => Ok((line, 1)),
+        }
+    }
+
+    // W A1 37 00 08 ...
+    fn read_wiimote_input(&self, line: &str) -> Dtm2txtResult<WiimoteInput> {
+        let mut tokens = line.split_whitespace();
+        if tokens.next() != Some("W") {
+            return Err(self.error(ControllerInputParseError::InvalidWiimoteReportError));
+        }
+
+        let report = tokens
+            .map(|token| {
+                if token.len() != 2 {
+                    return Err(self.error(ControllerInputParseError::InvalidWiimoteReportError));
+                }
+                u8::from_str_radix(token, 16)
+                    .map_err(|err| self.error(ControllerInputParseError::ParseIntError(err)))
+            })
+            .collect::<Dtm2txtResult<Vec<u8>>>()?;
+
+        if report.len() > 255 {
+            return Err(self.error(ControllerInputParseError::InvalidWiimoteReportError));
+        }
+
+        Ok(WiimoteInput {
+            report: report,
+        })
+    }
+
+    // |SABXYZUDLRlr|X,Y|CX,CY|LP,RP| [CD RST CC RSV]
+    fn read_mnemonic_input(&self, line: &str) -> Dtm2txtResult<ControllerInput> {
+        let mut fields = line[1..].splitn(5, '|');
+
+        let mut input = ControllerInput::NEUTRAL;
+        self.read_mnemonic_buttons(fields.next(), &mut input)?;
+
+        for &(x_axis, y_axis) in [(Axis::AnalogX, Axis::AnalogY), (Axis::CX, Axis::CY), (Axis::LPressure, Axis::RPressure)].iter() {
+            let mut values = self.get_token(fields.next())?.split(',');
+            input.set_axis(x_axis, self.read_axis(values.next().map(str::trim))?);
+            input.set_axis(y_axis, self.read_axis(values.next().map(str::trim))?);
+            if values.next().is_some() {
+                return Err(self.error(ControllerInputParseError::InvalidButtonError));
+            }
+        }
+
+        // Whatever follows the last '|' is the optional extra inputs.
+        self.read_extra_buttons(self.get_token(fields.next())?.split_whitespace(), &mut input)?;
+
+        Ok(input)
+    }
+
+    // SABXYZUDLRlr LP RP X Y CX CY [CD RST CC RSV]
+    fn read_compact_input(&self, line: &str) -> Dtm2txtResult<ControllerInput> {
+        let mut tokens = line.split_whitespace();
+        let mut input = ControllerInput::NEUTRAL;
+        self.read_mnemonic_buttons(tokens.next(), &mut input)?;
+        for &axis in Axis::ALL.iter() {
+            input.set_axis(axis, self.read_axis(tokens.next())?);
+        }
+        self.read_extra_buttons(tokens, &mut input)?;
+
+        Ok(input)
+    }
+
+    // One character per button, like `SA..........`.
+    fn read_mnemonic_buttons(&self, token_opt: Option<&str>, input: &mut ControllerInput) -> Dtm2txtResult<()> {
+        let buttons = self.get_token(token_opt)?;
+        if buttons.chars().count() != MNEMONICS.len() {
+            return Err(self.error(ControllerInputParseError::InvalidButtonError));
+        }
+        for ((&button, mnemonic), c) in MNEMONIC_BUTTONS.iter().zip(MNEMONICS.chars()).zip(buttons.chars()) {
+            let pressed = if c == mnemonic {
+                true
+            }
+            else if c == '.' {
+                false
+            }
+            else {
+                return Err(self.error(ControllerInputParseError::InvalidButtonError));
+            };
+            input.set_pressed(button, pressed);
+        }
+        Ok(())
+    }
+
+    fn read_extra_buttons<'a, I>(&self, tokens: I, input: &mut ControllerInput) -> Dtm2txtResult<()>
+        where I: Iterator<Item = &'a str>,
+    {
+        for token in tokens {
+            match token {
+                "CD" => input.change_disc = true,
+                "RST" => input.reset = true,
+                "CC" => input.controller_connected = true,
+                "RSV" => input.reserved = true,
+                _ => return Err(self.error(ControllerInputParseError::InvalidButtonError)),
+            }
+        }
+        Ok(())
+    }
+
+    // S A . . . . . . . . .. RT   0 255 128 128 128 128 .. RST .. ...
+    fn read_table_input(&self, line: &str) -> Dtm2txtResult<ControllerInput> {
+        let mut tokens = line.split_whitespace();
+        let mut input = ControllerInput::NEUTRAL;
+        for &button in MNEMONIC_BUTTONS.iter() {
+            let pressed = self.read_table_cell(tokens.next(), button)?;
+            input.set_pressed(button, pressed);
+        }
+        for &axis in Axis::ALL.iter() {
+            input.set_axis(axis, self.read_axis(tokens.next())?);
+        }
+        for &button in TABLE_EXTRAS.iter() {
+            let pressed = self.read_table_cell(tokens.next(), button)?;
+            input.set_pressed(button, pressed);
+        }
+        if tokens.next().is_some() {
+            return Err(self.error(ControllerInputParseError::InvalidButtonError));
+        }
+
+        Ok(input)
+    }
+
+    // The button's name, or dots as wide as it.
+    fn read_table_cell(&self, token_opt: Option<&str>, button: Button) -> Dtm2txtResult<bool> {
+        let token = self.get_token(token_opt)?;
+        if token == button.name() {
+            Ok(true)
+        }
+        else if token.len() == button.name().len() && is_dots(token) {
+            Ok(false)
+        }
+        else {
+            Err(self.error(ControllerInputParseError::InvalidButtonError))
+        }
+    }
+
+    fn read_controller_input(&self, line: &str) -> Dtm2txtResult<ControllerInput> {
         let mut tokens = line.split_whitespace();
         let start = self.read_button(tokens.next(), "S", "s")?;
         let a = self.read_button(tokens.next(), "A", "a")?;
@@ -132,8 +449,6 @@ impl InputReader {
             }
         }
 
-        self.line += 1;
-
         Ok(ControllerInput {
             start: start,
             a: a,
@@ -161,9 +476,201 @@ impl InputReader {
     }
 }
 
+fn is_dots(token: &str) -> bool {
+    !token.is_empty() && token.bytes().all(|byte| byte == b'.')
+}
+
+// The row of column names `FrameSyntax::Table` starts the inputs with, for
+// any port layout.
+fn is_table_columns(line: &str) -> bool {
+    let columns = || {
+        MNEMONIC_BUTTONS.iter().map(|button| button.name())
+            .chain(TABLE_AXES.iter().cloned())
+            .chain(TABLE_EXTRAS.iter().map(|button| button.name()))
+    };
+    // Checked on every line, so most are turned away by their start.
+    line.trim_start().starts_with("S A B ") && line.split(';').all(|part| part.split_whitespace().eq(columns()))
+}
+
+enum Block {
+    Define(String),
+    Loop(u64),
+}
+
+// Records along with how many inputs they come to once repeated, which is
+// kept under MAX_EXPANDED_INPUTS.
+#[derive(Default)]
+struct Records {
+    records: Vec<(InputRecord, u64)>,
+    inputs: u64,
+}
+
+impl Records {
+    fn add(&mut self, inputs: u64) -> Result<(), ControllerInputParseError> {
+        self.inputs = add_inputs(self.inputs, inputs)?;
+        Ok(())
+    }
+
+    fn repeat(&self, times: u64) -> Result<Records, ControllerInputParseError> {
+        if self.records.is_empty() {
+            return Ok(Records::default());
+        }
+        let inputs = self.inputs.checked_mul(times)
+            .filter(|&inputs| inputs <= MAX_EXPANDED_INPUTS)
+            .ok_or(ControllerInputParseError::TooManyInputsError)?;
+        let mut records = Vec::with_capacity(self.records.len() * times as usize);
+        for _ in 0..times {
+            records.extend_from_slice(&self.records);
+        }
+        Ok(Records {
+            records: records,
+            inputs: inputs,
+        })
+    }
+}
+
+// How many inputs a record comes to: one per port for a joined line, and one
+// otherwise.
+fn record_inputs(record: &InputRecord, repeat: u64) -> Result<u64, ControllerInputParseError> {
+    let per_record = match *record {
+        InputRecord::Ports(ref inputs) => inputs.len() as u64,
+        InputRecord::Controller(_) | InputRecord::Wiimote(_) => 1,
+    };
+    per_record.checked_mul(repeat).ok_or(ControllerInputParseError::TooManyInputsError)
+}
+
+fn add_inputs(inputs: u64, more: u64) -> Result<u64, ControllerInputParseError> {
+    inputs.checked_add(more)
+        .filter(|&inputs| inputs <= MAX_EXPANDED_INPUTS)
+        .ok_or(ControllerInputParseError::TooManyInputsError)
+}
+
+// Expands `!def` macros and `!loop` blocks as the lines come in. Records
+// inside a block are held back until it closes; the rest pass straight
+// through. A macro has to be defined before it's used, so it can't call
+// itself.
+#[derive(Default)]
+pub(crate) struct Expander {
+    macros: HashMap<String, Records>,
+    blocks: Vec<(Block, Records)>,
+}
+
+impl Expander {
+    pub(crate) fn record(&mut self, record: InputRecord, repeat: u64) -> Result<Option<(InputRecord, u64)>, ControllerInputParseError> {
+        match self.blocks.last_mut() {
+            Some(&mut (_, ref mut records)) => {
+                records.add(record_inputs(&record, repeat)?)?;
+                records.records.push((record, repeat));
+                Ok(None)
+            }
+            None => Ok(Some((record, repeat))),
+        }
+    }
+
+    // Returns the records the directive adds to the movie, which is nothing
+    // while a block is still open.
+    pub(crate) fn directive(&mut self, directive: Directive) -> Result<Vec<(InputRecord, u64)>, ControllerInputParseError> {
+        let expanded = match directive {
+            Directive::Define(name) => {
+                self.blocks.push((Block::Define(name), Records::default()));
+                return Ok(Vec::new());
+            }
+            Directive::Loop(times) => {
+                self.blocks.push((Block::Loop(times), Records::default()));
+                return Ok(Vec::new());
+            }
+            Directive::End => match self.blocks.pop() {
+                Some((Block::Define(name), records)) => {
+                    self.macros.insert(name, records);
+                    return Ok(Vec::new());
+                }
+                Some((Block::Loop(times), records)) => records.repeat(times)?,
+                None => return Err(ControllerInputParseError::UnmatchedBraceError),
+            },
+            Directive::Call(name, times) => match self.macros.get(&name) {
+                Some(records) => records.repeat(times)?,
+                None => return Err(ControllerInputParseError::UnknownMacroError(name)),
+            },
+        };
+
+        match self.blocks.last_mut() {
+            Some(&mut (_, ref mut records)) => {
+                records.add(expanded.inputs)?;
+                records.records.extend(expanded.records);
+                Ok(Vec::new())
+            }
+            None => Ok(expanded.records),
+        }
+    }
+
+    // Whether records are going straight to the movie rather than into a
+    // block.
+    pub(crate) fn is_idle(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub(crate) fn finish(&self) -> Result<(), ControllerInputParseError> {
+        if self.blocks.is_empty() {
+            Ok(())
+        }
+        else {
+            Err(ControllerInputParseError::UnclosedBlockError)
+        }
+    }
+}
+
+// The input lines of a txt file, after the header.
+pub(crate) type FrameLines<R> = Skip<Lines<BufReader<R>>>;
+
+// Where `decode_inputs` gets its parsed lines. Errors are reported by the
+// line number `reader` is on once a line comes back, so a source that reads
+// ahead has to set it.
+pub(crate) trait LineSource {
+    fn next_line(&mut self, reader: &mut InputReader) -> Option<Dtm2txtResult<InputLine>>;
+}
+
+impl<I> LineSource for I
+    where I: Iterator<Item = Result<String, IoError>>,
+{
+    fn next_line(&mut self, reader: &mut InputReader) -> Option<Dtm2txtResult<InputLine>> {
+        self.next().map(|line| reader.read_line(line))
+    }
+}
+
+// The JSON at the top of a txt file: the dtm header, plus whatever came after
+// the inputs in the dtm.
+#[derive(Deserialize)]
+struct TextHeader {
+    #[serde(flatten)]
+    header: DtmHeader,
+    #[serde(default, deserialize_with = "dtm::deserialize_hex")]
+    trailing: Vec<u8>,
+}
+
+// Everything a txt file holds besides the movie itself.
+pub struct DecodedText<S = Vec<ControllerInput>> {
+    pub dtm: Dtm<S>,
+    pub comments: Vec<Comment>,
+    pub warnings: Vec<DecodeWarning>,
+}
+
+// What's left of a txt file once its inputs have gone to an `InputSink`.
+pub(crate) struct DecodedRest {
+    pub header: DtmHeader,
+    pub trailing: Vec<u8>,
+    pub comments: Vec<Comment>,
+    pub warnings: Vec<DecodeWarning>,
+}
+
 pub struct TextDecoder<R> {
     inner: LineCountRead<R>,
     input_reader: InputReader,
+    lenient_header: bool,
+    header: Option<DtmHeader>,
+    options: DecodeOptions,
+    progress: Option<ProgressCallback>,
+    trailing: Vec<u8>,
+    warnings: Vec<DecodeWarning>,
 }
 
 impl<R> TextDecoder<R>
@@ -173,31 +680,379 @@ impl<R> TextDecoder<R>
         TextDecoder {
             inner: LineCountRead::new(inner),
             input_reader: InputReader::new(),
+            lenient_header: false,
+            header: None,
+            options: DecodeOptions::default(),
+            progress: None,
+            trailing: Vec::new(),
+            warnings: Vec::new(),
         }
     }
 
-    pub fn decode(mut self) -> Dtm2txtResult<Dtm>
-        where R: Read,
+    // Fills in header fields missing from the JSON with Dolphin's defaults,
+    // so a hand-written movie only needs the fields it cares about. Lenient
+    // decoding always does this.
+    pub fn lenient_header(mut self, lenient: bool) -> TextDecoder<R> {
+        self.lenient_header = lenient;
+        self
+    }
+
+    // For a piece of a movie that's only input lines, with no JSON header:
+    // `header` is used instead of reading one.
+    pub fn headerless(mut self, header: DtmHeader) -> TextDecoder<R> {
+        self.header = Some(header);
+        self
+    }
+
+    pub fn options(mut self, options: DecodeOptions) -> TextDecoder<R> {
+        self.options = options;
+        self
+    }
+
+    // Reports how far along the inputs it is; see `ProgressCallback`.
+    pub fn progress<F>(mut self, callback: F) -> TextDecoder<R>
+        where F: FnMut(u64, u64) + 'static,
+    {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    pub fn decode(self) -> Dtm2txtResult<Dtm> {
+        self.decode_into()
+    }
+
+    pub fn decode_into<S>(self) -> Dtm2txtResult<Dtm<S>>
+        where S: FrameStore + Default,
+    {
+        self.decode_all().map(|decoded| decoded.dtm)
+    }
+
+    // Also returns the `#` comments in the frame section, so they can be
+    // kept in a sidecar file.
+    pub fn decode_with_comments(self) -> Dtm2txtResult<(Dtm, Vec<Comment>)> {
+        self.decode_all().map(|decoded| (decoded.dtm, decoded.comments))
+    }
+
+    // Also returns what a lenient decode had to recover from.
+    pub fn decode_with_warnings(self) -> Dtm2txtResult<(Dtm, Vec<DecodeWarning>)> {
+        self.decode_all().map(|decoded| (decoded.dtm, decoded.warnings))
+    }
+
+    pub fn decode_all<S>(self) -> Dtm2txtResult<DecodedText<S>>
+        where S: FrameStore + Default,
+    {
+        let mut inputs = Inputs::<S>::default();
+        let decoded = self.decode_inputs(&mut inputs)?;
+
+        Ok(DecodedText {
+            dtm: Dtm {
+                header: decoded.header,
+                controller_data: inputs.controller_data,
+                wiimote_data: inputs.wiimote_data,
+                trailing: decoded.trailing,
+            },
+            comments: decoded.comments,
+            warnings: decoded.warnings,
+        })
+    }
+
+    // Hands the inputs to `sink` as they're read, returning everything else
+    // in the file once they're all in.
+    pub(crate) fn decode_inputs<K>(self, sink: &mut K) -> Dtm2txtResult<DecodedRest>
+        where K: InputSink,
     {
-        let mut header: DtmHeader = {
+        self.decode_inputs_with(sink, |lines| lines)
+    }
+
+    // Like `decode_inputs`, with `source` turning the input lines into parsed
+    // ones, e.g. reusing those of an earlier run from a cache.
+    pub(crate) fn decode_inputs_with<K, F, L>(mut self, sink: &mut K, source: F) -> Dtm2txtResult<DecodedRest>
+        where K: InputSink,
+              F: FnOnce(FrameLines<R>) -> L,
+              L: LineSource,
+    {
+        let mut header = self.decode_header()?;
+        let headerless = self.header.is_some();
+        let options = self.options;
+        let lenient = options.strictness == Strictness::Lenient;
+        let mut progress = Progress::new(self.progress.take(), header.input_count);
+        let mut trailing = self.take_trailing();
+        let mut warnings = mem::take(&mut self.warnings);
+
+        let (lines, mut input_reader) = self.into_frame_lines();
+        input_reader.directives = true;
+        let mut expander = Expander::default();
+        let mut comments = Vec::new();
+        // Every record read, including any outside --skip/--take.
+        let mut frames = 0;
+        let mut wiimote_frames = 0;
+        let ports = header.gamecube_ports();
+        let mut lines = source(lines);
+        while let Some(input_line) = lines.next_line(&mut input_reader) {
+            progress.update(frames + wiimote_frames);
+            let input_line = match input_line {
+                Ok(input_line) => input_line,
+                Err(Dtm2txtError::ControllerInputParseError{reason, line}) if lenient => {
+                    warnings.push(DecodeWarning::SkippedLine {
+                        line: line,
+                        reason: reason,
+                    });
+                    input_reader.skip_lines(1);
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            if let (Some(text), Some(frame)) = (input_line.comment, options.kept_position(frames + wiimote_frames)) {
+                comments.push(Comment {
+                    frame: frame,
+                    trailing: input_line.record.is_some(),
+                    text: text,
+                });
+            }
+            // Labels are checked where they're written; inside a !def or
+            // !loop there's no telling which frame a line will end up on.
+            if let (Some(found), Some(&InputRecord::Controller(_))) = (input_line.port, input_line.record.as_ref()) {
+                if let Some(&expected) = ports.get(frames as usize % ports.len().max(1)) {
+                    if found != expected && expander.is_idle() {
+                        let reason = ControllerInputParseError::WrongPortError {
+                            expected: expected,
+                            found: found,
+                        };
+                        let line = input_reader.line - 1;
+                        if lenient {
+                            warnings.push(DecodeWarning::SkippedLine {
+                                line: line,
+                                reason: reason,
+                            });
+                            continue;
+                        }
+                        return Err(Dtm2txtError::ControllerInputParseError {
+                            reason: reason,
+                            line: line,
+                        });
+                    }
+                }
+            }
+            let expanded = match (input_line.record, input_line.directive) {
+                (Some(record), _) => expander.record(record, input_line.repeat).map(|record| (record, Vec::new())),
+                (None, Some(directive)) => expander.directive(directive).map(|expanded| (None, expanded)),
+                (None, None) => continue,
+            };
+            let (record, expanded) = match expanded {
+                Ok(expanded) => expanded,
+                Err(reason) => {
+                    let line = input_reader.line - 1;
+                    if lenient {
+                        warnings.push(DecodeWarning::SkippedLine {
+                            line: line,
+                            reason: reason,
+                        });
+                        continue;
+                    }
+                    return Err(Dtm2txtError::ControllerInputParseError {
+                        reason: reason,
+                        line: line,
+                    });
+                }
+            };
+
+            for (record, repeat) in record.into_iter().chain(expanded) {
+                // A dtm can't say which record belongs to which kind of
+                // controller, so a movie has to stick to one. Nor can a
+                // `* N` on a line ask for more inputs than there's room for,
+                // and a joined line needs an input for every port, or every
+                // line after it would land on the wrong ones.
+                let problem = match record {
+                    InputRecord::Controller(_) | InputRecord::Ports(_) if wiimote_frames != 0 =>
+                        Some(ControllerInputParseError::MixedControllerTypesError),
+                    InputRecord::Wiimote(_) if frames != 0 =>
+                        Some(ControllerInputParseError::MixedControllerTypesError),
+                    InputRecord::Ports(ref inputs) if inputs.len() != ports.len() =>
+                        Some(ControllerInputParseError::WrongPortCountError {
+                            expected: ports.len(),
+                            found: inputs.len(),
+                        }),
+                    _ => record_inputs(&record, repeat)
+                        .and_then(|inputs| add_inputs(frames + wiimote_frames, inputs))
+                        .err(),
+                };
+                if let Some(reason) = problem {
+                    let line = input_reader.line - 1;
+                    if lenient {
+                        warnings.push(DecodeWarning::SkippedLine {
+                            line: line,
+                            reason: reason,
+                        });
+                        continue;
+                    }
+                    return Err(Dtm2txtError::ControllerInputParseError {
+                        reason: reason,
+                        line: line,
+                    });
+                }
+
+                match record {
+                    InputRecord::Controller(input) => {
+                        for _ in 0..options.kept(frames, repeat) {
+                            sink.push_controller(input)?;
+                        }
+                        frames += repeat;
+                    }
+                    InputRecord::Ports(ref inputs) => {
+                        for _ in 0..repeat {
+                            for &input in inputs.iter() {
+                                if options.kept(frames, 1) == 1 {
+                                    sink.push_controller(input)?;
+                                }
+                                frames += 1;
+                            }
+                        }
+                    }
+                    InputRecord::Wiimote(ref input) => {
+                        for _ in 0..options.kept(wiimote_frames, repeat) {
+                            sink.push_wiimote(input.clone())?;
+                        }
+                        wiimote_frames += repeat;
+                    }
+                }
+            }
+        }
+        if let Err(reason) = expander.finish() {
+            if !lenient {
+                return Err(Dtm2txtError::ControllerInputParseError {
+                    reason: reason,
+                    line: input_reader.line,
+                });
+            }
+            warnings.push(DecodeWarning::SkippedLine {
+                line: input_reader.line,
+                reason: reason,
+            });
+        }
+
+        progress.finish(frames + wiimote_frames);
+        // Without a header of its own, there's nothing to disagree with.
+        if !headerless {
+            options.check_consistency(&header, frames, wiimote_frames, &mut warnings)?;
+        }
+        header.input_count = frames + wiimote_frames;
+        options.fit_window(&mut header, &mut trailing, frames + wiimote_frames);
+
+        Ok(DecodedRest {
+            header: header,
+            trailing: trailing,
+            comments: comments,
+            warnings: warnings,
+        })
+    }
+
+    // Reads only the JSON header, without parsing any input lines.
+    pub fn decode_header(&mut self) -> Dtm2txtResult<DtmHeader> {
+        if let Some(ref header) = self.header {
+            self.input_reader.line = 1;
+            return Ok(header.clone());
+        }
+
+        let mut header = {
             let mut de = serde_json::Deserializer::new(JsonIoRead::new(&mut self.inner));
-            Deserialize::deserialize(&mut de)?
+            if self.lenient_header || self.options.strictness == Strictness::Lenient {
+                let mut fields: Value = Deserialize::deserialize(&mut de)?;
+                if let Some(trailing) = fields.as_object_mut().and_then(|fields| fields.remove("trailing")) {
+                    self.trailing = dtm::deserialize_hex(trailing)?;
+                }
+                header_patch::merge_header(&DtmHeader::default(), &fields)?
+            }
+            else {
+                let text_header: TextHeader = Deserialize::deserialize(&mut de)?;
+                self.trailing = text_header.trailing;
+                text_header.header
+            }
         };
 
+        self.options.check_reserved(&mut header, &mut self.warnings);
+
         // Add one to account for the fact that reading stops after last bracket.
         self.input_reader.line += self.inner.lines_read() + 1;
 
+        Ok(header)
+    }
+
+    // The bytes after the inputs, once the header has been decoded.
+    pub(crate) fn take_trailing(&mut self) -> Vec<u8> {
+        mem::take(&mut self.trailing)
+    }
+
+    pub(crate) fn into_frame_lines(self) -> (FrameLines<R>, InputReader) {
+        // The rest of the line the header ends on isn't an input line.
+        let header_lines = if self.header.is_some() { 0 } else { 1 };
         let line_reader = BufReader::new(self.inner.inner);
-        let mut controller_data = Vec::new();
-        for line in line_reader.lines().skip(1) {
-            controller_data.push(self.input_reader.read_controller_input(line)?);
+        (line_reader.lines().skip(header_lines), self.input_reader)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE: &str = "s a b x y z u d l r lt rt   0   0 128 128 128 128";
+
+    fn decode(lines: &str) -> Dtm2txtResult<Dtm> {
+        let header = DtmHeader::builder().gamecube_ports(&[0]).build();
+        TextDecoder::new(lines.as_bytes()).headerless(header).decode()
+    }
+
+    fn error_line(result: Dtm2txtResult<Dtm>) -> u64 {
+        match result {
+            Err(Dtm2txtError::ControllerInputParseError{reason: ControllerInputParseError::TooManyInputsError, line}) => line,
+            Err(err) => panic!("wrong error: {}", err),
+            Ok(dtm) => panic!("decoded {} inputs", dtm.controller_data.len()),
         }
+    }
 
-        header.input_count = controller_data.len() as u64;
+    #[test]
+    fn expands_loops_and_macros() {
+        let text = format!("!def tap {{\n{} * 2\n}}\n!loop 3 {{\n!tap\n{}\n}}\n", LINE, LINE);
+        assert_eq!(decode(&text).unwrap().controller_data.len(), 9);
+    }
 
-        Ok(Dtm {
-            header: header,
-            controller_data: controller_data,
-        })
+    #[test]
+    fn refuses_loops_past_the_limit() {
+        let text = format!("{}\n!loop 4096 {{\n!loop 4096 {{\n{} * 2\n}}\n}}\n", LINE, LINE);
+        assert_eq!(error_line(decode(&text)), 6);
+
+        // Records in a block count up as they come.
+        let text = format!("!def tap {{\n{} * 10000000\n}}\n!loop 1 {{\n!tap\n!tap\n}}\n", LINE);
+        assert_eq!(error_line(decode(&text)), 6);
+
+        // Nothing to repeat is fine however many times it's repeated.
+        assert!(decode("!loop 18446744073709551615 {\n}\n").unwrap().controller_data.is_empty());
+    }
+
+    #[test]
+    fn refuses_repeats_past_the_limit() {
+        let text = format!("{}\n{} * 18446744073709551615\n", LINE, LINE);
+        assert_eq!(error_line(decode(&text)), 2);
+
+        let text = format!("{} * {}\n", LINE, MAX_EXPANDED_INPUTS + 1);
+        assert_eq!(error_line(decode(&text)), 1);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn joined_lines_need_an_input_for_every_port() {
+        let decode_ports = |ports: &[u8], text: &str| {
+            let header = DtmHeader::builder().gamecube_ports(ports).build();
+            TextDecoder::new(text.as_bytes()).headerless(header).decode()
+        };
+        let pair = format!("{} ; {}", LINE, LINE);
+        assert_eq!(decode_ports(&[0, 1], &format!("{}\n{}\n", pair, pair)).unwrap().controller_data.len(), 4);
+
+        for &(ports, expected) in [(&[0, 1, 2][..], 3), (&[0][..], 1)].iter() {
+            match decode_ports(ports, &format!("{} ; {}\n{} ; {}\n", LINE, LINE, LINE, LINE)) {
+                Err(Dtm2txtError::ControllerInputParseError{reason: ControllerInputParseError::WrongPortCountError{expected: wanted, found}, line}) => {
+                    assert_eq!((wanted, found, line), (expected, 2, 1));
+                }
+                other => panic!("decoded {:?}", other.map(|dtm| dtm.controller_data.len())),
+            }
+        }
+    }
+}
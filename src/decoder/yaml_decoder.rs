@@ -0,0 +1,126 @@
+use std::io::Read;
+
+use serde_yaml;
+
+use dtm::{self, Dtm, DtmHeader};
+use decoder::options::{DecodeOptions, DecodeWarning, Strictness};
+use decoder::text_decoder::{InputReader, InputRecord};
+use error::{Dtm2txtError, ControllerInputParseError, Dtm2txtResult};
+use frames::FrameStore;
+
+#[derive(Deserialize)]
+struct YamlMovie {
+    header: DtmHeader,
+    #[serde(default, deserialize_with = "dtm::deserialize_hex")]
+    trailing: Vec<u8>,
+    frames: Option<Vec<String>>,
+}
+
+// Reads what `YamlEncoder` writes. Each frame entry takes the same syntax as
+// a txt input line, `* N` repeats included. Line numbers in errors count
+// frame entries, starting from 1.
+pub struct YamlDecoder<R> {
+    inner: R,
+    options: DecodeOptions,
+}
+
+impl<R> YamlDecoder<R>
+    where R: Read,
+{
+    pub fn new(inner: R) -> YamlDecoder<R> {
+        YamlDecoder {
+            inner: inner,
+            options: DecodeOptions::default(),
+        }
+    }
+
+    pub fn options(mut self, options: DecodeOptions) -> YamlDecoder<R> {
+        self.options = options;
+        self
+    }
+
+    pub fn decode(self) -> Dtm2txtResult<Dtm> {
+        self.decode_with_warnings().map(|(dtm, _)| dtm)
+    }
+
+    // Reads a file with or without frames.
+    pub fn decode_header(self) -> Dtm2txtResult<DtmHeader> {
+        let mut warnings = Vec::new();
+        let mut movie: YamlMovie = serde_yaml::from_reader(self.inner)?;
+        self.options.check_reserved(&mut movie.header, &mut warnings);
+        Ok(movie.header)
+    }
+
+    pub fn decode_with_warnings(self) -> Dtm2txtResult<(Dtm, Vec<DecodeWarning>)> {
+        let mut warnings = Vec::new();
+        let movie: YamlMovie = serde_yaml::from_reader(self.inner)?;
+        let mut header = movie.header;
+        self.options.check_reserved(&mut header, &mut warnings);
+        let lines = movie.frames.ok_or(Dtm2txtError::MissingFramesError)?;
+        let lenient = self.options.strictness == Strictness::Lenient;
+
+        let mut input_reader = InputReader::new();
+        input_reader.skip_lines(1);
+        let mut controller_data = Vec::new();
+        let mut wiimote_data = Vec::new();
+        for line in lines {
+            let input_line = match input_reader.read_line(Ok(line)) {
+                Ok(input_line) => input_line,
+                Err(Dtm2txtError::ControllerInputParseError{reason, line}) if lenient => {
+                    warnings.push(DecodeWarning::SkippedLine {
+                        line: line,
+                        reason: reason,
+                    });
+                    input_reader.skip_lines(1);
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            let record = match input_line.record {
+                Some(record) => record,
+                None => continue,
+            };
+
+            let mixed = match record {
+                InputRecord::Controller(_) | InputRecord::Ports(_) => !wiimote_data.is_empty(),
+                InputRecord::Wiimote(_) => !controller_data.is_empty(),
+            };
+            if mixed {
+                let reason = ControllerInputParseError::MixedControllerTypesError;
+                let line = input_reader.line - 1;
+                if lenient {
+                    warnings.push(DecodeWarning::SkippedLine {
+                        line: line,
+                        reason: reason,
+                    });
+                    continue;
+                }
+                return Err(Dtm2txtError::ControllerInputParseError {
+                    reason: reason,
+                    line: line,
+                });
+            }
+
+            for _ in 0..input_line.repeat {
+                match record {
+                    InputRecord::Controller(input) => controller_data.push(input),
+                    InputRecord::Ports(ref inputs) => controller_data.extend_from_slice(inputs),
+                    InputRecord::Wiimote(ref input) => wiimote_data.push(input.clone()),
+                }
+            }
+        }
+
+        let frames = FrameStore::len(&controller_data);
+        self.options.check_consistency(&header, frames, wiimote_data.len() as u64, &mut warnings)?;
+        header.input_count = frames + wiimote_data.len() as u64;
+
+        let mut dtm = Dtm {
+            header: header,
+            controller_data: controller_data,
+            wiimote_data: wiimote_data,
+            trailing: movie.trailing,
+        };
+        self.options.apply_window(&mut dtm);
+        Ok((dtm, warnings))
+    }
+}
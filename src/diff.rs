@@ -0,0 +1,140 @@
+use std::cmp;
+use std::fmt;
+
+use serde_json::{self, Value};
+
+use dtm::{Dtm, DtmHeader, ControllerInput, Button, Axis};
+use error::Dtm2txtResult;
+
+// A header field that has a different value in each movie.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeaderDifference {
+    pub field: String,
+    pub first: Value,
+    pub second: Value,
+}
+
+// How a single frame differs between two movies.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FrameDifference {
+    // The names of the buttons and axes that don't match.
+    Inputs(Vec<&'static str>),
+    WiimoteReport,
+    OnlyInFirst,
+    OnlyInSecond,
+}
+
+impl fmt::Display for FrameDifference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FrameDifference::Inputs(ref fields) => write!(f, "different {}", fields.join(", ")),
+            FrameDifference::WiimoteReport => f.write_str("different Wii remote reports"),
+            FrameDifference::OnlyInFirst => f.write_str("only in the first movie"),
+            FrameDifference::OnlyInSecond => f.write_str("only in the second movie"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MovieDiff {
+    pub header: Vec<HeaderDifference>,
+    pub first_difference: Option<(u64, FrameDifference)>,
+    pub differing_frames: u64,
+    // Whether the bytes after the inputs differ.
+    pub trailing: bool,
+}
+
+impl MovieDiff {
+    pub fn is_empty(&self) -> bool {
+        self.header.is_empty() && self.differing_frames == 0 && !self.trailing
+    }
+
+    fn add(&mut self, frame: u64, difference: FrameDifference) {
+        self.differing_frames += 1;
+        if self.first_difference.is_none() {
+            self.first_difference = Some((frame, difference));
+        }
+    }
+}
+
+// Compares headers through their JSON form, so fields are named the same way
+// as in a txt file.
+pub fn diff_headers(first: &DtmHeader, second: &DtmHeader) -> Dtm2txtResult<Vec<HeaderDifference>> {
+    let first = serde_json::to_value(first)?;
+    let second = serde_json::to_value(second)?;
+
+    let mut differences = Vec::new();
+    if let (Value::Object(first), Value::Object(second)) = (first, second) {
+        for (field, first_value) in first {
+            let second_value = second.get(&field).cloned().unwrap_or(Value::Null);
+            if first_value != second_value {
+                differences.push(HeaderDifference {
+                    field: field,
+                    first: first_value,
+                    second: second_value,
+                });
+            }
+        }
+    }
+    Ok(differences)
+}
+
+pub fn diff_inputs(first: &ControllerInput, second: &ControllerInput) -> Vec<&'static str> {
+    let buttons = Button::ALL.iter()
+        .filter(|&&button| first.pressed(button) != second.pressed(button))
+        .map(|button| button.long_name());
+    let axes = Axis::ALL.iter()
+        .filter(|&&axis| first.axis(axis) != second.axis(axis))
+        .map(|axis| axis.name());
+    buttons.chain(axes).collect()
+}
+
+// Walks two lists of records side by side, numbering them from `offset`.
+fn diff_records<T, F>(first: &[T], second: &[T], offset: u64, diff: &mut MovieDiff, compare: F)
+    where F: Fn(&T, &T) -> Option<FrameDifference>,
+{
+    for idx in 0..cmp::max(first.len(), second.len()) {
+        let difference = match (first.get(idx), second.get(idx)) {
+            (Some(first), Some(second)) => compare(first, second),
+            (Some(_), None) => Some(FrameDifference::OnlyInFirst),
+            _ => Some(FrameDifference::OnlyInSecond),
+        };
+        if let Some(difference) = difference {
+            diff.add(offset + idx as u64, difference);
+        }
+    }
+}
+
+impl Dtm {
+    // Frames are numbered the way the txt format lays them out: controller
+    // inputs first, then Wii remote reports.
+    pub fn diff(&self, other: &Dtm) -> Dtm2txtResult<MovieDiff> {
+        let mut diff = MovieDiff {
+            header: diff_headers(&self.header, &other.header)?,
+            first_difference: None,
+            differing_frames: 0,
+            trailing: self.trailing != other.trailing,
+        };
+
+        diff_records(&self.controller_data, &other.controller_data, 0, &mut diff, |first, second| {
+            let fields = diff_inputs(first, second);
+            if fields.is_empty() {
+                None
+            }
+            else {
+                Some(FrameDifference::Inputs(fields))
+            }
+        });
+        let offset = cmp::max(self.controller_data.len(), other.controller_data.len()) as u64;
+        diff_records(&self.wiimote_data, &other.wiimote_data, offset, &mut diff, |first, second| {
+            if first.report == second.report {
+                None
+            }
+            else {
+                Some(FrameDifference::WiimoteReport)
+            }
+        });
+
+        Ok(diff)
+    }
+}
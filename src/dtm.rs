@@ -1,96 +1,276 @@
 use std::fmt;
+use std::iter::FromIterator;
+use std::str::FromStr;
 
 use serde::{Serialize, Deserialize, Serializer, Deserializer};
 use serde::de::{self, Visitor, Unexpected};
 
-macro_rules! bytestring {
-    ($name:ident, $visitor_name: ident, $length:expr) => {
-        #[derive(Clone, Copy, Debug)]
-        pub struct $name(pub [u8; $length]);
-
-        impl Serialize for $name {
-            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-                where S: Serializer,
-            {
-                let mut bytestring = String::new();
-                for val in self.0.iter() {
-                    bytestring += &format!("{:02X}", val);
-                }
-
-                serializer.serialize_str(&bytestring)
-            }
+use buttons::Buttons;
+use error::ParseHexError;
+use frames::FrameStore;
+use humanize;
+
+// Fixed-size binary header fields, which txt headers show as all-caps hex
+// strings. Reading them is more forgiving; see `parse_hex`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct HexBytes<const N: usize>(pub [u8; N]);
+
+pub type AudioEmulator = HexBytes<16>;
+pub type Md5 = HexBytes<16>;
+pub type Reserved2 = HexBytes<12>;
+pub type GitRevision = HexBytes<20>;
+pub type Reserved3 = HexBytes<11>;
+
+impl<const N: usize> HexBytes<N> {
+    pub fn new(bytes: [u8; N]) -> HexBytes<N> {
+        HexBytes(bytes)
+    }
+
+    pub fn bytes(&self) -> &[u8; N] {
+        &self.0
+    }
+
+    pub fn into_bytes(self) -> [u8; N] {
+        self.0
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.iter().all(|&byte| byte == 0)
+    }
+}
+
+impl<const N: usize> Default for HexBytes<N> {
+    fn default() -> HexBytes<N> {
+        HexBytes([0; N])
+    }
+}
+
+impl<const N: usize> From<[u8; N]> for HexBytes<N> {
+    fn from(bytes: [u8; N]) -> HexBytes<N> {
+        HexBytes(bytes)
+    }
+}
+
+impl<const N: usize> AsRef<[u8]> for HexBytes<N> {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<const N: usize> fmt::Display for HexBytes<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&format_hex(&self.0))
+    }
+}
+
+impl<const N: usize> FromStr for HexBytes<N> {
+    type Err = ParseHexError;
+
+    fn from_str(text: &str) -> Result<HexBytes<N>, ParseHexError> {
+        let bytes = parse_hex(text)?;
+        if bytes.len() != N {
+            return Err(ParseHexError::WrongLength {
+                expected: N,
+                found: bytes.len(),
+            });
         }
+        let mut buffer = [0; N];
+        buffer.copy_from_slice(&bytes);
+        Ok(HexBytes(buffer))
+    }
+}
 
-        impl<'de> Deserialize<'de> for $name {
-            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-                where D: Deserializer<'de>,
-            {
-                deserializer.deserialize_str($visitor_name)
-            }
+impl<const N: usize> Serialize for HexBytes<N> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer,
+    {
+        serialize_hex(&self.0, serializer)
+    }
+}
+
+impl<'de, const N: usize> Deserialize<'de> for HexBytes<N> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: Deserializer<'de>,
+    {
+        deserializer.deserialize_str(HexBytesVisitor)
+    }
+}
+
+struct HexBytesVisitor<const N: usize>;
+
+impl<'de, const N: usize> Visitor<'de> for HexBytesVisitor<N> {
+    type Value = HexBytes<N>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a {}-byte long hex string", N)
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+        where E: de::Error,
+    {
+        value.parse().map_err(|err| de::Error::custom(err))
+    }
+}
+
+fn format_hex(bytes: &[u8]) -> String {
+    let mut hex = String::new();
+    for val in bytes.iter() {
+        hex += &format!("{:02X}", val);
+    }
+    hex
+}
+
+// Takes either case, and bytes may be split up by colons, dashes or
+// whitespace the way MD5s and git hashes get pasted around, as long as no
+// byte is split in half.
+fn parse_hex(text: &str) -> Result<Vec<u8>, ParseHexError> {
+    let nibble = |c: char| c.to_digit(16)
+        .map(|digit| digit as u8)
+        .ok_or(ParseHexError::InvalidCharacter(c));
+
+    let mut bytes = Vec::new();
+    for group in text.split(|c: char| c == ':' || c == '-' || c.is_whitespace()) {
+        let chars: Vec<char> = group.chars().collect();
+        if chars.len() % 2 != 0 {
+            return Err(ParseHexError::OddLength);
+        }
+        for pair in chars.chunks(2) {
+            bytes.push((nibble(pair[0])? << 4) | nibble(pair[1])?);
         }
+    }
+    Ok(bytes)
+}
+
+// Any-length hex strings, for `serialize_with`/`deserialize_with`.
+pub(crate) fn serialize_hex<T, S>(bytes: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where T: AsRef<[u8]>,
+          S: Serializer,
+{
+    serializer.serialize_str(&format_hex(bytes.as_ref()))
+}
 
-        struct $visitor_name;
+pub(crate) fn deserialize_hex<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+    where D: Deserializer<'de>,
+{
+    deserializer.deserialize_str(HexVisitor)
+}
 
-        impl<'de> Visitor<'de> for $visitor_name {
-            type Value = $name;
+struct HexVisitor;
 
-            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
-                formatter.write_str(concat!("a ", $length, "-byte long all-caps hex string"))
-            }
+impl<'de> Visitor<'de> for HexVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a hex string")
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+        where E: de::Error,
+    {
+        parse_hex(value).map_err(|err| de::Error::custom(err))
+    }
+}
 
-            fn visit_str<E>(self, value: &str) ->  Result<Self::Value, E>
-                where E: de::Error,
-            {
-                let mut buffer = [0; $length];
-
-                if value.len() != $length * 2 {
-                    return Err(de::Error::invalid_type(Unexpected::Other("string of invalid length"), &self));
-                }
-
-                let mut value_iter = value.chars();
-                let mut idx = 0;
-                while let Some(high_char) = value_iter.next() {
-                    // Should be guaranteed because previous check.
-                    let low_char = value_iter.next().unwrap();
-
-                    let high_nibble = if high_char <= 'F' && high_char >= 'A' {
-                        high_char as u8 - 'A' as u8 + 10
-                    }
-                    else if high_char <= '9' && high_char >= '0' {
-                        high_char as u8 - '0' as u8
-                    }
-                    else {
-                        return Err(de::Error::invalid_type(Unexpected::Other("invalid character"), &self));
-                    };
-
-                    let low_nibble = if low_char <= 'F' && low_char >= 'A' {
-                        low_char as u8 - 'A' as u8 + 10
-                    }
-                    else if low_char <= '9' && low_char >= '0' {
-                        low_char as u8 - '0' as u8
-                    }
-                    else {
-                        return Err(de::Error::invalid_type(Unexpected::Other("invalid character"), &self));
-                    };
-
-                    buffer[idx] = (high_nibble << 4) | low_nibble;
-
-                    idx += 1;
-                }
-
-                Ok($name(buffer))
+// The start time is written as a date with no time zone, since Dolphin takes
+// it from the recorder's clock. The raw number of seconds is still accepted
+// (and used for dates no one could mean).
+fn serialize_start_time<S>(secs: &u64, serializer: S) -> Result<S::Ok, S::Error>
+    where S: Serializer,
+{
+    match humanize::format_iso8601(*secs) {
+        Some(date) => serializer.serialize_str(&date),
+        None => serializer.serialize_u64(*secs),
+    }
+}
+
+fn deserialize_start_time<'de, D>(deserializer: D) -> Result<u64, D::Error>
+    where D: Deserializer<'de>,
+{
+    deserializer.deserialize_any(StartTimeVisitor)
+}
+
+struct StartTimeVisitor;
+
+impl<'de> Visitor<'de> for StartTimeVisitor {
+    type Value = u64;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a date like 2017-06-03T14:22:01 or a number of seconds since 1970")
+    }
+
+    fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E>
+        where E: de::Error,
+    {
+        Ok(value)
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+        where E: de::Error,
+    {
+        humanize::parse_iso8601(value)
+            .ok_or_else(|| de::Error::invalid_value(Unexpected::Str(value), &self))
+    }
+}
+
+// The exact bytes of a header string that isn't valid UTF-8, like an author
+// name in Shift-JIS. It sits next to a lossy text version of the string,
+// which is what gets shown and edited.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RawString(pub Vec<u8>);
+
+impl RawString {
+    // Splits the bytes of a header string into its text, plus the raw bytes
+    // when the text alone would lose some of them.
+    pub fn decode(bytes: Vec<u8>) -> (String, Option<RawString>) {
+        match String::from_utf8(bytes) {
+            Ok(text) => (text, None),
+            Err(err) => {
+                let bytes = err.into_bytes();
+                (String::from_utf8_lossy(&bytes).into_owned(), Some(RawString(bytes)))
             }
         }
-    };
+    }
+
+    // The bytes to write for a header string. The raw bytes only win while
+    // the text still matches them, so editing the text takes effect.
+    pub fn encode<'a>(text: &'a str, raw: &'a Option<RawString>) -> &'a [u8] {
+        match *raw {
+            Some(ref raw) if String::from_utf8_lossy(&raw.0) == text => &raw.0,
+            _ => text.as_bytes(),
+        }
+    }
+}
+
+impl Serialize for RawString {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer,
+    {
+        serialize_hex(&self.0, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RawString {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: Deserializer<'de>,
+    {
+        deserialize_hex(deserializer).map(RawString)
+    }
 }
 
-bytestring!(AudioEmulator, AudioEmulatorVisitor, 16);
-bytestring!(Md5, Md5Visitor, 16);
-bytestring!(Reserved2, Reserved2Visitor, 12);
-bytestring!(GitRevision, GitRevisionVisitor, 20);
-bytestring!(Reserved3, Reserved3Visitor, 11);
+// `with_a(true)` and so on, for building inputs in one expression.
+macro_rules! with_buttons {
+    ($($name:ident: $field:ident),* $(,)*) => {
+        $(
+            pub fn $name(mut self, pressed: bool) -> ControllerInput {
+                self.$field = pressed;
+                self
+            }
+        )*
+    };
+}
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "python", ::pyo3::pyclass(module = "dtm2txt", get_all, set_all, eq))]
 pub struct ControllerInput {
     pub start: bool,
     pub a: bool,
@@ -116,9 +296,522 @@ pub struct ControllerInput {
     pub c_y: u8,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Button {
+    Start,
+    A,
+    B,
+    X,
+    Y,
+    Z,
+    Up,
+    Down,
+    Left,
+    Right,
+    L,
+    R,
+    ChangeDisc,
+    Reset,
+    ControllerConnected,
+    Reserved,
+}
+
+impl Button {
+    pub const ALL: [Button; 16] = [
+        Button::Start, Button::A, Button::B, Button::X, Button::Y, Button::Z,
+        Button::Up, Button::Down, Button::Left, Button::Right, Button::L, Button::R,
+        Button::ChangeDisc, Button::Reset, Button::ControllerConnected, Button::Reserved,
+    ];
+
+    // The token used for this button in the txt format.
+    pub fn name(self) -> &'static str {
+        match self {
+            Button::Start => "S",
+            Button::A => "A",
+            Button::B => "B",
+            Button::X => "X",
+            Button::Y => "Y",
+            Button::Z => "Z",
+            Button::Up => "U",
+            Button::Down => "D",
+            Button::Left => "L",
+            Button::Right => "R",
+            Button::L => "LT",
+            Button::R => "RT",
+            Button::ChangeDisc => "CD",
+            Button::Reset => "RST",
+            Button::ControllerConnected => "CC",
+            Button::Reserved => "RSV",
+        }
+    }
+
+    // A name that is unambiguous on its own, used for column headers.
+    pub fn long_name(self) -> &'static str {
+        match self {
+            Button::Start => "start",
+            Button::A => "a",
+            Button::B => "b",
+            Button::X => "x",
+            Button::Y => "y",
+            Button::Z => "z",
+            Button::Up => "up",
+            Button::Down => "down",
+            Button::Left => "left",
+            Button::Right => "right",
+            Button::L => "l_trigger",
+            Button::R => "r_trigger",
+            Button::ChangeDisc => "change_disc",
+            Button::Reset => "reset",
+            Button::ControllerConnected => "controller_connected",
+            Button::Reserved => "reserved",
+        }
+    }
+
+    // Accepts either the txt format token or the long field name, in any case.
+    pub fn from_name(name: &str) -> Option<Button> {
+        let button = match name.to_ascii_lowercase().as_str() {
+            "s" | "start" => Button::Start,
+            "a" => Button::A,
+            "b" => Button::B,
+            "x" => Button::X,
+            "y" => Button::Y,
+            "z" => Button::Z,
+            "u" | "up" => Button::Up,
+            "d" | "down" => Button::Down,
+            "l" | "left" => Button::Left,
+            "r" | "right" => Button::Right,
+            "lt" | "l_trigger" => Button::L,
+            "rt" | "r_trigger" => Button::R,
+            "cd" | "change_disc" => Button::ChangeDisc,
+            "rst" | "reset" => Button::Reset,
+            "cc" | "controller_connected" => Button::ControllerConnected,
+            "rsv" | "reserved" => Button::Reserved,
+            _ => return None,
+        };
+        Some(button)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Axis {
+    LPressure,
+    RPressure,
+    AnalogX,
+    AnalogY,
+    CX,
+    CY,
+}
+
+impl Axis {
+    pub const ALL: [Axis; 6] = [
+        Axis::LPressure, Axis::RPressure, Axis::AnalogX, Axis::AnalogY, Axis::CX, Axis::CY,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Axis::LPressure => "l_pressure",
+            Axis::RPressure => "r_pressure",
+            Axis::AnalogX => "analog_x",
+            Axis::AnalogY => "analog_y",
+            Axis::CX => "c_x",
+            Axis::CY => "c_y",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Axis> {
+        Axis::ALL.iter()
+            .cloned()
+            .find(|axis| axis.name().eq_ignore_ascii_case(name))
+    }
+
+    // Where the axis sits in a controller record, after the two bytes of
+    // `Buttons`.
+    pub fn offset(self) -> usize {
+        match self {
+            Axis::LPressure => 2,
+            Axis::RPressure => 3,
+            Axis::AnalogX => 4,
+            Axis::AnalogY => 5,
+            Axis::CX => 6,
+            Axis::CY => 7,
+        }
+    }
+}
+
+impl ControllerInput {
+    // Nothing pressed, triggers released and both sticks centered.
+    pub const NEUTRAL: ControllerInput = ControllerInput {
+        start: false,
+        a: false,
+        b: false,
+        x: false,
+        y: false,
+        z: false,
+        up: false,
+        down: false,
+        left: false,
+        right: false,
+        l: false,
+        r: false,
+        change_disc: false,
+        reset: false,
+        controller_connected: false,
+        reserved: false,
+        l_pressure: 0,
+        r_pressure: 0,
+        analog_x: 128,
+        analog_y: 128,
+        c_x: 128,
+        c_y: 128,
+    };
+
+    // Reads a controller record as it's stored in a dtm. Any eight bytes are
+    // an input, so this can't fail.
+    pub fn from_bytes(record: &[u8; CONTROLLER_RECORD_SIZE]) -> ControllerInput {
+        let mut input = ControllerInput::NEUTRAL;
+        input.set_buttons(Buttons::from_bytes([record[0], record[1]]));
+        for &axis in Axis::ALL.iter() {
+            input.set_axis(axis, record[axis.offset()]);
+        }
+        input
+    }
+
+    // The controller record a dtm stores for this input.
+    pub fn to_bytes(&self) -> [u8; CONTROLLER_RECORD_SIZE] {
+        let mut record = [0; CONTROLLER_RECORD_SIZE];
+        record[..2].copy_from_slice(&self.buttons().to_bytes());
+        for &axis in Axis::ALL.iter() {
+            record[axis.offset()] = self.axis(axis);
+        }
+        record
+    }
+
+    pub fn pressed(&self, button: Button) -> bool {
+        match button {
+            Button::Start => self.start,
+            Button::A => self.a,
+            Button::B => self.b,
+            Button::X => self.x,
+            Button::Y => self.y,
+            Button::Z => self.z,
+            Button::Up => self.up,
+            Button::Down => self.down,
+            Button::Left => self.left,
+            Button::Right => self.right,
+            Button::L => self.l,
+            Button::R => self.r,
+            Button::ChangeDisc => self.change_disc,
+            Button::Reset => self.reset,
+            Button::ControllerConnected => self.controller_connected,
+            Button::Reserved => self.reserved,
+        }
+    }
+
+    pub fn set_pressed(&mut self, button: Button, pressed: bool) {
+        let field = match button {
+            Button::Start => &mut self.start,
+            Button::A => &mut self.a,
+            Button::B => &mut self.b,
+            Button::X => &mut self.x,
+            Button::Y => &mut self.y,
+            Button::Z => &mut self.z,
+            Button::Up => &mut self.up,
+            Button::Down => &mut self.down,
+            Button::Left => &mut self.left,
+            Button::Right => &mut self.right,
+            Button::L => &mut self.l,
+            Button::R => &mut self.r,
+            Button::ChangeDisc => &mut self.change_disc,
+            Button::Reset => &mut self.reset,
+            Button::ControllerConnected => &mut self.controller_connected,
+            Button::Reserved => &mut self.reserved,
+        };
+        *field = pressed;
+    }
+
+    pub fn axis(&self, axis: Axis) -> u8 {
+        match axis {
+            Axis::LPressure => self.l_pressure,
+            Axis::RPressure => self.r_pressure,
+            Axis::AnalogX => self.analog_x,
+            Axis::AnalogY => self.analog_y,
+            Axis::CX => self.c_x,
+            Axis::CY => self.c_y,
+        }
+    }
+
+    pub fn set_axis(&mut self, axis: Axis, value: u8) {
+        let field = match axis {
+            Axis::LPressure => &mut self.l_pressure,
+            Axis::RPressure => &mut self.r_pressure,
+            Axis::AnalogX => &mut self.analog_x,
+            Axis::AnalogY => &mut self.analog_y,
+            Axis::CX => &mut self.c_x,
+            Axis::CY => &mut self.c_y,
+        };
+        *field = value;
+    }
+
+    pub fn with_button(mut self, button: Button, pressed: bool) -> ControllerInput {
+        self.set_pressed(button, pressed);
+        self
+    }
+
+    pub fn with_axis(mut self, axis: Axis, value: u8) -> ControllerInput {
+        self.set_axis(axis, value);
+        self
+    }
+
+    with_buttons! {
+        with_start: start,
+        with_a: a,
+        with_b: b,
+        with_x: x,
+        with_y: y,
+        with_z: z,
+        with_up: up,
+        with_down: down,
+        with_left: left,
+        with_right: right,
+        with_l: l,
+        with_r: r,
+        with_change_disc: change_disc,
+        with_reset: reset,
+        with_controller_connected: controller_connected,
+        with_reserved: reserved,
+    }
+
+    // Main stick position.
+    pub fn with_analog(mut self, x: u8, y: u8) -> ControllerInput {
+        self.analog_x = x;
+        self.analog_y = y;
+        self
+    }
+
+    pub fn with_c_stick(mut self, x: u8, y: u8) -> ControllerInput {
+        self.c_x = x;
+        self.c_y = y;
+        self
+    }
+
+    // Analog trigger pressures.
+    pub fn with_triggers(mut self, l: u8, r: u8) -> ControllerInput {
+        self.l_pressure = l;
+        self.r_pressure = r;
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WiimoteButton {
+    Left,
+    Right,
+    Down,
+    Up,
+    Plus,
+    Two,
+    One,
+    B,
+    A,
+    Minus,
+    Home,
+}
+
+impl WiimoteButton {
+    pub const ALL: [WiimoteButton; 11] = [
+        WiimoteButton::Left, WiimoteButton::Right, WiimoteButton::Down, WiimoteButton::Up,
+        WiimoteButton::Plus, WiimoteButton::Two, WiimoteButton::One, WiimoteButton::B,
+        WiimoteButton::A, WiimoteButton::Minus, WiimoteButton::Home,
+    ];
+
+    // Bit in the two core button bytes of a report, first byte low.
+    fn mask(self) -> u16 {
+        match self {
+            WiimoteButton::Left => 0x0001,
+            WiimoteButton::Right => 0x0002,
+            WiimoteButton::Down => 0x0004,
+            WiimoteButton::Up => 0x0008,
+            WiimoteButton::Plus => 0x0010,
+            WiimoteButton::Two => 0x0100,
+            WiimoteButton::One => 0x0200,
+            WiimoteButton::B => 0x0400,
+            WiimoteButton::A => 0x0800,
+            WiimoteButton::Minus => 0x1000,
+            WiimoteButton::Home => 0x8000,
+        }
+    }
+}
+
+// One Wii remote input report, stored exactly as Dolphin recorded it. The
+// accessors decode the common parts when the report mode has them.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct WiimoteInput {
+    pub report: Vec<u8>,
+}
+
+impl WiimoteInput {
+    // Older Dolphin versions record the 0xA1 HID header, newer ones start at
+    // the report ID.
+    fn data(&self) -> &[u8] {
+        match self.report.first() {
+            Some(&0xA1) => &self.report[1..],
+            _ => &self.report,
+        }
+    }
+
+    pub fn report_id(&self) -> Option<u8> {
+        self.data().first().cloned()
+    }
+
+    pub fn core_buttons(&self) -> Option<u16> {
+        let data = self.data();
+        match self.report_id() {
+            Some(0x30..=0x37) | Some(0x3E) | Some(0x3F) if data.len() >= 3 =>
+                Some(data[1] as u16 | (data[2] as u16) << 8),
+            _ => None,
+        }
+    }
+
+    pub fn pressed(&self, button: WiimoteButton) -> Option<bool> {
+        self.core_buttons().map(|buttons| buttons & button.mask() != 0)
+    }
+
+    // The high eight bits of each accelerometer axis.
+    pub fn accel(&self) -> Option<(u8, u8, u8)> {
+        let data = self.data();
+        match self.report_id() {
+            Some(0x31) | Some(0x33) | Some(0x35) | Some(0x37) if data.len() >= 6 =>
+                Some((data[3], data[4], data[5])),
+            _ => None,
+        }
+    }
+}
+
+// Dolphin's PowerPC::CPUCore. Written to txt files by name, or as the raw
+// number for cores this doesn't know about, so those still round-trip.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CpuCore {
+    Interpreter,
+    Jit64,
+    JitIl,
+    JitArm64,
+    CachedInterpreter,
+    Unknown(u8),
+}
+
+impl CpuCore {
+    const NAMED: [CpuCore; 5] = [
+        CpuCore::Interpreter, CpuCore::Jit64, CpuCore::JitIl, CpuCore::JitArm64, CpuCore::CachedInterpreter,
+    ];
+
+    pub fn name(self) -> Option<&'static str> {
+        match self {
+            CpuCore::Interpreter => Some("Interpreter"),
+            CpuCore::Jit64 => Some("JIT64"),
+            CpuCore::JitIl => Some("JITIL"),
+            CpuCore::JitArm64 => Some("JITARM64"),
+            CpuCore::CachedInterpreter => Some("CachedInterpreter"),
+            CpuCore::Unknown(_) => None,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<CpuCore> {
+        CpuCore::NAMED.iter()
+            .cloned()
+            .find(|core| core.name().map(|core_name| core_name.eq_ignore_ascii_case(name)).unwrap_or(false))
+    }
+}
+
+impl From<u8> for CpuCore {
+    fn from(value: u8) -> CpuCore {
+        match value {
+            0 => CpuCore::Interpreter,
+            1 => CpuCore::Jit64,
+            2 => CpuCore::JitIl,
+            4 => CpuCore::JitArm64,
+            5 => CpuCore::CachedInterpreter,
+            value => CpuCore::Unknown(value),
+        }
+    }
+}
+
+impl From<CpuCore> for u8 {
+    fn from(core: CpuCore) -> u8 {
+        match core {
+            CpuCore::Interpreter => 0,
+            CpuCore::Jit64 => 1,
+            CpuCore::JitIl => 2,
+            CpuCore::JitArm64 => 4,
+            CpuCore::CachedInterpreter => 5,
+            CpuCore::Unknown(value) => value,
+        }
+    }
+}
+
+impl fmt::Display for CpuCore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name() {
+            Some(name) => f.write_str(name),
+            None => write!(f, "unknown ({})", u8::from(*self)),
+        }
+    }
+}
+
+impl Serialize for CpuCore {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer,
+    {
+        match self.name() {
+            Some(name) => serializer.serialize_str(name),
+            None => serializer.serialize_u8((*self).into()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for CpuCore {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: Deserializer<'de>,
+    {
+        deserializer.deserialize_any(CpuCoreVisitor)
+    }
+}
+
+struct CpuCoreVisitor;
+
+impl<'de> Visitor<'de> for CpuCoreVisitor {
+    type Value = CpuCore;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a CPU core name or a number from 0 to 255")
+    }
+
+    // Older txt files have the raw number.
+    fn visit_u64<E>(self, value: u64) -> Result<Self::Value, E>
+        where E: de::Error,
+    {
+        if value > u8::MAX as u64 {
+            return Err(de::Error::invalid_value(Unexpected::Unsigned(value), &self));
+        }
+        Ok(CpuCore::from(value as u8))
+    }
+
+    fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+        where E: de::Error,
+    {
+        CpuCore::from_name(value)
+            .ok_or_else(|| de::Error::invalid_value(Unexpected::Str(value), &self))
+    }
+}
+
+// Headers are equal when every field is, reserved bytes and raw strings
+// included, so equal headers always write out the same bytes. To look past
+// leftovers in the reserved space, compare copies that have had
+// `clear_unknown_reserved` called on them.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct DtmHeader {
     pub game_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub game_id_raw: Option<RawString>,
     pub wii_game: bool,
     pub controllers: u8,
     pub savestate: bool,
@@ -128,9 +821,14 @@ pub struct DtmHeader {
     pub reserved1: u64,
     pub rerecord_count: u32,
     pub author: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author_raw: Option<RawString>,
     pub video_backend: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video_backend_raw: Option<RawString>,
     pub audio_emulator: AudioEmulator,
     pub md5: Md5,
+    #[serde(serialize_with = "serialize_start_time", deserialize_with = "deserialize_start_time")]
     pub start_time: u64,
     pub valid_config: bool,
     pub idle_skipping: bool,
@@ -138,7 +836,7 @@ pub struct DtmHeader {
     pub progressive_scan: bool,
     pub dsp_hle: bool,
     pub fast_disc: bool,
-    pub cpu_core: u8,
+    pub cpu_core: CpuCore,
     pub efb_access: bool,
     pub efb_copy: bool,
     pub efb_to_texture: bool,
@@ -154,15 +852,166 @@ pub struct DtmHeader {
     pub sysconf_pal60: bool,
     pub reserved2: Reserved2,
     pub second_disc: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub second_disc_raw: Option<RawString>,
+    // Dolphin 3.x headers are the same 256 bytes, but these were still part
+    // of the reserved space and left zeroed. txt headers written for those
+    // movies may leave them out, which reads back as the same zeroes.
+    #[serde(default)]
     pub git_revision: GitRevision,
+    #[serde(default)]
     pub dsp_irom_hash: u32,
+    #[serde(default)]
     pub dsp_coef_hash: u32,
+    #[serde(default)]
     pub tick_count: u64,
     pub reserved3: Reserved3,
 }
 
-#[derive(Clone, Debug)]
-pub struct Dtm {
+// Every revision of the header so far has been exactly this long, growing new
+// fields out of its reserved bytes instead.
+pub const HEADER_SIZE: usize = 0x100;
+// What every dtm starts with.
+pub const DTM_MAGIC: &[u8; 4] = b"DTM\x1A";
+// Each GameCube controller input: two bytes of buttons, the two trigger
+// pressures and then both sticks. See `Buttons` for the bits and
+// `Axis::offset` for where the rest go.
+pub const CONTROLLER_RECORD_SIZE: usize = 8;
+// The most inputs a script's repeats or a txt file's `!loop`s, macros and
+// `* N` repeats may add up to. A few bytes of either can ask for any number,
+// and they're expanded as they're read, so more than this is refused rather
+// than allocated. It's well over a day of a four-controller movie.
+pub const MAX_EXPANDED_INPUTS: u64 = 1 << 24;
+
+// Bytes of reserved2 that Dolphin gives a meaning to: language, follow
+// branch, FMA, GBA controllers, widescreen and country code. Byte 1 and
+// everything after byte 6 are still reserved.
+const KNOWN_RESERVED2: [bool; 12] = [true, false, true, true, true, true, true, false, false, false, false, false];
+
+impl DtmHeader {
+    // Names of the reserved fields holding something no known Dolphin
+    // writes there, which usually means a newer Dolphin put a setting in
+    // them.
+    pub fn unknown_reserved(&self) -> Vec<&'static str> {
+        let mut fields = Vec::new();
+        if self.reserved1 != 0 {
+            fields.push("reserved1");
+        }
+        if self.reserved2.0.iter().zip(KNOWN_RESERVED2.iter()).any(|(&byte, &known)| !known && byte != 0) {
+            fields.push("reserved2");
+        }
+        if !self.reserved3.is_zero() {
+            fields.push("reserved3");
+        }
+        fields
+    }
+
+    // Zeroes the reserved bytes that no known Dolphin uses, leaving the
+    // settings newer ones keep in reserved2 alone.
+    pub fn clear_unknown_reserved(&mut self) {
+        self.reserved1 = 0;
+        for (byte, &known) in self.reserved2.0.iter_mut().zip(KNOWN_RESERVED2.iter()) {
+            if !known {
+                *byte = 0;
+            }
+        }
+        self.reserved3 = Reserved3::default();
+    }
+
+    // Clears everything that says who made a movie, and when and with which
+    // build, so it can be submitted anonymously or diffed against someone
+    // else's run without the noise. Settings are left alone.
+    pub fn anonymize(&mut self) {
+        self.author = String::new();
+        self.author_raw = None;
+        self.start_time = 0;
+        self.git_revision = GitRevision::default();
+        self.clear_unknown_reserved();
+    }
+}
+
+// Settings match a fresh Dolphin install recording with one controller in
+// port 1.
+impl Default for DtmHeader {
+    fn default() -> DtmHeader {
+        DtmHeader {
+            game_id: String::new(),
+            game_id_raw: None,
+            wii_game: false,
+            controllers: 0x01,
+            savestate: false,
+            vi_count: 0,
+            input_count: 0,
+            lag_counter: 0,
+            reserved1: 0,
+            rerecord_count: 0,
+            author: String::new(),
+            author_raw: None,
+            video_backend: "OGL".to_string(),
+            video_backend_raw: None,
+            audio_emulator: AudioEmulator::default(),
+            md5: Md5::default(),
+            start_time: 0,
+            valid_config: true,
+            idle_skipping: true,
+            dual_core: true,
+            progressive_scan: false,
+            dsp_hle: true,
+            fast_disc: false,
+            cpu_core: CpuCore::Jit64,
+            efb_access: false,
+            efb_copy: true,
+            efb_to_texture: true,
+            efb_copy_cache: false,
+            emulate_format_changes: false,
+            use_xfb: false,
+            use_real_xfb: false,
+            memory_cards: 0x01,
+            memory_card_blank: false,
+            bongos_plugged: 0,
+            sync_gpu: false,
+            netplay: false,
+            sysconf_pal60: true,
+            reserved2: Reserved2::default(),
+            second_disc: String::new(),
+            second_disc_raw: None,
+            git_revision: GitRevision::default(),
+            dsp_irom_hash: 0,
+            dsp_coef_hash: 0,
+            tick_count: 0,
+            reserved3: Reserved3::default(),
+        }
+    }
+}
+
+// Serializes to any self-describing serde format (JSON, CBOR, MessagePack and
+// so on) without going through the txt syntax. Two movies are equal when
+// their headers, inputs and trailing bytes are; see `DtmHeader` for what
+// that means for the reserved fields.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Dtm<S = Vec<ControllerInput>> {
     pub header: DtmHeader,
-    pub controller_data: Vec<ControllerInput>,
+    pub controller_data: S,
+    // Only Wii movies with Wii remotes and no GameCube controllers have these.
+    pub wiimote_data: Vec<WiimoteInput>,
+    // Whatever follows the last input in the file, like padding or data
+    // appended by other tools. Written back out untouched.
+    pub trailing: Vec<u8>,
+}
+
+impl<S> Dtm<S>
+    where S: FrameStore,
+{
+    // Moves the inputs into a different frame storage, e.g. `RleFrames`.
+    pub fn with_storage<T>(self) -> Dtm<T>
+        where T: FrameStore + FromIterator<ControllerInput>,
+    {
+        let controller_data = self.controller_data.frames().collect();
+        Dtm {
+            header: self.header,
+            controller_data: controller_data,
+            wiimote_data: self.wiimote_data,
+            trailing: self.trailing,
+        }
+    }
 }
\ No newline at end of file
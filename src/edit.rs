@@ -0,0 +1,385 @@
+use std::ops::{Bound, Range, RangeBounds};
+use std::path::{Path, PathBuf};
+
+use dtm::{Dtm, DtmHeader, ControllerInput, RawString};
+use error::{Dtm2txtError, Dtm2txtResult};
+use frames::FrameStore;
+
+// Dolphin looks for the savestate of a savestate-anchored movie next to it,
+// with ".sav" appended to the full movie filename.
+pub fn savestate_path<P>(movie_path: P) -> PathBuf
+    where P: AsRef<Path>,
+{
+    let mut path = movie_path.as_ref().as_os_str().to_owned();
+    path.push(".sav");
+    path.into()
+}
+
+// Dolphin reads header strings up to the first NUL, so anything after one
+// is just leftover memory.
+fn cut_at_nul(text: &mut String, raw: &mut Option<RawString>) {
+    let bytes = RawString::encode(text, raw).to_vec();
+    let end = bytes.iter().position(|&byte| byte == 0).unwrap_or(bytes.len());
+    let (cut_text, cut_raw) = RawString::decode(bytes[..end].to_vec());
+    *text = cut_text;
+    *raw = cut_raw;
+}
+
+pub(crate) fn scale(value: u64, part: u64, total: u64) -> u64 {
+    if total == 0 {
+        0
+    }
+    else {
+        (value as u128 * part as u128 / total as u128) as u64
+    }
+}
+
+impl Dtm {
+    // A movie of `frames` neutral frames for each GameCube controller in
+    // `header`, to start a movie by hand without recording one first. The
+    // counts and timing in the header are filled in to match, and every input
+    // says its controller is plugged in, as a recording's would.
+    pub fn blank(header: DtmHeader, frames: u64) -> Dtm {
+        let inputs = frames * header.gamecube_port_count().max(1) as u64;
+        let mut dtm = Dtm {
+            header: header,
+            controller_data: (0..inputs).map(|_| ControllerInput::NEUTRAL.with_controller_connected(true)).collect(),
+            wiimote_data: Vec::new(),
+            trailing: Vec::new(),
+        };
+        dtm.recount();
+        dtm.estimate_timing();
+        dtm
+    }
+}
+
+// Everything else goes through `FrameStore`, so it works with any frame
+// storage.
+impl<S> Dtm<S>
+    where S: FrameStore + Clone,
+{
+    // A frame holds one input for each connected GameCube port, one port
+    // after another.
+    pub fn frame_count(&self) -> u64 {
+        self.controller_data.len() / self.ports_per_frame()
+    }
+
+    pub(crate) fn ports_per_frame(&self) -> u64 {
+        self.header.gamecube_port_count().max(1) as u64
+    }
+
+    // Turns any range of frame numbers into indices into `controller_data`,
+    // covering every port of each frame, erroring if it reaches past the end
+    // of the movie.
+    pub(crate) fn resolve_range<R>(&self, range: R) -> Dtm2txtResult<Range<usize>>
+        where R: RangeBounds<u64>,
+    {
+        let frames = self.frame_count();
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end.saturating_add(1),
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => frames,
+        };
+
+        if end > frames || start > end {
+            return Err(Dtm2txtError::FrameOutOfRangeError {
+                frame: if end > frames { end } else { start },
+                frames: frames,
+            });
+        }
+
+        let ports = self.ports_per_frame();
+        Ok((start * ports) as usize..(end * ports) as usize)
+    }
+
+    // Edits frames in place, calling back once for each port's input. The
+    // callback gets the frame number along with the input, so no index
+    // arithmetic is needed on the caller's side.
+    pub fn visit_frames_mut<R, F>(&mut self, range: R, mut visit: F) -> Dtm2txtResult<()>
+        where R: RangeBounds<u64>,
+              F: FnMut(u64, &mut ControllerInput),
+    {
+        let range = self.resolve_range(range)?;
+        let ports = self.ports_per_frame();
+        for idx in range.start as u64..range.end as u64 {
+            if let Some(mut input) = self.controller_data.frame(idx) {
+                visit(idx / ports, &mut input);
+                self.controller_data.set_frame(idx, input);
+            }
+        }
+        Ok(())
+    }
+
+    // Inserts `frames` so the first of them becomes frame `at`. `at` may be
+    // the frame count, which appends. Like `controller_data`, `frames` holds
+    // an input for each port of a frame in turn.
+    pub fn insert_frames<I>(&mut self, at: u64, frames: I) -> Dtm2txtResult<()>
+        where I: IntoIterator<Item = ControllerInput>,
+    {
+        self.splice(at..at, frames).map(|_| ())
+    }
+
+    // Replaces the frames in `range` with `replacement`, which doesn't have to
+    // be the same length. Returns the frames that were replaced.
+    pub fn splice<R, I>(&mut self, range: R, replacement: I) -> Dtm2txtResult<S>
+        where R: RangeBounds<u64>,
+              I: IntoIterator<Item = ControllerInput>,
+    {
+        let range = self.resolve_range(range)?;
+        let (start, end) = (range.start as u64, range.end as u64);
+        let removed = self.controller_data.slice(start..end);
+        let rest = self.controller_data.slice(end..self.controller_data.len());
+        self.controller_data.truncate(start);
+        for input in replacement {
+            self.controller_data.push(input);
+        }
+        self.controller_data.append_from(&rest);
+        self.recount();
+        Ok(removed)
+    }
+
+    // Plays the frames in `range` `times` times in a row instead of once, for
+    // things like mashing through menus. Dolphin does not record per-frame VI
+    // or tick counts, so those counters grow with the number of inputs.
+    pub fn repeat_range<R>(&mut self, range: R, times: u64) -> Dtm2txtResult<()>
+        where R: RangeBounds<u64>,
+    {
+        let range = self.resolve_range(range)?;
+        let frames = self.frame_count();
+        let segment: Vec<ControllerInput> = self.controller_data
+            .slice(range.start as u64..range.end as u64)
+            .frames()
+            .collect();
+        let repeated = (0..times).flat_map(|_| segment.iter().cloned());
+        let ports = self.ports_per_frame();
+        self.splice(range.start as u64 / ports..range.end as u64 / ports, repeated)?;
+
+        let new_frames = self.frame_count();
+        self.header.vi_count = scale(self.header.vi_count, new_frames, frames);
+        self.header.tick_count = scale(self.header.tick_count, new_frames, frames);
+        self.header.lag_counter = scale(self.header.lag_counter, new_frames, frames);
+        self.recount();
+        Ok(())
+    }
+
+    // Returns the frames that were removed.
+    pub fn remove_range<R>(&mut self, range: R) -> Dtm2txtResult<S>
+        where R: RangeBounds<u64>,
+    {
+        self.splice(range, None)
+    }
+
+    // Guesses vi_count and tick_count for movies put together by hand, whose
+    // header still has a template's numbers. Assumes the game polls input
+    // once per VI, plus one VI for every lag frame; tick_count follows from
+    // the VI rate for the game's region.
+    pub fn estimate_timing(&mut self) {
+        let (inputs, controllers) = if self.controller_data.is_empty() {
+            (self.wiimote_data.len() as u64, self.header.wiimote_count())
+        }
+        else {
+            (self.controller_data.len(), self.header.gamecube_port_count())
+        };
+        let frames = inputs / controllers.max(1) as u64;
+        self.header.vi_count = frames + self.header.lag_counter;
+        let secs = self.header.vi_count as f64 / self.header.vi_rate();
+        self.header.tick_count = (secs * self.header.cpu_clock()).round() as u64;
+    }
+
+    // Rewrites everything in the movie that Dolphin ignores into one fixed
+    // form, so two movies with the same inputs and settings encode to the
+    // same bytes and can be compared with cmp or git. Unknown reserved bytes
+    // and trailing data are dropped, strings are cut at their first NUL,
+    // unused bits of the bongo and memory card masks are cleared and the
+    // input count is recounted.
+    pub fn canonicalize(&mut self) {
+        {
+            let header = &mut self.header;
+            header.clear_unknown_reserved();
+            cut_at_nul(&mut header.game_id, &mut header.game_id_raw);
+            cut_at_nul(&mut header.author, &mut header.author_raw);
+            cut_at_nul(&mut header.video_backend, &mut header.video_backend_raw);
+            cut_at_nul(&mut header.second_disc, &mut header.second_disc_raw);
+            // One bit per GameCube port, and one per memory card slot.
+            header.bongos_plugged &= 0x0F;
+            header.memory_cards &= 0x03;
+        }
+        self.trailing.clear();
+        self.recount();
+    }
+
+    // Sets input_count to the number of inputs the movie really has.
+    pub fn recount(&mut self) {
+        self.header.input_count = self.controller_data.len() + self.wiimote_data.len() as u64;
+    }
+
+    // Drops every frame from `frame` on.
+    pub fn truncate(&mut self, frame: u64) -> Dtm2txtResult<()> {
+        let range = self.resolve_range(frame..)?;
+        self.controller_data.truncate(range.start as u64);
+        self.recount();
+        Ok(())
+    }
+
+    // Produces a movie that starts at `frame` from a savestate. Dolphin does
+    // not record per-frame VI or tick counts, so those counters are scaled by
+    // the fraction of inputs kept.
+    pub fn reanchor(&self, frame: u64) -> Dtm2txtResult<Dtm<S>> {
+        let frames = self.frame_count();
+        if frame >= frames {
+            return Err(Dtm2txtError::FrameOutOfRangeError {
+                frame: frame,
+                frames: frames,
+            });
+        }
+
+        let remaining = frames - frame;
+        let controller_data = self.controller_data.slice(frame * self.ports_per_frame()..self.controller_data.len());
+
+        let mut header = self.header.clone();
+        header.savestate = true;
+        header.input_count = controller_data.len();
+        header.vi_count = scale(self.header.vi_count, remaining, frames);
+        header.tick_count = scale(self.header.tick_count, remaining, frames);
+        header.lag_counter = scale(self.header.lag_counter, remaining, frames);
+
+        Ok(Dtm {
+            header: header,
+            controller_data: controller_data,
+            wiimote_data: Vec::new(),
+            trailing: self.trailing.clone(),
+        })
+    }
+
+    // Copies the frames in `range` into a movie of their own, which starts
+    // from a savestate unless the range starts at frame 0.
+    pub fn extract<R>(&self, range: R) -> Dtm2txtResult<Dtm<S>>
+        where R: RangeBounds<u64>,
+    {
+        let range = self.resolve_range(range)?;
+        let inputs = self.controller_data.len();
+        let kept = (range.end - range.start) as u64;
+
+        // Inputs and frames are in the same proportion, so the counters can
+        // be scaled by either.
+        let mut header = self.header.clone();
+        header.savestate = self.header.savestate || range.start > 0;
+        header.input_count = kept;
+        header.vi_count = scale(self.header.vi_count, kept, inputs);
+        header.tick_count = scale(self.header.tick_count, kept, inputs);
+        header.lag_counter = scale(self.header.lag_counter, kept, inputs);
+
+        // Bytes after the inputs only stay with a range that runs to the end.
+        let trailing = if range.end as u64 == inputs {
+            self.trailing.clone()
+        }
+        else {
+            Vec::new()
+        };
+
+        Ok(Dtm {
+            header: header,
+            controller_data: self.controller_data.slice(range.start as u64..range.end as u64),
+            wiimote_data: Vec::new(),
+            trailing: trailing,
+        })
+    }
+
+    // Cuts the movie in two at `frame`. The first half keeps this movie's
+    // start, the second half is reanchored to start from a savestate, and the
+    // counters are shared out so the halves add up to the whole.
+    pub fn split_at(&self, frame: u64) -> Dtm2txtResult<(Dtm<S>, Dtm<S>)> {
+        let second = self.reanchor(frame)?;
+
+        let mut first = self.clone();
+        first.truncate(frame)?;
+        first.trailing.clear();
+        first.header.vi_count -= second.header.vi_count;
+        first.header.tick_count -= second.header.tick_count;
+        first.header.lag_counter -= second.header.lag_counter;
+
+        Ok((first, second))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Five frames for ports 1 and 2, with each input's record index in its
+    // main stick's x axis.
+    fn two_port_movie() -> Dtm {
+        let header = DtmHeader::builder().gamecube_ports(&[0, 1]).build();
+        let mut dtm = Dtm::blank(header, 5);
+        for (idx, input) in dtm.controller_data.iter_mut().enumerate() {
+            input.analog_x = idx as u8;
+        }
+        dtm.trailing = vec![1, 2];
+        dtm
+    }
+
+    fn records<S>(dtm: &Dtm<S>) -> Vec<u8>
+        where S: FrameStore,
+    {
+        dtm.controller_data.frames().map(|input| input.analog_x).collect()
+    }
+
+    #[test]
+    fn blank_inputs_are_plugged_in() {
+        let dtm = two_port_movie();
+        assert!(dtm.controller_data.iter().all(|input| input.controller_connected));
+        assert!(dtm.audit().is_empty());
+    }
+
+    #[test]
+    fn frames_hold_every_port() {
+        let dtm = two_port_movie();
+        assert_eq!(dtm.frame_count(), 5);
+        assert_eq!(dtm.resolve_range(1..3).unwrap(), 2..6);
+        assert!(dtm.resolve_range(4..6).is_err());
+    }
+
+    #[test]
+    fn extract_keeps_whole_frames() {
+        let dtm = two_port_movie();
+        let part = dtm.extract(1..4).unwrap();
+        assert_eq!(records(&part), vec![2, 3, 4, 5, 6, 7]);
+        assert_eq!(part.header.input_count, 6);
+        assert_eq!(part.frame_count(), 3);
+        assert!(part.header.savestate);
+        assert!(part.trailing.is_empty());
+        assert_eq!(dtm.extract(2..).unwrap().trailing, vec![1, 2]);
+    }
+
+    #[test]
+    fn split_at_cuts_between_frames() {
+        let dtm = two_port_movie();
+        let (first, second) = dtm.split_at(3).unwrap();
+        assert_eq!(records(&first), vec![0, 1, 2, 3, 4, 5]);
+        assert_eq!(records(&second), vec![6, 7, 8, 9]);
+        assert_eq!(first.header.input_count, 6);
+        assert_eq!(second.header.input_count, 4);
+        assert_eq!(first.header.vi_count + second.header.vi_count, dtm.header.vi_count);
+        assert!(dtm.split_at(5).is_err());
+    }
+
+    #[test]
+    fn edits_cover_every_port() {
+        let mut dtm = two_port_movie();
+        let mut seen = Vec::new();
+        dtm.visit_frames_mut(1..3, |frame, input| seen.push((frame, input.analog_x))).unwrap();
+        assert_eq!(seen, vec![(1, 2), (1, 3), (2, 4), (2, 5)]);
+
+        dtm.repeat_range(4.., 2).unwrap();
+        assert_eq!(records(&dtm), vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 8, 9]);
+        dtm.remove_range(0..1).unwrap();
+        dtm.truncate(4).unwrap();
+        assert_eq!(records(&dtm), vec![2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(dtm.header.input_count, 8);
+    }
+}
@@ -0,0 +1,103 @@
+use std::future::Future;
+use std::io::{Error as IoError, ErrorKind};
+use std::mem;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use tokio::io::AsyncWrite;
+
+use dtm::Dtm;
+use encoder::dtm_encoder::DtmEncoder;
+use error::{Dtm2txtError, Dtm2txtResult};
+use frames::FrameStore;
+
+// Writes a dtm to an `AsyncWrite` without blocking the runtime. The movie is
+// encoded in memory up front, the same way `DtmEncoder` does it, and then
+// written out as the writer takes it.
+pub struct AsyncDtmEncoder<W> {
+    inner: W,
+    allow_inconsistent: bool,
+    truncate_strings: bool,
+}
+
+impl<W> AsyncDtmEncoder<W>
+    where W: AsyncWrite + Unpin,
+{
+    pub fn new(inner: W) -> AsyncDtmEncoder<W> {
+        AsyncDtmEncoder {
+            inner: inner,
+            allow_inconsistent: false,
+            truncate_strings: false,
+        }
+    }
+
+    // See `DtmEncoder::allow_inconsistent`.
+    pub fn allow_inconsistent(mut self, allow: bool) -> AsyncDtmEncoder<W> {
+        self.allow_inconsistent = allow;
+        self
+    }
+
+    // See `DtmEncoder::truncate_strings`.
+    pub fn truncate_strings(mut self, truncate: bool) -> AsyncDtmEncoder<W> {
+        self.truncate_strings = truncate;
+        self
+    }
+
+    // A movie that can't be encoded fails the first time the future is
+    // polled, before anything is written.
+    pub fn encode<S>(self, dtm: &Dtm<S>) -> Encode<W>
+        where S: FrameStore,
+    {
+        let mut bytes = Vec::new();
+        let encoded = DtmEncoder::new(&mut bytes)
+            .allow_inconsistent(self.allow_inconsistent)
+            .truncate_strings(self.truncate_strings)
+            .encode(dtm);
+        Encode {
+            inner: self.inner,
+            bytes: bytes,
+            written: 0,
+            error: encoded.err(),
+        }
+    }
+}
+
+// The future returned by `AsyncDtmEncoder::encode`.
+pub struct Encode<W> {
+    inner: W,
+    bytes: Vec<u8>,
+    written: usize,
+    error: Option<Dtm2txtError>,
+}
+
+impl<W> Future for Encode<W>
+    where W: AsyncWrite + Unpin,
+{
+    type Output = Dtm2txtResult<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Dtm2txtResult<()>> {
+        let this = self.get_mut();
+        if let Some(err) = this.error.take() {
+            return Poll::Ready(Err(err));
+        }
+
+        while this.written < this.bytes.len() {
+            match Pin::new(&mut this.inner).poll_write(cx, &this.bytes[this.written..]) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err.into())),
+                Poll::Ready(Ok(0)) => {
+                    let err = IoError::new(ErrorKind::WriteZero, "failed to write whole movie");
+                    return Poll::Ready(Err(err.into()));
+                }
+                Poll::Ready(Ok(len)) => this.written += len,
+            }
+        }
+        // Nothing left to hold on to while the flush finishes.
+        mem::take(&mut this.bytes);
+
+        match Pin::new(&mut this.inner).poll_flush(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(result) => Poll::Ready(result.map_err(Dtm2txtError::from)),
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::fmt;
+use std::io::Write;
+
+use ciborium;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de::{self, Visitor};
+
+use dtm::{Dtm, DtmHeader};
+use error::Dtm2txtResult;
+use frames::FrameStore;
+
+// Writes a movie as CBOR. The header is a map by field name like in the JSON
+// serialization, so other tools can read it without knowing the layout and
+// can skip fields they don't know about. The inputs are byte strings laid out
+// like in a dtm: eight bytes per GameCube input, and a string per Wii remote
+// report. Spelling each input out as a map would make the file many times the
+// size of the dtm.
+pub struct CborEncoder<W> {
+    inner: W,
+}
+
+// What `CborEncoder` writes and `CborDecoder` reads.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct CborMovie {
+    pub header: DtmHeader,
+    pub controller_data: CborBytes,
+    pub wiimote_data: Vec<CborBytes>,
+    pub trailing: CborBytes,
+}
+
+// Bytes as a CBOR byte string, where serde would write an array of numbers.
+pub(crate) struct CborBytes(pub Vec<u8>);
+
+impl Serialize for CborBytes {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer,
+    {
+        serializer.serialize_bytes(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for CborBytes {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: Deserializer<'de>,
+    {
+        deserializer.deserialize_byte_buf(CborBytesVisitor)
+    }
+}
+
+struct CborBytesVisitor;
+
+impl<'de> Visitor<'de> for CborBytesVisitor {
+    type Value = CborBytes;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a byte string")
+    }
+
+    fn visit_bytes<E>(self, value: &[u8]) -> Result<Self::Value, E>
+        where E: de::Error,
+    {
+        Ok(CborBytes(value.to_vec()))
+    }
+
+    fn visit_byte_buf<E>(self, value: Vec<u8>) -> Result<Self::Value, E>
+        where E: de::Error,
+    {
+        Ok(CborBytes(value))
+    }
+}
+
+impl<W> CborEncoder<W>
+    where W: Write,
+{
+    pub fn new(inner: W) -> CborEncoder<W> {
+        CborEncoder {
+            inner: inner,
+        }
+    }
+
+    pub fn encode<S>(self, dtm: &Dtm<S>) -> Dtm2txtResult<()>
+        where S: FrameStore,
+    {
+        let movie = CborMovie {
+            header: dtm.header.clone(),
+            controller_data: CborBytes(dtm.controller_data.records().flat_map(|record| record.to_vec()).collect()),
+            wiimote_data: dtm.wiimote_data.iter().map(|input| CborBytes(input.report.clone())).collect(),
+            trailing: CborBytes(dtm.trailing.clone()),
+        };
+        ciborium::ser::into_writer(&movie, self.inner)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use decoder::cbor_decoder::CborDecoder;
+    use dtm::{ControllerInput, WiimoteInput, Button};
+
+    #[test]
+    fn round_trips_compactly() {
+        let mut dtm = Dtm::blank(DtmHeader::default(), 1000);
+        dtm.controller_data[10].set_pressed(Button::A, true);
+        dtm.controller_data[20].analog_x = 3;
+        dtm.trailing = vec![1, 2, 3];
+        let mut bytes = Vec::new();
+        CborEncoder::new(&mut bytes).encode(&dtm).unwrap();
+        assert!(bytes.len() < dtm.controller_data.len() * 8 + 1024);
+        assert_eq!(CborDecoder::new(&bytes[..]).decode().unwrap(), dtm);
+    }
+
+    #[test]
+    fn round_trips_wiimote_reports() {
+        let header = DtmHeader {
+            controllers: 0x10,
+            wii_game: true,
+            input_count: 2,
+            ..DtmHeader::default()
+        };
+        let dtm = Dtm {
+            header: header,
+            controller_data: Vec::<ControllerInput>::new(),
+            wiimote_data: vec![WiimoteInput { report: vec![0x30, 0x00, 0x08] }, WiimoteInput { report: vec![0xA1, 0x31, 0, 0, 1, 2, 3] }],
+            trailing: Vec::new(),
+        };
+        let mut bytes = Vec::new();
+        CborEncoder::new(&mut bytes).encode(&dtm).unwrap();
+        assert_eq!(CborDecoder::new(&bytes[..]).decode().unwrap(), dtm);
+    }
+}
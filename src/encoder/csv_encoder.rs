@@ -0,0 +1,109 @@
+use std::io::Write;
+
+use dtm::{Dtm, ControllerInput, Button, Axis};
+use error::{Dtm2txtError, Dtm2txtResult};
+use frames::FrameStore;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CsvColumn {
+    Frame,
+    Button(Button),
+    Axis(Axis),
+}
+
+impl CsvColumn {
+    pub fn name(self) -> &'static str {
+        match self {
+            CsvColumn::Frame => "frame",
+            CsvColumn::Button(button) => button.long_name(),
+            CsvColumn::Axis(axis) => axis.name(),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<CsvColumn> {
+        if name.eq_ignore_ascii_case("frame") {
+            return Some(CsvColumn::Frame);
+        }
+        Button::from_name(name)
+            .map(CsvColumn::Button)
+            .or_else(|| Axis::from_name(name).map(CsvColumn::Axis))
+    }
+}
+
+// Same order as a txt input line.
+pub const CSV_COLUMNS: [CsvColumn; 23] = [
+    CsvColumn::Frame,
+    CsvColumn::Button(Button::Start),
+    CsvColumn::Button(Button::A),
+    CsvColumn::Button(Button::B),
+    CsvColumn::Button(Button::X),
+    CsvColumn::Button(Button::Y),
+    CsvColumn::Button(Button::Z),
+    CsvColumn::Button(Button::Up),
+    CsvColumn::Button(Button::Down),
+    CsvColumn::Button(Button::Left),
+    CsvColumn::Button(Button::Right),
+    CsvColumn::Button(Button::L),
+    CsvColumn::Button(Button::R),
+    CsvColumn::Axis(Axis::LPressure),
+    CsvColumn::Axis(Axis::RPressure),
+    CsvColumn::Axis(Axis::AnalogX),
+    CsvColumn::Axis(Axis::AnalogY),
+    CsvColumn::Axis(Axis::CX),
+    CsvColumn::Axis(Axis::CY),
+    CsvColumn::Button(Button::ChangeDisc),
+    CsvColumn::Button(Button::Reset),
+    CsvColumn::Button(Button::ControllerConnected),
+    CsvColumn::Button(Button::Reserved),
+];
+
+// Writes the controller frames as a spreadsheet, one frame per row. Buttons
+// are 0 or 1 and axes are their raw values. The header metadata isn't
+// included.
+pub struct CsvEncoder<W> {
+    inner: W,
+}
+
+impl<W> CsvEncoder<W>
+    where W: Write,
+{
+    pub fn new(inner: W) -> CsvEncoder<W> {
+        CsvEncoder {
+            inner: inner,
+        }
+    }
+
+    pub fn encode<S>(mut self, dtm: &Dtm<S>) -> Dtm2txtResult<()>
+        where S: FrameStore,
+    {
+        if !dtm.wiimote_data.is_empty() {
+            return Err(Dtm2txtError::WiimoteDataUnsupportedError {
+                format: "csv",
+            });
+        }
+
+        let names: Vec<_> = CSV_COLUMNS.iter().map(|column| column.name()).collect();
+        writeln!(&mut self.inner, "{}", names.join(","))?;
+        for (frame, input) in dtm.controller_data.frames().enumerate() {
+            self.write_controller_input(frame as u64, &input)?;
+        }
+        Ok(())
+    }
+
+    fn write_controller_input(&mut self, frame: u64, input: &ControllerInput) -> Dtm2txtResult<()> {
+        let mut line = String::new();
+        for column in CSV_COLUMNS.iter() {
+            if !line.is_empty() {
+                line += ",";
+            }
+            match *column {
+                CsvColumn::Frame => line += &frame.to_string(),
+                CsvColumn::Button(button) => line += if input.pressed(button) { "1" } else { "0" },
+                CsvColumn::Axis(axis) => line += &input.axis(axis).to_string(),
+            }
+        }
+        line += "\n";
+
+        Ok(self.inner.write_all(line.as_bytes())?)
+    }
+}
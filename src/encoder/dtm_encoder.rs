@@ -1,18 +1,26 @@
-use std::io::Write;
+use std::io::{Seek, SeekFrom, Write};
+use std::str;
 
 use byteorder::{WriteBytesExt, LE};
 
-use dtm::{Dtm, DtmHeader, ControllerInput};
+use dtm::{Dtm, DtmHeader, DTM_MAGIC, HEADER_SIZE, CONTROLLER_RECORD_SIZE, RawString, ControllerInput, WiimoteInput};
+use consistency;
+use decoder::InputSink;
 use error::{Dtm2txtError, Dtm2txtResult};
+use frames::FrameStore;
+use progress::{Progress, ProgressCallback};
 
-const DTM_MAGIC: &[u8; 4] = b"DTM\x1A";
+// Inputs are gathered into chunks about this big before being written, so an
+// unbuffered writer isn't called for every frame.
+const CHUNK_SIZE: usize = 64 * 1024;
 
 trait WriteDtmExt: Write {
-    fn write_str(&mut self, val: &str, len: usize) -> Dtm2txtResult<()> {
-        let bytes = val.as_bytes();
+    fn write_str(&mut self, bytes: &[u8], len: usize, field: &'static str, truncate: bool) -> Dtm2txtResult<()> {
+        let bytes = if truncate { truncate_string(bytes, len) } else { bytes };
         if bytes.len() > len {
             return Err(Dtm2txtError::StringTooLongError {
-                found: val.len(),
+                field: field,
+                found: bytes.len(),
                 max: len,
             });
         }
@@ -32,8 +40,39 @@ trait WriteDtmExt: Write {
 
 impl<W> WriteDtmExt for W where W: Write {}
 
+// Cuts a string down to `len` bytes, without splitting a UTF-8 character
+// unless the string wasn't UTF-8 to begin with.
+fn truncate_string(bytes: &[u8], len: usize) -> &[u8] {
+    if bytes.len() <= len {
+        return bytes;
+    }
+    match str::from_utf8(bytes) {
+        Ok(text) => {
+            let end = (0..=len).rev().find(|&idx| text.is_char_boundary(idx)).unwrap_or(0);
+            &bytes[..end]
+        }
+        Err(_) => &bytes[..len],
+    }
+}
+
+// A report is stored after a byte giving its size, so it can't be any longer
+// than a byte can count.
+fn push_wiimote_record(bytes: &mut Vec<u8>, input: &WiimoteInput) -> Dtm2txtResult<()> {
+    if input.report.len() > 255 {
+        return Err(Dtm2txtError::WiimoteReportTooLongError {
+            len: input.report.len(),
+        });
+    }
+    bytes.push(input.report.len() as u8);
+    bytes.extend_from_slice(&input.report);
+    Ok(())
+}
+
 pub struct DtmEncoder<W> {
     inner: W,
+    allow_inconsistent: bool,
+    truncate_strings: bool,
+    progress: Option<ProgressCallback>,
 }
 
 impl<W> DtmEncoder<W>
@@ -42,91 +81,206 @@ impl<W> DtmEncoder<W>
     pub fn new(inner: W) -> DtmEncoder<W> {
         DtmEncoder {
             inner: inner,
+            allow_inconsistent: false,
+            truncate_strings: false,
+            progress: None,
         }
     }
 
-    pub fn encode(mut self, dtm: &Dtm) -> Dtm2txtResult<()> {
-        self.inner.write_all(DTM_MAGIC)?;
+    // By default, a movie whose header disagrees with its inputs is refused
+    // rather than written out for Dolphin to choke on.
+    pub fn allow_inconsistent(mut self, allow: bool) -> DtmEncoder<W> {
+        self.allow_inconsistent = allow;
+        self
+    }
+
+    // Cuts header strings that don't fit their field down to size instead
+    // of refusing them.
+    pub fn truncate_strings(mut self, truncate: bool) -> DtmEncoder<W> {
+        self.truncate_strings = truncate;
+        self
+    }
+
+    // Reports how far along the inputs it is; see `ProgressCallback`.
+    pub fn progress<F>(mut self, callback: F) -> DtmEncoder<W>
+        where F: FnMut(u64, u64) + 'static,
+    {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    pub fn encode<S>(mut self, dtm: &Dtm<S>) -> Dtm2txtResult<()>
+        where S: FrameStore,
+    {
+        self.check_consistency(&dtm.header, dtm.controller_data.len(), dtm.wiimote_data.len() as u64)?;
         self.encode_header(&dtm.header)?;
-        for frame in dtm.controller_data.iter() {
-            self.encode_controller_input(&frame)?;
+
+        let total = dtm.controller_data.len() + dtm.wiimote_data.len() as u64;
+        let mut progress = Progress::new(self.progress.take(), total);
+        let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+        for (done, record) in dtm.controller_data.records().enumerate() {
+            progress.update(done as u64);
+            chunk.extend_from_slice(&record);
+            self.write_chunk(&mut chunk, false)?;
         }
+        for (done, report) in dtm.wiimote_data.iter().enumerate() {
+            progress.update(dtm.controller_data.len() + done as u64);
+            push_wiimote_record(&mut chunk, report)?;
+            self.write_chunk(&mut chunk, false)?;
+        }
+        self.write_chunk(&mut chunk, true)?;
+        self.inner.write_all(&dtm.trailing)?;
+        progress.finish(total);
         Ok(())
     }
 
-    fn encode_header(&mut self, header: &DtmHeader) -> Dtm2txtResult<()> {
-        self.inner.write_str(&header.game_id, 6)?;
-        self.inner.write_bool(header.wii_game)?;
-        self.inner.write_u8(header.controllers)?;
-        self.inner.write_bool(header.savestate)?;
-        self.inner.write_u64::<LE>(header.vi_count)?;
-        self.inner.write_u64::<LE>(header.input_count)?;
-        self.inner.write_u64::<LE>(header.lag_counter)?;
-        self.inner.write_u64::<LE>(header.reserved1)?;
-        self.inner.write_u32::<LE>(header.rerecord_count)?;
-        self.inner.write_str(&header.author, 32)?;
-        self.inner.write_str(&header.video_backend, 16)?;
-        self.inner.write_all(&header.audio_emulator.0)?;
-        self.inner.write_all(&header.md5.0)?;
-        self.inner.write_u64::<LE>(header.start_time)?;
-        self.inner.write_bool(header.valid_config)?;
-        self.inner.write_bool(header.idle_skipping)?;
-        self.inner.write_bool(header.dual_core)?;
-        self.inner.write_bool(header.progressive_scan)?;
-        self.inner.write_bool(header.dsp_hle)?;
-        self.inner.write_bool(header.fast_disc)?;
-        self.inner.write_u8(header.cpu_core)?;
-        self.inner.write_bool(header.efb_access)?;
-        self.inner.write_bool(header.efb_copy)?;
-        self.inner.write_bool(header.efb_to_texture)?;
-        self.inner.write_bool(header.efb_copy_cache)?;
-        self.inner.write_bool(header.emulate_format_changes)?;
-        self.inner.write_bool(header.use_xfb)?;
-        self.inner.write_bool(header.use_real_xfb)?;
-        self.inner.write_u8(header.memory_cards)?;
-        self.inner.write_bool(header.memory_card_blank)?;
-        self.inner.write_u8(header.bongos_plugged)?;
-        self.inner.write_bool(header.sync_gpu)?;
-        self.inner.write_bool(header.netplay)?;
-        self.inner.write_bool(header.sysconf_pal60)?;
-        self.inner.write_all(&header.reserved2.0)?;
-        self.inner.write_str(&header.second_disc, 40)?;
-        self.inner.write_all(&header.git_revision.0)?;
-        self.inner.write_u32::<LE>(header.dsp_irom_hash)?;
-        self.inner.write_u32::<LE>(header.dsp_coef_hash)?;
-        self.inner.write_u64::<LE>(header.tick_count)?;
-        self.inner.write_all(&header.reserved3.0)?;
+    // Writes out the gathered inputs once there's a chunk's worth, or
+    // whatever there is at the end.
+    fn write_chunk(&mut self, chunk: &mut Vec<u8>, last: bool) -> Dtm2txtResult<()> {
+        if chunk.len() >= CHUNK_SIZE || (last && !chunk.is_empty()) {
+            self.inner.write_all(chunk)?;
+            chunk.clear();
+        }
         Ok(())
     }
 
-    fn encode_controller_input(&mut self, input: &ControllerInput) -> Dtm2txtResult<()> {
-        let mut byte1 = input.start as u8;
-        byte1 |= (input.a as u8) << 1;
-        byte1 |= (input.b as u8) << 2;
-        byte1 |= (input.x as u8) << 3;
-        byte1 |= (input.y as u8) << 4;
-        byte1 |= (input.z as u8) << 5;
-        byte1 |= (input.up as u8) << 6;
-        byte1 |= (input.down as u8) << 7;
-        self.inner.write_u8(byte1)?;
-
-        let mut byte2 = input.left as u8;
-        byte2 |= (input.right as u8) << 1;
-        byte2 |= (input.l as u8) << 2;
-        byte2 |= (input.r as u8) << 3;
-        byte2 |= (input.change_disc as u8) << 4;
-        byte2 |= (input.reset as u8) << 5;
-        byte2 |= (input.controller_connected as u8) << 6;
-        byte2 |= (input.reserved as u8) << 7;
-        self.inner.write_u8(byte2)?;
-
-        self.inner.write_u8(input.l_pressure)?;
-        self.inner.write_u8(input.r_pressure)?;
-        self.inner.write_u8(input.analog_x)?;
-        self.inner.write_u8(input.analog_y)?;
-        self.inner.write_u8(input.c_x)?;
-        self.inner.write_u8(input.c_y)?;
+    pub(crate) fn check_consistency(&self, header: &DtmHeader, frames: u64, wiimote_frames: u64) -> Dtm2txtResult<()> {
+        let problems = consistency::check(header, frames, wiimote_frames);
+        if problems.is_empty() || self.allow_inconsistent {
+            Ok(())
+        }
+        else {
+            Err(Dtm2txtError::InconsistentMovieError(problems))
+        }
+    }
 
+    pub(crate) fn encode_header(&mut self, header: &DtmHeader) -> Dtm2txtResult<()> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE);
+        bytes.write_all(DTM_MAGIC)?;
+        bytes.write_str(RawString::encode(&header.game_id, &header.game_id_raw), 6, "game_id", self.truncate_strings)?;
+        bytes.write_bool(header.wii_game)?;
+        bytes.write_u8(header.controllers)?;
+        bytes.write_bool(header.savestate)?;
+        bytes.write_u64::<LE>(header.vi_count)?;
+        bytes.write_u64::<LE>(header.input_count)?;
+        bytes.write_u64::<LE>(header.lag_counter)?;
+        bytes.write_u64::<LE>(header.reserved1)?;
+        bytes.write_u32::<LE>(header.rerecord_count)?;
+        bytes.write_str(RawString::encode(&header.author, &header.author_raw), 32, "author", self.truncate_strings)?;
+        bytes.write_str(RawString::encode(&header.video_backend, &header.video_backend_raw), 16, "video_backend", self.truncate_strings)?;
+        bytes.write_all(&header.audio_emulator.0)?;
+        bytes.write_all(&header.md5.0)?;
+        bytes.write_u64::<LE>(header.start_time)?;
+        bytes.write_bool(header.valid_config)?;
+        bytes.write_bool(header.idle_skipping)?;
+        bytes.write_bool(header.dual_core)?;
+        bytes.write_bool(header.progressive_scan)?;
+        bytes.write_bool(header.dsp_hle)?;
+        bytes.write_bool(header.fast_disc)?;
+        bytes.write_u8(header.cpu_core.into())?;
+        bytes.write_bool(header.efb_access)?;
+        bytes.write_bool(header.efb_copy)?;
+        bytes.write_bool(header.efb_to_texture)?;
+        bytes.write_bool(header.efb_copy_cache)?;
+        bytes.write_bool(header.emulate_format_changes)?;
+        bytes.write_bool(header.use_xfb)?;
+        bytes.write_bool(header.use_real_xfb)?;
+        bytes.write_u8(header.memory_cards)?;
+        bytes.write_bool(header.memory_card_blank)?;
+        bytes.write_u8(header.bongos_plugged)?;
+        bytes.write_bool(header.sync_gpu)?;
+        bytes.write_bool(header.netplay)?;
+        bytes.write_bool(header.sysconf_pal60)?;
+        bytes.write_all(&header.reserved2.0)?;
+        bytes.write_str(RawString::encode(&header.second_disc, &header.second_disc_raw), 40, "second_disc", self.truncate_strings)?;
+        bytes.write_all(&header.git_revision.0)?;
+        bytes.write_u32::<LE>(header.dsp_irom_hash)?;
+        bytes.write_u32::<LE>(header.dsp_coef_hash)?;
+        bytes.write_u64::<LE>(header.tick_count)?;
+        bytes.write_all(&header.reserved3.0)?;
+        debug_assert_eq!(bytes.len(), HEADER_SIZE, "header fields don't add up to HEADER_SIZE");
+        self.inner.write_all(&bytes)?;
         Ok(())
     }
+
+    pub(crate) fn encode_controller_input(&mut self, input: &ControllerInput) -> Dtm2txtResult<()> {
+        Ok(self.inner.write_all(&input.to_bytes())?)
+    }
+
+    pub(crate) fn encode_wiimote_input(&mut self, input: &WiimoteInput) -> Dtm2txtResult<()> {
+        let mut record = Vec::with_capacity(input.report.len() + 1);
+        push_wiimote_record(&mut record, input)?;
+        Ok(self.inner.write_all(&record)?)
+    }
+}
+
+impl<W> DtmEncoder<W>
+    where W: Write + Seek,
+{
+    // Starts a movie whose header isn't known until its inputs have been
+    // written: the header's space is skipped over for `DtmStream::finish` to
+    // come back and fill in.
+    pub(crate) fn stream(mut self) -> Dtm2txtResult<DtmStream<W>> {
+        let start = self.inner.stream_position()?;
+        self.inner.write_all(&[0; HEADER_SIZE])?;
+        Ok(DtmStream {
+            encoder: self,
+            start: start,
+            chunk: Vec::with_capacity(CHUNK_SIZE),
+            frames: 0,
+            wiimote_frames: 0,
+        })
+    }
+}
+
+pub(crate) struct DtmStream<W> {
+    encoder: DtmEncoder<W>,
+    start: u64,
+    chunk: Vec<u8>,
+    frames: u64,
+    wiimote_frames: u64,
+}
+
+impl<W> DtmStream<W>
+    where W: Write + Seek,
+{
+    // The GameCube and Wii remote inputs written so far.
+    pub(crate) fn counts(&self) -> (u64, u64) {
+        (self.frames, self.wiimote_frames)
+    }
+
+    // Writes the trailing bytes and goes back for the header. The inputs are
+    // already out by then, so a header that disagrees with them fails here
+    // with a partial movie left behind.
+    pub(crate) fn finish(mut self, header: &DtmHeader, trailing: &[u8]) -> Dtm2txtResult<W> {
+        self.encoder.write_chunk(&mut self.chunk, true)?;
+        self.encoder.check_consistency(header, self.frames, self.wiimote_frames)?;
+        self.encoder.inner.write_all(trailing)?;
+        let end = self.encoder.inner.stream_position()?;
+        self.encoder.inner.seek(SeekFrom::Start(self.start))?;
+        self.encoder.encode_header(header)?;
+        self.encoder.inner.seek(SeekFrom::Start(end))?;
+        self.encoder.inner.flush()?;
+        Ok(self.encoder.inner)
+    }
+}
+
+impl<W> InputSink for DtmStream<W>
+    where W: Write,
+{
+    fn push_controller(&mut self, input: ControllerInput) -> Dtm2txtResult<()> {
+        self.push_record(&input.to_bytes())
+    }
+
+    fn push_wiimote(&mut self, input: WiimoteInput) -> Dtm2txtResult<()> {
+        push_wiimote_record(&mut self.chunk, &input)?;
+        self.wiimote_frames += 1;
+        self.encoder.write_chunk(&mut self.chunk, false)
+    }
+
+    fn push_record(&mut self, record: &[u8; CONTROLLER_RECORD_SIZE]) -> Dtm2txtResult<()> {
+        self.chunk.extend_from_slice(record);
+        self.frames += 1;
+        self.encoder.write_chunk(&mut self.chunk, false)
+    }
 }
@@ -0,0 +1,32 @@
+use std::io::Write;
+
+use serde::Serialize;
+use serde_json;
+
+use dtm::Dtm;
+use error::Dtm2txtResult;
+
+// Writes the whole movie as one JSON document, header and inputs alike, for
+// tools that would rather parse a single value than the txt format's header
+// followed by input lines.
+pub struct JsonEncoder<W> {
+    inner: W,
+}
+
+impl<W> JsonEncoder<W>
+    where W: Write,
+{
+    pub fn new(inner: W) -> JsonEncoder<W> {
+        JsonEncoder {
+            inner: inner,
+        }
+    }
+
+    pub fn encode<S>(mut self, dtm: &Dtm<S>) -> Dtm2txtResult<()>
+        where S: Serialize,
+    {
+        serde_json::to_writer(&mut self.inner, dtm)?;
+        writeln!(self.inner)?;
+        Ok(())
+    }
+}
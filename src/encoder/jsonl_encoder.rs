@@ -0,0 +1,73 @@
+use std::io::Write;
+
+use serde::Serialize;
+use serde_json;
+
+use dtm::{self, Dtm, DtmHeader};
+use error::Dtm2txtResult;
+use frames::FrameStore;
+
+#[derive(Serialize)]
+struct JsonlHeader<'a> {
+    #[serde(flatten)]
+    header: &'a DtmHeader,
+    #[serde(skip_serializing_if = "<[u8]>::is_empty", serialize_with = "dtm::serialize_hex")]
+    trailing: &'a [u8],
+}
+
+// Numbers the inputs, so a line still says which frame it is once it's been
+// filtered out of the file.
+#[derive(Serialize)]
+struct JsonlFrame<T> {
+    frame: u64,
+    #[serde(flatten)]
+    input: T,
+}
+
+// Writes JSON Lines: the header as the txt header has it on the first line,
+// then one object per input. Meant for jq and other tools that read a line
+// at a time, so there's no decoder for it.
+pub struct JsonlEncoder<W> {
+    inner: W,
+}
+
+impl<W> JsonlEncoder<W>
+    where W: Write,
+{
+    pub fn new(inner: W) -> JsonlEncoder<W> {
+        JsonlEncoder {
+            inner: inner,
+        }
+    }
+
+    pub fn encode<S>(mut self, dtm: &Dtm<S>) -> Dtm2txtResult<()>
+        where S: FrameStore,
+    {
+        let header = JsonlHeader {
+            header: &dtm.header,
+            trailing: &dtm.trailing,
+        };
+        serde_json::to_writer(&mut self.inner, &header)?;
+        writeln!(self.inner)?;
+
+        for (frame, input) in dtm.controller_data.frames().enumerate() {
+            self.write_frame(frame as u64, input)?;
+        }
+        for (frame, input) in dtm.wiimote_data.iter().enumerate() {
+            self.write_frame(frame as u64, input)?;
+        }
+        Ok(())
+    }
+
+    fn write_frame<T>(&mut self, frame: u64, input: T) -> Dtm2txtResult<()>
+        where T: Serialize,
+    {
+        let line = JsonlFrame {
+            frame: frame,
+            input: input,
+        };
+        serde_json::to_writer(&mut self.inner, &line)?;
+        writeln!(self.inner)?;
+        Ok(())
+    }
+}
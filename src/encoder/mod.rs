@@ -1,2 +1,11 @@
 pub mod text_encoder;
-pub mod dtm_encoder;
\ No newline at end of file
+pub mod dtm_encoder;
+pub mod csv_encoder;
+pub mod jsonl_encoder;
+pub mod json_encoder;
+#[cfg(feature = "yaml")]
+pub mod yaml_encoder;
+#[cfg(feature = "cbor")]
+pub mod cbor_encoder;
+#[cfg(feature = "tokio")]
+pub mod async_dtm_encoder;
\ No newline at end of file
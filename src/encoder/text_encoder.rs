@@ -1,9 +1,30 @@
 use std::io::Write;
+use std::mem;
 
 use serde_json;
 
-use dtm::{Dtm, ControllerInput};
+use dtm::{self, Dtm, DtmHeader, ControllerInput, WiimoteInput, Button, Axis};
+use comments::Comment;
+use decoder::InputSink;
 use error::Dtm2txtResult;
+use frames::FrameStore;
+use progress::{Progress, ProgressCallback};
+
+// The button field of a mnemonic line has one character per button, in this
+// order, with '.' for buttons that aren't pressed.
+pub(crate) const MNEMONIC_BUTTONS: [Button; 12] = [
+    Button::Start, Button::A, Button::B, Button::X, Button::Y, Button::Z,
+    Button::Up, Button::Down, Button::Left, Button::Right, Button::L, Button::R,
+];
+pub(crate) const MNEMONICS: &str = "SABXYZUDLRlr";
+
+// A table line has a column for every input: the buttons above, named like
+// in a classic line, then the axes and these.
+pub(crate) const TABLE_EXTRAS: [Button; 4] = [
+    Button::ChangeDisc, Button::Reset, Button::ControllerConnected, Button::Reserved,
+];
+// Column names of the axes, in `Axis::ALL` order.
+pub(crate) const TABLE_AXES: [&str; 6] = ["LP", "RP", "AX", "AY", "CX", "CY"];
 
 macro_rules! format_input {
     ($string:expr, $val:expr, $upper:expr, $lower:expr) => {
@@ -16,8 +37,55 @@ macro_rules! format_input {
     };
 }
 
+// How controller input lines are written. The decoder reads either.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum FrameSyntax {
+    // S A b x y z u d l r lt rt   0   0 128 128 128 128
+    #[default]
+    Classic,
+    // |SA..........|128,128|128,128|0,0|
+    Mnemonic,
+    // S A . . . . . . . . .. ..   0   0 128 128 128 128 .. ... .. ...
+    // Every line is the same width, under a row of column names at the top
+    // of the inputs.
+    Table,
+    // SA.......... 0 0 128 128 128 128
+    Compact,
+}
+
+// How the inputs of a movie with several GameCube controllers are laid out.
+// Either way they're in the order a dtm stores them, one port after another
+// for each frame. The decoder reads all of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum PortLayout {
+    // One line per port, with nothing to say which is which.
+    #[default]
+    Interleaved,
+    // One line per port, starting with its number: `P2: S a b ...`
+    Labeled,
+    // One line per frame, with the ports separated by ` ; `.
+    Joined,
+}
+
+// The JSON at the top of a txt file. Bytes after the inputs go at the end of
+// the header rather than among the frames, and are left out when there are
+// none.
+#[derive(Serialize)]
+struct TextHeader<'a> {
+    #[serde(flatten)]
+    header: &'a DtmHeader,
+    #[serde(skip_serializing_if = "<[u8]>::is_empty", serialize_with = "dtm::serialize_hex")]
+    trailing: &'a [u8],
+}
+
 pub struct TextEncoder<W> {
     inner: W,
+    syntax: FrameSyntax,
+    layout: PortLayout,
+    collapse_repeats: bool,
+    comments: Vec<Comment>,
+    next_comment: usize,
+    progress: Option<ProgressCallback>,
 }
 
 impl<W> TextEncoder<W>
@@ -26,45 +94,476 @@ impl<W> TextEncoder<W>
     pub fn new(inner: W) -> TextEncoder<W> {
         TextEncoder {
             inner: inner,
+            syntax: FrameSyntax::default(),
+            layout: PortLayout::default(),
+            collapse_repeats: false,
+            comments: Vec::new(),
+            next_comment: 0,
+            progress: None,
         }
     }
 
-    pub fn encode(mut self, dtm: &Dtm) -> Dtm2txtResult<()> {
-        serde_json::to_writer_pretty(&mut self.inner, &dtm.header)?;
+    pub fn syntax(mut self, syntax: FrameSyntax) -> TextEncoder<W> {
+        self.syntax = syntax;
+        self
+    }
+
+    pub fn layout(mut self, layout: PortLayout) -> TextEncoder<W> {
+        self.layout = layout;
+        self
+    }
+
+    // Writes runs of identical frames as a single line ending in `* N`.
+    pub fn collapse_repeats(mut self, collapse_repeats: bool) -> TextEncoder<W> {
+        self.collapse_repeats = collapse_repeats;
+        self
+    }
+
+    // Puts `#` comments back between (or at the end of) the input lines.
+    pub fn comments(mut self, mut comments: Vec<Comment>) -> TextEncoder<W> {
+        comments.sort_by_key(|comment| (comment.frame, comment.trailing));
+        self.comments = comments;
+        self
+    }
+
+    // Reports how far along the inputs it is; see `ProgressCallback`.
+    pub fn progress<F>(mut self, callback: F) -> TextEncoder<W>
+        where F: FnMut(u64, u64) + 'static,
+    {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    pub fn encode<S>(mut self, dtm: &Dtm<S>) -> Dtm2txtResult<()>
+        where S: FrameStore,
+    {
+        let header = TextHeader {
+            header: &dtm.header,
+            trailing: &dtm.trailing,
+        };
+        serde_json::to_writer_pretty(&mut self.inner, &header)?;
         writeln!(&mut self.inner)?;
-        for input in dtm.controller_data.iter() {
-            self.write_controller_input(input)?;
+
+        let total = dtm.controller_data.len() + dtm.wiimote_data.len() as u64;
+        let mut progress = Progress::new(self.progress.take(), total);
+        let ports = dtm.header.gamecube_ports();
+        let per_line = match self.layout {
+            PortLayout::Joined => ports.len().max(1),
+            _ => 1,
+        };
+        if self.syntax == FrameSyntax::Table && !dtm.header.has_wiimote_inputs() {
+            let mut columns = String::new();
+            write_table_columns(&mut columns, self.layout, per_line);
+            writeln!(&mut self.inner, "{}", columns)?;
+        }
+        // Labeled lines for different ports never match, so only a movie
+        // with one port has runs to collapse.
+        let collapse_repeats = self.collapse_repeats && (self.layout != PortLayout::Labeled || ports.len() <= 1);
+
+        // The inputs for each line and the line itself go in buffers that are
+        // reused all the way through, since a long movie has millions of
+        // lines.
+        let mut frame = 0;
+        let mut records = dtm.controller_data.frames();
+        let mut inputs = Vec::with_capacity(per_line);
+        let mut upcoming = Vec::with_capacity(per_line);
+        let mut line = String::new();
+        let mut more = next_line_inputs(&mut upcoming, &mut records, per_line);
+        while more {
+            mem::swap(&mut inputs, &mut upcoming);
+            more = next_line_inputs(&mut upcoming, &mut records, per_line);
+            progress.update(frame);
+            let trailing = self.write_comments(frame)?;
+            let len = inputs.len() as u64;
+
+            // A run stops at the next commented frame so the comment has a
+            // line to go on.
+            let mut repeat = 1;
+            if collapse_repeats {
+                while more && upcoming == inputs && !self.has_comment(frame + repeat * len, len) {
+                    more = next_line_inputs(&mut upcoming, &mut records, per_line);
+                    repeat += 1;
+                }
+            }
+
+            line.clear();
+            write_line_inputs(&mut line, &inputs, self.syntax, self.layout, &ports, frame);
+            self.write_line(&mut line, repeat, trailing)?;
+            frame += repeat * len;
         }
+        for input in dtm.wiimote_data.iter() {
+            progress.update(frame);
+            let trailing = self.write_comments(frame)?;
+            line.clear();
+            write_wiimote_input(&mut line, input);
+            self.write_line(&mut line, 1, trailing)?;
+            frame += 1;
+        }
+        // Comments after the last input.
+        self.write_comments(u64::MAX)?;
+        progress.finish(total);
+
         Ok(())
     }
 
-    // S A B X Y Z U D L R LT 0 0 0 0 0 0 [CD RST CC RSV]
-    fn write_controller_input(&mut self, input: &ControllerInput) -> Dtm2txtResult<()> {
-        let mut line = String::new();
-        format_input!(line, input.start, "S ", "s ");
-        format_input!(line, input.a, "A ", "a ");
-        format_input!(line, input.b, "B ", "b ");
-        format_input!(line, input.x, "X ", "x ");
-        format_input!(line, input.y, "Y ", "y ");
-        format_input!(line, input.z, "Z ", "z ");
-        format_input!(line, input.up, "U ", "u ");
-        format_input!(line, input.down, "D ", "d ");
-        format_input!(line, input.left, "L ", "l ");
-        format_input!(line, input.right, "R ", "r ");
-        format_input!(line, input.l, "LT ", "lt ");
-        format_input!(line, input.r, "RT ", "rt ");
-        line += &(format!("{:3} ", input.l_pressure));
-        line += &(format!("{:3} ", input.r_pressure));
-        line += &(format!("{:3} ", input.analog_x));
-        line += &(format!("{:3} ", input.analog_y));
-        line += &(format!("{:3} ", input.c_x));
-        line += &(format!("{:3}", input.c_y));
-        format_input!(line, input.change_disc, " CD", "");
-        format_input!(line, input.reset, " RST", "");
-        format_input!(line, input.controller_connected, " CC", "");
-        format_input!(line, input.reserved, " RSV", "");
-        line += "\n";
+    // Writes the header and hands back a stream to write the inputs to one at
+    // a time, for movies that are still being recorded. Repeats aren't
+    // collapsed, since a line can't wait to see what comes next.
+    pub fn stream(self, header: &DtmHeader) -> Dtm2txtResult<TextStream<W>> {
+        self.start_stream(header, &[], true)
+    }
+
+    // Like `stream`, for a movie whose trailing bytes are already known. Lines
+    // are only flushed after each one when someone is waiting on them.
+    pub(crate) fn start_stream(mut self, header: &DtmHeader, trailing: &[u8], flush_lines: bool) -> Dtm2txtResult<TextStream<W>> {
+        let header = TextHeader {
+            header: header,
+            trailing: trailing,
+        };
+        serde_json::to_writer_pretty(&mut self.inner, &header)?;
+        writeln!(&mut self.inner)?;
+        let ports = header.header.gamecube_ports();
+        if self.syntax == FrameSyntax::Table && !header.header.has_wiimote_inputs() {
+            let mut columns = String::new();
+            write_table_columns(&mut columns, self.layout, ports.len().max(1));
+            writeln!(&mut self.inner, "{}", columns)?;
+        }
+        self.inner.flush()?;
+
+        Ok(TextStream {
+            inner: self.inner,
+            syntax: self.syntax,
+            layout: self.layout,
+            ports: ports,
+            frame: 0,
+            pending: Vec::new(),
+            line: String::new(),
+            flush_lines: flush_lines,
+        })
+    }
+
+    // Whether the next comment comes before the end of the `len` frames from
+    // `frame`.
+    fn has_comment(&self, frame: u64, len: u64) -> bool {
+        self.comments.get(self.next_comment)
+            .map(|comment| comment.frame < frame + len)
+            .unwrap_or(false)
+    }
+
+    // Writes the comments on their own lines before `frame` and returns the
+    // text of any trailing comments for its line.
+    fn write_comments(&mut self, frame: u64) -> Dtm2txtResult<Option<String>> {
+        let mut trailing: Option<String> = None;
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.frame > frame {
+                break;
+            }
+            if comment.frame == frame && comment.trailing {
+                trailing = Some(match trailing {
+                    Some(text) => text + " # " + &comment.text,
+                    None => comment.text.clone(),
+                });
+            }
+            else {
+                writeln!(&mut self.inner, "# {}", comment.text)?;
+            }
+            self.next_comment += 1;
+        }
+        Ok(trailing)
+    }
+
+    fn write_line(&mut self, line: &mut String, repeat: u64, trailing: Option<String>) -> Dtm2txtResult<()> {
+        if repeat > 1 {
+            line.push_str(" * ");
+            push_number(line, repeat, 0);
+        }
+        if let Some(text) = trailing {
+            line.push_str(" # ");
+            line.push_str(&text);
+        }
+        line.push('\n');
 
         Ok(self.inner.write_all(line.as_bytes())?)
     }
-}
\ No newline at end of file
+}
+
+// Inputs written by `TextEncoder::stream`. Each line is flushed as soon as
+// it's complete.
+pub struct TextStream<W> {
+    inner: W,
+    syntax: FrameSyntax,
+    layout: PortLayout,
+    ports: Vec<u8>,
+    frame: u64,
+    // Inputs waiting for the rest of their joined line.
+    pending: Vec<ControllerInput>,
+    line: String,
+    flush_lines: bool,
+}
+
+impl<W> TextStream<W>
+    where W: Write,
+{
+    pub fn write_input(&mut self, input: &ControllerInput) -> Dtm2txtResult<()> {
+        self.pending.push(*input);
+        let per_line = match self.layout {
+            PortLayout::Joined => self.ports.len().max(1),
+            _ => 1,
+        };
+        if self.pending.len() < per_line {
+            return Ok(());
+        }
+
+        self.write_pending()
+    }
+
+    pub fn write_wiimote_input(&mut self, input: &WiimoteInput) -> Dtm2txtResult<()> {
+        self.line.clear();
+        write_wiimote_input(&mut self.line, input);
+        self.frame += 1;
+        self.write_line()
+    }
+
+    // Writes out a joined line the stream ended partway through.
+    pub fn finish(mut self) -> Dtm2txtResult<W> {
+        if !self.pending.is_empty() {
+            self.write_pending()?;
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    fn write_pending(&mut self) -> Dtm2txtResult<()> {
+        self.line.clear();
+        write_line_inputs(&mut self.line, &self.pending, self.syntax, self.layout, &self.ports, self.frame);
+        self.frame += self.pending.len() as u64;
+        self.pending.clear();
+        self.write_line()
+    }
+
+    fn write_line(&mut self) -> Dtm2txtResult<()> {
+        self.line.push('\n');
+        self.inner.write_all(self.line.as_bytes())?;
+        if self.flush_lines {
+            self.inner.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl<W> InputSink for TextStream<W>
+    where W: Write,
+{
+    fn push_controller(&mut self, input: ControllerInput) -> Dtm2txtResult<()> {
+        self.write_input(&input)
+    }
+
+    fn push_wiimote(&mut self, input: WiimoteInput) -> Dtm2txtResult<()> {
+        self.write_wiimote_input(&input)
+    }
+}
+
+// Fills `inputs` with the next line's worth of records, returning false once
+// there are none left.
+fn next_line_inputs<I>(inputs: &mut Vec<ControllerInput>, records: &mut I, per_line: usize) -> bool
+    where I: Iterator<Item = ControllerInput>,
+{
+    inputs.clear();
+    inputs.extend(records.take(per_line));
+    !inputs.is_empty()
+}
+
+// One line of controller inputs, starting at `frame`.
+fn write_line_inputs(line: &mut String, inputs: &[ControllerInput], syntax: FrameSyntax, layout: PortLayout, ports: &[u8], frame: u64) {
+    if layout == PortLayout::Labeled {
+        if let Some(port) = ports.get(frame as usize % ports.len().max(1)) {
+            line.push('P');
+            push_number(line, *port as u64 + 1, 0);
+            line.push_str(": ");
+        }
+    }
+    for (idx, input) in inputs.iter().enumerate() {
+        if idx > 0 {
+            line.push_str(" ; ");
+        }
+        match syntax {
+            FrameSyntax::Classic => write_controller_input(line, input),
+            FrameSyntax::Mnemonic => write_mnemonic_input(line, input),
+            FrameSyntax::Table => write_table_input(line, input),
+            FrameSyntax::Compact => write_compact_input(line, input),
+        }
+    }
+}
+
+// The names over the columns of table lines, lined up with the lines of
+// `layout`: past the `P1: ` of a labeled line, or once for each port of a
+// joined one.
+fn write_table_columns(line: &mut String, layout: PortLayout, per_line: usize) {
+    if layout == PortLayout::Labeled {
+        line.push_str("    ");
+    }
+    let copies = if layout == PortLayout::Joined { per_line } else { 1 };
+    for idx in 0..copies {
+        if idx > 0 {
+            line.push_str(" ; ");
+        }
+        for &button in MNEMONIC_BUTTONS.iter() {
+            line.push_str(button.name());
+            line.push(' ');
+        }
+        for name in TABLE_AXES.iter() {
+            for _ in name.len()..3 {
+                line.push(' ');
+            }
+            line.push_str(name);
+            line.push(' ');
+        }
+        for (idx, &button) in TABLE_EXTRAS.iter().enumerate() {
+            if idx > 0 {
+                line.push(' ');
+            }
+            line.push_str(button.name());
+        }
+    }
+}
+
+// Appends `value` right-aligned in `width` columns, like `{:width$}` but
+// without going through the formatting machinery for every field.
+fn push_number(line: &mut String, value: u64, width: usize) {
+    let mut digits = [0; 20];
+    let mut start = digits.len();
+    let mut rest = value;
+    loop {
+        start -= 1;
+        digits[start] = b'0' + (rest % 10) as u8;
+        rest /= 10;
+        if rest == 0 {
+            break;
+        }
+    }
+    for _ in (digits.len() - start)..width {
+        line.push(' ');
+    }
+    for &digit in digits[start..].iter() {
+        line.push(digit as char);
+    }
+}
+
+// The writers below append to a line, so one buffer can be reused for every
+// frame.
+
+// W A1 37 00 08 ...
+pub(crate) fn write_wiimote_input(line: &mut String, input: &WiimoteInput) {
+    const HEX_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+    line.push('W');
+    for &byte in input.report.iter() {
+        line.push(' ');
+        line.push(HEX_DIGITS[(byte >> 4) as usize] as char);
+        line.push(HEX_DIGITS[(byte & 0xF) as usize] as char);
+    }
+}
+
+// |SABXYZUDLRlr|X,Y|CX,CY|LP,RP| [CD RST CC RSV]
+pub(crate) fn write_mnemonic_input(line: &mut String, input: &ControllerInput) {
+    line.push('|');
+    push_mnemonic_buttons(line, input);
+    let pairs = [
+        (input.analog_x, input.analog_y),
+        (input.c_x, input.c_y),
+        (input.l_pressure, input.r_pressure),
+    ];
+    for &(first, second) in pairs.iter() {
+        line.push('|');
+        push_number(line, first as u64, 0);
+        line.push(',');
+        push_number(line, second as u64, 0);
+    }
+    line.push('|');
+    push_extra_buttons(line, input);
+}
+
+// SABXYZUDLRlr LP RP X Y CX CY [CD RST CC RSV]
+pub(crate) fn write_compact_input(line: &mut String, input: &ControllerInput) {
+    push_mnemonic_buttons(line, input);
+    for &axis in Axis::ALL.iter() {
+        line.push(' ');
+        push_number(line, input.axis(axis) as u64, 0);
+    }
+    push_extra_buttons(line, input);
+}
+
+// The button field shared by mnemonic and compact lines.
+fn push_mnemonic_buttons(line: &mut String, input: &ControllerInput) {
+    for (&button, mnemonic) in MNEMONIC_BUTTONS.iter().zip(MNEMONICS.chars()) {
+        line.push(if input.pressed(button) { mnemonic } else { '.' });
+    }
+}
+
+// The extra inputs, which only show up when they're pressed.
+fn push_extra_buttons(line: &mut String, input: &ControllerInput) {
+    format_input!(*line, input.change_disc, " CD", "");
+    format_input!(*line, input.reset, " RST", "");
+    format_input!(*line, input.controller_connected, " CC", "");
+    format_input!(*line, input.reserved, " RSV", "");
+}
+
+// A button's column name when it's pressed and as many dots when it isn't.
+fn push_table_cell(line: &mut String, button: Button, pressed: bool) {
+    if pressed {
+        line.push_str(button.name());
+    }
+    else {
+        for _ in 0..button.name().len() {
+            line.push('.');
+        }
+    }
+}
+
+// S A . . . . . . . . .. RT   0 255 128 128 128 128 .. RST .. ...
+pub(crate) fn write_table_input(line: &mut String, input: &ControllerInput) {
+    for &button in MNEMONIC_BUTTONS.iter() {
+        push_table_cell(line, button, input.pressed(button));
+        line.push(' ');
+    }
+    for &axis in Axis::ALL.iter() {
+        push_number(line, input.axis(axis) as u64, 3);
+        line.push(' ');
+    }
+    for (idx, &button) in TABLE_EXTRAS.iter().enumerate() {
+        if idx > 0 {
+            line.push(' ');
+        }
+        push_table_cell(line, button, input.pressed(button));
+    }
+}
+
+// S A B X Y Z U D L R LT 0 0 0 0 0 0 [CD RST CC RSV]
+pub(crate) fn write_controller_input(line: &mut String, input: &ControllerInput) {
+    format_input!(*line, input.start, "S ", "s ");
+    format_input!(*line, input.a, "A ", "a ");
+    format_input!(*line, input.b, "B ", "b ");
+    format_input!(*line, input.x, "X ", "x ");
+    format_input!(*line, input.y, "Y ", "y ");
+    format_input!(*line, input.z, "Z ", "z ");
+    format_input!(*line, input.up, "U ", "u ");
+    format_input!(*line, input.down, "D ", "d ");
+    format_input!(*line, input.left, "L ", "l ");
+    format_input!(*line, input.right, "R ", "r ");
+    format_input!(*line, input.l, "LT ", "lt ");
+    format_input!(*line, input.r, "RT ", "rt ");
+    let values = [input.l_pressure, input.r_pressure, input.analog_x, input.analog_y, input.c_x, input.c_y];
+    for (idx, &value) in values.iter().enumerate() {
+        if idx > 0 {
+            line.push(' ');
+        }
+        push_number(line, value as u64, 3);
+    }
+    push_extra_buttons(line, input);
+}
+
+// For callers that only want the one line.
+pub(crate) fn format_controller_input(input: &ControllerInput) -> String {
+    let mut line = String::new();
+    write_controller_input(&mut line, input);
+    line
+}
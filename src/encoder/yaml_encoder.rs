@@ -0,0 +1,85 @@
+use std::io::Write;
+
+use serde_yaml;
+
+use dtm::{self, Dtm, DtmHeader};
+use encoder::text_encoder::{self, FrameSyntax};
+use error::Dtm2txtResult;
+use frames::FrameStore;
+
+// The whole movie as one YAML document. Frames are kept as txt input lines,
+// one list entry each, since a map per frame would bury the inputs.
+#[derive(Serialize)]
+struct YamlMovie<'a> {
+    header: &'a DtmHeader,
+    #[serde(skip_serializing_if = "<[u8]>::is_empty", serialize_with = "dtm::serialize_hex")]
+    trailing: &'a [u8],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    frames: Option<Vec<String>>,
+}
+
+pub struct YamlEncoder<W> {
+    inner: W,
+    syntax: FrameSyntax,
+    frames: bool,
+}
+
+impl<W> YamlEncoder<W>
+    where W: Write,
+{
+    pub fn new(inner: W) -> YamlEncoder<W> {
+        YamlEncoder {
+            inner: inner,
+            syntax: FrameSyntax::default(),
+            frames: true,
+        }
+    }
+
+    pub fn syntax(mut self, syntax: FrameSyntax) -> YamlEncoder<W> {
+        self.syntax = syntax;
+        self
+    }
+
+    // Leaving the frames out gives a file for reading or editing the header
+    // alone; it can't be decoded back into a movie.
+    pub fn frames(mut self, frames: bool) -> YamlEncoder<W> {
+        self.frames = frames;
+        self
+    }
+
+    pub fn encode<S>(mut self, dtm: &Dtm<S>) -> Dtm2txtResult<()>
+        where S: FrameStore,
+    {
+        let frames = if self.frames {
+            let mut lines: Vec<String> = dtm.controller_data.frames()
+                .map(|input| {
+                    let mut line = String::new();
+                    match self.syntax {
+                        FrameSyntax::Classic => text_encoder::write_controller_input(&mut line, &input),
+                        FrameSyntax::Mnemonic => text_encoder::write_mnemonic_input(&mut line, &input),
+                        FrameSyntax::Table => text_encoder::write_table_input(&mut line, &input),
+                        FrameSyntax::Compact => text_encoder::write_compact_input(&mut line, &input),
+                    }
+                    line
+                })
+                .collect();
+            lines.extend(dtm.wiimote_data.iter().map(|input| {
+                let mut line = String::new();
+                text_encoder::write_wiimote_input(&mut line, input);
+                line
+            }));
+            Some(lines)
+        }
+        else {
+            None
+        };
+
+        let movie = YamlMovie {
+            header: &dtm.header,
+            trailing: &dtm.trailing,
+            frames: frames,
+        };
+        serde_yaml::to_writer(&mut self.inner, &movie)?;
+        Ok(())
+    }
+}
@@ -5,6 +5,17 @@ use std::string::FromUtf8Error;
 use std::num::ParseIntError;
 
 use serde_json::error::Error as JsonError;
+use zip::result::ZipError;
+#[cfg(feature = "yaml")]
+use serde_yaml::Error as YamlError;
+#[cfg(feature = "cbor")]
+use ciborium::de::Error as CborDecodeError;
+#[cfg(feature = "cbor")]
+use ciborium::ser::Error as CborEncodeError;
+
+use concat::ConcatProblem;
+use consistency::Inconsistency;
+use dtm::MAX_EXPANDED_INPUTS;
 
 #[derive(Debug)]
 pub enum ControllerInputParseError {
@@ -12,6 +23,27 @@ pub enum ControllerInputParseError {
     IoError(IoError),
     MissingTokenError,
     InvalidButtonError,
+    InvalidWiimoteReportError,
+    MixedControllerTypesError,
+    InvalidRepeatError,
+    InvalidDirectiveError,
+    UnknownMacroError(String),
+    UnmatchedBraceError,
+    UnclosedBlockError,
+    UnsupportedDirectiveError,
+    InvalidPortLabelError,
+    TooManyInputsError,
+    // Ports are numbered from 0.
+    WrongPortError {
+        expected: u8,
+        found: u8,
+    },
+    // A `;`-joined line with a different number of inputs than there are
+    // connected controllers.
+    WrongPortCountError {
+        expected: usize,
+        found: usize,
+    },
 }
 
 impl fmt::Display for ControllerInputParseError {
@@ -21,24 +53,197 @@ impl fmt::Display for ControllerInputParseError {
             ControllerInputParseError::IoError(ref e) => e.fmt(f),
             ControllerInputParseError::MissingTokenError => f.write_str("missing a button or axis"),
             ControllerInputParseError::InvalidButtonError => f.write_str("invalid button value"),
+            ControllerInputParseError::InvalidWiimoteReportError => f.write_str("invalid Wii remote report"),
+            ControllerInputParseError::MixedControllerTypesError => f.write_str("GameCube and Wii remote inputs can't be mixed"),
+            ControllerInputParseError::InvalidRepeatError => f.write_str("a line can't be repeated 0 times"),
+            ControllerInputParseError::InvalidDirectiveError => f.write_str("invalid directive"),
+            ControllerInputParseError::UnknownMacroError(ref name) => write!(f, "macro '{}' isn't defined", name),
+            ControllerInputParseError::UnmatchedBraceError => f.write_str("'}' without a !def or !loop to close"),
+            ControllerInputParseError::UnclosedBlockError => f.write_str("!def or !loop is never closed"),
+            ControllerInputParseError::UnsupportedDirectiveError => f.write_str("!def and !loop can't be used here"),
+            ControllerInputParseError::InvalidPortLabelError => f.write_str("invalid port label (expected P1: to P4:)"),
+            ControllerInputParseError::TooManyInputsError => write!(f, "inputs expand to more than {}", MAX_EXPANDED_INPUTS),
+            ControllerInputParseError::WrongPortError{expected, found} =>
+                write!(f, "line is labeled P{} but is for P{}", found + 1, expected + 1),
+            ControllerInputParseError::WrongPortCountError{expected, found} =>
+                write!(f, "line has inputs for {} controllers but {} are connected", found, expected),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FilterParseError {
+    ParseIntError(ParseIntError),
+    UnexpectedCharacter(char),
+    UnexpectedToken(String),
+    UnexpectedEnd,
+    UnknownIdentifier(String),
+    ExpectedComparison(String),
+}
+
+impl fmt::Display for FilterParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FilterParseError::ParseIntError(ref e) => e.fmt(f),
+            FilterParseError::UnexpectedCharacter(c) => write!(f, "unexpected character '{}'", c),
+            FilterParseError::UnexpectedToken(ref token) => write!(f, "unexpected '{}'", token),
+            FilterParseError::UnexpectedEnd => f.write_str("unexpected end of expression"),
+            FilterParseError::UnknownIdentifier(ref name) => write!(f, "unknown button or axis '{}'", name),
+            FilterParseError::ExpectedComparison(ref name) => write!(f, "'{}' must be compared to a value", name),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ScriptParseError {
+    ParseIntError(ParseIntError),
+    UnexpectedCharacter(char),
+    UnexpectedToken(String),
+    UnexpectedEnd,
+    UnknownCommand(String),
+    ExpectedButton,
+    ValueOutOfRange(u64),
+    TooManyFrames,
+}
+
+impl fmt::Display for ScriptParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ScriptParseError::ParseIntError(ref e) => e.fmt(f),
+            ScriptParseError::UnexpectedCharacter(c) => write!(f, "unexpected character '{}'", c),
+            ScriptParseError::UnexpectedToken(ref token) => write!(f, "unexpected '{}'", token),
+            ScriptParseError::UnexpectedEnd => f.write_str("unexpected end of script"),
+            ScriptParseError::UnknownCommand(ref name) => write!(f, "unknown command '{}'", name),
+            ScriptParseError::ExpectedButton => f.write_str("expected a button"),
+            ScriptParseError::ValueOutOfRange(value) => write!(f, "{} is out of range (must be 0 to 255)", value),
+            ScriptParseError::TooManyFrames => write!(f, "script expands to more than {} frames", MAX_EXPANDED_INPUTS),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseHexError {
+    InvalidCharacter(char),
+    OddLength,
+    WrongLength {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for ParseHexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseHexError::InvalidCharacter(c) => write!(f, "invalid hex character '{}'", c),
+            ParseHexError::OddLength => f.write_str("hex digits don't pair up into bytes"),
+            ParseHexError::WrongLength{expected, found} =>
+                write!(f, "expected {} bytes of hex but found {}", expected, found),
         }
     }
 }
 
+impl Error for ParseHexError {}
+
 #[derive(Debug)]
 pub enum Dtm2txtError {
     IoError(IoError),
     FromUtf8Error(FromUtf8Error),
     JsonError(JsonError),
+    ZipError(ZipError),
+    #[cfg(feature = "yaml")]
+    YamlError(YamlError),
+    #[cfg(feature = "cbor")]
+    CborDecodeError(CborDecodeError<IoError>),
+    #[cfg(feature = "cbor")]
+    CborEncodeError(CborEncodeError<IoError>),
     StringTooLongError {
+        field: &'static str,
         found: usize,
         max: usize,
     },
+    InvalidStringError {
+        field: &'static str,
+        reason: FromUtf8Error,
+    },
     BadMagicError,
     ControllerInputParseError{
         reason: ControllerInputParseError,
         line: u64,
     },
+    FilterParseError {
+        reason: FilterParseError,
+        column: usize,
+    },
+    ScriptParseError {
+        reason: ScriptParseError,
+        line: u64,
+    },
+    TooManyInputsError {
+        inputs: u64,
+    },
+    InvalidTransformError {
+        spec: String,
+        reason: String,
+    },
+    InvalidSnapStepError {
+        step: f64,
+    },
+    FrameOutOfRangeError {
+        frame: u64,
+        frames: u64,
+    },
+    UnsatisfiableConstraintError {
+        frame: u64,
+    },
+    InconsistentMovieError(Vec<Inconsistency>),
+    IncompatibleMoviesError(Vec<ConcatProblem>),
+    UnsupportedControllerLayoutError {
+        controllers: u8,
+    },
+    WiimoteReportTooLongError {
+        len: usize,
+    },
+    WiimoteDataUnsupportedError {
+        format: &'static str,
+    },
+    WiimoteViewError,
+    HeaderPatchNotObjectError,
+    UnknownHeaderFieldError {
+        field: String,
+    },
+    InvalidPortError {
+        port: u8,
+    },
+    InvalidGameIdError {
+        game_id: String,
+    },
+    // Ports are numbered from 0.
+    PortNotConnectedError {
+        port: u8,
+    },
+    PortTrackLengthError {
+        port: u8,
+        found: usize,
+        expected: usize,
+    },
+    DuplicatePortError {
+        port: u8,
+    },
+    TruncatedInputsError {
+        expected: u64,
+        found: u64,
+    },
+    MissingFramesError,
+    UnsupportedPatchVersionError {
+        version: u8,
+    },
+    OverlappingHunksError {
+        frame: u64,
+    },
+    PatchParentMismatchError {
+        expected: u64,
+        found: u64,
+    },
 }
 
 impl fmt::Display for Dtm2txtError {
@@ -47,24 +252,125 @@ impl fmt::Display for Dtm2txtError {
             Dtm2txtError::IoError(ref e) => e.fmt(f),
             Dtm2txtError::FromUtf8Error(ref e) => e.fmt(f),
             Dtm2txtError::JsonError(ref e) => e.fmt(f),
-            Dtm2txtError::StringTooLongError{found, max} =>
-                write!(f, "string too long (found {}, max {})", found, max),
+            Dtm2txtError::ZipError(ref e) => e.fmt(f),
+            #[cfg(feature = "yaml")]
+            Dtm2txtError::YamlError(ref e) => e.fmt(f),
+            #[cfg(feature = "cbor")]
+            Dtm2txtError::CborDecodeError(ref e) => e.fmt(f),
+            #[cfg(feature = "cbor")]
+            Dtm2txtError::CborEncodeError(ref e) => e.fmt(f),
+            Dtm2txtError::StringTooLongError{field, found, max} =>
+                write!(f, "{} is too long ({} bytes, max {})", field, found, max),
+            Dtm2txtError::InvalidStringError{field, ref reason} =>
+                write!(f, "{} is not valid UTF-8 ({})", field, reason),
             Dtm2txtError::BadMagicError => f.write_str("bad magic"),
             Dtm2txtError::ControllerInputParseError{ref reason, line} =>
                 write!(f, "{} on line {}", reason, line),
+            Dtm2txtError::FilterParseError{ref reason, column} =>
+                write!(f, "{} at column {}", reason, column),
+            Dtm2txtError::ScriptParseError{ref reason, line} =>
+                write!(f, "{} on line {}", reason, line),
+            Dtm2txtError::TooManyInputsError{inputs} =>
+                write!(f, "{} inputs is more than the {} that can be expanded", inputs, MAX_EXPANDED_INPUTS),
+            Dtm2txtError::InvalidTransformError{ref spec, ref reason} =>
+                write!(f, "invalid transform '{}': {}", spec, reason),
+            Dtm2txtError::InvalidSnapStepError{step} =>
+                write!(f, "can't snap every {} degrees (must be more than 0 and at most 360)", step),
+            Dtm2txtError::FrameOutOfRangeError{frame, frames} =>
+                write!(f, "frame {} out of range (movie has {} frames)", frame, frames),
+            Dtm2txtError::UnsatisfiableConstraintError{frame} =>
+                write!(f, "could not generate an input matching the constraint for frame {}", frame),
+            Dtm2txtError::UnsupportedControllerLayoutError{controllers} =>
+                write!(f, "can't tell apart GameCube and Wii remote inputs (controllers {:#04X})", controllers),
+            Dtm2txtError::WiimoteReportTooLongError{len} =>
+                write!(f, "Wii remote report too long (found {}, max 255)", len),
+            Dtm2txtError::WiimoteDataUnsupportedError{format} =>
+                write!(f, "{} files can't hold Wii remote inputs", format),
+            Dtm2txtError::WiimoteViewError =>
+                f.write_str("Wii remote inputs vary in size, so they can't be viewed in place; decode the movie instead"),
+            Dtm2txtError::HeaderPatchNotObjectError =>
+                f.write_str("header patch must be a JSON object"),
+            Dtm2txtError::UnknownHeaderFieldError{ref field} =>
+                write!(f, "unknown header field \"{}\"", field),
+            Dtm2txtError::InvalidGameIdError{ref game_id} =>
+                write!(f, "game ID '{}' isn't six letters and digits", game_id),
+            Dtm2txtError::InvalidPortError{port} =>
+                write!(f, "invalid controller port {} (must be 0 to 3)", port),
+            Dtm2txtError::PortNotConnectedError{port} =>
+                write!(f, "nothing is connected to GameCube port {}", port + 1),
+            Dtm2txtError::PortTrackLengthError{port, found, expected} =>
+                write!(f, "port {} has {} inputs but other ports have {}", port, found, expected),
+            Dtm2txtError::DuplicatePortError{port} =>
+                write!(f, "port {} has more than one track", port),
+            Dtm2txtError::TruncatedInputsError{expected, found} =>
+                write!(f, "file ends after {} inputs but the header says there are {}", found, expected),
+            Dtm2txtError::MissingFramesError => f.write_str("file only holds a header, not the frames"),
+            Dtm2txtError::UnsupportedPatchVersionError{version} =>
+                write!(f, "unsupported patch version {}", version),
+            Dtm2txtError::OverlappingHunksError{frame} =>
+                write!(f, "patch changes frame {} more than once", frame),
+            Dtm2txtError::PatchParentMismatchError{expected, found} =>
+                write!(f, "patch was made for a movie with input hash {:016x}, not {:016x}", expected, found),
+            Dtm2txtError::InconsistentMovieError(ref problems) => {
+                f.write_str("inconsistent movie")?;
+                for problem in problems.iter() {
+                    write!(f, "; {}", problem)?;
+                }
+                Ok(())
+            }
+            Dtm2txtError::IncompatibleMoviesError(ref problems) => {
+                f.write_str("movies can't be joined")?;
+                for problem in problems.iter() {
+                    write!(f, "; {}", problem)?;
+                }
+                Ok(())
+            }
         }
     }
 }
 
 impl Error for Dtm2txtError {
-    fn cause(&self) -> Option<&Error> {
+    fn cause(&self) -> Option<&dyn Error> {
         match *self {
             Dtm2txtError::IoError(ref e) => Some(e),
             Dtm2txtError::FromUtf8Error(ref e) => Some(e),
             Dtm2txtError::JsonError(ref e) => Some(e),
+            Dtm2txtError::ZipError(ref e) => Some(e),
+            #[cfg(feature = "yaml")]
+            Dtm2txtError::YamlError(ref e) => Some(e),
+            #[cfg(feature = "cbor")]
+            Dtm2txtError::CborDecodeError(ref e) => Some(e),
+            #[cfg(feature = "cbor")]
+            Dtm2txtError::CborEncodeError(ref e) => Some(e),
             Dtm2txtError::StringTooLongError{..} => None,
+            Dtm2txtError::InvalidStringError{ref reason, ..} => Some(reason),
             Dtm2txtError::BadMagicError => None,
             Dtm2txtError::ControllerInputParseError{..} => None,
+            Dtm2txtError::FilterParseError{..} => None,
+            Dtm2txtError::ScriptParseError{..} => None,
+            Dtm2txtError::TooManyInputsError{..} => None,
+            Dtm2txtError::InvalidTransformError{..} => None,
+            Dtm2txtError::InvalidSnapStepError{..} => None,
+            Dtm2txtError::FrameOutOfRangeError{..} => None,
+            Dtm2txtError::UnsatisfiableConstraintError{..} => None,
+            Dtm2txtError::InconsistentMovieError(_) => None,
+            Dtm2txtError::IncompatibleMoviesError(_) => None,
+            Dtm2txtError::UnsupportedControllerLayoutError{..} => None,
+            Dtm2txtError::WiimoteReportTooLongError{..} => None,
+            Dtm2txtError::WiimoteDataUnsupportedError{..} => None,
+            Dtm2txtError::WiimoteViewError => None,
+            Dtm2txtError::HeaderPatchNotObjectError => None,
+            Dtm2txtError::UnknownHeaderFieldError{..} => None,
+            Dtm2txtError::InvalidPortError{..} => None,
+            Dtm2txtError::InvalidGameIdError{..} => None,
+            Dtm2txtError::PortNotConnectedError{..} => None,
+            Dtm2txtError::PortTrackLengthError{..} => None,
+            Dtm2txtError::DuplicatePortError{..} => None,
+            Dtm2txtError::TruncatedInputsError{..} => None,
+            Dtm2txtError::MissingFramesError => None,
+            Dtm2txtError::UnsupportedPatchVersionError{..} => None,
+            Dtm2txtError::OverlappingHunksError{..} => None,
+            Dtm2txtError::PatchParentMismatchError{..} => None,
         }
     }
 }
@@ -87,4 +393,31 @@ impl From<JsonError> for Dtm2txtError {
     }
 }
 
+impl From<ZipError> for Dtm2txtError {
+    fn from(error: ZipError) -> Dtm2txtError {
+        Dtm2txtError::ZipError(error)
+    }
+}
+
+#[cfg(feature = "yaml")]
+impl From<YamlError> for Dtm2txtError {
+    fn from(error: YamlError) -> Dtm2txtError {
+        Dtm2txtError::YamlError(error)
+    }
+}
+
+#[cfg(feature = "cbor")]
+impl From<CborDecodeError<IoError>> for Dtm2txtError {
+    fn from(error: CborDecodeError<IoError>) -> Dtm2txtError {
+        Dtm2txtError::CborDecodeError(error)
+    }
+}
+
+#[cfg(feature = "cbor")]
+impl From<CborEncodeError<IoError>> for Dtm2txtError {
+    fn from(error: CborEncodeError<IoError>) -> Dtm2txtError {
+        Dtm2txtError::CborEncodeError(error)
+    }
+}
+
 pub type Dtm2txtResult<T> = Result<T, Dtm2txtError>;
\ No newline at end of file
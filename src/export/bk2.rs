@@ -0,0 +1,112 @@
+use std::io::{Cursor, Write};
+
+use zip::{CompressionMethod, ZipWriter};
+use zip::write::FileOptions;
+
+use dtm::{Dtm, DtmHeader, ControllerInput, Axis};
+use encoder::text_encoder::{MNEMONIC_BUTTONS, MNEMONICS};
+use error::{Dtm2txtError, Dtm2txtResult};
+use export::MovieFormat;
+
+// Sticks and triggers come before the buttons on each controller, the way
+// BizHawk logs analog controls.
+const AXES: [(Axis, &str); 6] = [
+    (Axis::AnalogX, "X Axis"), (Axis::AnalogY, "Y Axis"), (Axis::CX, "C X Axis"), (Axis::CY, "C Y Axis"),
+    (Axis::LPressure, "L Pressure"), (Axis::RPressure, "R Pressure"),
+];
+// Same order as MNEMONIC_BUTTONS.
+const BUTTON_NAMES: [&str; 12] = ["Start", "A", "B", "X", "Y", "Z", "Up", "Down", "Left", "Right", "L", "R"];
+
+// A BizHawk-style bk2: a zip holding a Header.txt of key-value lines and an
+// Input Log.txt with one line per frame. There's no GameCube core to play it
+// back, but the input log is easy to read into other TAS tools.
+pub struct Bk2;
+
+fn header_txt(header: &DtmHeader) -> String {
+    let mut text = String::from("MovieVersion BizHawk v2.0.0\n");
+    text += "Platform GC\n";
+    text += "Core Dolphin\n";
+    text += &format!("GameName {}\n", header.game_id);
+    text += &format!("Author {}\n", header.author);
+    text += &format!("rerecordCount {}\n", header.rerecord_count);
+    if header.has_game_md5() {
+        text += &format!("MD5 {}\n", header.md5);
+    }
+    if header.savestate {
+        text += "StartsFromSavestate True\n";
+    }
+    text
+}
+
+// The console buttons come first, then one group per connected port, named
+// the way Dolphin numbers ports.
+fn log_key(ports: &[u8]) -> String {
+    let mut key = String::from("LogKey:#Reset|Change Disc|");
+    for port in ports.iter() {
+        key.push('#');
+        let names = AXES.iter().map(|&(_, name)| name).chain(BUTTON_NAMES.iter().cloned());
+        for name in names {
+            key += &format!("P{} {}|", port + 1, name);
+        }
+    }
+    key
+}
+
+// One input per port. Reset and disc changes apply to the whole console, so
+// they're taken from the first port.
+fn input_line(inputs: &[ControllerInput]) -> String {
+    let mut line = String::from("|");
+    let console = inputs.first();
+    line.push(if console.map(|input| input.reset).unwrap_or(false) { 'r' } else { '.' });
+    line.push(if console.map(|input| input.change_disc).unwrap_or(false) { 'c' } else { '.' });
+    line.push('|');
+    for input in inputs.iter() {
+        for &(axis, _) in AXES.iter() {
+            line += &format!("{:5},", input.axis(axis));
+        }
+        for (&button, mnemonic) in MNEMONIC_BUTTONS.iter().zip(MNEMONICS.chars()) {
+            line.push(if input.pressed(button) { mnemonic } else { '.' });
+        }
+        line.push('|');
+    }
+    line
+}
+
+fn input_log(dtm: &Dtm) -> String {
+    let ports = dtm.header.gamecube_ports();
+    let mut log = String::from("[Input]\n");
+    log += &log_key(&ports);
+    log.push('\n');
+    for frame in dtm.controller_data.chunks(ports.len().max(1)) {
+        log += &input_line(frame);
+        log.push('\n');
+    }
+    log += "[/Input]\n";
+    log
+}
+
+impl MovieFormat for Bk2 {
+    fn name(&self) -> &'static str {
+        "bk2"
+    }
+
+    // The zip is put together in memory, since zip files need seeking.
+    fn export(&self, dtm: &Dtm, writer: &mut dyn Write) -> Dtm2txtResult<()> {
+        if !dtm.wiimote_data.is_empty() {
+            return Err(Dtm2txtError::WiimoteDataUnsupportedError {
+                format: "bk2",
+            });
+        }
+
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+        zip.start_file("Header.txt", options)?;
+        zip.write_all(header_txt(&dtm.header).as_bytes())?;
+        zip.start_file("Input Log.txt", options)?;
+        zip.write_all(input_log(dtm).as_bytes())?;
+        let bytes = zip.finish()?.into_inner();
+
+        writer.write_all(&bytes)?;
+        Ok(writer.flush()?)
+    }
+}
@@ -0,0 +1,27 @@
+use std::io::Write;
+
+use dtm::Dtm;
+use error::Dtm2txtResult;
+
+pub mod bk2;
+pub mod overlay;
+
+// The movie format of some other tool, which inputs can be exported to for
+// use there. Only the inputs and the header fields the format has room for
+// make it across, so there's no way back to the same dtm.
+pub trait MovieFormat {
+    // Used with --format, and as the extension of exported files.
+    fn name(&self) -> &'static str;
+
+    fn export(&self, dtm: &Dtm, writer: &mut dyn Write) -> Dtm2txtResult<()>;
+}
+
+// Every format movies can be exported to. A new format only needs adding
+// here to be picked up by the command line.
+pub const FORMATS: &[&dyn MovieFormat] = &[&bk2::Bk2, &overlay::Overlay, &overlay::Srt];
+
+pub fn find(name: &str) -> Option<&'static dyn MovieFormat> {
+    FORMATS.iter()
+        .cloned()
+        .find(|format| format.name().eq_ignore_ascii_case(name))
+}
@@ -0,0 +1,162 @@
+use std::io::Write;
+
+use serde_json;
+
+use dtm::{Dtm, DtmHeader, ControllerInput};
+use encoder::text_encoder::MNEMONIC_BUTTONS;
+use error::{Dtm2txtError, Dtm2txtResult};
+use export::MovieFormat;
+use humanize;
+
+// Input events for the input displays laid over TAS encodes, as JSON. There's
+// one event each time the inputs change, saying when it happens and how long
+// it lasts, so an overlay only has to redraw on events.
+pub struct Overlay;
+
+// The same events as SubRip subtitles, which most video editors and encoders
+// can burn into a video as they are.
+pub struct Srt;
+
+#[derive(Serialize)]
+struct OverlayFile<'a> {
+    game_id: &'a str,
+    frame_rate: f64,
+    ports: Vec<u8>,
+    events: Vec<OverlayEvent>,
+}
+
+#[derive(Serialize)]
+struct OverlayEvent {
+    frame: u64,
+    frames: u64,
+    time: f64,
+    duration: f64,
+    inputs: Vec<PortState>,
+}
+
+// What an input display shows of a port. Inputs it doesn't show, like
+// Controller Connected, don't start a new event.
+#[derive(Serialize, PartialEq)]
+struct PortState {
+    port: u8,
+    buttons: Vec<&'static str>,
+    main_stick: [u8; 2],
+    c_stick: [u8; 2],
+    triggers: [u8; 2],
+}
+
+impl PortState {
+    fn new(port: u8, input: &ControllerInput) -> PortState {
+        PortState {
+            port: port + 1,
+            buttons: MNEMONIC_BUTTONS.iter()
+                .filter(|&&button| input.pressed(button))
+                .map(|button| button.name())
+                .collect(),
+            main_stick: [input.analog_x, input.analog_y],
+            c_stick: [input.c_x, input.c_y],
+            triggers: [input.l_pressure, input.r_pressure],
+        }
+    }
+
+    // P1: A Z  128,128  C 128,128  L 0  R 0
+    fn caption(&self) -> String {
+        let mut caption = format!("P{}:", self.port);
+        for name in self.buttons.iter() {
+            caption.push(' ');
+            caption.push_str(name);
+        }
+        caption += &format!("  {},{}  C {},{}  L {}  R {}",
+            self.main_stick[0], self.main_stick[1], self.c_stick[0], self.c_stick[1], self.triggers[0], self.triggers[1]);
+        caption
+    }
+}
+
+// Frames are spread evenly over the movie's length, which takes lag into
+// account better than going by the refresh rate alone. A movie without a VI
+// count falls back to one frame per refresh.
+fn seconds_per_frame(header: &DtmHeader, frames: u64) -> f64 {
+    if header.vi_count != 0 && frames != 0 {
+        header.duration_secs() / frames as f64
+    }
+    else {
+        1.0 / header.vi_rate()
+    }
+}
+
+// The frames of every port at once, grouped into runs that don't change.
+// Frames count polls of all the ports together, like a txt file with
+// `--layout joined` does.
+fn events(dtm: &Dtm, format: &'static str) -> Dtm2txtResult<Vec<OverlayEvent>> {
+    if !dtm.wiimote_data.is_empty() {
+        return Err(Dtm2txtError::WiimoteDataUnsupportedError {
+            format: format,
+        });
+    }
+
+    let ports = dtm.header.gamecube_ports();
+    let frames = dtm.controller_data.chunks(ports.len().max(1));
+    let frame_secs = seconds_per_frame(&dtm.header, frames.len() as u64);
+    let mut events: Vec<OverlayEvent> = Vec::new();
+    for (frame, inputs) in frames.enumerate() {
+        let states: Vec<PortState> = ports.iter()
+            .zip(inputs.iter())
+            .map(|(&port, input)| PortState::new(port, input))
+            .collect();
+        if let Some(event) = events.last_mut() {
+            if event.inputs == states {
+                event.frames += 1;
+                event.duration = event.frames as f64 * frame_secs;
+                continue;
+            }
+        }
+        events.push(OverlayEvent {
+            frame: frame as u64,
+            frames: 1,
+            time: frame as f64 * frame_secs,
+            duration: frame_secs,
+            inputs: states,
+        });
+    }
+    Ok(events)
+}
+
+impl MovieFormat for Overlay {
+    fn name(&self) -> &'static str {
+        "overlay"
+    }
+
+    fn export(&self, dtm: &Dtm, writer: &mut dyn Write) -> Dtm2txtResult<()> {
+        let ports = dtm.header.gamecube_ports();
+        let frames = dtm.controller_data.len() as u64 / ports.len().max(1) as u64;
+        let file = OverlayFile {
+            game_id: &dtm.header.game_id,
+            frame_rate: 1.0 / seconds_per_frame(&dtm.header, frames),
+            ports: ports.iter().map(|port| port + 1).collect(),
+            events: events(dtm, "overlay")?,
+        };
+        serde_json::to_writer(&mut *writer, &file)?;
+        writeln!(writer)?;
+        Ok(writer.flush()?)
+    }
+}
+
+impl MovieFormat for Srt {
+    fn name(&self) -> &'static str {
+        "srt"
+    }
+
+    // One numbered cue per event, with a line for each port.
+    fn export(&self, dtm: &Dtm, writer: &mut dyn Write) -> Dtm2txtResult<()> {
+        let timestamp = |secs: f64| humanize::format_duration(secs).replace('.', ",");
+        for (idx, event) in events(dtm, "srt")?.iter().enumerate() {
+            writeln!(writer, "{}", idx + 1)?;
+            writeln!(writer, "{} --> {}", timestamp(event.time), timestamp(event.time + event.duration))?;
+            for port in event.inputs.iter() {
+                writeln!(writer, "{}", port.caption())?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(writer.flush()?)
+    }
+}
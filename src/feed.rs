@@ -0,0 +1,89 @@
+use std::io::Write;
+
+use dtm::{ControllerInput, Button};
+use encoder::text_encoder;
+use error::Dtm2txtResult;
+
+// Names Dolphin's pipe input uses for each button.
+const PIPE_BUTTONS: [(Button, &str); 12] = [
+    (Button::A, "A"), (Button::B, "B"), (Button::X, "X"), (Button::Y, "Y"),
+    (Button::Z, "Z"), (Button::Start, "START"), (Button::L, "L"), (Button::R, "R"),
+    (Button::Up, "D_UP"), (Button::Down, "D_DOWN"), (Button::Left, "D_LEFT"), (Button::Right, "D_RIGHT"),
+];
+
+// What a feed writes for each frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum FeedProtocol {
+    // The commands Dolphin's pipe input reads, like `PRESS A` and
+    // `SET MAIN 0.5 1.0`. Only what changed since the last frame is sent.
+    #[default]
+    Dolphin,
+    // One txt input line per frame.
+    Text,
+}
+
+// Hands inputs to another program one frame at a time, flushing after each
+// so it sees them as they come. Pacing the frames is up to the caller.
+pub struct FrameFeed<W> {
+    inner: W,
+    protocol: FeedProtocol,
+    last: Option<ControllerInput>,
+}
+
+// Dolphin reads stick and trigger positions from 0.0 to 1.0.
+fn pipe_axis(value: u8) -> String {
+    format!("{:.3}", value as f64 / 255.0)
+}
+
+impl<W> FrameFeed<W>
+    where W: Write,
+{
+    pub fn new(inner: W) -> FrameFeed<W> {
+        FrameFeed {
+            inner: inner,
+            protocol: FeedProtocol::default(),
+            last: None,
+        }
+    }
+
+    pub fn protocol(mut self, protocol: FeedProtocol) -> FrameFeed<W> {
+        self.protocol = protocol;
+        self
+    }
+
+    pub fn write_frame(&mut self, input: &ControllerInput) -> Dtm2txtResult<()> {
+        match self.protocol {
+            FeedProtocol::Dolphin => self.write_pipe_commands(input)?,
+            FeedProtocol::Text => writeln!(&mut self.inner, "{}", text_encoder::format_controller_input(input))?,
+        }
+        self.last = Some(*input);
+        Ok(self.inner.flush()?)
+    }
+
+    // The first frame sets everything, since there's no telling what state
+    // the other end is in.
+    fn write_pipe_commands(&mut self, input: &ControllerInput) -> Dtm2txtResult<()> {
+        let first = self.last.is_none();
+        let last = self.last.unwrap_or(*input);
+
+        for &(button, name) in PIPE_BUTTONS.iter() {
+            let pressed = input.pressed(button);
+            if first || last.pressed(button) != pressed {
+                writeln!(&mut self.inner, "{} {}", if pressed { "PRESS" } else { "RELEASE" }, name)?;
+            }
+        }
+        if first || (last.analog_x, last.analog_y) != (input.analog_x, input.analog_y) {
+            writeln!(&mut self.inner, "SET MAIN {} {}", pipe_axis(input.analog_x), pipe_axis(input.analog_y))?;
+        }
+        if first || (last.c_x, last.c_y) != (input.c_x, input.c_y) {
+            writeln!(&mut self.inner, "SET C {} {}", pipe_axis(input.c_x), pipe_axis(input.c_y))?;
+        }
+        if first || last.l_pressure != input.l_pressure {
+            writeln!(&mut self.inner, "SET L {}", pipe_axis(input.l_pressure))?;
+        }
+        if first || last.r_pressure != input.r_pressure {
+            writeln!(&mut self.inner, "SET R {}", pipe_axis(input.r_pressure))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,217 @@
+// C functions for reading and writing dtm files, so tools in other languages
+// can use the same parser. include/dtm2txt.h declares them.
+//
+// Functions that can fail return 0 on success, or -1 with the reason
+// available from dtm2txt_last_error. Only GameCube movies are supported,
+// since Wii remote reports don't fit a fixed-size struct.
+
+// What callers have to guarantee about each pointer is in the header, where
+// C programmers will look for it.
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::slice;
+
+use serde_json;
+
+use buttons::Buttons;
+use decoder::dtm_decoder::DtmDecoder;
+use dtm::{Dtm, DtmHeader, ControllerInput};
+use encoder::dtm_encoder::DtmEncoder;
+
+// One input, laid out like a dtm's: `buttons` uses the bits of `Buttons`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Dtm2txtInput {
+    pub buttons: u16,
+    pub l_pressure: u8,
+    pub r_pressure: u8,
+    pub analog_x: u8,
+    pub analog_y: u8,
+    pub c_x: u8,
+    pub c_y: u8,
+}
+
+// A whole movie. The header is a NUL-terminated JSON object with the same
+// fields as a txt header, which is easier to get at from C than 50-odd
+// struct members and doesn't break when a field is added.
+#[repr(C)]
+pub struct Dtm2txtMovie {
+    pub header_json: *mut c_char,
+    pub inputs: *mut Dtm2txtInput,
+    pub input_count: usize,
+    // Bytes after the inputs.
+    pub trailing: *mut u8,
+    pub trailing_len: usize,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+fn status(result: Result<(), String>) -> c_int {
+    match result {
+        Ok(()) => 0,
+        Err(message) => {
+            set_last_error(message);
+            -1
+        }
+    }
+}
+
+impl<'a> From<&'a ControllerInput> for Dtm2txtInput {
+    fn from(input: &'a ControllerInput) -> Dtm2txtInput {
+        Dtm2txtInput {
+            buttons: input.buttons().bits(),
+            l_pressure: input.l_pressure,
+            r_pressure: input.r_pressure,
+            analog_x: input.analog_x,
+            analog_y: input.analog_y,
+            c_x: input.c_x,
+            c_y: input.c_y,
+        }
+    }
+}
+
+impl<'a> From<&'a Dtm2txtInput> for ControllerInput {
+    fn from(input: &'a Dtm2txtInput) -> ControllerInput {
+        let mut controller_input = ControllerInput {
+            l_pressure: input.l_pressure,
+            r_pressure: input.r_pressure,
+            analog_x: input.analog_x,
+            analog_y: input.analog_y,
+            c_x: input.c_x,
+            c_y: input.c_y,
+            ..ControllerInput::NEUTRAL
+        };
+        controller_input.set_buttons(Buttons::from_bits(input.buttons));
+        controller_input
+    }
+}
+
+// Hands a slice over to C. It comes back through `free_boxed`.
+fn into_raw<T>(items: Vec<T>) -> *mut T {
+    Box::into_raw(items.into_boxed_slice()) as *mut T
+}
+
+unsafe fn free_boxed<T>(items: *mut T, len: usize) {
+    if !items.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(items, len)));
+    }
+}
+
+// C allows a null pointer for an empty array.
+unsafe fn borrow_slice<'a, T>(items: *const T, len: usize) -> Result<&'a [T], String> {
+    match (items.is_null(), len) {
+        (true, 0) => Ok(&[]),
+        (true, _) => Err("array pointer is null".to_string()),
+        (false, _) => Ok(slice::from_raw_parts(items, len)),
+    }
+}
+
+fn decode(data: &[u8]) -> Result<Dtm2txtMovie, String> {
+    let dtm = DtmDecoder::new(data).decode().map_err(|err| err.to_string())?;
+    if !dtm.wiimote_data.is_empty() {
+        return Err("Wii remote inputs aren't supported".to_string());
+    }
+
+    let header_json = serde_json::to_string(&dtm.header).map_err(|err| err.to_string())?;
+    let header_json = CString::new(header_json).map_err(|err| err.to_string())?;
+    let inputs: Vec<Dtm2txtInput> = dtm.controller_data.iter().map(Dtm2txtInput::from).collect();
+    Ok(Dtm2txtMovie {
+        header_json: header_json.into_raw(),
+        input_count: inputs.len(),
+        inputs: into_raw(inputs),
+        trailing_len: dtm.trailing.len(),
+        trailing: into_raw(dtm.trailing),
+    })
+}
+
+unsafe fn encode(movie: &Dtm2txtMovie) -> Result<Vec<u8>, String> {
+    if movie.header_json.is_null() {
+        return Err("header_json is null".to_string());
+    }
+    let header_json = CStr::from_ptr(movie.header_json).to_str().map_err(|err| err.to_string())?;
+    let header: DtmHeader = serde_json::from_str(header_json).map_err(|err| format!("Invalid header: {}", err))?;
+    let inputs = borrow_slice(movie.inputs, movie.input_count)?;
+    let trailing = borrow_slice(movie.trailing, movie.trailing_len)?;
+
+    let dtm: Dtm = Dtm {
+        header: header,
+        controller_data: inputs.iter().map(ControllerInput::from).collect(),
+        wiimote_data: Vec::new(),
+        trailing: trailing.to_vec(),
+    };
+    let mut data = Vec::new();
+    DtmEncoder::new(&mut data).encode(&dtm).map_err(|err| err.to_string())?;
+    Ok(data)
+}
+
+// Decodes the `len` bytes of a dtm at `data` into `*movie`, which has to be
+// released with dtm2txt_movie_free.
+#[no_mangle]
+pub unsafe extern "C" fn dtm2txt_decode(data: *const u8, len: usize, movie: *mut Dtm2txtMovie) -> c_int {
+    status(borrow_slice(data, len).and_then(|data| {
+        if movie.is_null() {
+            return Err("movie pointer is null".to_string());
+        }
+        ptr::write(movie, decode(data)?);
+        Ok(())
+    }))
+}
+
+// Encodes a movie as dtm bytes, storing them in `*data` and their length in
+// `*len`. They have to be released with dtm2txt_bytes_free. The movie can
+// come from dtm2txt_decode or be put together by the caller; its header's
+// counts have to agree with its inputs, as when writing any dtm.
+#[no_mangle]
+pub unsafe extern "C" fn dtm2txt_encode(movie: *const Dtm2txtMovie, data: *mut *mut u8, len: *mut usize) -> c_int {
+    if movie.is_null() || data.is_null() || len.is_null() {
+        return status(Err("null pointer passed to dtm2txt_encode".to_string()));
+    }
+    status(encode(&*movie).map(|bytes| {
+        *len = bytes.len();
+        *data = into_raw(bytes);
+    }))
+}
+
+// Releases what dtm2txt_decode filled in and zeroes the movie. Only for
+// movies from dtm2txt_decode; the caller frees ones it put together itself.
+#[no_mangle]
+pub unsafe extern "C" fn dtm2txt_movie_free(movie: *mut Dtm2txtMovie) {
+    if let Some(movie) = movie.as_mut() {
+        if !movie.header_json.is_null() {
+            drop(CString::from_raw(movie.header_json));
+        }
+        free_boxed(movie.inputs, movie.input_count);
+        free_boxed(movie.trailing, movie.trailing_len);
+        ptr::write(movie, Dtm2txtMovie {
+            header_json: ptr::null_mut(),
+            inputs: ptr::null_mut(),
+            input_count: 0,
+            trailing: ptr::null_mut(),
+            trailing_len: 0,
+        });
+    }
+}
+
+// Releases bytes from dtm2txt_encode.
+#[no_mangle]
+pub unsafe extern "C" fn dtm2txt_bytes_free(data: *mut u8, len: usize) {
+    free_boxed(data, len);
+}
+
+// Why the last call on this thread failed, or null if none has. The string
+// stays valid until the next failing call.
+#[no_mangle]
+pub extern "C" fn dtm2txt_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map(|message| message.as_ptr()).unwrap_or(ptr::null()))
+}
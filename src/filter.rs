@@ -0,0 +1,418 @@
+use std::fmt;
+use std::str::FromStr;
+
+use dtm::{Dtm, ControllerInput, Button, Axis};
+use error::{Dtm2txtError, FilterParseError, Dtm2txtResult};
+use frames::FrameStore;
+
+// Anything that can decide whether a frame is selected. Searching, trimming
+// with `extract --where`, statistics and `Matching` transforms all take one of
+// these, so a parsed `Filter` and a plain closure are interchangeable.
+pub trait FramePredicate {
+    fn matches(&self, frame: u64, input: &ControllerInput) -> bool;
+}
+
+impl<F> FramePredicate for F
+    where F: Fn(u64, &ControllerInput) -> bool,
+{
+    fn matches(&self, frame: u64, input: &ControllerInput) -> bool {
+        self(frame, input)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand {
+    Frame,
+    Axis(Axis),
+    Value(u64),
+}
+
+impl Operand {
+    pub(crate) fn eval(&self, frame: u64, input: &ControllerInput) -> u64 {
+        match *self {
+            Operand::Frame => frame,
+            Operand::Axis(axis) => input.axis(axis) as u64,
+            Operand::Value(value) => value,
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operand::Frame => f.write_str("frame"),
+            Operand::Axis(axis) => f.write_str(axis.name()),
+            Operand::Value(value) => value.fmt(f),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Eq => "==",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        }
+    }
+
+    pub(crate) fn test(self, lhs: u64, rhs: u64) -> bool {
+        match self {
+            Comparison::Eq => lhs == rhs,
+            Comparison::Ne => lhs != rhs,
+            Comparison::Lt => lhs < rhs,
+            Comparison::Le => lhs <= rhs,
+            Comparison::Gt => lhs > rhs,
+            Comparison::Ge => lhs >= rhs,
+        }
+    }
+
+    // The comparison that holds exactly when this one doesn't.
+    pub(crate) fn negated(self) -> Comparison {
+        match self {
+            Comparison::Eq => Comparison::Ne,
+            Comparison::Ne => Comparison::Eq,
+            Comparison::Lt => Comparison::Ge,
+            Comparison::Le => Comparison::Gt,
+            Comparison::Gt => Comparison::Le,
+            Comparison::Ge => Comparison::Lt,
+        }
+    }
+
+    // The same comparison with its operands swapped, so `5 < x` is `x > 5`.
+    pub(crate) fn flipped(self) -> Comparison {
+        match self {
+            Comparison::Lt => Comparison::Gt,
+            Comparison::Le => Comparison::Ge,
+            Comparison::Gt => Comparison::Lt,
+            Comparison::Ge => Comparison::Le,
+            other => other,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Filter {
+    Pressed(Button),
+    Compare(Operand, Comparison, Operand),
+    Not(Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+}
+
+impl Filter {
+    pub fn pressed(button: Button) -> Filter {
+        Filter::Pressed(button)
+    }
+
+    pub fn compare(lhs: Operand, comparison: Comparison, rhs: Operand) -> Filter {
+        Filter::Compare(lhs, comparison, rhs)
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(self) -> Filter {
+        Filter::Not(Box::new(self))
+    }
+
+    pub fn and(self, other: Filter) -> Filter {
+        Filter::And(Box::new(self), Box::new(other))
+    }
+
+    pub fn or(self, other: Filter) -> Filter {
+        Filter::Or(Box::new(self), Box::new(other))
+    }
+
+    // Parses expressions like `A && !B && analog_x > 200`. Buttons use either
+    // their txt format token or long name; axes and `frame` must be compared
+    // against a number.
+    pub fn parse(expr: &str) -> Dtm2txtResult<Filter> {
+        let tokens = tokenize(expr)?;
+        let mut parser = Parser {
+            tokens: tokens,
+            pos: 0,
+            end: expr.len() + 1,
+        };
+        let filter = parser.parse_or()?;
+        match parser.tokens.get(parser.pos) {
+            Some(&(ref token, column)) => Err(parse_error(FilterParseError::UnexpectedToken(token.to_string()), column)),
+            None => Ok(filter),
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = Dtm2txtError;
+
+    fn from_str(expr: &str) -> Dtm2txtResult<Filter> {
+        Filter::parse(expr)
+    }
+}
+
+impl FramePredicate for Filter {
+    fn matches(&self, frame: u64, input: &ControllerInput) -> bool {
+        match *self {
+            Filter::Pressed(button) => input.pressed(button),
+            Filter::Compare(ref lhs, comparison, ref rhs) =>
+                comparison.test(lhs.eval(frame, input), rhs.eval(frame, input)),
+            Filter::Not(ref inner) => !inner.matches(frame, input),
+            Filter::And(ref lhs, ref rhs) => lhs.matches(frame, input) && rhs.matches(frame, input),
+            Filter::Or(ref lhs, ref rhs) => lhs.matches(frame, input) || rhs.matches(frame, input),
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Filter::Pressed(button) => f.write_str(button.name()),
+            Filter::Compare(ref lhs, comparison, ref rhs) => write!(f, "{} {} {}", lhs, comparison.symbol(), rhs),
+            Filter::Not(ref inner) => write!(f, "!({})", inner),
+            Filter::And(ref lhs, ref rhs) => write!(f, "({} && {})", lhs, rhs),
+            Filter::Or(ref lhs, ref rhs) => write!(f, "({} || {})", lhs, rhs),
+        }
+    }
+}
+
+impl<S> Dtm<S>
+    where S: FrameStore + Clone,
+{
+    // The frames where the input of any connected port matches, each once.
+    // The predicate is given the frame number, not the input's index, so
+    // `frame` means the same with one controller as with four.
+    pub fn find_frames<P>(&self, predicate: &P) -> Vec<u64>
+        where P: FramePredicate,
+    {
+        let ports = self.ports_per_frame();
+        let mut frames: Vec<u64> = Vec::new();
+        for (idx, input) in self.controller_data.frames().enumerate() {
+            let frame = idx as u64 / ports;
+            if frames.last() != Some(&frame) && predicate.matches(frame, &input) {
+                frames.push(frame);
+            }
+        }
+        frames
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(u64),
+    Comparison(Comparison),
+    Not,
+    And,
+    Or,
+    LParen,
+    RParen,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Token::Ident(ref name) => f.write_str(name),
+            Token::Number(value) => value.fmt(f),
+            Token::Comparison(comparison) => f.write_str(comparison.symbol()),
+            Token::Not => f.write_str("!"),
+            Token::And => f.write_str("&&"),
+            Token::Or => f.write_str("||"),
+            Token::LParen => f.write_str("("),
+            Token::RParen => f.write_str(")"),
+        }
+    }
+}
+
+fn parse_error(reason: FilterParseError, column: usize) -> Dtm2txtError {
+    Dtm2txtError::FilterParseError {
+        reason: reason,
+        column: column,
+    }
+}
+
+// Columns are 1-indexed, like line numbers in the txt decoder.
+fn tokenize(expr: &str) -> Dtm2txtResult<Vec<(Token, usize)>> {
+    let mut tokens = Vec::new();
+    let mut chars = expr.char_indices().peekable();
+
+    while let Some((idx, c)) = chars.next() {
+        let column = idx + 1;
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '&' | '|' => {
+                match chars.next() {
+                    Some((_, next)) if next == c => {}
+                    _ => return Err(parse_error(FilterParseError::UnexpectedCharacter(c), column)),
+                }
+                if c == '&' { Token::And } else { Token::Or }
+            }
+            '!' | '=' | '<' | '>' => {
+                let followed_by_eq = matches!(chars.peek(), Some(&(_, '=')));
+                if followed_by_eq {
+                    chars.next();
+                }
+                match (c, followed_by_eq) {
+                    ('!', false) => Token::Not,
+                    ('!', true) => Token::Comparison(Comparison::Ne),
+                    ('=', true) => Token::Comparison(Comparison::Eq),
+                    ('<', false) => Token::Comparison(Comparison::Lt),
+                    ('<', true) => Token::Comparison(Comparison::Le),
+                    ('>', false) => Token::Comparison(Comparison::Gt),
+                    ('>', true) => Token::Comparison(Comparison::Ge),
+                    _ => return Err(parse_error(FilterParseError::UnexpectedCharacter(c), column)),
+                }
+            }
+            c if c.is_ascii_alphanumeric() || c == '_' => {
+                let mut word = c.to_string();
+                while let Some(&(_, next)) = chars.peek() {
+                    if !(next.is_ascii_alphanumeric() || next == '_') {
+                        break;
+                    }
+                    word.push(next);
+                    chars.next();
+                }
+
+                if c.is_ascii_digit() {
+                    let value = word.parse::<u64>()
+                        .map_err(|err| parse_error(FilterParseError::ParseIntError(err), column))?;
+                    Token::Number(value)
+                }
+                else {
+                    Token::Ident(word)
+                }
+            }
+            _ => return Err(parse_error(FilterParseError::UnexpectedCharacter(c), column)),
+        };
+        tokens.push((token, column));
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Dtm2txtResult<(Token, usize)> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.clone())
+            }
+            None => Err(parse_error(FilterParseError::UnexpectedEnd, self.end)),
+        }
+    }
+
+    fn parse_or(&mut self) -> Dtm2txtResult<Filter> {
+        let mut filter = self.parse_and()?;
+        while let Some(&Token::Or) = self.peek() {
+            self.pos += 1;
+            filter = filter.or(self.parse_and()?);
+        }
+        Ok(filter)
+    }
+
+    fn parse_and(&mut self) -> Dtm2txtResult<Filter> {
+        let mut filter = self.parse_unary()?;
+        while let Some(&Token::And) = self.peek() {
+            self.pos += 1;
+            filter = filter.and(self.parse_unary()?);
+        }
+        Ok(filter)
+    }
+
+    fn parse_unary(&mut self) -> Dtm2txtResult<Filter> {
+        if let Some(&Token::Not) = self.peek() {
+            self.pos += 1;
+            return Ok(self.parse_unary()?.not());
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Dtm2txtResult<Filter> {
+        let (token, column) = self.next()?;
+        match token {
+            Token::LParen => {
+                let filter = self.parse_or()?;
+                match self.next()? {
+                    (Token::RParen, _) => Ok(filter),
+                    (token, column) => Err(parse_error(FilterParseError::UnexpectedToken(token.to_string()), column)),
+                }
+            }
+            Token::Ident(ref name) if !self.comparison_follows() => {
+                match Button::from_name(name) {
+                    Some(button) => Ok(Filter::pressed(button)),
+                    None if self.to_operand(&token, column).is_ok() =>
+                        Err(parse_error(FilterParseError::ExpectedComparison(name.clone()), column)),
+                    None => Err(parse_error(FilterParseError::UnknownIdentifier(name.clone()), column)),
+                }
+            }
+            token => {
+                let lhs = self.to_operand(&token, column)?;
+                let comparison = match self.next()? {
+                    (Token::Comparison(comparison), _) => comparison,
+                    (_, column) => return Err(parse_error(FilterParseError::ExpectedComparison(token.to_string()), column)),
+                };
+                let (rhs_token, rhs_column) = self.next()?;
+                let rhs = self.to_operand(&rhs_token, rhs_column)?;
+                Ok(Filter::compare(lhs, comparison, rhs))
+            }
+        }
+    }
+
+    fn comparison_follows(&self) -> bool {
+        matches!(self.peek(), Some(&Token::Comparison(_)))
+    }
+
+    fn to_operand(&self, token: &Token, column: usize) -> Dtm2txtResult<Operand> {
+        match *token {
+            Token::Number(value) => Ok(Operand::Value(value)),
+            Token::Ident(ref name) if name.eq_ignore_ascii_case("frame") => Ok(Operand::Frame),
+            Token::Ident(ref name) => {
+                Axis::from_name(name)
+                    .map(Operand::Axis)
+                    .ok_or_else(|| parse_error(FilterParseError::UnknownIdentifier(name.clone()), column))
+            }
+            ref token => Err(parse_error(FilterParseError::UnexpectedToken(token.to_string()), column)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dtm::DtmHeader;
+
+    #[test]
+    fn finds_frames_of_multi_port_movies() {
+        let header = DtmHeader::builder().gamecube_ports(&[0, 1]).build();
+        let mut dtm = Dtm::blank(header, 5);
+        dtm.controller_data[3].z = true;
+        dtm.controller_data[4].z = true;
+        dtm.controller_data[5].z = true;
+
+        assert_eq!(dtm.find_frames(&Filter::parse("Z").unwrap()), vec![1, 2]);
+        assert_eq!(dtm.find_frames(&Filter::parse("frame >= 3").unwrap()), vec![3, 4]);
+        assert!(dtm.find_frames(&Filter::parse("frame >= 8").unwrap()).is_empty());
+    }
+}
@@ -0,0 +1,438 @@
+use std::iter::FromIterator;
+use std::ops::Range;
+
+use serde::{Serialize, Deserialize, Serializer, Deserializer};
+use serde::de;
+
+use dtm::{ControllerInput, CONTROLLER_RECORD_SIZE};
+
+// Storage for a movie's inputs. `Vec<ControllerInput>` is the default;
+// `RleFrames` keeps runs of identical inputs as a single entry, which is far
+// smaller for long movies that mostly hold the same buttons, and `RawFrames`
+// keeps the bytes of a dtm as they are.
+pub trait FrameStore {
+    fn len(&self) -> u64;
+    fn frame(&self, frame: u64) -> Option<ControllerInput>;
+    fn set_frame(&mut self, frame: u64, input: ControllerInput);
+    fn push(&mut self, input: ControllerInput);
+    fn truncate(&mut self, len: u64);
+    fn frames<'a>(&'a self) -> Box<dyn Iterator<Item = ControllerInput> + 'a>;
+
+    // A copy of the inputs in `range`, which has to be within the store.
+    fn slice(&self, range: Range<u64>) -> Self
+        where Self: Sized;
+
+    // Appends a copy of all of `other`'s inputs.
+    fn append_from(&mut self, other: &Self)
+        where Self: Sized;
+
+    // A hint that about `additional` more inputs are coming.
+    fn reserve(&mut self, _additional: u64) {}
+
+    // Adds an input given as the bytes a dtm stores it as.
+    fn push_record(&mut self, record: &[u8; CONTROLLER_RECORD_SIZE]) {
+        self.push(ControllerInput::from_bytes(record));
+    }
+
+    // The inputs as the bytes a dtm stores them as.
+    fn records<'a>(&'a self) -> Box<dyn Iterator<Item = [u8; CONTROLLER_RECORD_SIZE]> + 'a> {
+        Box::new(self.frames().map(|input| input.to_bytes()))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl FrameStore for Vec<ControllerInput> {
+    fn len(&self) -> u64 {
+        Vec::len(self) as u64
+    }
+
+    fn frame(&self, frame: u64) -> Option<ControllerInput> {
+        self.as_slice().get(frame as usize).cloned()
+    }
+
+    fn set_frame(&mut self, frame: u64, input: ControllerInput) {
+        self[frame as usize] = input;
+    }
+
+    fn push(&mut self, input: ControllerInput) {
+        Vec::push(self, input);
+    }
+
+    fn truncate(&mut self, len: u64) {
+        Vec::truncate(self, len as usize);
+    }
+
+    fn frames<'a>(&'a self) -> Box<dyn Iterator<Item = ControllerInput> + 'a> {
+        Box::new(self.as_slice().iter().cloned())
+    }
+
+    fn slice(&self, range: Range<u64>) -> Vec<ControllerInput> {
+        self[range.start as usize..range.end as usize].to_vec()
+    }
+
+    fn append_from(&mut self, other: &Vec<ControllerInput>) {
+        self.extend_from_slice(other);
+    }
+
+    fn reserve(&mut self, additional: u64) {
+        Vec::reserve(self, additional as usize);
+    }
+}
+
+// Runs are always merged with their neighbours when they match, so two of
+// these holding the same frames compare and hash the same.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RleFrames {
+    runs: Vec<(ControllerInput, u64)>,
+    // Exclusive end frame of each run, for binary searching.
+    ends: Vec<u64>,
+}
+
+impl RleFrames {
+    pub fn new() -> RleFrames {
+        RleFrames::default()
+    }
+
+    pub fn runs(&self) -> &[(ControllerInput, u64)] {
+        &self.runs
+    }
+
+    fn run_index(&self, frame: u64) -> Option<usize> {
+        if frame >= FrameStore::len(self) {
+            return None;
+        }
+        match self.ends.binary_search(&frame) {
+            Ok(idx) => Some(idx + 1),
+            Err(idx) => Some(idx),
+        }
+    }
+
+    // Adds a run, merging it into the last one when they match. Empty runs
+    // would throw off the binary search, so they're dropped.
+    fn push_run(&mut self, input: ControllerInput, count: u64) {
+        if count == 0 {
+            return;
+        }
+        let end = FrameStore::len(self) + count;
+        match self.runs.last_mut() {
+            Some(last) if last.0 == input => {
+                last.1 += count;
+                *self.ends.last_mut().unwrap() = end;
+            }
+            _ => {
+                self.runs.push((input, count));
+                self.ends.push(end);
+            }
+        }
+    }
+
+    // Drops the run at `idx`, which has to be empty by now.
+    fn remove_run(&mut self, idx: usize) {
+        self.runs.remove(idx);
+        self.ends.remove(idx);
+    }
+}
+
+// Only the runs are serialized; the ends are rebuilt from them.
+impl Serialize for RleFrames {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer,
+    {
+        self.runs.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RleFrames {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: Deserializer<'de>,
+    {
+        let runs: Vec<(ControllerInput, u64)> = Deserialize::deserialize(deserializer)?;
+        let mut frames = RleFrames::new();
+        let mut len: u64 = 0;
+        for (input, count) in runs {
+            len = len.checked_add(count)
+                .ok_or_else(|| de::Error::custom("run lengths add up to more frames than fit in 64 bits"))?;
+            frames.push_run(input, count);
+        }
+        Ok(frames)
+    }
+}
+
+impl FrameStore for RleFrames {
+    fn len(&self) -> u64 {
+        self.ends.last().cloned().unwrap_or(0)
+    }
+
+    fn frame(&self, frame: u64) -> Option<ControllerInput> {
+        self.run_index(frame).map(|idx| self.runs[idx].0)
+    }
+
+    // Only the run holding the frame and its neighbours change: the frame
+    // moves into a neighbour that matches, or splits its run in up to three.
+    fn set_frame(&mut self, frame: u64, input: ControllerInput) {
+        let idx = self.run_index(frame).expect("frame out of range");
+        let (old_input, count) = self.runs[idx];
+        if old_input == input {
+            return;
+        }
+
+        let end = self.ends[idx];
+        let start = end - count;
+        let joins_previous = frame == start && idx > 0 && self.runs[idx - 1].0 == input;
+        let joins_next = frame == end - 1 && idx + 1 < self.runs.len() && self.runs[idx + 1].0 == input;
+        if joins_previous && joins_next {
+            // A run of one frame between two matching runs, which become one.
+            self.runs[idx - 1].1 += 1 + self.runs[idx + 1].1;
+            self.ends[idx - 1] = self.ends[idx + 1];
+            self.runs.drain(idx..idx + 2);
+            self.ends.drain(idx..idx + 2);
+        }
+        else if joins_previous {
+            self.runs[idx - 1].1 += 1;
+            self.ends[idx - 1] += 1;
+            self.runs[idx].1 -= 1;
+            if count == 1 {
+                self.remove_run(idx);
+            }
+        }
+        else if joins_next {
+            self.runs[idx + 1].1 += 1;
+            self.runs[idx].1 -= 1;
+            self.ends[idx] -= 1;
+            if count == 1 {
+                self.remove_run(idx);
+            }
+        }
+        else if count == 1 {
+            self.runs[idx].0 = input;
+        }
+        else if frame == start {
+            self.runs[idx].1 -= 1;
+            self.runs.insert(idx, (input, 1));
+            self.ends.insert(idx, frame + 1);
+        }
+        else if frame == end - 1 {
+            self.runs[idx].1 -= 1;
+            self.ends[idx] -= 1;
+            self.runs.insert(idx + 1, (input, 1));
+            self.ends.insert(idx + 1, end);
+        }
+        else {
+            self.runs[idx].1 = frame - start;
+            self.ends[idx] = frame;
+            self.runs.splice(idx + 1..idx + 1, vec![(input, 1), (old_input, end - frame - 1)]);
+            self.ends.splice(idx + 1..idx + 1, vec![frame + 1, end]);
+        }
+    }
+
+    fn push(&mut self, input: ControllerInput) {
+        self.push_run(input, 1);
+    }
+
+    fn truncate(&mut self, len: u64) {
+        let idx = match self.run_index(len) {
+            Some(idx) => idx,
+            None => return,
+        };
+        let run_start = self.ends[idx] - self.runs[idx].1;
+        if run_start == len {
+            self.runs.truncate(idx);
+            self.ends.truncate(idx);
+        }
+        else {
+            self.runs.truncate(idx + 1);
+            self.ends.truncate(idx + 1);
+            self.runs[idx].1 = len - run_start;
+            self.ends[idx] = len;
+        }
+    }
+
+    fn frames<'a>(&'a self) -> Box<dyn Iterator<Item = ControllerInput> + 'a> {
+        Box::new(self.runs.iter().flat_map(|&(input, count)| (0..count).map(move |_| input)))
+    }
+
+    fn slice(&self, range: Range<u64>) -> RleFrames {
+        self.frames().skip(range.start as usize).take((range.end - range.start) as usize).collect()
+    }
+
+    fn append_from(&mut self, other: &RleFrames) {
+        for &(input, count) in other.runs.iter() {
+            self.push_run(input, count);
+        }
+    }
+}
+
+impl FromIterator<ControllerInput> for RleFrames {
+    fn from_iter<I>(iter: I) -> RleFrames
+        where I: IntoIterator<Item = ControllerInput>,
+    {
+        let mut frames = RleFrames::new();
+        for input in iter {
+            frames.push(input);
+        }
+        frames
+    }
+}
+
+// Keeps each input as the bytes a dtm stores it as, and only turns them into
+// a `ControllerInput` when one is asked for. Tools that copy, cut or join
+// dtm files this way never have to look inside the inputs at all.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RawFrames {
+    records: Vec<[u8; CONTROLLER_RECORD_SIZE]>,
+}
+
+impl RawFrames {
+    pub fn new() -> RawFrames {
+        RawFrames::default()
+    }
+
+    pub fn as_slice(&self) -> &[[u8; CONTROLLER_RECORD_SIZE]] {
+        &self.records
+    }
+}
+
+impl FrameStore for RawFrames {
+    fn len(&self) -> u64 {
+        self.records.len() as u64
+    }
+
+    fn frame(&self, frame: u64) -> Option<ControllerInput> {
+        self.records.get(frame as usize).map(ControllerInput::from_bytes)
+    }
+
+    fn set_frame(&mut self, frame: u64, input: ControllerInput) {
+        self.records[frame as usize] = input.to_bytes();
+    }
+
+    fn push(&mut self, input: ControllerInput) {
+        self.records.push(input.to_bytes());
+    }
+
+    fn truncate(&mut self, len: u64) {
+        self.records.truncate(len as usize);
+    }
+
+    fn frames<'a>(&'a self) -> Box<dyn Iterator<Item = ControllerInput> + 'a> {
+        Box::new(self.records.iter().map(ControllerInput::from_bytes))
+    }
+
+    fn slice(&self, range: Range<u64>) -> RawFrames {
+        RawFrames {
+            records: self.records[range.start as usize..range.end as usize].to_vec(),
+        }
+    }
+
+    fn append_from(&mut self, other: &RawFrames) {
+        self.records.extend_from_slice(&other.records);
+    }
+
+    fn reserve(&mut self, additional: u64) {
+        self.records.reserve(additional as usize);
+    }
+
+    fn push_record(&mut self, record: &[u8; CONTROLLER_RECORD_SIZE]) {
+        self.records.push(*record);
+    }
+
+    fn records<'a>(&'a self) -> Box<dyn Iterator<Item = [u8; CONTROLLER_RECORD_SIZE]> + 'a> {
+        Box::new(self.records.iter().cloned())
+    }
+}
+
+impl FromIterator<ControllerInput> for RawFrames {
+    fn from_iter<I>(iter: I) -> RawFrames
+        where I: IntoIterator<Item = ControllerInput>,
+    {
+        RawFrames {
+            records: iter.into_iter().map(|input| input.to_bytes()).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dtm::Axis;
+    use fuzz::Rng;
+    use serde_json;
+
+    fn input(value: u8) -> ControllerInput {
+        let mut input = ControllerInput::NEUTRAL;
+        input.set_axis(Axis::AnalogX, value);
+        input
+    }
+
+    // What every edit has to leave behind: runs that are never empty or
+    // next to a matching run, and ends that add up.
+    fn check(frames: &RleFrames, expected: &[ControllerInput]) {
+        assert_eq!(frames.frames().collect::<Vec<_>>(), expected);
+        let mut end = 0;
+        for (idx, &(input, count)) in frames.runs.iter().enumerate() {
+            assert!(count > 0);
+            if idx > 0 {
+                assert!(frames.runs[idx - 1].0 != input);
+            }
+            end += count;
+            assert_eq!(frames.ends[idx], end);
+        }
+        assert_eq!(frames.ends.len(), frames.runs.len());
+        assert_eq!(*frames, expected.iter().cloned().collect());
+    }
+
+    #[test]
+    fn set_frame_matches_vec() {
+        let mut rng = Rng::new(3);
+        let mut expected: Vec<ControllerInput> = (0..200).map(|frame| input((frame / 7 % 3) as u8)).collect();
+        let mut frames: RleFrames = expected.iter().cloned().collect();
+        for _ in 0..5000 {
+            let frame = rng.below(expected.len() as u32) as u64;
+            let value = input(rng.below(3) as u8);
+            FrameStore::set_frame(&mut expected, frame, value);
+            frames.set_frame(frame, value);
+            check(&frames, &expected);
+        }
+    }
+
+    #[test]
+    fn truncate_and_append() {
+        let expected: Vec<ControllerInput> = (0..50).map(|frame| input((frame / 4) as u8)).collect();
+        let mut frames: RleFrames = expected.iter().cloned().collect();
+        frames.truncate(22);
+        check(&frames, &expected[..22]);
+        frames.truncate(20);
+        check(&frames, &expected[..20]);
+
+        let tail: RleFrames = expected[20..].iter().cloned().collect();
+        frames.append_from(&tail);
+        check(&frames, &expected);
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let expected: Vec<ControllerInput> = (0..30).map(|frame| input((frame / 5) as u8)).collect();
+        let frames: RleFrames = expected.iter().cloned().collect();
+        let json = serde_json::to_string(&frames).unwrap();
+        let decoded: RleFrames = serde_json::from_str(&json).unwrap();
+        check(&decoded, &expected);
+    }
+
+    #[test]
+    fn deserialize_merges_runs() {
+        let runs = vec![(input(1), 2), (input(1), 3), (input(2), 0), (input(1), 1), (input(4), 2)];
+        let json = serde_json::to_string(&runs).unwrap();
+        let frames: RleFrames = serde_json::from_str(&json).unwrap();
+        assert_eq!(frames.runs(), &[(input(1), 6), (input(4), 2)]);
+        assert_eq!(frames.len(), 8);
+    }
+
+    #[test]
+    fn deserialize_refuses_overflowing_runs() {
+        let runs = vec![(input(1), u64::MAX), (input(2), 1)];
+        let json = serde_json::to_string(&runs).unwrap();
+        assert!(serde_json::from_str::<RleFrames>(&json).is_err());
+    }
+}
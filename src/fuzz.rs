@@ -0,0 +1,261 @@
+use dtm::{Dtm, DtmHeader, ControllerInput, Button, Axis};
+use error::{Dtm2txtError, Dtm2txtResult};
+use filter::{Filter, FramePredicate, Operand, Comparison};
+
+// Give up on a frame after this many inputs fail the constraint. Only the
+// parts of it that tie several axes or buttons together are left to chance,
+// so this takes a constraint few inputs can meet.
+const MAX_ATTEMPTS: u32 = 10000;
+
+// SplitMix64. Small, seedable, and good enough for input fuzzing; the same
+// seed always produces the same movie.
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng {
+            state: seed,
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    pub fn next_u8(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    // True with probability `chance`, which should be between 0 and 1.
+    pub fn chance(&mut self, chance: f64) -> bool {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < chance
+    }
+
+    // A number from 0 up to but not including `bound`, which has to fit in
+    // 32 bits.
+    pub fn below(&mut self, bound: u32) -> u32 {
+        (((self.next_u64() >> 32) * bound as u64) >> 32) as u32
+    }
+}
+
+pub struct FuzzOptions {
+    pub frames: u64,
+    pub seed: u64,
+    // Buttons that may be pressed; all others stay released.
+    pub buttons: Vec<Button>,
+    pub press_chance: f64,
+    // Every generated frame must match this, if given.
+    pub constraint: Option<Filter>,
+}
+
+impl FuzzOptions {
+    // Defaults to the twelve controller buttons. Change disc, reset and the
+    // reserved bit are left out since they derail a movie immediately.
+    pub fn new(frames: u64, seed: u64) -> FuzzOptions {
+        FuzzOptions {
+            frames: frames,
+            seed: seed,
+            buttons: Button::ALL[..12].to_vec(),
+            press_chance: 0.25,
+            constraint: None,
+        }
+    }
+}
+
+// What a constraint allows on one frame, taken one axis or button at a time:
+// the values each axis can take, as a bitset per axis in `Axis::ALL` order,
+// and the buttons that have to be pressed or released. Sampling from these
+// meets constraints like `analog_x == 128` every time, where random inputs
+// would almost never.
+struct Allowed {
+    axes: [[u64; 4]; 6],
+    buttons: Vec<(Button, bool)>,
+    // Set when the constraint can't be met at all.
+    impossible: bool,
+}
+
+impl Allowed {
+    // Buttons outside of `buttons` always stay released.
+    fn new(constraint: Option<&Filter>, buttons: &[Button], frame: u64) -> Allowed {
+        let mut allowed = Allowed {
+            axes: [[!0; 4]; 6],
+            buttons: Vec::new(),
+            impossible: false,
+        };
+        if let Some(constraint) = constraint {
+            allowed.restrict(constraint, false, frame);
+        }
+
+        let no_values = allowed.axes.iter().any(|values| values.iter().all(|&word| word == 0));
+        let conflicting = allowed.buttons.iter()
+            .any(|&(button, pressed)| allowed.buttons.contains(&(button, !pressed)));
+        let unavailable = allowed.buttons.iter()
+            .any(|&(button, pressed)| pressed && !buttons.contains(&button));
+        allowed.impossible |= no_values || conflicting || unavailable;
+        allowed
+    }
+
+    // Narrows things down by what `filter` (or its negation) requires of
+    // every matching input. The rest, like comparisons between two axes or
+    // either side of an `||`, is left for `generate` to check.
+    fn restrict(&mut self, filter: &Filter, negated: bool, frame: u64) {
+        match *filter {
+            Filter::Pressed(button) => self.buttons.push((button, !negated)),
+            Filter::Compare(lhs, comparison, rhs) => {
+                let comparison = if negated { comparison.negated() } else { comparison };
+                self.restrict_compare(lhs, comparison, rhs, frame);
+            }
+            Filter::Not(ref inner) => self.restrict(inner, !negated, frame),
+            Filter::And(ref lhs, ref rhs) if !negated => {
+                self.restrict(lhs, false, frame);
+                self.restrict(rhs, false, frame);
+            }
+            // !(a || b) is !a && !b.
+            Filter::Or(ref lhs, ref rhs) if negated => {
+                self.restrict(lhs, true, frame);
+                self.restrict(rhs, true, frame);
+            }
+            _ => (),
+        }
+    }
+
+    fn restrict_compare(&mut self, lhs: Operand, comparison: Comparison, rhs: Operand, frame: u64) {
+        let constant = |operand: Operand| operand.eval(frame, &ControllerInput::NEUTRAL);
+        let (axis, comparison, value) = match (lhs, rhs) {
+            (Operand::Axis(_), Operand::Axis(_)) => return,
+            (Operand::Axis(axis), other) => (axis, comparison, constant(other)),
+            (other, Operand::Axis(axis)) => (axis, comparison.flipped(), constant(other)),
+            (lhs, rhs) => {
+                if !comparison.test(constant(lhs), constant(rhs)) {
+                    self.impossible = true;
+                }
+                return;
+            }
+        };
+
+        let idx = Axis::ALL.iter().position(|&other| other == axis).unwrap_or(0);
+        let values = &mut self.axes[idx];
+        for candidate in 0..256 {
+            if !comparison.test(candidate, value) {
+                values[candidate as usize / 64] &= !(1 << (candidate % 64));
+            }
+        }
+    }
+
+    // A value `axis` is allowed to take. Axes anything goes for take the same
+    // random values as when there's no constraint, so a seed keeps making the
+    // same movie.
+    fn sample_axis(&self, rng: &mut Rng, idx: usize) -> u8 {
+        let values = &self.axes[idx];
+        let count: u32 = values.iter().map(|word| word.count_ones()).sum();
+        if count == 256 {
+            return rng.next_u8();
+        }
+
+        // `new` has already ruled out axes without any values.
+        let nth = rng.below(count) as usize;
+        (0..=255u8)
+            .filter(|&value| values[value as usize / 64] & (1 << (value % 64)) != 0)
+            .nth(nth)
+            .unwrap_or(0)
+    }
+}
+
+fn random_input(rng: &mut Rng, options: &FuzzOptions, allowed: &Allowed) -> ControllerInput {
+    let mut input = ControllerInput::NEUTRAL.with_controller_connected(true);
+
+    for button in options.buttons.iter() {
+        let required = allowed.buttons.iter().find(|&&(other, _)| other == *button);
+        let pressed = match required {
+            Some(&(_, pressed)) => pressed,
+            None => rng.chance(options.press_chance),
+        };
+        input.set_pressed(*button, pressed);
+    }
+    for (idx, axis) in Axis::ALL.iter().enumerate() {
+        let value = allowed.sample_axis(rng, idx);
+        input.set_axis(*axis, value);
+    }
+
+    input
+}
+
+// Generates a movie of random inputs on top of `header`. Only the counters
+// are changed in the header; VIs are assumed to be one per input.
+pub fn generate(mut header: DtmHeader, options: &FuzzOptions) -> Dtm2txtResult<Dtm> {
+    let mut rng = Rng::new(options.seed);
+    let mut controller_data = Vec::new();
+
+    for frame in 0..options.frames {
+        let unsatisfiable = Dtm2txtError::UnsatisfiableConstraintError {
+            frame: frame,
+        };
+        let allowed = Allowed::new(options.constraint.as_ref(), &options.buttons, frame);
+        if allowed.impossible {
+            return Err(unsatisfiable);
+        }
+
+        let mut attempts = 0;
+        let input = loop {
+            let input = random_input(&mut rng, options, &allowed);
+            let accepted = options.constraint
+                .as_ref()
+                .map(|constraint| constraint.matches(frame, &input))
+                .unwrap_or(true);
+            if accepted {
+                break input;
+            }
+
+            attempts += 1;
+            if attempts >= MAX_ATTEMPTS {
+                return Err(unsatisfiable);
+            }
+        };
+        controller_data.push(input);
+    }
+
+    header.input_count = options.frames;
+    header.vi_count = options.frames;
+
+    Ok(Dtm {
+        header: header,
+        controller_data: controller_data,
+        wiimote_data: Vec::new(),
+        trailing: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fuzz(constraint: &str) -> Dtm2txtResult<Dtm> {
+        let mut options = FuzzOptions::new(200, 1);
+        options.constraint = Some(Filter::parse(constraint).unwrap());
+        generate(DtmHeader::default(), &options)
+    }
+
+    #[test]
+    fn meets_narrow_constraints() {
+        let constraint = "analog_x == 128 && c_y >= 250 && !(l_pressure > 3) && A && !B && analog_y > analog_x";
+        let dtm = fuzz(constraint).unwrap();
+        let filter = Filter::parse(constraint).unwrap();
+        assert!(dtm.controller_data.iter().all(|input| filter.matches(0, input)));
+    }
+
+    #[test]
+    fn refuses_impossible_constraints() {
+        for constraint in ["analog_x > 255", "A && !A", "c_x < 3 && c_x > 3", "frame > 300", "reset"].iter() {
+            match fuzz(constraint) {
+                Err(Dtm2txtError::UnsatisfiableConstraintError{frame}) => assert_eq!(frame, 0),
+                other => panic!("{}: {:?}", constraint, other.map(|_| ())),
+            }
+        }
+    }
+}
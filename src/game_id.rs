@@ -0,0 +1,164 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use serde::de;
+
+use dtm::DtmHeader;
+use error::{Dtm2txtError, Dtm2txtResult};
+
+// What kind of release a game ID belongs to, from its first character.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum System {
+    GameCube,
+    Wii,
+    WiiWare,
+    Other(char),
+}
+
+impl System {
+    fn from_code(code: char) -> System {
+        match code {
+            // D is used by demo discs.
+            'G' | 'D' => System::GameCube,
+            'R' | 'S' => System::Wii,
+            'W' => System::WiiWare,
+            other => System::Other(other),
+        }
+    }
+}
+
+impl fmt::Display for System {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            System::GameCube => f.write_str("GameCube"),
+            System::Wii => f.write_str("Wii"),
+            System::WiiWare => f.write_str("WiiWare"),
+            System::Other(code) => write!(f, "system {}", code),
+        }
+    }
+}
+
+// Where a game was released, from the fourth character of its ID. European
+// releases use several codes, one per language or country.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Region {
+    Usa,
+    Japan,
+    Korea,
+    Europe(char),
+    Other(char),
+}
+
+// Region codes of PAL releases.
+const PAL_REGIONS: &[char] = &['D', 'F', 'H', 'I', 'P', 'S', 'U', 'X', 'Y'];
+
+impl Region {
+    fn from_code(code: char) -> Region {
+        match code {
+            'E' => Region::Usa,
+            'J' => Region::Japan,
+            'K' => Region::Korea,
+            code if PAL_REGIONS.contains(&code) => Region::Europe(code),
+            other => Region::Other(other),
+        }
+    }
+
+    // PAL consoles run at 50Hz unless set to PAL60.
+    pub fn is_pal(self) -> bool {
+        matches!(self, Region::Europe(_))
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Region::Usa => f.write_str("NTSC-U"),
+            Region::Japan => f.write_str("NTSC-J"),
+            Region::Korea => f.write_str("NTSC-K"),
+            Region::Europe(_) => f.write_str("PAL"),
+            Region::Other(code) => write!(f, "region {}", code),
+        }
+    }
+}
+
+// A game ID like GALE01: system, two characters for the game, region and a
+// two character maker code. Always six ASCII letters or digits.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct GameId(String);
+
+impl GameId {
+    pub fn parse(game_id: &str) -> Dtm2txtResult<GameId> {
+        if game_id.len() != 6 || !game_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(Dtm2txtError::InvalidGameIdError {
+                game_id: game_id.to_string(),
+            });
+        }
+        Ok(GameId(game_id.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    fn code(&self, idx: usize) -> char {
+        self.0.as_bytes()[idx] as char
+    }
+
+    pub fn system(&self) -> System {
+        System::from_code(self.code(0))
+    }
+
+    // The two characters naming the game itself, like AL for Melee.
+    pub fn game_code(&self) -> &str {
+        &self.0[1..3]
+    }
+
+    pub fn region(&self) -> Region {
+        Region::from_code(self.code(3))
+    }
+
+    // The publisher, like 01 for Nintendo.
+    pub fn maker_code(&self) -> &str {
+        &self.0[4..6]
+    }
+}
+
+impl fmt::Display for GameId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for GameId {
+    type Err = Dtm2txtError;
+
+    fn from_str(game_id: &str) -> Dtm2txtResult<GameId> {
+        GameId::parse(game_id)
+    }
+}
+
+impl Serialize for GameId {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer,
+    {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for GameId {
+    fn deserialize<D>(deserializer: D) -> Result<GameId, D::Error>
+        where D: Deserializer<'de>,
+    {
+        let game_id = String::deserialize(deserializer)?;
+        GameId::parse(&game_id).map_err(de::Error::custom)
+    }
+}
+
+impl DtmHeader {
+    // The header keeps game_id as a plain string so that any movie can be
+    // read and written back, malformed ID or not.
+    pub fn parsed_game_id(&self) -> Dtm2txtResult<GameId> {
+        GameId::parse(&self.game_id)
+    }
+}
@@ -0,0 +1,91 @@
+use std::hash::Hasher;
+use std::io::{self, Read};
+
+use md5;
+
+use dtm::{Dtm, DtmHeader, Md5};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+// 64-bit FNV-1a. Unlike `DefaultHasher`, its output is stable across Rust
+// versions, so hashes can be written to disk.
+pub struct Fnv1a {
+    state: u64,
+}
+
+impl Fnv1a {
+    pub fn new() -> Fnv1a {
+        Fnv1a {
+            state: FNV_OFFSET_BASIS,
+        }
+    }
+
+    pub fn hash_bytes(bytes: &[u8]) -> u64 {
+        let mut hasher = Fnv1a::new();
+        hasher.write(bytes);
+        hasher.finish()
+    }
+}
+
+impl Default for Fnv1a {
+    fn default() -> Fnv1a {
+        Fnv1a::new()
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.state ^= *byte as u64;
+            self.state = self.state.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.state
+    }
+}
+
+// Hashes a game image the way Dolphin does for a movie's md5: the whole file
+// as it is on disk, so compressed images won't match.
+pub fn game_md5<R>(mut reader: R) -> io::Result<Md5>
+    where R: Read,
+{
+    let mut context = md5::Context::new();
+    let mut buf = vec![0; 1 << 20];
+    loop {
+        let len = reader.read(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        context.consume(&buf[..len]);
+    }
+    Ok(Md5::from(context.compute().0))
+}
+
+impl DtmHeader {
+    // Dolphin only fills in the md5 when told to, so an all-zero one says
+    // nothing about the game.
+    pub fn has_game_md5(&self) -> bool {
+        !self.md5.is_zero()
+    }
+}
+
+impl Dtm {
+    // Hashes just the inputs, laid out the way a dtm stores them, so two
+    // runs that only differ in author, rerecords or other header fields get
+    // the same hash.
+    pub fn input_hash(&self) -> u64 {
+        let mut hasher = Fnv1a::new();
+        for input in self.controller_data.iter() {
+            hasher.write(&input.buttons().to_bytes());
+            hasher.write(&[input.l_pressure, input.r_pressure, input.analog_x, input.analog_y, input.c_x, input.c_y]);
+        }
+        for input in self.wiimote_data.iter() {
+            hasher.write_u64(input.report.len() as u64);
+            hasher.write(&input.report);
+        }
+        hasher.finish()
+    }
+}
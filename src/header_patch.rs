@@ -0,0 +1,65 @@
+use std::io::{Read, Write, Seek, SeekFrom};
+
+use serde_json::{self, Value};
+
+use decoder::dtm_decoder::DtmDecoder;
+use dtm::DtmHeader;
+use encoder::dtm_encoder::DtmEncoder;
+use error::{Dtm2txtError, Dtm2txtResult};
+
+// Returns `header` with the fields in `patch` (a JSON object using the same
+// field names as the txt header) replaced. Fields the patch leaves out keep
+// their value.
+pub fn merge_header(header: &DtmHeader, patch: &Value) -> Dtm2txtResult<DtmHeader> {
+    let patch = match *patch {
+        Value::Object(ref patch) => patch,
+        _ => return Err(Dtm2txtError::HeaderPatchNotObjectError),
+    };
+
+    let mut merged = serde_json::to_value(header)?;
+    if let Value::Object(ref mut fields) = merged {
+        for (name, value) in patch.iter() {
+            match fields.get_mut(name) {
+                Some(field) => *field = value.clone(),
+                None => return Err(Dtm2txtError::UnknownHeaderFieldError {
+                    field: name.clone(),
+                }),
+            }
+        }
+    }
+
+    Ok(serde_json::from_value(merged)?)
+}
+
+// Rewrites the header of the dtm in `file` in place. Only the first 256 bytes
+// are touched, so the input count always stays what it was. Returns the new
+// header.
+pub fn patch_header<F>(mut file: F, patch: &Value, allow_inconsistent: bool) -> Dtm2txtResult<DtmHeader>
+    where F: Read + Write + Seek,
+{
+    let old_header = DtmDecoder::new(&mut file).decode_header()?;
+    let mut header = merge_header(&old_header, patch)?;
+    header.input_count = old_header.input_count;
+
+    // The inputs don't change, so they're still whatever kind of record the
+    // old header said they were.
+    let wiimote_frames = if old_header.has_wiimote_inputs() {
+        old_header.input_count
+    }
+    else {
+        0
+    };
+
+    let mut bytes = Vec::new();
+    {
+        let mut encoder = DtmEncoder::new(&mut bytes).allow_inconsistent(allow_inconsistent);
+        encoder.check_consistency(&header, old_header.input_count - wiimote_frames, wiimote_frames)?;
+        encoder.encode_header(&header)?;
+    }
+
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&bytes)?;
+    file.flush()?;
+
+    Ok(header)
+}
@@ -0,0 +1,142 @@
+use dtm::DtmHeader;
+
+const NTSC_VI_RATE: f64 = 60000.0 / 1001.0;
+const PAL_VI_RATE: f64 = 50.0;
+const GAMECUBE_CPU_CLOCK: f64 = 486_000_000.0;
+const WII_CPU_CLOCK: f64 = 729_000_000.0;
+
+impl DtmHeader {
+    // PAL games run at 50Hz unless the console was set to PAL60.
+    pub fn vi_rate(&self) -> f64 {
+        let pal = self.parsed_game_id()
+            .map(|game_id| game_id.region().is_pal())
+            .unwrap_or(false);
+        if pal && !self.sysconf_pal60 {
+            PAL_VI_RATE
+        }
+        else {
+            NTSC_VI_RATE
+        }
+    }
+
+    pub fn duration_secs(&self) -> f64 {
+        self.vi_count as f64 / self.vi_rate()
+    }
+
+    // Emulated CPU ticks per second.
+    pub fn cpu_clock(&self) -> f64 {
+        if self.wii_game { WII_CPU_CLOCK } else { GAMECUBE_CPU_CLOCK }
+    }
+
+    // Emulated CPU time, which drifts from the VI based length when the game
+    // lags.
+    pub fn tick_duration_secs(&self) -> f64 {
+        self.tick_count as f64 / self.cpu_clock()
+    }
+}
+
+// hh:mm:ss.fff, with hours growing past two digits if needed.
+pub fn format_duration(secs: f64) -> String {
+    let millis = (secs * 1000.0).round() as u64;
+    format!("{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000)
+}
+
+// The start time as a date and time like 2017-06-03 14:22:01. Dolphin counts
+// it from the recorder's own clock, so it's the wall clock time wherever the
+// movie was recorded, and there's no time zone to show.
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let time = secs % 86400;
+    let (year, month, day) = civil_from_days(days);
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year, month, day, time / 3600, time / 60 % 60, time % 60)
+}
+
+// The start time as it appears in txt headers, like 2017-06-03T14:22:01, with
+// no time zone for the same reason. Timestamps past year 9999 get None, since
+// they wouldn't read back.
+pub fn format_iso8601(secs: u64) -> Option<String> {
+    let days = (secs / 86400) as i64;
+    let time = secs % 86400;
+    let (year, month, day) = civil_from_days(days);
+    if year > 9999 {
+        return None;
+    }
+    Some(format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year, month, day, time / 3600, time / 60 % 60, time % 60))
+}
+
+// Reads what `format_iso8601` writes. A space works in place of the 'T', so
+// `info`'s format is accepted too. A 'Z' or " UTC" on the end, as older
+// versions wrote, is ignored: the time is the same wall clock time either way.
+pub fn parse_iso8601(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let text = text.strip_suffix("UTC")
+        .map(|text| text.trim_end())
+        .or_else(|| text.strip_suffix('Z'))
+        .unwrap_or(text);
+    let bytes = text.as_bytes();
+    if bytes.len() != 19 || bytes[4] != b'-' || bytes[7] != b'-' || (bytes[10] != b'T' && bytes[10] != b' ')
+        || bytes[13] != b':' || bytes[16] != b':' {
+        return None;
+    }
+    let number = |start: usize, end: usize| -> Option<u32> {
+        let digits = &text[start..end];
+        if digits.bytes().all(|byte| byte.is_ascii_digit()) { digits.parse().ok() } else { None }
+    };
+    let (year, month, day) = (number(0, 4)?, number(5, 7)?, number(8, 10)?);
+    let (hour, minute, second) = (number(11, 13)?, number(14, 16)?, number(17, 19)?);
+    if year < 1970 || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    let days = days_from_civil(year as i64, month, day);
+    // Rejects months and days that don't exist, like February 30th.
+    if civil_from_days(days) != (year as i64, month, day) {
+        return None;
+    }
+    Some(days as u64 * 86400 + hour as u64 * 3600 + minute as u64 * 60 + second as u64)
+}
+
+// The inverse of `civil_from_days`.
+pub(crate) fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let yoe = year - era * 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 } as i64;
+    let doy = (153 * mp + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+// Howard Hinnant's days-to-civil-date algorithm.
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = if z >= 0 { z } else { z - 146096 } / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_are_wall_clock_times() {
+        assert_eq!(format_timestamp(1496499721), "2017-06-03 14:22:01");
+        assert_eq!(format_iso8601(1496499721).unwrap(), "2017-06-03T14:22:01");
+        for text in ["2017-06-03T14:22:01", "2017-06-03 14:22:01", "2017-06-03T14:22:01Z", "2017-06-03 14:22:01 UTC"].iter() {
+            assert_eq!(parse_iso8601(text), Some(1496499721));
+        }
+    }
+}
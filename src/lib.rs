@@ -1,10 +1,59 @@
+#![allow(clippy::redundant_field_names)]
+
 extern crate byteorder;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
 extern crate serde_json;
+extern crate md5;
+extern crate zip;
+#[cfg(feature = "yaml")]
+extern crate serde_yaml;
+#[cfg(feature = "cbor")]
+extern crate ciborium;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+#[cfg(feature = "python")]
+extern crate pyo3;
+// pyo3's macros refer to `::core`, which needs declaring in a 2015 crate.
+#[cfg(feature = "python")]
+extern crate core;
 
 pub mod dtm;
+pub mod game_id;
+pub mod buttons;
+pub mod builder;
+pub mod frames;
 pub mod error;
 pub mod decoder;
-pub mod encoder;
\ No newline at end of file
+pub mod encoder;
+pub mod filter;
+pub mod script;
+pub mod edit;
+pub mod concat;
+pub mod diff;
+pub mod stats;
+pub mod hash;
+pub mod cache;
+pub mod fuzz;
+pub mod humanize;
+pub mod version;
+pub mod transform;
+pub mod consistency;
+pub mod ports;
+pub mod comments;
+pub mod header_patch;
+pub mod progress;
+pub mod verify;
+pub mod patch;
+pub mod export;
+pub mod audit;
+pub mod validate;
+pub mod feed;
+pub mod view;
+pub mod transcode;
+pub mod timeline;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "python")]
+pub mod python;
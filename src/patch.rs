@@ -0,0 +1,317 @@
+use std::cmp;
+use std::io::{Read, Write};
+
+use byteorder::{ReadBytesExt, WriteBytesExt, LE};
+use serde_json::{self, Map, Value};
+
+use decoder::dtm_decoder::DtmDecoder;
+use dtm::{Dtm, DtmHeader, ControllerInput, WiimoteInput};
+use encoder::dtm_encoder::DtmEncoder;
+use error::{Dtm2txtError, Dtm2txtResult};
+
+const PATCH_MAGIC: &[u8; 4] = b"DTMP";
+const PATCH_VERSION: u8 = 1;
+
+// A run of records in the base movie, replaced by `inserted`. The two don't
+// have to be the same length, so a hunk can also insert or remove frames.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hunk<T> {
+    pub start: u64,
+    pub removed: u64,
+    pub inserted: Vec<T>,
+}
+
+// What it takes to turn one movie into another: the header fields that
+// changed and the runs of frames that did, so sharing an edit to a long
+// movie doesn't mean sharing the whole movie.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MoviePatch {
+    // `Dtm::input_hash` of the movie the patch was made against.
+    pub parent_hash: u64,
+    // Header fields named as in a txt file, with their new values.
+    pub header: Map<String, Value>,
+    // Hunks are in order and refer to frame numbers in the base movie.
+    pub controller_hunks: Vec<Hunk<ControllerInput>>,
+    pub wiimote_hunks: Vec<Hunk<WiimoteInput>>,
+    // The new bytes after the inputs, if they changed.
+    pub trailing: Option<Vec<u8>>,
+}
+
+// Compares through the JSON form like `diff_headers`, but also notices the
+// optional fields that are only there in one of the headers.
+fn header_changes(base: &DtmHeader, target: &DtmHeader) -> Dtm2txtResult<Map<String, Value>> {
+    let mut changes = Map::new();
+    if let (Value::Object(base), Value::Object(target)) = (serde_json::to_value(base)?, serde_json::to_value(target)?) {
+        for (field, value) in target.iter() {
+            if base.get(field) != Some(value) {
+                changes.insert(field.clone(), value.clone());
+            }
+        }
+        for field in base.keys().filter(|field| !target.contains_key(*field)) {
+            changes.insert(field.clone(), Value::Null);
+        }
+    }
+    Ok(changes)
+}
+
+fn apply_header_changes(header: &DtmHeader, changes: &Map<String, Value>) -> Dtm2txtResult<DtmHeader> {
+    let mut merged = serde_json::to_value(header)?;
+    if let Value::Object(ref mut fields) = merged {
+        for (field, value) in changes.iter() {
+            fields.insert(field.clone(), value.clone());
+        }
+    }
+    Ok(serde_json::from_value(merged)?)
+}
+
+// Skips whatever the two lists have in common at either end, then covers the
+// rest with one hunk per run of differing records. A difference in length
+// goes at the end of the middle part, so inserting a few frames only costs
+// those frames.
+fn diff_hunks<T>(base: &[T], target: &[T]) -> Vec<Hunk<T>>
+    where T: Clone + PartialEq,
+{
+    let prefix = base.iter().zip(target.iter()).take_while(|&(a, b)| a == b).count();
+    let suffix = base[prefix..].iter().rev()
+        .zip(target[prefix..].iter().rev())
+        .take_while(|&(a, b)| a == b)
+        .count();
+    let base = &base[prefix..base.len() - suffix];
+    let target = &target[prefix..target.len() - suffix];
+    let common = cmp::min(base.len(), target.len());
+
+    let mut hunks: Vec<Hunk<T>> = Vec::new();
+    let mut idx = 0;
+    while idx < common {
+        if base[idx] == target[idx] {
+            idx += 1;
+            continue;
+        }
+        let start = idx;
+        while idx < common && base[idx] != target[idx] {
+            idx += 1;
+        }
+        hunks.push(Hunk {
+            start: (prefix + start) as u64,
+            removed: (idx - start) as u64,
+            inserted: target[start..idx].to_vec(),
+        });
+    }
+
+    if base.len() != target.len() {
+        let start = (prefix + common) as u64;
+        let removed = (base.len() - common) as u64;
+        let inserted = &target[common..];
+        match hunks.last_mut() {
+            Some(ref mut hunk) if hunk.start + hunk.removed == start => {
+                hunk.removed += removed;
+                hunk.inserted.extend_from_slice(inserted);
+            }
+            _ => hunks.push(Hunk {
+                start: start,
+                removed: removed,
+                inserted: inserted.to_vec(),
+            }),
+        }
+    }
+    hunks
+}
+
+// Makes sure the hunks are in order and fit in `len` records before anything
+// is changed.
+fn check_hunks<T>(hunks: &[Hunk<T>], len: usize) -> Dtm2txtResult<()> {
+    let mut end = 0;
+    for hunk in hunks.iter() {
+        if hunk.start < end {
+            return Err(Dtm2txtError::OverlappingHunksError {
+                frame: hunk.start,
+            });
+        }
+        end = hunk.start.saturating_add(hunk.removed);
+    }
+    if end > len as u64 {
+        return Err(Dtm2txtError::FrameOutOfRangeError {
+            frame: end,
+            frames: len as u64,
+        });
+    }
+    Ok(())
+}
+
+// Goes back to front, so the frame numbers of the hunks still to come stay
+// the same.
+fn apply_hunks<T>(records: &mut Vec<T>, hunks: &[Hunk<T>])
+    where T: Clone,
+{
+    for hunk in hunks.iter().rev() {
+        let start = hunk.start as usize;
+        let end = start + hunk.removed as usize;
+        records.splice(start..end, hunk.inserted.iter().cloned());
+    }
+}
+
+fn write_hunks<W, T, F>(writer: &mut W, hunks: &[Hunk<T>], write_record: F) -> Dtm2txtResult<()>
+    where W: Write,
+          F: Fn(&mut W, &T) -> Dtm2txtResult<()>,
+{
+    writer.write_u64::<LE>(hunks.len() as u64)?;
+    for hunk in hunks.iter() {
+        writer.write_u64::<LE>(hunk.start)?;
+        writer.write_u64::<LE>(hunk.removed)?;
+        writer.write_u64::<LE>(hunk.inserted.len() as u64)?;
+        for record in hunk.inserted.iter() {
+            write_record(writer, record)?;
+        }
+    }
+    Ok(())
+}
+
+// Counts come straight from the file, so nothing is allocated up front.
+fn read_hunks<R, T, F>(reader: &mut R, read_record: F) -> Dtm2txtResult<Vec<Hunk<T>>>
+    where R: Read,
+          F: Fn(&mut R) -> Dtm2txtResult<T>,
+{
+    let count = reader.read_u64::<LE>()?;
+    let mut hunks = Vec::new();
+    for _ in 0..count {
+        let start = reader.read_u64::<LE>()?;
+        let removed = reader.read_u64::<LE>()?;
+        let inserted_count = reader.read_u64::<LE>()?;
+        let mut inserted = Vec::new();
+        for _ in 0..inserted_count {
+            inserted.push(read_record(reader)?);
+        }
+        hunks.push(Hunk {
+            start: start,
+            removed: removed,
+            inserted: inserted,
+        });
+    }
+    Ok(hunks)
+}
+
+impl MoviePatch {
+    pub fn is_empty(&self) -> bool {
+        self.header.is_empty()
+            && self.controller_hunks.is_empty()
+            && self.wiimote_hunks.is_empty()
+            && self.trailing.is_none()
+    }
+
+    // Number of frames the patch replaces or adds.
+    pub fn changed_frames(&self) -> u64 {
+        let controller = self.controller_hunks.iter().map(|hunk| cmp::max(hunk.removed, hunk.inserted.len() as u64));
+        let wiimote = self.wiimote_hunks.iter().map(|hunk| cmp::max(hunk.removed, hunk.inserted.len() as u64));
+        controller.chain(wiimote).sum()
+    }
+
+    // The header changes are stored as JSON, so fields added to the header
+    // later don't need a new patch version. Inputs are stored the way a dtm
+    // stores them.
+    pub fn write<W>(&self, mut writer: W) -> Dtm2txtResult<()>
+        where W: Write,
+    {
+        writer.write_all(PATCH_MAGIC)?;
+        writer.write_u8(PATCH_VERSION)?;
+        writer.write_u64::<LE>(self.parent_hash)?;
+
+        let header = serde_json::to_vec(&self.header)?;
+        writer.write_u64::<LE>(header.len() as u64)?;
+        writer.write_all(&header)?;
+
+        match self.trailing {
+            Some(ref trailing) => {
+                writer.write_u8(1)?;
+                writer.write_u64::<LE>(trailing.len() as u64)?;
+                writer.write_all(trailing)?;
+            }
+            None => writer.write_u8(0)?,
+        }
+
+        write_hunks(&mut writer, &self.controller_hunks, |writer, input| {
+            DtmEncoder::new(writer).encode_controller_input(input)
+        })?;
+        write_hunks(&mut writer, &self.wiimote_hunks, |writer, input| {
+            DtmEncoder::new(writer).encode_wiimote_input(input)
+        })?;
+        Ok(writer.flush()?)
+    }
+
+    pub fn read<R>(mut reader: R) -> Dtm2txtResult<MoviePatch>
+        where R: Read,
+    {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != PATCH_MAGIC {
+            return Err(Dtm2txtError::BadMagicError);
+        }
+        let version = reader.read_u8()?;
+        if version != PATCH_VERSION {
+            return Err(Dtm2txtError::UnsupportedPatchVersionError {
+                version: version,
+            });
+        }
+        let parent_hash = reader.read_u64::<LE>()?;
+
+        let header_len = reader.read_u64::<LE>()?;
+        let header = serde_json::from_reader((&mut reader).take(header_len))?;
+
+        let trailing = if reader.read_u8()? != 0 {
+            let len = reader.read_u64::<LE>()?;
+            let mut trailing = Vec::new();
+            (&mut reader).take(len).read_to_end(&mut trailing)?;
+            Some(trailing)
+        }
+        else {
+            None
+        };
+
+        let controller_hunks = read_hunks(&mut reader, |reader| DtmDecoder::new(reader).decode_controller_input())?;
+        let wiimote_hunks = read_hunks(&mut reader, |reader| DtmDecoder::new(reader).decode_wiimote_input())?;
+
+        Ok(MoviePatch {
+            parent_hash: parent_hash,
+            header: header,
+            controller_hunks: controller_hunks,
+            wiimote_hunks: wiimote_hunks,
+            trailing: trailing,
+        })
+    }
+}
+
+impl Dtm {
+    // The patch that turns this movie into `target`.
+    pub fn make_patch(&self, target: &Dtm) -> Dtm2txtResult<MoviePatch> {
+        Ok(MoviePatch {
+            parent_hash: self.input_hash(),
+            header: header_changes(&self.header, &target.header)?,
+            controller_hunks: diff_hunks(&self.controller_data, &target.controller_data),
+            wiimote_hunks: diff_hunks(&self.wiimote_data, &target.wiimote_data),
+            trailing: if self.trailing != target.trailing { Some(target.trailing.clone()) } else { None },
+        })
+    }
+
+    // Nothing is changed unless the whole patch applies. A patch made
+    // against a movie with different inputs is refused, since its frame
+    // numbers would land on the wrong frames without any sign of it.
+    pub fn apply_patch(&mut self, patch: &MoviePatch) -> Dtm2txtResult<()> {
+        let hash = self.input_hash();
+        if hash != patch.parent_hash {
+            return Err(Dtm2txtError::PatchParentMismatchError {
+                expected: patch.parent_hash,
+                found: hash,
+            });
+        }
+        let header = apply_header_changes(&self.header, &patch.header)?;
+        check_hunks(&patch.controller_hunks, self.controller_data.len())?;
+        check_hunks(&patch.wiimote_hunks, self.wiimote_data.len())?;
+
+        self.header = header;
+        apply_hunks(&mut self.controller_data, &patch.controller_hunks);
+        apply_hunks(&mut self.wiimote_data, &patch.wiimote_hunks);
+        if let Some(ref trailing) = patch.trailing {
+            self.trailing = trailing.clone();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+use std::mem;
+
+use dtm::{Dtm, DtmHeader, ControllerInput};
+use consistency::Inconsistency;
+use error::{Dtm2txtError, Dtm2txtResult};
+
+// The inputs of one GameCube controller port. Ports are numbered from 0.
+#[derive(Clone, Debug)]
+pub struct PortTrack {
+    pub port: u8,
+    pub inputs: Vec<ControllerInput>,
+}
+
+impl DtmHeader {
+    // Connected GameCube ports, in the order Dolphin polls them.
+    pub fn gamecube_ports(&self) -> Vec<u8> {
+        (0..4).filter(|port| self.controllers & (1 << port) != 0).collect()
+    }
+
+    // Connected Wii remotes, numbered from 0 like the ports.
+    pub fn wiimote_slots(&self) -> Vec<u8> {
+        (0..4).filter(|slot| self.controllers & (0x10 << slot) != 0).collect()
+    }
+}
+
+impl Dtm {
+    // Splits the interleaved inputs into one track per connected port.
+    pub fn by_port(&self) -> Dtm2txtResult<Vec<PortTrack>> {
+        let ports = self.header.gamecube_ports();
+        let frames = self.controller_data.len();
+        if ports.is_empty() || frames % ports.len() != 0 {
+            return Err(Dtm2txtError::InconsistentMovieError(vec![Inconsistency::UnevenPortData {
+                ports: ports.len() as u32,
+                frames: frames as u64,
+            }]));
+        }
+
+        let mut tracks: Vec<PortTrack> = ports.iter()
+            .map(|&port| PortTrack {
+                port: port,
+                inputs: Vec::with_capacity(frames / ports.len()),
+            })
+            .collect();
+        for (idx, input) in self.controller_data.iter().enumerate() {
+            tracks[idx % ports.len()].inputs.push(*input);
+        }
+
+        Ok(tracks)
+    }
+
+    // The inputs of one port as a movie of their own, as if that controller
+    // were the only one connected, so one player can be edited at a time.
+    pub fn single_port(&self, port: u8) -> Dtm2txtResult<Dtm> {
+        let track = self.by_port()?
+            .into_iter()
+            .find(|track| track.port == port)
+            .ok_or(Dtm2txtError::PortNotConnectedError {
+                port: port,
+            })?;
+        let mut dtm = Dtm::from_ports(self.header.clone(), vec![track])?;
+        dtm.trailing = self.trailing.clone();
+        Ok(dtm)
+    }
+
+    // Puts the inputs of `track` (a movie with one port, like `single_port`
+    // makes) back in as the inputs of `port`. It has to be as long as the
+    // other ports, so the frames still line up.
+    pub fn replace_port(&mut self, port: u8, track: &Dtm) -> Dtm2txtResult<()> {
+        let mut tracks = self.by_port()?;
+        {
+            let replaced = tracks.iter_mut()
+                .find(|track| track.port == port)
+                .ok_or(Dtm2txtError::PortNotConnectedError {
+                    port: port,
+                })?;
+            replaced.inputs = track.controller_data.clone();
+        }
+        let mut dtm = Dtm::from_ports(self.header.clone(), tracks)?;
+        dtm.trailing = mem::take(&mut self.trailing);
+        *self = dtm;
+        Ok(())
+    }
+
+    // Interleaves per-port tracks back into a movie. The header's GameCube
+    // port bits and input count are set from the tracks.
+    pub fn from_ports(mut header: DtmHeader, mut tracks: Vec<PortTrack>) -> Dtm2txtResult<Dtm> {
+        tracks.sort_by_key(|track| track.port);
+
+        let len = tracks.first().map(|track| track.inputs.len()).unwrap_or(0);
+        for track in tracks.iter() {
+            if track.port > 3 {
+                return Err(Dtm2txtError::InvalidPortError {
+                    port: track.port,
+                });
+            }
+            if track.inputs.len() != len {
+                return Err(Dtm2txtError::PortTrackLengthError {
+                    port: track.port,
+                    found: track.inputs.len(),
+                    expected: len,
+                });
+            }
+        }
+        if let Some(pair) = tracks.windows(2).find(|pair| pair[0].port == pair[1].port) {
+            return Err(Dtm2txtError::DuplicatePortError {
+                port: pair[0].port,
+            });
+        }
+
+        let mut controller_data = Vec::with_capacity(len * tracks.len());
+        for idx in 0..len {
+            for track in tracks.iter() {
+                controller_data.push(track.inputs[idx]);
+            }
+        }
+
+        header.controllers &= 0xF0;
+        for track in tracks.iter() {
+            header.controllers |= 1 << track.port;
+        }
+        header.input_count = controller_data.len() as u64;
+
+        Ok(Dtm {
+            header: header,
+            controller_data: controller_data,
+            wiimote_data: Vec::new(),
+            trailing: Vec::new(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(port: u8, frames: usize) -> PortTrack {
+        PortTrack {
+            port: port,
+            inputs: vec![ControllerInput::NEUTRAL; frames],
+        }
+    }
+
+    #[test]
+    fn from_ports_rejects_duplicate_ports() {
+        match Dtm::from_ports(DtmHeader::default(), vec![track(1, 3), track(0, 3), track(1, 3)]) {
+            Err(Dtm2txtError::DuplicatePortError{port}) => assert_eq!(port, 1),
+            other => panic!("got {:?}", other.map(|dtm| dtm.header.controllers)),
+        }
+    }
+
+    #[test]
+    fn tracks_round_trip() {
+        let dtm = Dtm::from_ports(DtmHeader::default(), vec![track(2, 4), track(0, 4)]).unwrap();
+        assert_eq!(dtm.header.controllers & 0x0F, 0b101);
+        assert_eq!(dtm.controller_data.len(), 8);
+        let tracks = dtm.by_port().unwrap();
+        assert_eq!(tracks.iter().map(|track| track.port).collect::<Vec<_>>(), vec![0, 2]);
+    }
+}
@@ -0,0 +1,44 @@
+// Called with the number of inputs handled so far and the number expected in
+// total, which comes from the header's input_count.
+pub type ProgressCallback = Box<dyn FnMut(u64, u64)>;
+
+// How many times over a whole movie the callback gets called, at most.
+const REPORTS: u64 = 1000;
+
+// Rate-limits a progress callback so huge movies don't spend their time
+// reporting.
+pub(crate) struct Progress {
+    callback: Option<ProgressCallback>,
+    total: u64,
+    step: u64,
+    next_report: u64,
+}
+
+impl Progress {
+    pub(crate) fn new(callback: Option<ProgressCallback>, total: u64) -> Progress {
+        Progress {
+            callback: callback,
+            total: total,
+            step: (total / REPORTS).max(1),
+            next_report: 0,
+        }
+    }
+
+    pub(crate) fn update(&mut self, done: u64) {
+        if done < self.next_report {
+            return;
+        }
+        if let Some(ref mut callback) = self.callback {
+            callback(done, self.total);
+        }
+        self.next_report = done + self.step;
+    }
+
+    // The header's input count is only an estimate for txt files, so the
+    // final report says the total is however many inputs there really were.
+    pub(crate) fn finish(&mut self, done: u64) {
+        if let Some(ref mut callback) = self.callback {
+            callback(done, done);
+        }
+    }
+}
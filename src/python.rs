@@ -0,0 +1,284 @@
+// A Python module for reading and editing movies, built with
+// `--features python`:
+//
+//     import dtm2txt
+//     movie = dtm2txt.Dtm.open("run.dtm")
+//     print(movie.header.author, len(movie))
+//     held = sum(1 for frame in movie if frame.a)
+//
+// Frames are only the GameCube controller inputs. Indexing gives a copy of
+// the input, so an edited one has to be assigned back.
+
+// pyo3's generated wrappers convert every PyResult they return, which clippy
+// flags on each method.
+#![allow(clippy::useless_conversion)]
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use pyo3::exceptions::{PyAttributeError, PyIndexError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyIterator, PyList, PySlice};
+use serde_json::{self, Value};
+
+use decoder::dtm_decoder::DtmDecoder;
+use decoder::text_decoder::TextDecoder;
+use dtm::{Dtm, DtmHeader, ControllerInput, WiimoteInput};
+use encoder::dtm_encoder::DtmEncoder;
+use encoder::text_encoder::{self, TextEncoder};
+use error::Dtm2txtError;
+use header_patch;
+
+impl From<Dtm2txtError> for PyErr {
+    fn from(err: Dtm2txtError) -> PyErr {
+        match err {
+            Dtm2txtError::IoError(err) => err.into(),
+            err => PyValueError::new_err(err.to_string()),
+        }
+    }
+}
+
+// Header fields go through JSON both ways, so they have the same names and
+// values as in a txt header.
+fn to_python(py: Python, value: &Value) -> PyResult<PyObject> {
+    let json = py.import_bound("json")?;
+    Ok(json.call_method1("loads", (value.to_string(),))?.unbind())
+}
+
+fn header_fields(header: &DtmHeader) -> PyResult<Value> {
+    serde_json::to_value(header).map_err(|err| PyValueError::new_err(err.to_string()))
+}
+
+fn from_python(value: &Bound<PyAny>) -> PyResult<Value> {
+    let json = value.py().import_bound("json")?;
+    let text: String = json.call_method1("dumps", (value,))?.extract()?;
+    serde_json::from_str(&text).map_err(|err| PyValueError::new_err(err.to_string()))
+}
+
+#[pymethods]
+impl ControllerInput {
+    // A neutral input; set the fields to press things.
+    #[new]
+    fn py_new() -> ControllerInput {
+        ControllerInput::NEUTRAL
+    }
+
+    fn __repr__(&self) -> String {
+        format!("ControllerInput('{}')", text_encoder::format_controller_input(self))
+    }
+}
+
+#[pyclass(name = "DtmHeader", module = "dtm2txt", eq)]
+#[derive(Clone, PartialEq)]
+pub struct PyDtmHeader {
+    header: DtmHeader,
+}
+
+#[pymethods]
+impl PyDtmHeader {
+    // Dolphin's defaults for a new recording.
+    #[new]
+    fn py_new() -> PyDtmHeader {
+        PyDtmHeader {
+            header: DtmHeader::default(),
+        }
+    }
+
+    fn __getattr__(&self, py: Python, name: &str) -> PyResult<PyObject> {
+        let fields = header_fields(&self.header)?;
+        match fields.get(name) {
+            Some(value) => to_python(py, value),
+            None => Err(PyAttributeError::new_err(format!("DtmHeader has no field '{}'", name))),
+        }
+    }
+
+    fn __setattr__(&mut self, name: &str, value: &Bound<PyAny>) -> PyResult<()> {
+        let mut patch = serde_json::Map::new();
+        patch.insert(name.to_string(), from_python(value)?);
+        self.header = header_patch::merge_header(&self.header, &Value::Object(patch))?;
+        Ok(())
+    }
+
+    fn __dir__(&self) -> PyResult<Vec<String>> {
+        let fields = header_fields(&self.header)?;
+        Ok(fields.as_object().map(|fields| fields.keys().cloned().collect()).unwrap_or_default())
+    }
+
+    fn to_dict(&self, py: Python) -> PyResult<PyObject> {
+        to_python(py, &header_fields(&self.header)?)
+    }
+
+    fn __repr__(&self) -> PyResult<String> {
+        Ok(format!("DtmHeader({})", header_fields(&self.header)?))
+    }
+}
+
+#[pyclass(name = "Dtm", module = "dtm2txt", sequence)]
+pub struct PyDtm {
+    // Shared with Python, so `movie.header.author = ...` sticks.
+    #[pyo3(get, set)]
+    header: Py<PyDtmHeader>,
+    controller_data: Vec<ControllerInput>,
+    wiimote_data: Vec<WiimoteInput>,
+    trailing: Vec<u8>,
+}
+
+impl PyDtm {
+    fn wrap(py: Python, dtm: Dtm) -> PyResult<PyDtm> {
+        Ok(PyDtm {
+            header: Py::new(py, PyDtmHeader { header: dtm.header })?,
+            controller_data: dtm.controller_data,
+            wiimote_data: dtm.wiimote_data,
+            trailing: dtm.trailing,
+        })
+    }
+
+    fn to_dtm(&self, py: Python) -> Dtm {
+        Dtm {
+            header: self.header.borrow(py).header.clone(),
+            controller_data: self.controller_data.clone(),
+            wiimote_data: self.wiimote_data.clone(),
+            trailing: self.trailing.clone(),
+        }
+    }
+
+    // Python indexes count back from the end when negative.
+    fn position(&self, index: isize) -> PyResult<usize> {
+        let len = self.controller_data.len() as isize;
+        let position = if index < 0 { index + len } else { index };
+        if position < 0 || position >= len {
+            return Err(PyIndexError::new_err("frame index out of range"));
+        }
+        Ok(position as usize)
+    }
+}
+
+fn is_txt(path: &Path) -> bool {
+    path.extension().map(|extension| extension == "txt").unwrap_or(false)
+}
+
+#[pymethods]
+impl PyDtm {
+    // A movie of `frames` neutral frames, like `dtm2txt new`.
+    #[new]
+    #[pyo3(signature = (header=None, frames=0))]
+    fn py_new(py: Python, header: Option<PyDtmHeader>, frames: u64) -> PyResult<PyDtm> {
+        let header = header.map(|header| header.header).unwrap_or_default();
+        PyDtm::wrap(py, Dtm::blank(header, frames))
+    }
+
+    // Reads a dtm, or a txt file if the name ends in .txt.
+    #[staticmethod]
+    fn open(py: Python, path: PathBuf) -> PyResult<PyDtm> {
+        let file = BufReader::new(File::open(&path)?);
+        let dtm = if is_txt(&path) {
+            TextDecoder::new(file).decode()?
+        }
+        else {
+            DtmDecoder::new(file).decode()?
+        };
+        PyDtm::wrap(py, dtm)
+    }
+
+    #[staticmethod]
+    fn from_bytes(py: Python, data: &[u8]) -> PyResult<PyDtm> {
+        PyDtm::wrap(py, DtmDecoder::new(data).decode()?)
+    }
+
+    #[staticmethod]
+    fn from_txt(py: Python, text: &str) -> PyResult<PyDtm> {
+        PyDtm::wrap(py, TextDecoder::new(text.as_bytes()).decode()?)
+    }
+
+    // Writes a dtm, or a txt file if the name ends in .txt. The header's
+    // counts have to match the inputs; `recount` fixes them after frames
+    // were added or removed.
+    #[pyo3(signature = (path, allow_inconsistent=false))]
+    fn save(&self, py: Python, path: PathBuf, allow_inconsistent: bool) -> PyResult<()> {
+        let file = File::create(&path)?;
+        let dtm = self.to_dtm(py);
+        if is_txt(&path) {
+            TextEncoder::new(file).encode(&dtm)?;
+        }
+        else {
+            DtmEncoder::new(file).allow_inconsistent(allow_inconsistent).encode(&dtm)?;
+        }
+        Ok(())
+    }
+
+    #[pyo3(signature = (allow_inconsistent=false))]
+    fn to_bytes<'py>(&self, py: Python<'py>, allow_inconsistent: bool) -> PyResult<Bound<'py, PyBytes>> {
+        let mut data = Vec::new();
+        DtmEncoder::new(&mut data).allow_inconsistent(allow_inconsistent).encode(&self.to_dtm(py))?;
+        Ok(PyBytes::new_bound(py, &data))
+    }
+
+    fn to_txt(&self, py: Python) -> PyResult<String> {
+        let mut text = Vec::new();
+        TextEncoder::new(&mut text).encode(&self.to_dtm(py))?;
+        String::from_utf8(text).map_err(|err| Dtm2txtError::from(err).into())
+    }
+
+    // Sets the header's input, VI and tick counts to fit the inputs, like
+    // `convert --fix-counts`.
+    fn recount(&mut self, py: Python) {
+        let mut dtm = self.to_dtm(py);
+        dtm.recount();
+        self.header.borrow_mut(py).header = dtm.header;
+    }
+
+    // All the frames at once, as a list of copies.
+    #[getter]
+    fn frames<'py>(&self, py: Python<'py>) -> Bound<'py, PyList> {
+        PyList::new_bound(py, self.controller_data.iter().map(|input| input.into_py(py)))
+    }
+
+    #[setter]
+    fn set_frames(&mut self, frames: Vec<ControllerInput>) {
+        self.controller_data = frames;
+    }
+
+    fn append(&mut self, input: ControllerInput) {
+        self.controller_data.push(input);
+    }
+
+    fn __len__(&self) -> usize {
+        self.controller_data.len()
+    }
+
+    // An index gives one frame and a slice gives a list of them.
+    fn __getitem__(&self, py: Python, index: &Bound<PyAny>) -> PyResult<PyObject> {
+        if let Ok(slice) = index.downcast::<PySlice>() {
+            let indices = slice.indices(self.controller_data.len() as isize)?;
+            let frames = (0..indices.slicelength)
+                .map(|idx| self.controller_data[(indices.start + idx as isize * indices.step) as usize].into_py(py));
+            return Ok(PyList::new_bound(py, frames).into_py(py));
+        }
+        let position = self.position(index.extract()?)?;
+        Ok(self.controller_data[position].into_py(py))
+    }
+
+    fn __setitem__(&mut self, index: isize, input: ControllerInput) -> PyResult<()> {
+        let position = self.position(index)?;
+        self.controller_data[position] = input;
+        Ok(())
+    }
+
+    fn __iter__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyIterator>> {
+        self.frames(py).as_any().iter()
+    }
+
+    fn __repr__(&self, py: Python) -> String {
+        let header = &self.header.borrow(py).header;
+        format!("Dtm(game_id='{}', frames={})", header.game_id, self.controller_data.len())
+    }
+}
+
+#[pymodule]
+fn dtm2txt(module: &Bound<PyModule>) -> PyResult<()> {
+    module.add_class::<PyDtm>()?;
+    module.add_class::<PyDtmHeader>()?;
+    module.add_class::<ControllerInput>()?;
+    Ok(())
+}
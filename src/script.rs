@@ -0,0 +1,401 @@
+use std::fmt;
+use std::str::FromStr;
+
+use dtm::{Dtm, DtmHeader, ControllerInput, Button, MAX_EXPANDED_INPUTS};
+use error::{Dtm2txtError, ScriptParseError, Dtm2txtResult};
+
+// One step of a script, held for a number of frames:
+//
+//     hold A 30; press Z; stick 255,128 for 12
+//     hold B stick 0,128 cstick 128,255 triggers 255,0 for 5
+//     wait 60
+//     repeat 10 { press A; wait 1 }
+//
+// Everything in a statement happens on the same frames, and anything it
+// doesn't mention is released or centered. A statement lasts one frame
+// unless given a count, with or without "for". Statements end at a
+// semicolon or the end of the line, and # starts a comment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Statement {
+    Input {
+        input: ControllerInput,
+        frames: u64,
+    },
+    Repeat {
+        times: u64,
+        body: Vec<Statement>,
+    },
+}
+
+// A movie written as statements instead of frame by frame. Buttons are named
+// like in txt files or csv columns, so "l" is left on the D-pad and "lt" the
+// L trigger.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Script {
+    pub statements: Vec<Statement>,
+}
+
+fn frame_count(statements: &[Statement]) -> u64 {
+    statements.iter()
+        .map(|statement| match *statement {
+            Statement::Input { frames, .. } => frames,
+            Statement::Repeat { times, ref body } => times.saturating_mul(frame_count(body)),
+        })
+        .fold(0, u64::saturating_add)
+}
+
+fn expand(statements: &[Statement], frames: &mut Vec<ControllerInput>) {
+    for statement in statements.iter() {
+        match *statement {
+            Statement::Input { input, frames: count } => {
+                frames.extend((0..count).map(|_| input));
+            }
+            Statement::Repeat { times, ref body } => {
+                for _ in 0..times {
+                    expand(body, frames);
+                }
+            }
+        }
+    }
+}
+
+impl Script {
+    pub fn parse(text: &str) -> Dtm2txtResult<Script> {
+        let (tokens, end) = tokenize(text)?;
+        let mut parser = Parser {
+            tokens: tokens,
+            pos: 0,
+            end: end,
+        };
+        Ok(Script {
+            statements: parser.parse_block(false)?.0,
+        })
+    }
+
+    // Saturates at u64::MAX for scripts that don't come from `parse`.
+    pub fn frame_count(&self) -> u64 {
+        frame_count(&self.statements)
+    }
+
+    // Every frame the script stands for, as long as they fit under
+    // MAX_EXPANDED_INPUTS. `parse` already refuses scripts that don't.
+    pub fn frames(&self) -> Dtm2txtResult<Vec<ControllerInput>> {
+        let count = self.frame_count();
+        if count > MAX_EXPANDED_INPUTS {
+            return Err(Dtm2txtError::TooManyInputsError {
+                inputs: count,
+            });
+        }
+        let mut frames = Vec::with_capacity(count as usize);
+        expand(&self.statements, &mut frames);
+        Ok(frames)
+    }
+
+    // The frames are for a single controller, so `header` should have just
+    // one connected. Its counts are filled in to match the frames.
+    pub fn compile(&self, header: DtmHeader) -> Dtm2txtResult<Dtm> {
+        let mut dtm = Dtm {
+            header: header,
+            controller_data: self.frames()?,
+            wiimote_data: Vec::new(),
+            trailing: Vec::new(),
+        };
+        dtm.recount();
+        dtm.estimate_timing();
+        Ok(dtm)
+    }
+}
+
+impl FromStr for Script {
+    type Err = Dtm2txtError;
+
+    fn from_str(text: &str) -> Dtm2txtResult<Script> {
+        Script::parse(text)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Number(u64),
+    Comma,
+    Plus,
+    LBrace,
+    RBrace,
+    // A semicolon or the end of a line.
+    End,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Token::Word(ref word) => f.write_str(word),
+            Token::Number(value) => value.fmt(f),
+            Token::Comma => f.write_str(","),
+            Token::Plus => f.write_str("+"),
+            Token::LBrace => f.write_str("{"),
+            Token::RBrace => f.write_str("}"),
+            Token::End => f.write_str(";"),
+        }
+    }
+}
+
+fn parse_error(reason: ScriptParseError, line: u64) -> Dtm2txtError {
+    Dtm2txtError::ScriptParseError {
+        reason: reason,
+        line: line,
+    }
+}
+
+// Returns the tokens with their line numbers, and the number of the last
+// line for errors at the end of the script.
+fn tokenize(text: &str) -> Dtm2txtResult<(Vec<(Token, u64)>, u64)> {
+    let mut tokens = Vec::new();
+    let mut line_number = 0;
+
+    for (idx, line) in text.lines().enumerate() {
+        line_number = idx as u64 + 1;
+        let code = line.split('#').next().unwrap_or_default();
+        let mut chars = code.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            let token = match c {
+                c if c.is_whitespace() => continue,
+                ';' => Token::End,
+                ',' => Token::Comma,
+                '+' => Token::Plus,
+                '{' => Token::LBrace,
+                '}' => Token::RBrace,
+                c if c.is_ascii_alphanumeric() || c == '_' => {
+                    let mut word = c.to_string();
+                    while let Some(&next) = chars.peek() {
+                        if !(next.is_ascii_alphanumeric() || next == '_') {
+                            break;
+                        }
+                        word.push(next);
+                        chars.next();
+                    }
+
+                    if c.is_ascii_digit() {
+                        let value = word.parse::<u64>()
+                            .map_err(|err| parse_error(ScriptParseError::ParseIntError(err), line_number))?;
+                        Token::Number(value)
+                    }
+                    else {
+                        Token::Word(word)
+                    }
+                }
+                _ => return Err(parse_error(ScriptParseError::UnexpectedCharacter(c), line_number)),
+            };
+            tokens.push((token, line_number));
+        }
+        tokens.push((Token::End, line_number));
+    }
+
+    Ok((tokens, line_number))
+}
+
+struct Parser {
+    tokens: Vec<(Token, u64)>,
+    pos: usize,
+    end: u64,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Dtm2txtResult<(Token, u64)> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.clone())
+            }
+            None => Err(parse_error(ScriptParseError::UnexpectedEnd, self.end)),
+        }
+    }
+
+    fn expect(&mut self, expected: Token) -> Dtm2txtResult<()> {
+        match self.next()? {
+            (ref token, _) if *token == expected => Ok(()),
+            (token, line) => Err(parse_error(ScriptParseError::UnexpectedToken(token.to_string()), line)),
+        }
+    }
+
+    fn parse_number(&mut self) -> Dtm2txtResult<u64> {
+        match self.next()? {
+            (Token::Number(value), _) => Ok(value),
+            (token, line) => Err(parse_error(ScriptParseError::UnexpectedToken(token.to_string()), line)),
+        }
+    }
+
+    fn parse_byte(&mut self) -> Dtm2txtResult<u8> {
+        let line = self.tokens.get(self.pos).map(|&(_, line)| line).unwrap_or(self.end);
+        let value = self.parse_number()?;
+        if value > u8::MAX as u64 {
+            return Err(parse_error(ScriptParseError::ValueOutOfRange(value), line));
+        }
+        Ok(value as u8)
+    }
+
+    // "255,128"
+    fn parse_pair(&mut self) -> Dtm2txtResult<(u8, u8)> {
+        let first = self.parse_byte()?;
+        self.expect(Token::Comma)?;
+        let second = self.parse_byte()?;
+        Ok((first, second))
+    }
+
+    // "A", "A B" or "A+B".
+    fn parse_buttons(&mut self, input: &mut ControllerInput) -> Dtm2txtResult<()> {
+        let mut expect_button = true;
+        loop {
+            let button = match self.peek() {
+                Some(Token::Word(name)) => Button::from_name(name),
+                _ => None,
+            };
+            match button {
+                Some(button) => {
+                    self.pos += 1;
+                    input.set_pressed(button, true);
+                    expect_button = false;
+                }
+                None if expect_button => {
+                    let line = self.tokens.get(self.pos).map(|&(_, line)| line).unwrap_or(self.end);
+                    return Err(parse_error(ScriptParseError::ExpectedButton, line));
+                }
+                None => return Ok(()),
+            }
+            if let Some(&Token::Plus) = self.peek() {
+                self.pos += 1;
+                expect_button = true;
+            }
+        }
+    }
+
+    // Statements up to the end of the script, or up to the closing brace of
+    // a repeat, with the number of frames they make. That's kept under
+    // MAX_EXPANDED_INPUTS at every level, so nested repeats can't overflow it.
+    fn parse_block(&mut self, nested: bool) -> Dtm2txtResult<(Vec<Statement>, u64)> {
+        let mut statements = Vec::new();
+        let mut frames: u64 = 0;
+        loop {
+            let line = self.tokens.get(self.pos).map(|&(_, line)| line).unwrap_or(self.end);
+            let (statement, statement_frames) = match self.peek().cloned() {
+                None if nested => return Err(parse_error(ScriptParseError::UnexpectedEnd, self.end)),
+                None => return Ok((statements, frames)),
+                Some(Token::End) => {
+                    self.pos += 1;
+                    continue;
+                }
+                Some(Token::RBrace) if nested => {
+                    self.pos += 1;
+                    return Ok((statements, frames));
+                }
+                Some(Token::Word(ref word)) if word.eq_ignore_ascii_case("repeat") => {
+                    self.pos += 1;
+                    let times = self.parse_number()?;
+                    self.expect(Token::LBrace)?;
+                    let (body, body_frames) = self.parse_block(true)?;
+                    let repeat = Statement::Repeat {
+                        times: times,
+                        body: body,
+                    };
+                    (repeat, times.checked_mul(body_frames))
+                }
+                Some(_) => {
+                    let (input, input_frames) = self.parse_input()?;
+                    let input = Statement::Input {
+                        input: input,
+                        frames: input_frames,
+                    };
+                    (input, Some(input_frames))
+                }
+            };
+            frames = statement_frames
+                .and_then(|statement_frames| frames.checked_add(statement_frames))
+                .filter(|&frames| frames <= MAX_EXPANDED_INPUTS)
+                .ok_or_else(|| parse_error(ScriptParseError::TooManyFrames, line))?;
+            statements.push(statement);
+        }
+    }
+
+    // The input a statement makes, and for how many frames.
+    fn parse_input(&mut self) -> Dtm2txtResult<(ControllerInput, u64)> {
+        // Scripts play on a plugged in controller, which Dolphin records on
+        // every frame.
+        let mut input = ControllerInput::NEUTRAL.with_controller_connected(true);
+        let mut frames = 1;
+        loop {
+            match self.peek() {
+                None | Some(&Token::End) | Some(&Token::RBrace) => break,
+                _ => {}
+            }
+            let (token, line) = self.next()?;
+            match token {
+                Token::Number(count) => frames = count,
+                Token::Word(ref word) => match word.to_ascii_lowercase().as_str() {
+                    "press" | "hold" => self.parse_buttons(&mut input)?,
+                    "wait" => {}
+                    "for" => frames = self.parse_number()?,
+                    "stick" => {
+                        let (x, y) = self.parse_pair()?;
+                        input = input.with_analog(x, y);
+                    }
+                    "cstick" => {
+                        let (x, y) = self.parse_pair()?;
+                        input = input.with_c_stick(x, y);
+                    }
+                    "triggers" => {
+                        let (l, r) = self.parse_pair()?;
+                        input = input.with_triggers(l, r);
+                    }
+                    _ => return Err(parse_error(ScriptParseError::UnknownCommand(word.clone()), line)),
+                },
+                token => return Err(parse_error(ScriptParseError::UnexpectedToken(token.to_string()), line)),
+            }
+        }
+        Ok((input, frames))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_repeats() {
+        let script = Script::parse("repeat 3 { press A; wait 2 }\nhold B for 4").unwrap();
+        assert_eq!(script.frame_count(), 13);
+        let frames = script.frames().unwrap();
+        assert_eq!(frames.len(), 13);
+        assert!(frames[3].a && !frames[4].a);
+        assert!(frames[12].b);
+        assert!(frames.iter().all(|input| input.controller_connected));
+    }
+
+    #[test]
+    fn refuses_scripts_past_the_limit() {
+        let nested = "repeat 1000 { repeat 1000 { repeat 1000 { press A } } }";
+        match Script::parse(nested) {
+            Err(Dtm2txtError::ScriptParseError{reason: ScriptParseError::TooManyFrames, line}) => assert_eq!(line, 1),
+            other => panic!("parsed {:?}", other),
+        }
+        let overflowing = "repeat 18446744073709551615 {\n  repeat 2 { wait 1 }\n}";
+        assert!(Script::parse(overflowing).is_err());
+        let long = format!("wait {}\nwait 1", MAX_EXPANDED_INPUTS);
+        match Script::parse(&long) {
+            Err(Dtm2txtError::ScriptParseError{reason: ScriptParseError::TooManyFrames, line}) => assert_eq!(line, 2),
+            other => panic!("parsed {:?}", other),
+        }
+
+        let built = Script {
+            statements: vec![Statement::Repeat {
+                times: u64::MAX,
+                body: vec![Statement::Input { input: ControllerInput::NEUTRAL, frames: 2 }],
+            }],
+        };
+        assert!(built.compile(DtmHeader::default()).is_err());
+    }
+}
@@ -0,0 +1,278 @@
+use std::f64::consts::PI;
+
+use dtm::{Dtm, ControllerInput, Button};
+use filter::FramePredicate;
+
+// How far a stick can be from center, in either direction, and still count as
+// neutral. Real controllers rarely rest exactly on 128.
+const STICK_DEADZONE: i32 = 20;
+// Trigger pressures up to this count as released.
+const TRIGGER_DEADZONE: u8 = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StickDirection {
+    Neutral,
+    Up,
+    UpRight,
+    Right,
+    DownRight,
+    Down,
+    DownLeft,
+    Left,
+    UpLeft,
+}
+
+impl StickDirection {
+    pub const ALL: [StickDirection; 9] = [
+        StickDirection::Neutral, StickDirection::Up, StickDirection::UpRight,
+        StickDirection::Right, StickDirection::DownRight, StickDirection::Down,
+        StickDirection::DownLeft, StickDirection::Left, StickDirection::UpLeft,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            StickDirection::Neutral => "neutral",
+            StickDirection::Up => "up",
+            StickDirection::UpRight => "up-right",
+            StickDirection::Right => "right",
+            StickDirection::DownRight => "down-right",
+            StickDirection::Down => "down",
+            StickDirection::DownLeft => "down-left",
+            StickDirection::Left => "left",
+            StickDirection::UpLeft => "up-left",
+        }
+    }
+
+    // Sorts a stick position into one of eight 45 degree slices, or neutral
+    // inside the deadzone. Higher y values are up.
+    pub fn from_position(x: u8, y: u8) -> StickDirection {
+        let dx = x as i32 - 128;
+        let dy = y as i32 - 128;
+        if dx.abs() <= STICK_DEADZONE && dy.abs() <= STICK_DEADZONE {
+            return StickDirection::Neutral;
+        }
+
+        let angle = (dy as f64).atan2(dx as f64);
+        let slice = (angle / (PI / 4.0)).round() as i32;
+        match slice.rem_euclid(8) {
+            0 => StickDirection::Right,
+            1 => StickDirection::UpRight,
+            2 => StickDirection::Up,
+            3 => StickDirection::UpLeft,
+            4 => StickDirection::Left,
+            5 => StickDirection::DownLeft,
+            6 => StickDirection::Down,
+            _ => StickDirection::DownRight,
+        }
+    }
+
+    fn index(self) -> usize {
+        StickDirection::ALL.iter()
+            .position(|&direction| direction == self)
+            .unwrap_or(0)
+    }
+}
+
+// How many frames a stick spent pointing each way.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StickUsage {
+    counts: [u64; 9],
+}
+
+impl StickUsage {
+    pub fn frames(&self, direction: StickDirection) -> u64 {
+        self.counts[direction.index()]
+    }
+
+    fn add(&mut self, x: u8, y: u8) {
+        self.counts[StickDirection::from_position(x, y).index()] += 1;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ButtonStats {
+    pub button: Button,
+    // Separate presses, so holding a button for 100 frames counts once.
+    pub presses: u64,
+    pub held_frames: u64,
+    pub longest_hold: u64,
+}
+
+// A run of frames with nothing pressed and both sticks and triggers at rest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IdleStretch {
+    pub start: u64,
+    pub frames: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MovieStatistics {
+    pub frames: u64,
+    pub wiimote_frames: u64,
+    pub buttons: Vec<ButtonStats>,
+    pub longest_idle: Option<IdleStretch>,
+    pub main_stick: StickUsage,
+    pub c_stick: StickUsage,
+    // Length going by the VI count and by emulated CPU time.
+    pub duration_secs: f64,
+    pub tick_duration_secs: f64,
+}
+
+// Statistics over one stretch of a movie, for seeing how busy each part of
+// it is.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WindowStats {
+    pub start: u64,
+    pub frames: u64,
+    // Presses started in the window, in `Button::ALL` order. A press that
+    // started before the window isn't counted again.
+    pub presses: Vec<u64>,
+    // How far each stick moved, in stick units, counting the move into the
+    // window's first frame.
+    pub main_stick_travel: f64,
+    pub c_stick_travel: f64,
+}
+
+impl WindowStats {
+    pub fn presses(&self, button: Button) -> u64 {
+        Button::ALL.iter()
+            .position(|&other| other == button)
+            .map(|idx| self.presses[idx])
+            .unwrap_or(0)
+    }
+}
+
+fn stick_distance(from: (u8, u8), to: (u8, u8)) -> f64 {
+    let dx = to.0 as f64 - from.0 as f64;
+    let dy = to.1 as f64 - from.1 as f64;
+    (dx * dx + dy * dy).sqrt()
+}
+
+fn is_idle(input: &ControllerInput) -> bool {
+    // Controller Connected is set on every frame a controller is plugged in,
+    // so it doesn't count as input.
+    let pressed = input.buttons().iter().any(|button| button != Button::ControllerConnected);
+    !pressed
+        && StickDirection::from_position(input.analog_x, input.analog_y) == StickDirection::Neutral
+        && StickDirection::from_position(input.c_x, input.c_y) == StickDirection::Neutral
+        && input.l_pressure <= TRIGGER_DEADZONE
+        && input.r_pressure <= TRIGGER_DEADZONE
+}
+
+impl Dtm {
+    // Statistics over the GameCube controller inputs. Wii remote reports are
+    // only counted.
+    pub fn statistics(&self) -> MovieStatistics {
+        self.statistics_where(&|_: u64, _: &ControllerInput| true)
+    }
+
+    // Statistics over only the inputs the predicate picks, such as a parsed
+    // `Filter`. A skipped input ends any hold or idle stretch running through
+    // it, and `frames` counts the inputs that were picked.
+    pub fn statistics_where<P>(&self, predicate: &P) -> MovieStatistics
+        where P: FramePredicate,
+    {
+        let mut buttons: Vec<ButtonStats> = Button::ALL.iter()
+            .map(|&button| ButtonStats {
+                button: button,
+                presses: 0,
+                held_frames: 0,
+                longest_hold: 0,
+            })
+            .collect();
+        let mut holds = vec![0u64; buttons.len()];
+        let mut main_stick = StickUsage::default();
+        let mut c_stick = StickUsage::default();
+        let mut longest_idle: Option<IdleStretch> = None;
+        let mut idle_start = None;
+        let mut picked = 0;
+
+        let ports = self.ports_per_frame();
+        for (idx, input) in self.controller_data.iter().enumerate() {
+            let frame = idx as u64 / ports;
+            if !predicate.matches(frame, input) {
+                for hold in holds.iter_mut() {
+                    *hold = 0;
+                }
+                idle_start = None;
+                continue;
+            }
+            picked += 1;
+
+            for (stats, hold) in buttons.iter_mut().zip(holds.iter_mut()) {
+                if input.pressed(stats.button) {
+                    if *hold == 0 {
+                        stats.presses += 1;
+                    }
+                    *hold += 1;
+                    stats.held_frames += 1;
+                    stats.longest_hold = stats.longest_hold.max(*hold);
+                }
+                else {
+                    *hold = 0;
+                }
+            }
+
+            main_stick.add(input.analog_x, input.analog_y);
+            c_stick.add(input.c_x, input.c_y);
+
+            if is_idle(input) {
+                let start = *idle_start.get_or_insert(frame);
+                let frames = frame - start + 1;
+                if longest_idle.map(|idle| frames > idle.frames).unwrap_or(true) {
+                    longest_idle = Some(IdleStretch {
+                        start: start,
+                        frames: frames,
+                    });
+                }
+            }
+            else {
+                idle_start = None;
+            }
+        }
+
+        MovieStatistics {
+            frames: picked,
+            wiimote_frames: self.wiimote_data.len() as u64,
+            buttons: buttons,
+            longest_idle: longest_idle,
+            main_stick: main_stick,
+            c_stick: c_stick,
+            duration_secs: self.header.duration_secs(),
+            tick_duration_secs: self.header.tick_duration_secs(),
+        }
+    }
+
+    // The movie in stretches of `window` frames (the last one may be
+    // shorter). The ports of a movie with several controllers take turns, so
+    // take one with `single_port` first.
+    pub fn window_statistics(&self, window: u64) -> Vec<WindowStats> {
+        let window = window.max(1) as usize;
+        let mut windows = Vec::new();
+        let mut previous: Option<&ControllerInput> = None;
+        for (idx, inputs) in self.controller_data.chunks(window).enumerate() {
+            let mut stats = WindowStats {
+                start: (idx * window) as u64,
+                frames: inputs.len() as u64,
+                presses: vec![0; Button::ALL.len()],
+                main_stick_travel: 0.0,
+                c_stick_travel: 0.0,
+            };
+            for input in inputs.iter() {
+                let was_pressed = |button| previous.map(|previous| previous.pressed(button)).unwrap_or(false);
+                for (presses, &button) in stats.presses.iter_mut().zip(Button::ALL.iter()) {
+                    if input.pressed(button) && !was_pressed(button) {
+                        *presses += 1;
+                    }
+                }
+                if let Some(previous) = previous {
+                    stats.main_stick_travel += stick_distance((previous.analog_x, previous.analog_y), (input.analog_x, input.analog_y));
+                    stats.c_stick_travel += stick_distance((previous.c_x, previous.c_y), (input.c_x, input.c_y));
+                }
+                previous = Some(input);
+            }
+            windows.push(stats);
+        }
+        windows
+    }
+}
@@ -0,0 +1,42 @@
+use dtm::{Dtm, Button};
+use frames::FrameStore;
+
+// One row of a timeline: a character per frame from `start` on, '#' for the
+// frames the button is held and '.' for the rest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimelineRow {
+    pub start: u64,
+    pub marks: String,
+}
+
+impl<S> Dtm<S>
+    where S: FrameStore,
+{
+    // When `button` is held, `per_row` frames to a row, for seeing the rhythm
+    // of a movie's inputs at a glance. The ports of a movie with several
+    // controllers take turns, so take one with `single_port` first.
+    pub fn timeline(&self, button: Button, per_row: usize) -> Vec<TimelineRow> {
+        let per_row = per_row.max(1);
+        let mut rows = Vec::new();
+        let mut marks = String::with_capacity(per_row);
+        let mut start = 0;
+        for (frame, input) in self.controller_data.frames().enumerate() {
+            if marks.len() == per_row {
+                rows.push(TimelineRow {
+                    start: start,
+                    marks: marks.clone(),
+                });
+                marks.clear();
+                start = frame as u64;
+            }
+            marks.push(if input.pressed(button) { '#' } else { '.' });
+        }
+        if !marks.is_empty() {
+            rows.push(TimelineRow {
+                start: start,
+                marks: marks,
+            });
+        }
+        rows
+    }
+}
@@ -0,0 +1,82 @@
+use std::io::{Read, Seek, Write};
+
+use decoder::dtm_decoder::DtmDecoder;
+use decoder::options::DecodeWarning;
+use decoder::text_decoder::TextDecoder;
+use dtm::DtmHeader;
+use encoder::dtm_encoder::DtmEncoder;
+use encoder::text_encoder::TextEncoder;
+use error::Dtm2txtResult;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    DtmToTxt,
+    TxtToDtm,
+}
+
+// What went through a transcode, for reporting on the movie afterwards
+// without having its inputs.
+#[derive(Debug)]
+pub struct Transcoded {
+    pub header: DtmHeader,
+    pub frames: u64,
+    pub wiimote_frames: u64,
+    pub warnings: Vec<DecodeWarning>,
+}
+
+// Converts a movie between dtm and txt one input at a time, so a five hour
+// movie takes no more memory than a five second one. The dtm side has to be
+// seekable: a dtm being read is gone through twice, since the txt header
+// holds what comes after the inputs, and a dtm being written gets its header
+// once the inputs are all in.
+pub fn transcode<R, W>(reader: R, writer: W, direction: Direction) -> Dtm2txtResult<Transcoded>
+    where R: Read + Seek,
+          W: Write + Seek,
+{
+    match direction {
+        Direction::DtmToTxt => dtm_to_txt(DtmDecoder::new(reader), TextEncoder::new(writer)),
+        Direction::TxtToDtm => txt_to_dtm(TextDecoder::new(reader), DtmEncoder::new(writer)),
+    }
+}
+
+// `transcode` with the decoder and encoder set up as wanted. Lines are
+// written like `TextEncoder::stream` writes them, so comments and
+// `collapse_repeats` are left out.
+pub fn dtm_to_txt<R, W>(decoder: DtmDecoder<R>, encoder: TextEncoder<W>) -> Dtm2txtResult<Transcoded>
+    where R: Read + Seek,
+          W: Write,
+{
+    let (header, stream, warnings) = decoder.decode_streaming(|header, trailing| {
+        encoder.start_stream(header, trailing, false)
+    })?;
+    stream.finish()?;
+
+    let (frames, wiimote_frames) = if header.has_wiimote_inputs() { (0, header.input_count) } else { (header.input_count, 0) };
+    Ok(Transcoded {
+        header: header,
+        frames: frames,
+        wiimote_frames: wiimote_frames,
+        warnings: warnings,
+    })
+}
+
+// `transcode` with the decoder and encoder set up as wanted. The txt file's
+// comments are dropped. A header that disagrees with the inputs is only
+// found out once they've been written, so the writer is left with a partial
+// movie when that fails.
+pub fn txt_to_dtm<R, W>(decoder: TextDecoder<R>, encoder: DtmEncoder<W>) -> Dtm2txtResult<Transcoded>
+    where R: Read,
+          W: Write + Seek,
+{
+    let mut stream = encoder.stream()?;
+    let decoded = decoder.decode_inputs(&mut stream)?;
+    let (frames, wiimote_frames) = stream.counts();
+    stream.finish(&decoded.header, &decoded.trailing)?;
+
+    Ok(Transcoded {
+        header: decoded.header,
+        frames: frames,
+        wiimote_frames: wiimote_frames,
+        warnings: decoded.warnings,
+    })
+}
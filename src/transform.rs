@@ -0,0 +1,563 @@
+use std::f64::consts::PI;
+use std::mem;
+use std::ops::{Bound, RangeBounds};
+use std::str::FromStr;
+
+use dtm::{Dtm, ControllerInput, Button, Axis};
+use error::{Dtm2txtError, Dtm2txtResult};
+use filter::FramePredicate;
+
+// Anything that edits frames one at a time, given each frame's number. The
+// counterpart of `FramePredicate`: a closure works as well as the transforms
+// here, and a list of them applies each in turn.
+pub trait FrameTransform {
+    fn transform(&self, frame: u64, input: &mut ControllerInput);
+}
+
+impl<F> FrameTransform for F
+    where F: Fn(u64, &mut ControllerInput),
+{
+    fn transform(&self, frame: u64, input: &mut ControllerInput) {
+        self(frame, input)
+    }
+}
+
+impl<T> FrameTransform for [T]
+    where T: FrameTransform,
+{
+    fn transform(&self, frame: u64, input: &mut ControllerInput) {
+        for transform in self.iter() {
+            transform.transform(frame, input);
+        }
+    }
+}
+
+impl<T> FrameTransform for Vec<T>
+    where T: FrameTransform,
+{
+    fn transform(&self, frame: u64, input: &mut ControllerInput) {
+        self[..].transform(frame, input)
+    }
+}
+
+const STICK_CENTER: f64 = 128.0;
+const STICK_RADIUS: f64 = 127.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stick {
+    Main,
+    C,
+}
+
+impl Stick {
+    pub fn axes(self) -> (Axis, Axis) {
+        match self {
+            Stick::Main => (Axis::AnalogX, Axis::AnalogY),
+            Stick::C => (Axis::CX, Axis::CY),
+        }
+    }
+}
+
+fn to_axis(value: f64) -> u8 {
+    value.round().clamp(0.0, 255.0) as u8
+}
+
+// Snaps a stick to the nearest of a set of angles, optionally also setting
+// its distance from center. Neutral sticks are left alone.
+#[derive(Clone, Debug)]
+pub struct SnapToAngle {
+    pub stick: Stick,
+    // Degrees counterclockwise from right.
+    pub angles: Vec<f64>,
+    // Fraction of full tilt to snap to, or `None` to keep the current tilt.
+    pub magnitude: Option<f64>,
+}
+
+impl SnapToAngle {
+    // Cardinals and diagonals at full tilt.
+    pub fn new(stick: Stick) -> SnapToAngle {
+        SnapToAngle {
+            stick: stick,
+            angles: (0..8).map(|idx| idx as f64 * 45.0).collect(),
+            magnitude: Some(1.0),
+        }
+    }
+
+    // Every `step` degrees starting from right, at full tilt.
+    pub fn every(stick: Stick, step: f64) -> Dtm2txtResult<SnapToAngle> {
+        if !(step > 0.0 && step <= 360.0) {
+            return Err(Dtm2txtError::InvalidSnapStepError {
+                step: step,
+            });
+        }
+        let count = (360.0 / step).round().max(1.0) as u32;
+        Ok(SnapToAngle {
+            stick: stick,
+            angles: (0..count).map(|idx| idx as f64 * step).collect(),
+            magnitude: Some(1.0),
+        })
+    }
+
+    pub fn apply(&self, input: &mut ControllerInput) {
+        let (x_axis, y_axis) = self.stick.axes();
+        let dx = input.axis(x_axis) as f64 - STICK_CENTER;
+        let dy = input.axis(y_axis) as f64 - STICK_CENTER;
+        let radius = (dx * dx + dy * dy).sqrt();
+        if radius < 1.0 {
+            return;
+        }
+
+        let angle = dy.atan2(dx);
+        let snapped = match self.angles.iter()
+            .map(|degrees| degrees.to_radians())
+            .min_by(|a, b| angle_distance(angle, *a).total_cmp(&angle_distance(angle, *b)))
+        {
+            Some(snapped) => snapped,
+            None => return,
+        };
+
+        let radius = match self.magnitude {
+            Some(magnitude) => magnitude * STICK_RADIUS,
+            None => radius.min(STICK_RADIUS),
+        };
+        input.set_axis(x_axis, to_axis(STICK_CENTER + radius * snapped.cos()));
+        input.set_axis(y_axis, to_axis(STICK_CENTER + radius * snapped.sin()));
+    }
+}
+
+impl FrameTransform for SnapToAngle {
+    fn transform(&self, _frame: u64, input: &mut ControllerInput) {
+        self.apply(input)
+    }
+}
+
+// Centers a stick exactly when it's within `radius` of the center, which is
+// where real controllers drift around without being touched.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Deadzone {
+    pub stick: Stick,
+    pub radius: f64,
+}
+
+impl Deadzone {
+    pub fn new(stick: Stick, radius: f64) -> Deadzone {
+        Deadzone {
+            stick: stick,
+            radius: radius,
+        }
+    }
+
+    pub fn apply(&self, input: &mut ControllerInput) {
+        let (x_axis, y_axis) = self.stick.axes();
+        let dx = input.axis(x_axis) as f64 - STICK_CENTER;
+        let dy = input.axis(y_axis) as f64 - STICK_CENTER;
+        if (dx * dx + dy * dy).sqrt() <= self.radius {
+            input.set_axis(x_axis, STICK_CENTER as u8);
+            input.set_axis(y_axis, STICK_CENTER as u8);
+        }
+    }
+}
+
+impl FrameTransform for Deadzone {
+    fn transform(&self, _frame: u64, input: &mut ControllerInput) {
+        self.apply(input)
+    }
+}
+
+fn angle_distance(a: f64, b: f64) -> f64 {
+    let diff = (a - b).abs() % (2.0 * PI);
+    diff.min(2.0 * PI - diff)
+}
+
+// Reflects an axis around the stick's center, so 128 stays put and full
+// left becomes full right.
+fn reflect(value: u8) -> u8 {
+    (256 - value as u16).min(255) as u8
+}
+
+// Mirrors horizontal inputs: left and right on the d-pad trade places, and
+// both sticks' x axes are reflected.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Mirror;
+
+impl FrameTransform for Mirror {
+    fn transform(&self, _frame: u64, input: &mut ControllerInput) {
+        mem::swap(&mut input.left, &mut input.right);
+        input.analog_x = reflect(input.analog_x);
+        input.c_x = reflect(input.c_x);
+    }
+}
+
+// Limits a transform to some of the frames.
+#[derive(Clone, Debug)]
+pub struct InRange<T> {
+    start: Bound<u64>,
+    end: Bound<u64>,
+    transform: T,
+}
+
+impl<T> InRange<T> {
+    pub fn new<R>(range: R, transform: T) -> InRange<T>
+        where R: RangeBounds<u64>,
+    {
+        InRange {
+            start: range.start_bound().cloned(),
+            end: range.end_bound().cloned(),
+            transform: transform,
+        }
+    }
+}
+
+impl<T> FrameTransform for InRange<T>
+    where T: FrameTransform,
+{
+    fn transform(&self, frame: u64, input: &mut ControllerInput) {
+        if (self.start, self.end).contains(&frame) {
+            self.transform.transform(frame, input);
+        }
+    }
+}
+
+// Limits a transform to the inputs a predicate picks, such as a parsed
+// `Filter`. The predicate sees each input before anything changes it.
+#[derive(Clone, Debug)]
+pub struct Matching<P, T> {
+    predicate: P,
+    transform: T,
+}
+
+impl<P, T> Matching<P, T> {
+    pub fn new(predicate: P, transform: T) -> Matching<P, T> {
+        Matching {
+            predicate: predicate,
+            transform: transform,
+        }
+    }
+}
+
+impl<P, T> FrameTransform for Matching<P, T>
+    where P: FramePredicate,
+          T: FrameTransform,
+{
+    fn transform(&self, frame: u64, input: &mut ControllerInput) {
+        if self.predicate.matches(frame, input) {
+            self.transform.transform(frame, input);
+        }
+    }
+}
+
+// Bulk corrections for movies recorded with a controller whose calibration
+// was off. Results saturate at 0 and 255.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AxisAdjust {
+    Offset(i16),
+    // Multiplies the distance from the axis' resting value: the center for
+    // sticks, released for triggers.
+    Scale(f64),
+    // Moves this value to the center, stretching each side so that 0 and 255
+    // stay put.
+    Recenter(u8),
+}
+
+impl AxisAdjust {
+    pub fn apply(self, axis: Axis, value: u8) -> u8 {
+        let rest = match axis {
+            Axis::LPressure | Axis::RPressure => 0.0,
+            _ => STICK_CENTER,
+        };
+        match self {
+            AxisAdjust::Offset(offset) => (value as i32 + offset as i32).clamp(0, 255) as u8,
+            AxisAdjust::Scale(factor) => to_axis(rest + (value as f64 - rest) * factor),
+            AxisAdjust::Recenter(from) => {
+                let (value, from) = (value as f64, from as f64);
+                if value < from {
+                    to_axis(value * STICK_CENTER / from)
+                }
+                else if value > from {
+                    to_axis(STICK_CENTER + (value - from) * (255.0 - STICK_CENTER) / (255.0 - from))
+                }
+                else {
+                    STICK_CENTER as u8
+                }
+            }
+        }
+    }
+}
+
+// The simple edits the command line can ask for with --transform.
+#[derive(Clone, Debug)]
+pub enum Transform {
+    // Presses the second button wherever the first one was pressed, and
+    // releases the first.
+    Remap(Button, Button),
+    Swap(Button, Button),
+    Release(Button),
+    SetAxis(Axis, u8),
+    Adjust(Axis, AxisAdjust),
+    Snap(SnapToAngle),
+    Deadzone(Deadzone),
+    Mirror,
+}
+
+impl Transform {
+    // Parses `remap:A=B`, `swap:X,Y`, `release:Z`, `set:analog_x=128`,
+    // `offset:analog_x=+3`, `scale:c_y=1.1`, `recenter:analog_y=127`,
+    // `snap:main` or `snap:c`, optionally with an angle step and a magnitude
+    // as in `snap:main=30,0.8` (or `keep` to keep each tilt), `deadzone:main=8`
+    // and `mirror`. Buttons and axes are named like in filter expressions.
+    pub fn parse(spec: &str) -> Dtm2txtResult<Transform> {
+        let invalid = |reason: String| Dtm2txtError::InvalidTransformError {
+            spec: spec.to_string(),
+            reason: reason,
+        };
+        let button = |name: &str| Button::from_name(name.trim())
+            .ok_or_else(|| invalid(format!("unknown button '{}'", name.trim())));
+        let separated = |separator: char| invalid(format!("expected two values separated by '{}'", separator));
+
+        let (name, args) = spec.split_once(':').unwrap_or((spec, ""));
+        let axis_value = || {
+            let (axis, value) = args.split_once('=').ok_or_else(|| separated('='))?;
+            let axis = Axis::from_name(axis.trim())
+                .ok_or_else(|| invalid(format!("unknown axis '{}'", axis.trim())))?;
+            Ok::<_, Dtm2txtError>((axis, value.trim()))
+        };
+        let stick = |name: &str| match name.trim() {
+            "main" => Ok(Stick::Main),
+            "c" => Ok(Stick::C),
+            other => Err(invalid(format!("unknown stick '{}' (expected main or c)", other))),
+        };
+        match name {
+            "remap" => {
+                let (from, to) = args.split_once('=').ok_or_else(|| separated('='))?;
+                Ok(Transform::Remap(button(from)?, button(to)?))
+            }
+            "swap" => {
+                let (first, second) = args.split_once(',').ok_or_else(|| separated(','))?;
+                Ok(Transform::Swap(button(first)?, button(second)?))
+            }
+            "release" => Ok(Transform::Release(button(args)?)),
+            "set" => {
+                let (axis, value) = axis_value()?;
+                let value = value.parse::<u8>().map_err(|err| invalid(err.to_string()))?;
+                Ok(Transform::SetAxis(axis, value))
+            }
+            "offset" => {
+                let (axis, offset) = axis_value()?;
+                let offset = offset.parse::<i16>().map_err(|err| invalid(err.to_string()))?;
+                Ok(Transform::Adjust(axis, AxisAdjust::Offset(offset)))
+            }
+            "scale" => {
+                let (axis, factor) = axis_value()?;
+                let factor = factor.parse::<f64>().map_err(|err| invalid(err.to_string()))?;
+                if !factor.is_finite() {
+                    return Err(invalid("scale must be a finite number".to_string()));
+                }
+                Ok(Transform::Adjust(axis, AxisAdjust::Scale(factor)))
+            }
+            "recenter" => {
+                let (axis, from) = axis_value()?;
+                let from = from.parse::<u8>().map_err(|err| invalid(err.to_string()))?;
+                Ok(Transform::Adjust(axis, AxisAdjust::Recenter(from)))
+            }
+            "snap" => {
+                let (name, options) = args.split_once('=').unwrap_or((args, ""));
+                let stick = stick(name)?;
+                if options.trim().is_empty() {
+                    return Ok(Transform::Snap(SnapToAngle::new(stick)));
+                }
+                let (step, magnitude) = options.split_once(',').unwrap_or((options, ""));
+                let step = step.trim().parse::<f64>().map_err(|err| invalid(err.to_string()))?;
+                let mut snap = SnapToAngle::every(stick, step).map_err(|err| invalid(err.to_string()))?;
+                snap.magnitude = match magnitude.trim() {
+                    "" => Some(1.0),
+                    "keep" => None,
+                    magnitude => {
+                        let magnitude = magnitude.parse::<f64>().map_err(|err| invalid(err.to_string()))?;
+                        if !(0.0..=1.0).contains(&magnitude) {
+                            return Err(invalid("the magnitude must be between 0 and 1".to_string()));
+                        }
+                        Some(magnitude)
+                    }
+                };
+                Ok(Transform::Snap(snap))
+            }
+            "deadzone" => {
+                let (name, radius) = args.split_once('=').ok_or_else(|| separated('='))?;
+                let radius = radius.trim().parse::<f64>().map_err(|err| invalid(err.to_string()))?;
+                if radius.is_nan() || radius < 0.0 {
+                    return Err(invalid("the radius can't be negative".to_string()));
+                }
+                Ok(Transform::Deadzone(Deadzone::new(stick(name)?, radius)))
+            }
+            "mirror" if args.is_empty() => Ok(Transform::Mirror),
+            _ => Err(invalid("expected remap, swap, release, set, offset, scale, recenter, snap, deadzone or mirror".to_string())),
+        }
+    }
+}
+
+impl FromStr for Transform {
+    type Err = Dtm2txtError;
+
+    fn from_str(spec: &str) -> Dtm2txtResult<Transform> {
+        Transform::parse(spec)
+    }
+}
+
+impl FrameTransform for Transform {
+    fn transform(&self, frame: u64, input: &mut ControllerInput) {
+        match *self {
+            Transform::Remap(from, to) => {
+                if input.pressed(from) {
+                    input.set_pressed(from, false);
+                    input.set_pressed(to, true);
+                }
+            }
+            Transform::Swap(first, second) => {
+                let pressed = input.pressed(first);
+                input.set_pressed(first, input.pressed(second));
+                input.set_pressed(second, pressed);
+            }
+            Transform::Release(button) => input.set_pressed(button, false),
+            Transform::SetAxis(axis, value) => input.set_axis(axis, value),
+            Transform::Adjust(axis, adjust) => {
+                let value = adjust.apply(axis, input.axis(axis));
+                input.set_axis(axis, value);
+            }
+            Transform::Snap(ref snap) => snap.transform(frame, input),
+            Transform::Deadzone(ref deadzone) => deadzone.transform(frame, input),
+            Transform::Mirror => Mirror.transform(frame, input),
+        }
+    }
+}
+
+// Applies a transform to frames as they stream past, for edits during a
+// conversion that never holds the whole movie.
+pub struct TransformFrames<I, T> {
+    frames: I,
+    transform: T,
+    frame: u64,
+}
+
+impl<I, T> Iterator for TransformFrames<I, T>
+    where I: Iterator<Item = ControllerInput>,
+          T: FrameTransform,
+{
+    type Item = ControllerInput;
+
+    fn next(&mut self) -> Option<ControllerInput> {
+        let mut input = self.frames.next()?;
+        self.transform.transform(self.frame, &mut input);
+        self.frame += 1;
+        Some(input)
+    }
+}
+
+pub fn transform_frames<I, T>(frames: I, transform: T) -> TransformFrames<I::IntoIter, T>
+    where I: IntoIterator<Item = ControllerInput>,
+          T: FrameTransform,
+{
+    TransformFrames {
+        frames: frames.into_iter(),
+        transform: transform,
+        frame: 0,
+    }
+}
+
+impl Dtm {
+    pub fn snap_to_angle<R>(&mut self, range: R, snap: &SnapToAngle) -> Dtm2txtResult<()>
+        where R: RangeBounds<u64>,
+    {
+        self.visit_frames_mut(range, |_, input| snap.apply(input))
+    }
+
+    pub fn apply_deadzone<R>(&mut self, range: R, deadzone: &Deadzone) -> Dtm2txtResult<()>
+        where R: RangeBounds<u64>,
+    {
+        self.visit_frames_mut(range, |_, input| deadzone.apply(input))
+    }
+
+    pub fn adjust_axis<R>(&mut self, range: R, axis: Axis, adjust: AxisAdjust) -> Dtm2txtResult<()>
+        where R: RangeBounds<u64>,
+    {
+        self.visit_frames_mut(range, |_, input| {
+            let value = adjust.apply(axis, input.axis(axis));
+            input.set_axis(axis, value);
+        })
+    }
+
+    pub fn mirror<R>(&mut self, range: R) -> Dtm2txtResult<()>
+        where R: RangeBounds<u64>,
+    {
+        self.visit_frames_mut(range, |frame, input| Mirror.transform(frame, input))
+    }
+
+    // Runs every controller input through a transform, along with the frame
+    // it's in. Wii remote reports aren't touched.
+    pub fn map_frames<T>(&mut self, transform: &T)
+        where T: FrameTransform + ?Sized,
+    {
+        let ports = self.ports_per_frame();
+        for (idx, input) in self.controller_data.iter_mut().enumerate() {
+            transform.transform(idx as u64 / ports, input);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dtm::DtmHeader;
+
+    #[test]
+    fn offset_saturates() {
+        assert_eq!(AxisAdjust::Offset(i16::MAX).apply(Axis::AnalogX, 200), 255);
+        assert_eq!(AxisAdjust::Offset(i16::MIN).apply(Axis::AnalogX, 200), 0);
+        assert_eq!(AxisAdjust::Offset(-3).apply(Axis::CY, 130), 127);
+    }
+
+    #[test]
+    fn ranges_count_frames_of_every_port() {
+        let header = DtmHeader::builder().gamecube_ports(&[0, 3]).build();
+        let mut dtm = Dtm::blank(header, 3);
+        dtm.map_frames(&InRange::new(1..2, Transform::SetAxis(Axis::AnalogX, 7)));
+        let values: Vec<u8> = dtm.controller_data.iter().map(|input| input.analog_x).collect();
+        assert_eq!(values, vec![128, 128, 7, 7, 128, 128]);
+    }
+
+    #[test]
+    fn matching_sees_inputs_before_the_change() {
+        let header = DtmHeader::builder().gamecube_ports(&[0]).build();
+        let mut dtm = Dtm::blank(header, 3);
+        dtm.controller_data[1].analog_x = 200;
+        let set_both = vec![Transform::SetAxis(Axis::AnalogX, 0), Transform::SetAxis(Axis::AnalogY, 0)];
+        dtm.map_frames(&Matching::new(|_, input: &ControllerInput| input.analog_x > 128, set_both));
+        let values: Vec<(u8, u8)> = dtm.controller_data.iter().map(|input| (input.analog_x, input.analog_y)).collect();
+        assert_eq!(values, vec![(128, 128), (0, 0), (128, 128)]);
+    }
+
+    #[test]
+    fn snap_step_is_validated() {
+        for &step in [0.0, -45.0, 361.0, f64::NAN].iter() {
+            assert!(SnapToAngle::every(Stick::Main, step).is_err());
+        }
+        assert_eq!(SnapToAngle::every(Stick::Main, 90.0).unwrap().angles, vec![0.0, 90.0, 180.0, 270.0]);
+    }
+
+    #[test]
+    fn snap_parses_step_and_magnitude() {
+        let snap = match Transform::parse("snap:c=30,0.5").unwrap() {
+            Transform::Snap(snap) => snap,
+            other => panic!("parsed {:?}", other),
+        };
+        assert_eq!(snap.stick, Stick::C);
+        assert_eq!(snap.angles.len(), 12);
+        assert_eq!(snap.magnitude, Some(0.5));
+
+        match Transform::parse("snap:main=90,keep").unwrap() {
+            Transform::Snap(snap) => assert_eq!(snap.magnitude, None),
+            other => panic!("parsed {:?}", other),
+        }
+        assert!(Transform::parse("snap:main=0").is_err());
+        assert!(Transform::parse("snap:main=45,2").is_err());
+    }
+}
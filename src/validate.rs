@@ -0,0 +1,78 @@
+use std::fmt;
+use std::path::Path;
+
+use consistency::{self, Inconsistency};
+use dtm::{Dtm, DtmHeader};
+use frames::FrameStore;
+
+// Things about a movie that are likely mistakes, from outright problems
+// Dolphin would choke on to settings that are merely unusual.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationWarning {
+    Inconsistent(Inconsistency),
+    EmptyGameId,
+    MalformedGameId(String),
+    NoControllers,
+    // Movies recorded during netplay rarely sync when played back alone.
+    Netplay,
+    NoVis,
+}
+
+impl fmt::Display for ValidationWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValidationWarning::Inconsistent(ref problem) => problem.fmt(f),
+            ValidationWarning::EmptyGameId => f.write_str("game_id is empty"),
+            ValidationWarning::MalformedGameId(ref game_id) =>
+                write!(f, "game_id '{}' isn't six letters and digits", game_id),
+            ValidationWarning::NoControllers => f.write_str("no controllers are connected"),
+            ValidationWarning::Netplay => f.write_str("movie was recorded during netplay and may not sync offline"),
+            ValidationWarning::NoVis => f.write_str("vi_count is 0 even though there are inputs"),
+        }
+    }
+}
+
+// `Dtm::validate` for a movie known only by its header and how many inputs
+// it has, like one that went through `transcode`.
+pub fn check(header: &DtmHeader, frames: u64, wiimote_frames: u64) -> Vec<ValidationWarning> {
+    let mut warnings: Vec<ValidationWarning> = consistency::check(header, frames, wiimote_frames)
+        .into_iter()
+        .map(ValidationWarning::Inconsistent)
+        .collect();
+
+    if header.game_id.is_empty() {
+        warnings.push(ValidationWarning::EmptyGameId);
+    }
+    else if header.parsed_game_id().is_err() {
+        warnings.push(ValidationWarning::MalformedGameId(header.game_id.clone()));
+    }
+    // consistency already complains when there are inputs.
+    if header.controllers == 0 && frames + wiimote_frames == 0 {
+        warnings.push(ValidationWarning::NoControllers);
+    }
+    if header.netplay {
+        warnings.push(ValidationWarning::Netplay);
+    }
+    if header.vi_count == 0 && frames + wiimote_frames != 0 {
+        warnings.push(ValidationWarning::NoVis);
+    }
+    warnings
+}
+
+impl<S> Dtm<S>
+    where S: FrameStore,
+{
+    pub fn validate(&self) -> Vec<ValidationWarning> {
+        check(&self.header, self.controller_data.len(), self.wiimote_data.len() as u64)
+    }
+
+    // Also checks that a savestate-anchored movie has its savestate next to
+    // where it's stored.
+    pub fn validate_at(&self, movie_path: &Path) -> Vec<ValidationWarning> {
+        let mut warnings = self.validate();
+        if let Some(problem) = consistency::check_savestate(&self.header, movie_path) {
+            warnings.push(ValidationWarning::Inconsistent(problem));
+        }
+        warnings
+    }
+}
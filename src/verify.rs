@@ -0,0 +1,68 @@
+use std::fmt;
+
+use decoder::dtm_decoder::DtmDecoder;
+use decoder::text_decoder::TextDecoder;
+use dtm::HEADER_SIZE;
+use encoder::dtm_encoder::DtmEncoder;
+use encoder::text_encoder::TextEncoder;
+use error::Dtm2txtResult;
+
+// The first byte where a round-tripped dtm stops matching the original.
+// `None` means that file ended first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    pub offset: u64,
+    pub original: Option<u8>,
+    pub round_tripped: Option<u8>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let describe = |byte: Option<u8>| match byte {
+            Some(byte) => format!("0x{:02X}", byte),
+            None => "end of file".to_string(),
+        };
+        write!(f, "offset 0x{:X}: original has {}, round trip has {}",
+            self.offset, describe(self.original), describe(self.round_tripped))?;
+        if self.offset < HEADER_SIZE as u64 {
+            f.write_str(" (in the header)")?;
+        }
+        Ok(())
+    }
+}
+
+pub fn first_mismatch(original: &[u8], round_tripped: &[u8]) -> Option<Mismatch> {
+    let len = original.len().max(round_tripped.len());
+    (0..len)
+        .find(|&idx| original.get(idx) != round_tripped.get(idx))
+        .map(|idx| Mismatch {
+            offset: idx as u64,
+            original: original.get(idx).cloned(),
+            round_tripped: round_tripped.get(idx).cloned(),
+        })
+}
+
+// Decodes `original` and encodes it again, optionally by way of the txt
+// format, returning where the result differs from the original bytes.
+// Consistency checks are skipped; a movie that was inconsistent to begin with
+// should still come back unchanged.
+pub fn verify_round_trip(original: &[u8], through_text: bool) -> Dtm2txtResult<Option<Mismatch>> {
+    let mut dtm = DtmDecoder::new(original).decode()?;
+
+    if through_text {
+        let mut txt = Vec::new();
+        TextEncoder::new(&mut txt).encode(&dtm)?;
+        let input_count = dtm.header.input_count;
+        dtm = TextDecoder::new(&txt[..]).decode()?;
+        // The txt decoder counts the input lines, which would hide a header
+        // that disagreed with them.
+        dtm.header.input_count = input_count;
+    }
+
+    let mut round_tripped = Vec::new();
+    DtmEncoder::new(&mut round_tripped)
+        .allow_inconsistent(true)
+        .encode(&dtm)?;
+
+    Ok(first_mismatch(original, &round_tripped))
+}
@@ -0,0 +1,105 @@
+use std::fmt;
+
+use dtm::{DtmHeader, CpuCore};
+
+// Broad ranges of Dolphin releases, distinguished by which header fields they
+// fill in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DolphinEra {
+    // 3.x and earlier: no revision, DSP hashes or tick count. Those bytes
+    // are zero in these movies, and are kept that way on the way back.
+    Legacy,
+    // 4.0 through the early 5.0 development builds.
+    Dolphin4,
+    // 5.0: language, branch following and FMA flags in reserved2.
+    Dolphin5,
+    // 5.0-13xxx onward: GBA controllers, widescreen and country code.
+    Modern,
+}
+
+impl fmt::Display for DolphinEra {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DolphinEra::Legacy => f.write_str("3.x or older"),
+            DolphinEra::Dolphin4 => f.write_str("4.0 era"),
+            DolphinEra::Dolphin5 => f.write_str("5.0 era"),
+            DolphinEra::Modern => f.write_str("5.0-13000 or newer"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct VersionGuess {
+    pub era: DolphinEra,
+    // Hex git revision, when the movie recorded one.
+    pub revision: Option<String>,
+    // Human-readable reasons behind the guess.
+    pub clues: Vec<String>,
+}
+
+impl fmt::Display for VersionGuess {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.era.fmt(f)?;
+        if let Some(ref revision) = self.revision {
+            write!(f, " (revision {})", revision)?;
+        }
+        Ok(())
+    }
+}
+
+impl DtmHeader {
+    pub fn guess_dolphin_version(&self) -> VersionGuess {
+        let mut era = DolphinEra::Legacy;
+        let mut clues = Vec::new();
+        let mut at_least = |min_era: DolphinEra, clue: &str| {
+            clues.push(clue.to_string());
+            if min_era > era {
+                era = min_era;
+            }
+        };
+
+        let revision_recorded = !self.git_revision.is_zero();
+        if revision_recorded {
+            at_least(DolphinEra::Dolphin4, "git revision is recorded");
+        }
+        if self.dsp_irom_hash != 0 || self.dsp_coef_hash != 0 {
+            at_least(DolphinEra::Dolphin4, "DSP ROM hashes are recorded");
+        }
+        if self.tick_count != 0 {
+            at_least(DolphinEra::Dolphin4, "tick count is recorded");
+        }
+
+        // reserved2 holds language, a reserved byte, follow branch, FMA, GBA
+        // controllers, widescreen and country code in newer builds.
+        let reserved2 = &self.reserved2.0;
+        if reserved2[0] != 0 || reserved2[2] != 0 || reserved2[3] != 0 {
+            at_least(DolphinEra::Dolphin5, "language, branch following or FMA flags are set");
+        }
+        if reserved2[4] != 0 || reserved2[5] != 0 || reserved2[6] != 0 {
+            at_least(DolphinEra::Modern, "GBA controller, widescreen or country code fields are set");
+        }
+
+        match self.video_backend.as_str() {
+            "Vulkan" | "D3D12" => at_least(DolphinEra::Dolphin5, "video backend was added in 5.0"),
+            "Metal" => at_least(DolphinEra::Modern, "video backend was added after 5.0-16000"),
+            "Direct3D9" | "Direct3D11" | "DX9" | "DX11" => clues.push("video backend name predates 5.0".to_string()),
+            _ => {}
+        }
+        if self.cpu_core == CpuCore::JitIl {
+            clues.push("JITIL CPU core was removed during 5.0 development".to_string());
+        }
+
+        let revision = if revision_recorded {
+            Some(self.git_revision.0.iter().map(|byte| format!("{:02x}", byte)).collect())
+        }
+        else {
+            None
+        };
+
+        VersionGuess {
+            era: era,
+            revision: revision,
+            clues: clues,
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use std::convert::TryInto;
+
+use decoder::dtm_decoder::DtmDecoder;
+use dtm::{Dtm, DtmHeader, HEADER_SIZE, CONTROLLER_RECORD_SIZE, ControllerInput};
+use error::{Dtm2txtError, Dtm2txtResult};
+
+// A dtm read in place from bytes already in memory, like a memory-mapped
+// file. Only the header is parsed up front; each input is read from its
+// 8-byte record when asked for, so looking through a huge movie doesn't need
+// a struct per frame.
+pub struct DtmView<'a> {
+    pub header: DtmHeader,
+    records: &'a [u8],
+    trailing: &'a [u8],
+}
+
+impl<'a> DtmView<'a> {
+    // The input count comes from the header, and anything after that many
+    // records is kept as the trailing bytes, like `DtmDecoder` does. Only
+    // GameCube movies can be viewed.
+    pub fn from_bytes(bytes: &'a [u8]) -> Dtm2txtResult<DtmView<'a>> {
+        let header = DtmDecoder::new(bytes).decode_header()?;
+        if header.has_wiimote_inputs() {
+            return Err(Dtm2txtError::WiimoteViewError);
+        }
+
+        let inputs = &bytes[HEADER_SIZE..];
+        let available = (inputs.len() / CONTROLLER_RECORD_SIZE) as u64;
+        if available < header.input_count {
+            return Err(Dtm2txtError::TruncatedInputsError {
+                expected: header.input_count,
+                found: available,
+            });
+        }
+        let (records, trailing) = inputs.split_at(header.input_count as usize * CONTROLLER_RECORD_SIZE);
+
+        Ok(DtmView {
+            header: header,
+            records: records,
+            trailing: trailing,
+        })
+    }
+
+    pub fn len(&self) -> u64 {
+        (self.records.len() / CONTROLLER_RECORD_SIZE) as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    // The raw bytes of one input, as the file has them.
+    pub fn record(&self, frame: u64) -> Option<&'a [u8; CONTROLLER_RECORD_SIZE]> {
+        let start = (frame as usize).checked_mul(CONTROLLER_RECORD_SIZE)?;
+        let records = self.records;
+        records.get(start..start.checked_add(CONTROLLER_RECORD_SIZE)?)?.try_into().ok()
+    }
+
+    pub fn frame(&self, frame: u64) -> Option<ControllerInput> {
+        self.record(frame).map(ControllerInput::from_bytes)
+    }
+
+    pub fn frames(&self) -> impl Iterator<Item = ControllerInput> + 'a {
+        self.records.chunks_exact(CONTROLLER_RECORD_SIZE)
+            .map(|record| ControllerInput::from_bytes(record.try_into().unwrap()))
+    }
+
+    pub fn trailing(&self) -> &'a [u8] {
+        self.trailing
+    }
+
+    // Copies everything out into an ordinary movie, for editing.
+    pub fn to_dtm(&self) -> Dtm {
+        Dtm {
+            header: self.header.clone(),
+            controller_data: self.frames().collect(),
+            wiimote_data: Vec::new(),
+            trailing: self.trailing.to_vec(),
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+// A fresh directory for one test to work in.
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("dtm2txt-cli-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn dtm2txt(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_dtm2txt"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+fn new_movie(dir: &Path, name: &str, frames: u32) {
+    let frames = frames.to_string();
+    let output = dtm2txt(dir, &["new", "--game-id", "GALE01", "--frames", &frames, "-o", name]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn failed_txt_conversion_keeps_existing_dtm() {
+    let dir = scratch_dir("keep-dtm");
+    new_movie(&dir, "movie.dtm", 10);
+    let original = fs::read(dir.join("movie.dtm")).unwrap();
+    assert!(dtm2txt(&dir, &["movie.dtm"]).status.success());
+
+    let text = fs::read_to_string(dir.join("movie.txt")).unwrap();
+    fs::write(dir.join("movie.txt"), text + "not an input line\n").unwrap();
+    // A bare filename always overwrites, like when a file is dropped on the
+    // executable.
+    assert!(!dtm2txt(&dir, &["movie.txt"]).status.success());
+
+    assert_eq!(fs::read(dir.join("movie.dtm")).unwrap(), original);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 2, "partial output was left behind");
+}
+
+#[test]
+fn failed_dtm_conversion_keeps_existing_txt() {
+    let dir = scratch_dir("keep-txt");
+    new_movie(&dir, "full.dtm", 600);
+    let mut bytes = fs::read(dir.join("full.dtm")).unwrap();
+    bytes.truncate(bytes.len() / 2);
+    fs::write(dir.join("bad.dtm"), bytes).unwrap();
+    fs::write(dir.join("bad.txt"), "hand edited\n").unwrap();
+
+    assert!(!dtm2txt(&dir, &["bad.dtm"]).status.success());
+
+    assert_eq!(fs::read_to_string(dir.join("bad.txt")).unwrap(), "hand edited\n");
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 3, "partial output was left behind");
+}
+
+#[test]
+fn transcoding_replaces_existing_output() {
+    let dir = scratch_dir("replace");
+    new_movie(&dir, "movie.dtm", 10);
+    fs::write(dir.join("movie.txt"), "old\n").unwrap();
+
+    assert!(dtm2txt(&dir, &["movie.dtm"]).status.success());
+
+    assert!(fs::read_to_string(dir.join("movie.txt")).unwrap().contains("\"game_id\": \"GALE01\""));
+}
+
+// Five frames for ports 1 and 2.
+fn two_port_movie(dir: &Path, name: &str) {
+    new_movie(dir, "one-port.txt", 10);
+    let text = fs::read_to_string(dir.join("one-port.txt")).unwrap();
+    fs::write(dir.join(name), text.replace("\"controllers\": 1,", "\"controllers\": 3,")).unwrap();
+    fs::remove_file(dir.join("one-port.txt")).unwrap();
+}
+
+fn input_lines(text: &str) -> usize {
+    text.lines().skip_while(|line| *line != "}").skip(1).count()
+}
+
+#[test]
+fn split_counts_frames_of_every_port() {
+    let dir = scratch_dir("split-ports");
+    two_port_movie(&dir, "p2.txt");
+
+    let output = dtm2txt(&dir, &["split", "p2.txt", "--at", "3"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    assert_eq!(input_lines(&fs::read_to_string(dir.join("p2-1.txt")).unwrap()), 6);
+    assert_eq!(input_lines(&fs::read_to_string(dir.join("p2-2.txt")).unwrap()), 4);
+}
+
+#[test]
+fn refused_split_writes_nothing() {
+    let dir = scratch_dir("split-refused");
+    two_port_movie(&dir, "p2.txt");
+    let text = fs::read_to_string(dir.join("p2.txt")).unwrap();
+    // An input too many for the two ports.
+    let extra = text.lines().last().unwrap().to_string();
+    fs::write(dir.join("p2.txt"), text.replace("\"input_count\": 10,", "\"input_count\": 11,") + &extra + "\n").unwrap();
+    assert!(dtm2txt(&dir, &["p2.txt", "-o", "p2.dtm", "--allow-inconsistent"]).status.success());
+    fs::remove_file(dir.join("p2.txt")).unwrap();
+
+    assert!(!dtm2txt(&dir, &["split", "p2.dtm", "--at", "3"]).status.success());
+
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1, "a half was written");
+}
+
+#[test]
+fn extract_keeps_every_port_of_its_frames() {
+    let dir = scratch_dir("extract-ports");
+    two_port_movie(&dir, "p2.txt");
+
+    let output = dtm2txt(&dir, &["extract", "p2.txt", "--range", "1..4", "-o", "e.txt"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("can't be split evenly"));
+
+    let text = fs::read_to_string(dir.join("e.txt")).unwrap();
+    assert_eq!(input_lines(&text), 6);
+    assert!(text.contains("\"input_count\": 6,"));
+}
+
+#[test]
+fn find_matches_frames_of_the_given_port() {
+    let dir = scratch_dir("find-ports");
+    two_port_movie(&dir, "p2.txt");
+
+    assert!(!dtm2txt(&dir, &["find", "p2.txt", "--where", "frame >= 3"]).status.success());
+
+    let output = dtm2txt(&dir, &["find", "p2.txt", "--where", "frame >= 3", "--port", "2"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3\n4\n");
+}
+
+#[test]
+fn refused_encode_keeps_existing_output() {
+    let dir = scratch_dir("refused-encode");
+    two_port_movie(&dir, "p2.txt");
+    let text = fs::read_to_string(dir.join("p2.txt")).unwrap();
+    let extra = text.lines().last().unwrap().to_string();
+    fs::write(dir.join("p2.txt"), text.replace("\"input_count\": 10,", "\"input_count\": 11,") + &extra + "\n").unwrap();
+    fs::write(dir.join("p2.dtm"), "old").unwrap();
+
+    // Setting a field makes this a full decode and encode rather than a
+    // transcode.
+    let output = dtm2txt(&dir, &["p2.txt", "-o", "p2.dtm", "--force", "--set", "author=\"me\""]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("inconsistent"));
+
+    assert_eq!(fs::read_to_string(dir.join("p2.dtm")).unwrap(), "old");
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 2, "partial output was left behind");
+}
+
+#[test]
+fn where_picks_frames_for_extract_stats_and_transforms() {
+    let dir = scratch_dir("where");
+    new_movie(&dir, "blank.txt", 10);
+    let text = fs::read_to_string(dir.join("blank.txt")).unwrap();
+    let mut lines: Vec<String> = text.lines().map(String::from).collect();
+    let first_input = lines.len() - 10;
+    for &frame in [3, 6].iter() {
+        lines[first_input + frame] = lines[first_input + frame].replacen(" a ", " A ", 1);
+    }
+    fs::write(dir.join("movie.txt"), lines.join("\n") + "\n").unwrap();
+
+    let output = dtm2txt(&dir, &["extract", "movie.txt", "--where", "A", "-o", "trimmed.txt"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(input_lines(&fs::read_to_string(dir.join("trimmed.txt")).unwrap()), 4);
+
+    let output = dtm2txt(&dir, &["stats", "movie.txt", "--where", "A"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Frames:       2 of 10 match"));
+
+    let output = dtm2txt(&dir, &["convert", "movie.txt", "edited.txt", "--transform", "set:c_x=0", "--where", "A"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let edited = fs::read_to_string(dir.join("edited.txt")).unwrap();
+    let moved: Vec<&str> = edited.lines().filter(|line| line.ends_with("  0 128 CC")).collect();
+    assert_eq!(moved.len(), 2);
+    assert!(moved.iter().all(|line| line.contains(" A ")));
+}
+
+#[test]
+fn extract_to_stdout_keeps_warnings_out_of_the_movie() {
+    let dir = scratch_dir("extract-stdout");
+    new_movie(&dir, "movie.txt", 10);
+
+    let output = dtm2txt(&dir, &["extract", "movie.txt", "--range", "4..", "-o", "-", "--format", "txt"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(input_lines(&String::from_utf8_lossy(&output.stdout)), 6);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("starts from a savestate"));
+}
+
+#[test]
+fn json_movies_work_as_header_templates() {
+    let dir = scratch_dir("json-template");
+    let output = dtm2txt(&dir, &["new", "--game", "GMSE01", "--author", "someone", "--frames", "3", "-o", "movie.json"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    fs::write(dir.join("route.dts"), "hold A 4\n").unwrap();
+
+    let output = dtm2txt(&dir, &["compile", "route.dts", "--header", "movie.json", "-o", "route.txt"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let text = fs::read_to_string(dir.join("route.txt")).unwrap();
+    assert!(text.contains("\"game_id\": \"GMSE01\"") && text.contains("\"author\": \"someone\""));
+    assert_eq!(input_lines(&text), 4);
+}
@@ -0,0 +1,136 @@
+extern crate dtm2txt;
+
+use dtm2txt::decoder::csv_decoder::CsvDecoder;
+use dtm2txt::decoder::dtm_decoder::DtmDecoder;
+use dtm2txt::decoder::json_decoder::JsonDecoder;
+use dtm2txt::decoder::text_decoder::TextDecoder;
+use dtm2txt::dtm::{Dtm, DtmHeader, WiimoteInput, Button};
+use dtm2txt::encoder::csv_encoder::CsvEncoder;
+use dtm2txt::encoder::dtm_encoder::DtmEncoder;
+use dtm2txt::encoder::json_encoder::JsonEncoder;
+use dtm2txt::encoder::text_encoder::{TextEncoder, FrameSyntax, PortLayout};
+use dtm2txt::fuzz::{self, FuzzOptions};
+
+const SYNTAXES: [FrameSyntax; 4] = [FrameSyntax::Classic, FrameSyntax::Mnemonic, FrameSyntax::Table, FrameSyntax::Compact];
+const LAYOUTS: [PortLayout; 3] = [PortLayout::Interleaved, PortLayout::Labeled, PortLayout::Joined];
+
+// Random inputs on every button and axis, one port after another for each
+// frame, plus a few inputs with the rarely used bits set.
+fn movie(ports: &[u8], frames: u64) -> Dtm {
+    let header = DtmHeader::builder()
+        .game_id("GALE01")
+        .author("round trip")
+        .gamecube_ports(ports)
+        .build();
+    let mut dtm = fuzz::generate(header, &FuzzOptions::new(frames * ports.len() as u64, 2024)).unwrap();
+    dtm.header.vi_count = frames;
+    for (frame, &button) in Button::ALL[12..].iter().enumerate() {
+        dtm.controller_data[frame].set_pressed(button, true);
+    }
+    dtm.controller_data[5] = dtm.controller_data[5].with_controller_connected(false);
+    dtm.trailing = vec![0xDE, 0xAD];
+    dtm
+}
+
+fn to_txt(dtm: &Dtm, syntax: FrameSyntax, layout: PortLayout) -> String {
+    let mut bytes = Vec::new();
+    TextEncoder::new(&mut bytes).syntax(syntax).layout(layout).encode(dtm).unwrap();
+    String::from_utf8(bytes).unwrap()
+}
+
+#[test]
+fn txt_syntaxes_round_trip() {
+    let dtm = movie(&[0], 300);
+    for &syntax in SYNTAXES.iter() {
+        let text = to_txt(&dtm, syntax, PortLayout::Interleaved);
+        let decoded = TextDecoder::new(text.as_bytes()).decode().unwrap();
+        assert_eq!(decoded, dtm, "{:?}", syntax);
+    }
+}
+
+#[test]
+fn txt_layouts_round_trip() {
+    let dtm = movie(&[0, 2, 3], 200);
+    for &syntax in SYNTAXES.iter() {
+        for &layout in LAYOUTS.iter() {
+            let text = to_txt(&dtm, syntax, layout);
+            let decoded = TextDecoder::new(text.as_bytes()).decode().unwrap();
+            assert_eq!(decoded, dtm, "{:?} {:?}", syntax, layout);
+        }
+    }
+}
+
+#[test]
+fn txt_collapsed_repeats_round_trip() {
+    let mut dtm = movie(&[0], 100);
+    for frame in 20..60 {
+        dtm.controller_data[frame] = dtm.controller_data[19];
+    }
+    for &syntax in SYNTAXES.iter() {
+        let mut bytes = Vec::new();
+        TextEncoder::new(&mut bytes).syntax(syntax).collapse_repeats(true).encode(&dtm).unwrap();
+        assert_eq!(TextDecoder::new(&bytes[..]).decode().unwrap(), dtm, "{:?}", syntax);
+    }
+}
+
+#[test]
+fn txt_wiimote_round_trip() {
+    let header = DtmHeader::builder()
+        .game_id("RMGE01")
+        .wiimotes(&[0])
+        .build();
+    let dtm = Dtm {
+        header: DtmHeader {
+            input_count: 3,
+            ..header
+        },
+        controller_data: Vec::new(),
+        wiimote_data: vec![
+            WiimoteInput { report: vec![0x30, 0x00, 0x08] },
+            WiimoteInput { report: vec![0xA1, 0x31, 0x00, 0x00, 0x80, 0x80, 0x9A] },
+            WiimoteInput { report: vec![0x30, 0x10, 0x00] },
+        ],
+        trailing: Vec::new(),
+    };
+    let text = to_txt(&dtm, FrameSyntax::Classic, PortLayout::Interleaved);
+    assert_eq!(TextDecoder::new(text.as_bytes()).decode().unwrap(), dtm);
+}
+
+#[test]
+fn dtm_round_trip() {
+    let dtm = movie(&[0, 1], 500);
+    let mut bytes = Vec::new();
+    DtmEncoder::new(&mut bytes).encode(&dtm).unwrap();
+    assert_eq!(DtmDecoder::new(&bytes[..]).decode().unwrap(), dtm);
+}
+
+#[test]
+fn csv_round_trip() {
+    // A csv file only holds the inputs.
+    let mut dtm = movie(&[0], 300);
+    dtm.trailing.clear();
+    let mut bytes = Vec::new();
+    CsvEncoder::new(&mut bytes).encode(&dtm).unwrap();
+    let decoded = CsvDecoder::new(&bytes[..]).header(dtm.header.clone()).decode().unwrap();
+    assert_eq!(decoded, dtm);
+}
+
+#[test]
+fn json_round_trip() {
+    let dtm = movie(&[1, 2], 200);
+    let mut bytes = Vec::new();
+    JsonEncoder::new(&mut bytes).encode(&dtm).unwrap();
+    assert_eq!(JsonDecoder::new(&bytes[..]).decode().unwrap(), dtm);
+}
+
+#[cfg(feature = "cbor")]
+#[test]
+fn cbor_round_trip() {
+    use dtm2txt::decoder::cbor_decoder::CborDecoder;
+    use dtm2txt::encoder::cbor_encoder::CborEncoder;
+
+    let dtm = movie(&[0, 3], 200);
+    let mut bytes = Vec::new();
+    CborEncoder::new(&mut bytes).encode(&dtm).unwrap();
+    assert_eq!(CborDecoder::new(&bytes[..]).decode().unwrap(), dtm);
+}